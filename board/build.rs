@@ -0,0 +1,141 @@
+//Generates the static machine description from a playfield file
+//so the firmware never has to parse configuration at runtime.
+//The file defaults to machine.toml next to this script and can
+//be overridden with the MACHINE_CONFIG environment variable.
+use serde::Deserialize;
+use std::{collections::HashMap, env, fmt::Write as _, fs, path::PathBuf};
+
+//How many of each actuator src/periphs.rs has room for, and the input bits they share.
+const MAX_BASICS: usize = 16;
+const MAX_FLIPPERS: usize = 2;
+const INPUT_BITS: usize = 16;
+
+#[derive(Deserialize)]
+struct Machine {
+    #[serde(default)]
+    actuator: Vec<Actuator>,
+}
+
+#[derive(Deserialize)]
+struct Actuator {
+    name: String,
+    kind: String,
+    input: String,
+    pwm: String,
+    #[serde(default)]
+    inverted: bool,
+    #[serde(default)]
+    restart: Option<String>,
+}
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=MACHINE_CONFIG");
+    let path = env::var("MACHINE_CONFIG").unwrap_or_else(|_| "machine.toml".into());
+    println!("cargo:rerun-if-changed={}", path);
+
+    let text = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("failed to read machine config {}: {}", path, e));
+    let machine: Machine =
+        toml::from_str(&text).unwrap_or_else(|e| panic!("invalid machine config {}: {}", path, e));
+
+    let mut out = String::new();
+    out.push_str("pub const MACHINE: MachineDesc = MachineDesc {\n    actuators: &[\n");
+    for actuator in &machine.actuator {
+        writeln!(
+            out,
+            "        ActuatorDesc {{ name: {:?}, kind: {}, input: {}, pwm: {}, inverted: {}, restart: {} }},",
+            actuator.name,
+            kind(actuator),
+            input(actuator),
+            pwm(actuator),
+            actuator.inverted,
+            restart(actuator),
+        )
+        .unwrap();
+    }
+    out.push_str("    ],\n};\n");
+    check(&machine);
+
+    let dest = PathBuf::from(env::var("OUT_DIR").unwrap()).join("machine.rs");
+    fs::write(dest, out).unwrap();
+}
+
+//Refuses what the firmware would otherwise only find at boot: kind and input pairs it
+//can't build, more actuators than it has room for and PWM channels driven twice.
+fn check(machine: &Machine) {
+    let (mut basics, mut flippers) = (0, 0);
+    let mut channels = HashMap::new();
+    for actuator in &machine.actuator {
+        if let Some(other) = channels.insert(pwm(actuator), &actuator.name) {
+            panic!(
+                "{}: pwm channel {:?} is already used by {}",
+                actuator.name, actuator.pwm, other
+            );
+        }
+        match (actuator.kind.as_str(), actuator.input.as_str()) {
+            ("basic", "single") => basics += 1,
+            ("flipper", "dual") => flippers += 1,
+            (kind, input) => panic!(
+                "{}: a {} actuator can't have {} input",
+                actuator.name, kind, input
+            ),
+        }
+    }
+    if basics > MAX_BASICS {
+        panic!("{} basic actuators, at most {} fit", basics, MAX_BASICS);
+    }
+    if flippers > MAX_FLIPPERS {
+        panic!("{} flippers, at most {} fit", flippers, MAX_FLIPPERS);
+    }
+    if basics + 2 * flippers > INPUT_BITS {
+        panic!(
+            "actuators need {} input bits, only {} are scanned",
+            basics + 2 * flippers,
+            INPUT_BITS
+        );
+    }
+}
+
+fn kind(actuator: &Actuator) -> &'static str {
+    match actuator.kind.as_str() {
+        "basic" => "ActuatorKind::Basic",
+        "flipper" => "ActuatorKind::Flipper",
+        other => panic!("{}: unknown actuator kind {:?}", actuator.name, other),
+    }
+}
+
+fn input(actuator: &Actuator) -> &'static str {
+    match actuator.input.as_str() {
+        "single" => "InputKind::Single",
+        "dual" => "InputKind::Dual",
+        other => panic!("{}: unknown input type {:?}", actuator.name, other),
+    }
+}
+
+fn restart(actuator: &Actuator) -> &'static str {
+    match actuator.restart.as_ref().map(String::as_str) {
+        None | Some("off") => "RestartPolicy::StayOff",
+        Some("resume") => "RestartPolicy::Resume",
+        Some("home") => "RestartPolicy::Home",
+        Some(other) => panic!("{}: unknown restart policy {:?}", actuator.name, other),
+    }
+}
+
+fn pwm(actuator: &Actuator) -> String {
+    if actuator.pwm == "tc3" {
+        return "Configuration::Tc3".into();
+    }
+
+    let mut parts = actuator.pwm.splitn(2, ':');
+    let timer = match parts.next() {
+        Some("tcc0") => "Tcc0",
+        Some("tcc1") => "Tcc1",
+        Some("tcc2") => "Tcc2",
+        _ => panic!("{}: unknown pwm timer {:?}", actuator.name, actuator.pwm),
+    };
+    let channel = match parts.next() {
+        Some(c @ "0") | Some(c @ "1") | Some(c @ "2") | Some(c @ "3") => c,
+        _ => panic!("{}: invalid pwm channel {:?}", actuator.name, actuator.pwm),
+    };
+    format!("Configuration::{}(Channel::_{})", timer, channel)
+}
@@ -0,0 +1,7 @@
+#!/bin/sh
+# Builds the board firmware and runs the Renode integration tests.
+# Requires renode and renode-test on the PATH.
+set -e
+cd "$(dirname "$0")"
+(cd .. && cargo build --release)
+renode-test --exclude pending board.robot "$@"
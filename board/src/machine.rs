@@ -0,0 +1,9 @@
+//Playfield description generated by build.rs from machine.toml
+#[allow(unused_imports)]
+use solenoids::{
+    machine::{ActuatorDesc, ActuatorKind, InputKind, MachineDesc},
+    pwm::{Channel, Configuration},
+    restart::RestartPolicy,
+};
+
+include!(concat!(env!("OUT_DIR"), "/machine.rs"));
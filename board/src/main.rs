@@ -19,20 +19,30 @@ use rtfm;
 //for this program.
 use hal::{
     clock::GenericClockController,
-    delay::Delay,
     gpio::{Output, Pa17, Pa5, PushPull},
     pac::Peripherals,
     prelude::*,
-    spi_master,
+    sercom::SPIMaster4,
+    time::Hertz,
+    timer::TimerCounter,
+    watchdog::{Watchdog, WatchdogTimeout},
 };
 
 //Create a comms object to interact with the other boards.
 use palantir::{feather_bus as bus, Palantir};
-use solenoids;
+use solenoids::{
+    self,
+    controller::ShiftTiming,
+    status::{Code, StatusLed},
+    sysclock::{self, SysClock},
+};
 
 //Set up the Uartbus for use with palantir
 use bus::UartBus;
 
+//bring in the generated playfield description
+mod machine;
+
 //bring in periphs.rs module
 mod periphs;
 
@@ -40,6 +50,9 @@ mod periphs;
 //palantir to create a slave process later on
 const DEVICE_ADDRESS: u8 = 0x2;
 
+//How often the inputs are scanned and the actuators updated
+const SCAN_RATE_HZ: u32 = 1000;
+
 //Alias the pin names
 type ReceiveEnablePin = Pa5<Output<PushPull>>;
 type StatusLEDPin = Pa17<Output<PushPull>>;
@@ -51,9 +64,9 @@ const APP: () = {
     struct Resources<'a> {
         palantir: Palantir<UartBus<ReceiveEnablePin>>,
         sercom0: hal::pac::SERCOM0,
-        status_led: StatusLEDPin,
-        delay: Delay,
+        status_led: StatusLed<StatusLEDPin>,
         solenoids: periphs::Solenoids,
+        clock: SysClock,
     }
     //Initialization sequence/Object definition
     #[init]
@@ -84,21 +97,26 @@ const APP: () = {
         // This MUST be done AFTER
         uart.enable_rxc_interrupt();
 
-        let spi = spi_master(
-            &mut clocks,
-            1.mhz(),
+        //The input shift registers are read over SERCOM4, using the
+        //same timing the solenoids input controller latches with.
+        let timing = ShiftTiming::default();
+        let gclk0 = clocks.gclk0();
+        let spi = SPIMaster4::new(
+            &clocks.sercom4_core(&gclk0).unwrap(),
+            Hertz(timing.frequency),
+            timing.mode(),
             peripherals.SERCOM4,
             &mut peripherals.PM,
-            pins.sck,
-            pins.mosi,
-            pins.miso,
-            &mut pins.port,
+            (
+                pins.miso.into_pad(&mut pins.port),
+                pins.mosi.into_pad(&mut pins.port),
+                pins.sck.into_pad(&mut pins.port),
+            ),
         );
 
         //load a0 to bring in a latch output
         let load_pin = pins.a0.into_push_pull_output(&mut pins.port);
 
-
         let pwm_controller = solenoids::pwm::Controller::new(
             &mut clocks,
             100.hz(),
@@ -109,23 +127,77 @@ const APP: () = {
             &mut peripherals.PM,
         );
 
+        //TC4 paces the input scan, TC3 is busy with PWM; the controller
+        //starts it
+        let mut scan_timer = TimerCounter::tc4_(
+            &clocks.tc4_tc5(&gclk0).unwrap(),
+            peripherals.TC4,
+            &mut peripherals.PM,
+        );
+        scan_timer.enable_interrupt();
+
+        //The controller only feeds it from scans that read the inputs, so a
+        //hung scan or a dead input bus resets the board, which leaves every
+        //coil off. About 250ms at the WDT's 1.024kHz clock.
+        let mut watchdog = Watchdog::new(peripherals.WDT);
+        watchdog.start(WatchdogTimeout::Cycles256 as u8);
+
+        //SysTick keeps the millisecond clock, which also times the
+        //input latch pulses now that Delay can't have SysTick
+        let clock = SysClock::new(cx.core.SYST, gclk0);
+
         //bring in another group of resources
 
         init::LateResources {
             palantir: Palantir::new_slave(DEVICE_ADDRESS, uart),
             sercom0: unsafe { Peripherals::steal().SERCOM0 },
-            status_led: pins.d13.into_push_pull_output(&mut pins.port),
-            delay: Delay::new(cx.core.SYST, &mut clocks),
-            solenoids: periphs::Solenoids::new(pwm_controller, spi, load_pin),
+            status_led: StatusLed::new(pins.d13.into_push_pull_output(&mut pins.port)),
+            solenoids: periphs::Solenoids::new(
+                pwm_controller,
+                spi,
+                load_pin,
+                clock.delay(),
+                timing,
+                &mut clocks,
+                peripherals.EIC,
+                &mut pins.port,
+                scan_timer,
+                SCAN_RATE_HZ.hz(),
+                watchdog,
+            ),
+            clock,
         }
     }
 
     //This is where stuff will occur
-    #[idle(resources = [status_led])]
-    fn idle(cx: idle::Context) -> ! {
+    #[idle]
+    fn idle(_cx: idle::Context) -> ! {
         loop {}
     }
 
+    //millisecond clock, above everything so no tick is ever late
+    #[task(binds = SysTick, priority = 3)]
+    fn systick(_cx: systick::Context) {
+        sysclock::on_systick();
+    }
+
+    //flipper buttons, above comms so a press is never held up by the bus
+    #[task(binds = EIC, priority = 2, resources = [solenoids])]
+    fn eic(cx: eic::Context) {
+        cx.resources.solenoids.on_direct_input();
+    }
+
+    //periodic input scan and actuator update, the status LED
+    //blinks the fault code while the inputs can't be read
+    #[task(binds = TC4, resources = [solenoids, status_led])]
+    fn tc4(mut cx: tc4::Context) {
+        if let Some(tick) = cx.resources.solenoids.lock(|s| s.on_tick()) {
+            let code = if tick.is_ok() { Code::Ok } else { Code::Fault };
+            cx.resources.status_led.set_code(code);
+            let _ = cx.resources.status_led.update(sysclock::millis());
+        }
+    }
+
     //comms stuff
     #[task(binds = SERCOM0, resources = [palantir, sercom0])]
     fn sercom0(cx: sercom0::Context) {
@@ -1,61 +1,250 @@
+use core::cell::RefCell;
+use core::mem::MaybeUninit;
+use cortex_m::interrupt::{self, Mutex};
 use feather_m0 as hal;
 
 use hal::{
-    gpio::{Output, Pa12, Pa2, Pb10, Pb11, PfD, PushPull},
-    prelude::*,
+    clock::GenericClockController,
+    gpio::{Output, Pa12, Pa2, Pb10, Pb11, PfD, Port, PushPull},
+    pac::EIC,
     sercom::{SPIMaster4, Sercom4Pad0, Sercom4Pad2, Sercom4Pad3},
+    time::Hertz,
+    timer::TimerCounter4,
+    watchdog::Watchdog,
 };
 
+use heapless::{consts::*, Vec};
 use solenoids::{
-    actuators::Basic,
-    pwm::{Channel, Configuration, Controller},
-    Actuator, InputArray, InputData, SingleInput,
+    actuators::{Basic, Flipper},
+    controller::{
+        Controller, ControllerBuilder, Erased, Evaluate, SPIControllerBuilder, ScheduledController,
+        ShiftTiming,
+    },
+    direct::{DirectInputs, Line},
+    machine::{ActuatorInfo, ActuatorKind, InputKind},
+    output::OutputDriver,
+    pwm::{self, Configuration, State},
+    restart::{BlackBox, RestartPolicy},
+    sysclock::SysDelay,
+    wrappers::Restart,
+    Actuator, DualInput, Error, InputArray, InputType, SingleInput,
 };
 
+use crate::machine::MACHINE;
+
 type Bus = SPIMaster4<Sercom4Pad0<Pa12<PfD>>, Sercom4Pad2<Pb10<PfD>>, Sercom4Pad3<Pb11<PfD>>>;
 type LoadPin = Pa2<Output<PushPull>>;
+type Inputs = SPIControllerBuilder<Bus, LoadPin, SysDelay>;
+type Coil = &'static mut (dyn Evaluate + Send);
+type Coils = Controller<Inputs, Coil, PwmChannel>;
+type Scan = ScheduledController<Inputs, Coil, PwmChannel, TimerCounter4, u16, Watchdog>;
+
+//Flipper buttons go straight to the EIC on D11 (PA16) and D12 (PA19),
+//in the order the flippers appear in machine.toml.
+const FLIPPER_PINS: [(u8, u8); 2] = [(16, 0), (19, 3)];
+
+const OFF: State = State {
+    enabled: false,
+    duty_cycle: 0,
+};
+
+//Left alone by the runtime so it survives a soft reset; after a
+//power cycle its contents fail validation and read as all off.
+#[link_section = ".uninit.BLACKBOX"]
+static mut BLACKBOX: MaybeUninit<BlackBox> = MaybeUninit::uninit();
+
+//The actuators are moved here by init so the controller can hold
+//every kind of them as a `&'static mut dyn Evaluate`.
+static mut BASICS: MaybeUninit<Vec<Erased<SingleInput, Restart<Basic>>, U16>> =
+    MaybeUninit::uninit();
+static mut FLIPPERS: MaybeUninit<Vec<Erased<DualInput, Restart<Flipper>>, U2>> =
+    MaybeUninit::uninit();
+
+//Every channel drives the one pwm controller, so it is shared
+//through a critical section.
+static PWM: Mutex<RefCell<Option<pwm::Controller>>> = Mutex::new(RefCell::new(None));
+
+//One channel of the shared pwm controller.
+pub struct PwmChannel(Configuration);
+
+impl OutputDriver for PwmChannel {
+    fn apply(&mut self, state: &State) {
+        interrupt::free(|cs| {
+            if let Some(pwm) = PWM.borrow(cs).borrow_mut().as_mut() {
+                pwm.apply(&self.0, state);
+            }
+        });
+    }
+
+    fn state(&self) -> State {
+        interrupt::free(|cs| {
+            PWM.borrow(cs)
+                .borrow()
+                .as_ref()
+                .map_or(OFF, |pwm| pwm.state(&self.0))
+        })
+    }
+}
 
 pub struct Solenoids {
-    pwm: Controller,
-    input_array: InputArray,
-    bus: Bus,
-    load_pin: LoadPin,
+    scan: Scan,
+    direct: DirectInputs,
+    //The flippers' places in the controller, a bit per actuator.
+    flippers: u16,
 
-    pin1: Basic,
-    pin2: Basic,
+    blackbox: &'static mut BlackBox,
 }
 
 impl Solenoids {
-    pub fn new(pwm: Controller, input_bus: Bus, input_load_pin: LoadPin) -> Self {
-        let mut input_array = InputArray::new();
-        let pin1 = input_array.make_actuator(Configuration::Tc3).unwrap();
-        let pin2 = input_array
-            .make_actuator(Configuration::Tcc0(Channel::_0))
-            .unwrap();
+    pub fn new(
+        mut pwm: pwm::Controller,
+        input_bus: Bus,
+        input_load_pin: LoadPin,
+        delay: SysDelay,
+        timing: ShiftTiming,
+        clocks: &mut GenericClockController,
+        eic: EIC,
+        port: &mut Port,
+        scan_timer: TimerCounter4,
+        scan_rate: Hertz,
+        watchdog: Watchdog,
+    ) -> Self {
+        //only init touches the black box before it is moved into Solenoids
+        let blackbox = unsafe { &mut *BLACKBOX.as_mut_ptr() };
+
+        let inputs = ControllerBuilder::new_spi(input_bus, input_load_pin, delay).timing(timing);
+        let mut controller: Coils = Controller::new(inputs, InputArray::new());
+        let mut basics = Vec::new();
+        let mut flippers = Vec::new();
+        for desc in MACHINE.actuators {
+            if pwm.claim(&desc.pwm).is_err() {
+                unreachable!("{}: pwm channels are checked by build.rs", desc.name);
+            }
+            pwm.set_inverted(&desc.pwm, desc.inverted);
+            let input_array = controller.inputs_mut();
+            let pushed = match (desc.kind, desc.input) {
+                (ActuatorKind::Basic, InputKind::Single) => {
+                    let basic: Basic = input_array.make_actuator(desc.pwm).unwrap();
+                    basics
+                        .push(Erased::new(restore(blackbox, basic, desc.restart)))
+                        .is_ok()
+                }
+                (ActuatorKind::Flipper, InputKind::Dual) => {
+                    let flipper: Flipper = input_array.make_actuator(desc.pwm).unwrap();
+                    flippers
+                        .push(Erased::new(restore(blackbox, flipper, desc.restart)))
+                        .is_ok()
+                }
+                _ => unreachable!("{}: kind and input are checked by build.rs", desc.name),
+            };
+            assert!(pushed, "too many actuators, also checked by build.rs");
+        }
+        interrupt::free(|cs| PWM.borrow(cs).replace(Some(pwm)));
+
+        //only init gets here, once, before anything can reach the actuators
+        let (basics, flippers) = unsafe {
+            BASICS.as_mut_ptr().write(basics);
+            FLIPPERS.as_mut_ptr().write(flippers);
+            (&mut *BASICS.as_mut_ptr(), &mut *FLIPPERS.as_mut_ptr())
+        };
+
+        //registered in machine.toml order, so actuator ids match the controller's
+        let mut basics = basics.iter_mut();
+        let mut flippers = flippers.iter_mut();
+        let mut flipper_mask = 0;
+        let mut flipper_bits = Vec::<u8, U2>::new();
+        for (index, desc) in MACHINE.actuators.iter().enumerate() {
+            let actuator: Coil = match desc.kind {
+                ActuatorKind::Basic => basics.next().unwrap(),
+                ActuatorKind::Flipper => {
+                    let flipper = flippers.next().unwrap();
+                    flipper_mask |= 1 << index;
+                    let bit = flipper.inner().input_config().start_offset();
+                    let _ = flipper_bits.push(bit as u8);
+                    flipper
+                }
+            };
+            if controller.register(actuator, PwmChannel(desc.pwm)).is_err() {
+                unreachable!("{}: the actuator count is checked by build.rs", desc.name);
+            }
+        }
+
+        //unused lines are parked on the last input bit
+        let line = |i: usize| Line {
+            pin: FLIPPER_PINS[i].0,
+            extint: FLIPPER_PINS[i].1,
+            bit: flipper_bits.get(i).copied().unwrap_or(15),
+        };
+        let direct =
+            DirectInputs::new(clocks, eic, port, controller.inputs_mut(), line(0), line(1));
+
         Self {
-            pwm,
-            input_array,
-            bus: input_bus,
-            load_pin: input_load_pin,
-            pin1,
-            pin2,
+            scan: controller
+                .with_watchdog(watchdog)
+                .run_at(scan_timer, scan_rate),
+            direct,
+            flippers: flipper_mask,
+            blackbox,
         }
     }
 
-    pub fn update_states(&mut self) {
-        self.read_inputs();
+    //Called from the EIC interrupt; only the flippers are updated so
+    //the button-to-coil path never waits on the input scan.
+    pub fn on_direct_input(&mut self) {
+        let controller = self.scan.controller_mut();
+        if self.direct.handle(controller.inputs_mut()) {
+            controller.refresh(self.flippers);
+            self.record(self.flippers);
+        }
+    }
 
-        self.update_pin1(self.input_array.read(self.pin1.input_config()))
+    //Called from the TC4 interrupt. None if the scan period hadn't
+    //elapsed, otherwise whether the inputs could be read. The controller
+    //keeps the last good inputs through a failed scan, and holds every
+    //coil off once too many fail in a row.
+    pub fn on_tick(&mut self) -> Option<Result<(), Error>> {
+        //inputs are stamped one tick per scan period, so at the 1kHz
+        //scan rate the timed decorators count in milliseconds
+        let tick = self.scan.on_tick()?.map(|_| ());
+        self.record(u16::MAX);
+        Some(tick)
     }
 
-    fn read_inputs(&mut self) {
-        self.load_pin.set_low().unwrap();
-        let mut buf = [0u8; 2];
-        self.bus.transfer(&mut buf).unwrap();
-        self.load_pin.set_high().unwrap();
+    //Every actuator in machine.toml order, with its binding and the
+    //state its channel is in.
+    pub fn actuators(&self) -> impl Iterator<Item = ActuatorInfo<'static>> + '_ {
+        MACHINE
+            .actuators
+            .iter()
+            .zip(self.scan.controller().actuators())
+            .enumerate()
+            .map(|(id, (desc, (actuator, output)))| ActuatorInfo {
+                id,
+                desc,
+                input_offset: actuator.input_offset(),
+                state: output.state(),
+            })
+    }
 
-        self.input_array.update(u16::from_le_bytes(buf));
+    //Notes which of the actuators in `mask` are on in the black box.
+    fn record(&mut self, mask: u16) {
+        let actuators = self.scan.controller().actuators();
+        for (index, (actuator, output)) in actuators.iter().enumerate() {
+            if mask & (1 << index) != 0 {
+                self.blackbox
+                    .record(actuator.input_offset() as u8, output.state().enabled);
+            }
+        }
     }
+}
 
-    fn update_pin1(&mut self, data: InputData<SingleInput>) {}
+//Wraps an actuator with its restart policy, checking the black box
+//for whether it was on before the reset.
+fn restore<I: InputType, A: Actuator<I>>(
+    blackbox: &BlackBox,
+    actuator: A,
+    policy: RestartPolicy,
+) -> Restart<A> {
+    let was_active = blackbox.was_active(actuator.input_config().start_offset() as u8);
+    Restart::wrap(actuator, policy, was_active)
 }
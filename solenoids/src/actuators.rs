@@ -1,5 +1,7 @@
 use crate::pwm::{Configuration, State};
-use crate::{pwm, Actuator, InputConfig, InputData, SingleInput};
+use crate::{
+    pwm, Actuator, DualInput, Error, InputConfig, InputData, InputType, Param, SingleInput,
+};
 
 pub struct Basic {
     input_config: InputConfig<SingleInput>,
@@ -22,7 +24,7 @@ impl Actuator<SingleInput> for Basic {
         &self.pwm_config
     }
 
-    fn update_state(&self, data: &InputData<SingleInput>, curr_state: State) -> State {
+    fn update_state(&mut self, data: &InputData<SingleInput>, curr_state: State) -> State {
         if data.is_input1_high() {
             State {
                 enabled: true,
@@ -36,3 +38,868 @@ impl Actuator<SingleInput> for Basic {
         }
     }
 }
+
+pub type UpdateFn<I> = fn(&InputData<I>, State) -> State;
+
+/// FnActuator uses a plain function as its update behaviour. Build one with
+/// `InputArray::make_fn_actuator`; when made through `Actuator::new` it simply holds its
+/// current state until given a function with `with_fn`.
+pub struct FnActuator<I: InputType> {
+    input_config: InputConfig<I>,
+    pwm_config: pwm::Configuration,
+    update: UpdateFn<I>,
+}
+
+impl<I: InputType> FnActuator<I> {
+    pub fn from_fn(
+        input_config: InputConfig<I>,
+        pwm_config: Configuration,
+        update: UpdateFn<I>,
+    ) -> Self {
+        Self {
+            input_config,
+            pwm_config,
+            update,
+        }
+    }
+
+    pub fn with_fn(mut self, update: UpdateFn<I>) -> Self {
+        self.update = update;
+        self
+    }
+}
+
+impl<I: InputType> Actuator<I> for FnActuator<I> {
+    fn new(input_config: InputConfig<I>, pwm_config: Configuration) -> Self {
+        Self::from_fn(input_config, pwm_config, |_, curr_state| curr_state)
+    }
+
+    fn input_config(&self) -> &InputConfig<I> {
+        &self.input_config
+    }
+
+    fn pwm_config(&self) -> &Configuration {
+        &self.pwm_config
+    }
+
+    fn update_state(&mut self, data: &InputData<I>, curr_state: State) -> State {
+        (self.update)(data, curr_state)
+    }
+}
+
+/// Counter fires a burst each time its input has risen `threshold` times, e.g. a flasher
+/// on every third spinner rip, so cosmetic feedback doesn't need the master to count
+/// switch events over the bus.
+pub struct Counter {
+    input_config: InputConfig<SingleInput>,
+    pwm_config: pwm::Configuration,
+    threshold: u16,
+    burst: u32,
+    duty_cycle: u32,
+    count: u16,
+    remaining: u32,
+    last: bool,
+}
+
+impl Counter {
+    pub fn with_threshold(mut self, threshold: u16) -> Self {
+        self.threshold = threshold.max(1);
+        self
+    }
+
+    /// Sets how many update cycles the burst lasts and the duty it fires at.
+    pub fn with_burst(mut self, cycles: u32, duty_cycle: u32) -> Self {
+        self.burst = cycles;
+        self.duty_cycle = duty_cycle;
+        self
+    }
+
+    pub fn count(&self) -> u16 {
+        self.count
+    }
+
+    pub fn reset(&mut self) {
+        self.count = 0;
+        self.remaining = 0;
+    }
+}
+
+/// Made through `Actuator::new`, a counter fires a one cycle burst at full duty on every
+/// rising edge.
+impl Actuator<SingleInput> for Counter {
+    fn new(input_config: InputConfig<SingleInput>, pwm_config: Configuration) -> Self {
+        Self {
+            input_config,
+            pwm_config,
+            threshold: 1,
+            burst: 1,
+            duty_cycle: u32::MAX,
+            count: 0,
+            remaining: 0,
+            last: false,
+        }
+    }
+
+    fn input_config(&self) -> &InputConfig<SingleInput> {
+        &self.input_config
+    }
+
+    fn pwm_config(&self) -> &Configuration {
+        &self.pwm_config
+    }
+
+    fn update_state(&mut self, data: &InputData<SingleInput>, curr_state: State) -> State {
+        let high = data.is_input1_high();
+        if high && !self.last {
+            self.count += 1;
+            if self.count >= self.threshold {
+                self.count = 0;
+                self.remaining = self.burst;
+            }
+        }
+        self.last = high;
+
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            State {
+                enabled: true,
+                duty_cycle: self.duty_cycle,
+            }
+        } else {
+            State {
+                enabled: false,
+                duty_cycle: curr_state.duty_cycle,
+            }
+        }
+    }
+
+    fn set_param(&mut self, param: Param, value: u32) -> Result<(), Error> {
+        match param {
+            Param::KickDuty => self.duty_cycle = value,
+            Param::PulseTime => self.burst = value,
+            _ => return Err(Error::Unsupported),
+        }
+        Ok(())
+    }
+}
+
+/// Flipper drives a flipper coil with an end-of-stroke switch. Input 1 is the cabinet
+/// button and input 2 the EOS switch: the coil runs at power duty until EOS closes, then
+/// drops to hold duty. If EOS opens again while the button is held (the ball knocked the
+/// flipper back), power duty is re-applied for a few cycles.
+pub struct Flipper {
+    input_config: InputConfig<DualInput>,
+    pwm_config: pwm::Configuration,
+    power_duty: u32,
+    hold_duty: u32,
+    repower_cycles: u32,
+    repower: u32,
+    eos_reached: bool,
+    last_eos: bool,
+}
+
+impl Flipper {
+    pub fn with_duty(mut self, power_duty: u32, hold_duty: u32) -> Self {
+        self.power_duty = power_duty;
+        self.hold_duty = hold_duty;
+        self
+    }
+
+    /// Sets how many update cycles power duty is re-applied for when EOS opens while the
+    /// button is held.
+    pub fn with_repower(mut self, cycles: u32) -> Self {
+        self.repower_cycles = cycles;
+        self
+    }
+
+    fn next_duty(&mut self, eos: bool) -> u32 {
+        if eos {
+            self.eos_reached = true;
+            self.repower = 0;
+            return self.hold_duty;
+        }
+        if !self.eos_reached {
+            return self.power_duty;
+        }
+
+        if self.last_eos {
+            self.repower = self.repower_cycles;
+        }
+        if self.repower > 0 {
+            self.repower -= 1;
+            self.power_duty
+        } else {
+            self.hold_duty
+        }
+    }
+}
+
+/// Made through `Actuator::new`, a flipper uses full power duty, a quarter of that to hold,
+/// and re-applies power for 5 cycles.
+impl Actuator<DualInput> for Flipper {
+    fn new(input_config: InputConfig<DualInput>, pwm_config: Configuration) -> Self {
+        Self {
+            input_config,
+            pwm_config,
+            power_duty: u32::MAX,
+            hold_duty: u32::MAX / 4,
+            repower_cycles: 5,
+            repower: 0,
+            eos_reached: false,
+            last_eos: false,
+        }
+    }
+
+    fn input_config(&self) -> &InputConfig<DualInput> {
+        &self.input_config
+    }
+
+    fn pwm_config(&self) -> &Configuration {
+        &self.pwm_config
+    }
+
+    fn update_state(&mut self, data: &InputData<DualInput>, curr_state: State) -> State {
+        let eos = data.is_input2_high();
+        let state = if data.is_input1_high() {
+            State {
+                enabled: true,
+                duty_cycle: self.next_duty(eos),
+            }
+        } else {
+            self.eos_reached = false;
+            self.repower = 0;
+            State {
+                enabled: false,
+                duty_cycle: curr_state.duty_cycle,
+            }
+        };
+        self.last_eos = eos;
+        state
+    }
+
+    fn hold_capable(&self) -> bool {
+        true
+    }
+
+    fn set_param(&mut self, param: Param, value: u32) -> Result<(), Error> {
+        match param {
+            Param::KickDuty => self.power_duty = value,
+            Param::HoldDuty => self.hold_duty = value,
+            _ => return Err(Error::Unsupported),
+        }
+        Ok(())
+    }
+}
+
+/// PID gains as fixed point values with `GAIN_SHIFT` fractional bits, so regulation doesn't
+/// need floating point on an FPU-less MCU. The output is in duty cycle units.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Gains {
+    pub kp: i32,
+    pub ki: i32,
+    pub kd: i32,
+}
+
+pub const GAIN_SHIFT: u32 = 8;
+
+/// PidActuator regulates its duty cycle towards a setpoint using a feedback value such as
+/// an ADC-sampled magnet current or mechanism position. The input bits select the
+/// setpoint, so a `TriInput` can choose between seven setpoints; with every input low the
+/// output is off and the controller is reset.
+///
+/// Feedback isn't part of the input word, so the application must call `set_feedback`
+/// with a fresh sample before each update.
+pub struct PidActuator<I: InputType> {
+    input_config: InputConfig<I>,
+    pwm_config: pwm::Configuration,
+    gains: Gains,
+    setpoints: [i32; 8],
+    feedback: i32,
+    integral: i64,
+    last_error: Option<i64>,
+}
+
+impl<I: InputType> PidActuator<I> {
+    pub fn with_gains(mut self, gains: Gains) -> Self {
+        self.gains = gains;
+        self
+    }
+
+    /// Sets the setpoint used when the input bits equal `bits`.
+    pub fn with_setpoint(mut self, bits: u16, setpoint: i32) -> Self {
+        if let Some(s) = self.setpoints.get_mut(bits as usize) {
+            *s = setpoint;
+        }
+        self
+    }
+
+    pub fn set_feedback(&mut self, feedback: i32) {
+        self.feedback = feedback;
+    }
+
+    pub fn reset(&mut self) {
+        self.integral = 0;
+        self.last_error = None;
+    }
+
+    fn regulate(&mut self, setpoint: i32) -> u32 {
+        let error = setpoint as i64 - self.feedback as i64;
+        let derivative = self.last_error.map_or(0, |last| error - last);
+        self.last_error = Some(error);
+
+        let integral = self.integral + error;
+        let output = (self.gains.kp as i64 * error
+            + self.gains.ki as i64 * integral
+            + self.gains.kd as i64 * derivative)
+            >> GAIN_SHIFT;
+
+        // Only integrate while unsaturated, or while the error is pulling the output back
+        // into range, so the integral can't wind up.
+        let max = u32::MAX as i64;
+        if (output >= 0 || error > 0) && (output <= max || error < 0) {
+            self.integral = integral;
+        }
+        output.max(0).min(max) as u32
+    }
+}
+
+/// Made through `Actuator::new`, a PID actuator has zero gains and setpoints.
+impl<I: InputType> Actuator<I> for PidActuator<I> {
+    fn new(input_config: InputConfig<I>, pwm_config: Configuration) -> Self {
+        Self {
+            input_config,
+            pwm_config,
+            gains: Gains::default(),
+            setpoints: [0; 8],
+            feedback: 0,
+            integral: 0,
+            last_error: None,
+        }
+    }
+
+    fn input_config(&self) -> &InputConfig<I> {
+        &self.input_config
+    }
+
+    fn pwm_config(&self) -> &Configuration {
+        &self.pwm_config
+    }
+
+    fn update_state(&mut self, data: &InputData<I>, curr_state: State) -> State {
+        let bits = data.bits() as usize;
+        if bits == 0 || bits >= self.setpoints.len() {
+            self.reset();
+            return State {
+                enabled: false,
+                duty_cycle: curr_state.duty_cycle,
+            };
+        }
+
+        State {
+            enabled: true,
+            duty_cycle: self.regulate(self.setpoints[bits]),
+        }
+    }
+
+    fn hold_capable(&self) -> bool {
+        true
+    }
+}
+
+/// Velocity measures the time between input 1 and input 2 closing, e.g. two optos in a
+/// shooter lane, and kicks with a duty scaled by that time. Times use the units of the
+/// input timestamps (see `InputArray::update_at`). Anything at or faster than `fast` kicks
+/// at `fast_duty`, anything at or slower than `slow` at `slow_duty`, and times between are
+/// interpolated.
+pub struct Velocity {
+    input_config: InputConfig<DualInput>,
+    pwm_config: pwm::Configuration,
+    fast: u32,
+    slow: u32,
+    timeout: u32,
+    fast_duty: u32,
+    slow_duty: u32,
+    pulse: u32,
+    armed_at: Option<u32>,
+    remaining: u32,
+    duty_cycle: u32,
+    last: u16,
+}
+
+impl Velocity {
+    pub fn with_window(mut self, fast: u32, slow: u32) -> Self {
+        self.fast = fast;
+        self.slow = slow.max(fast);
+        self
+    }
+
+    pub fn with_duty(mut self, fast_duty: u32, slow_duty: u32) -> Self {
+        self.fast_duty = fast_duty;
+        self.slow_duty = slow_duty;
+        self
+    }
+
+    /// Sets how many update cycles the kick lasts.
+    pub fn with_pulse(mut self, cycles: u32) -> Self {
+        self.pulse = cycles;
+        self
+    }
+
+    /// Input 2 closing longer than `timeout` after input 1 doesn't kick.
+    pub fn with_timeout(mut self, timeout: u32) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The duty a kick with the given time between switches fires at.
+    pub fn duty_for(&self, elapsed: u32) -> u32 {
+        if elapsed <= self.fast {
+            return self.fast_duty;
+        }
+        if elapsed >= self.slow {
+            return self.slow_duty;
+        }
+
+        let span = (self.slow - self.fast) as i64;
+        let pos = (elapsed - self.fast) as i64;
+        let delta = self.slow_duty as i64 - self.fast_duty as i64;
+        (self.fast_duty as i64 + delta * pos / span) as u32
+    }
+}
+
+/// Made through `Actuator::new`, a velocity kick fires for one cycle at full duty however
+/// fast the switches close.
+impl Actuator<DualInput> for Velocity {
+    fn new(input_config: InputConfig<DualInput>, pwm_config: Configuration) -> Self {
+        Self {
+            input_config,
+            pwm_config,
+            fast: 0,
+            slow: 0,
+            timeout: u32::MAX,
+            fast_duty: u32::MAX,
+            slow_duty: u32::MAX,
+            pulse: 1,
+            armed_at: None,
+            remaining: 0,
+            duty_cycle: 0,
+            last: 0,
+        }
+    }
+
+    fn input_config(&self) -> &InputConfig<DualInput> {
+        &self.input_config
+    }
+
+    fn pwm_config(&self) -> &Configuration {
+        &self.pwm_config
+    }
+
+    fn update_state(&mut self, data: &InputData<DualInput>, curr_state: State) -> State {
+        let bits = data.bits();
+        let rose = bits & !self.last;
+        self.last = bits;
+        let now = data.timestamp();
+
+        if let Some(start) = self.armed_at {
+            if now.wrapping_sub(start) > self.timeout {
+                self.armed_at = None;
+            }
+        }
+        if rose & 0b01 != 0 {
+            self.armed_at = Some(now);
+        } else if rose & 0b10 != 0 {
+            if let Some(start) = self.armed_at.take() {
+                self.duty_cycle = self.duty_for(now.wrapping_sub(start));
+                self.remaining = self.pulse;
+            }
+        }
+
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            State {
+                enabled: true,
+                duty_cycle: self.duty_cycle,
+            }
+        } else {
+            State {
+                enabled: false,
+                duty_cycle: curr_state.duty_cycle,
+            }
+        }
+    }
+
+    fn set_param(&mut self, param: Param, value: u32) -> Result<(), Error> {
+        match param {
+            Param::PulseTime => self.pulse = value,
+            _ => return Err(Error::Unsupported),
+        }
+        Ok(())
+    }
+}
+
+/// Chime fires a short strike on each rising edge of its input, then waits a re-cock delay
+/// before the next strike can fire. Triggers arriving faster than strikes can be played are
+/// queued, up to a limit, and played in turn.
+pub struct Chime {
+    input_config: InputConfig<SingleInput>,
+    pwm_config: pwm::Configuration,
+    strike_cycles: u32,
+    recock_cycles: u32,
+    duty_cycle: u32,
+    max_queued: u8,
+    queued: u8,
+    striking: u32,
+    recocking: u32,
+    last: bool,
+}
+
+impl Chime {
+    /// Sets how many update cycles each strike lasts and the duty it fires at.
+    pub fn with_strike(mut self, cycles: u32, duty_cycle: u32) -> Self {
+        self.strike_cycles = cycles.max(1);
+        self.duty_cycle = duty_cycle;
+        self
+    }
+
+    /// Sets how many update cycles the output stays off after each strike.
+    pub fn with_recock(mut self, cycles: u32) -> Self {
+        self.recock_cycles = cycles;
+        self
+    }
+
+    /// Sets how many strikes can wait behind the one playing. Extra triggers are dropped.
+    pub fn with_queue(mut self, max_queued: u8) -> Self {
+        self.max_queued = max_queued;
+        self
+    }
+
+    pub fn queued(&self) -> u8 {
+        self.queued
+    }
+}
+
+/// Made through `Actuator::new`, a chime strikes for one cycle at full duty with no re-cock
+/// delay and queues up to four strikes.
+impl Actuator<SingleInput> for Chime {
+    fn new(input_config: InputConfig<SingleInput>, pwm_config: Configuration) -> Self {
+        Self {
+            input_config,
+            pwm_config,
+            strike_cycles: 1,
+            recock_cycles: 0,
+            duty_cycle: u32::MAX,
+            max_queued: 4,
+            queued: 0,
+            striking: 0,
+            recocking: 0,
+            last: false,
+        }
+    }
+
+    fn input_config(&self) -> &InputConfig<SingleInput> {
+        &self.input_config
+    }
+
+    fn pwm_config(&self) -> &Configuration {
+        &self.pwm_config
+    }
+
+    fn update_state(&mut self, data: &InputData<SingleInput>, curr_state: State) -> State {
+        let high = data.is_input1_high();
+        let idle = self.striking == 0 && self.recocking == 0;
+        if high && !self.last {
+            // An idle chime plays the trigger straight away rather than queueing it.
+            let limit = self.max_queued.saturating_add(idle as u8);
+            self.queued = self.queued.saturating_add(1).min(limit);
+        }
+        self.last = high;
+
+        if idle && self.queued > 0 {
+            self.queued -= 1;
+            self.striking = self.strike_cycles;
+        }
+
+        if self.striking > 0 {
+            self.striking -= 1;
+            if self.striking == 0 {
+                self.recocking = self.recock_cycles;
+            }
+            return State {
+                enabled: true,
+                duty_cycle: self.duty_cycle,
+            };
+        }
+
+        self.recocking = self.recocking.saturating_sub(1);
+        State {
+            enabled: false,
+            duty_cycle: curr_state.duty_cycle,
+        }
+    }
+
+    fn set_param(&mut self, param: Param, value: u32) -> Result<(), Error> {
+        match param {
+            Param::KickDuty => self.duty_cycle = value,
+            Param::PulseTime => self.strike_cycles = value.max(1),
+            _ => return Err(Error::Unsupported),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::actuators::{
+        Chime, Counter, Flipper, FnActuator, Gains, PidActuator, Velocity, GAIN_SHIFT,
+    };
+    use crate::pwm::{Configuration, State};
+    use crate::{Actuator, DualInput, InputArray, InputData, SingleInput};
+
+    fn both_high(data: &InputData<DualInput>, curr_state: State) -> State {
+        State {
+            enabled: data.is_input1_high() && data.is_input2_high(),
+            duty_cycle: curr_state.duty_cycle,
+        }
+    }
+
+    #[test]
+    fn fn_actuator_uses_function() {
+        let mut inputs = InputArray::new();
+        let mut actuator = inputs
+            .make_fn_actuator(Configuration::Tc3, both_high)
+            .unwrap();
+        let off = State {
+            enabled: false,
+            duty_cycle: 10,
+        };
+
+        inputs.update(1);
+        let state = actuator.update_state(&inputs.read(actuator.input_config()), off);
+        assert!(!state.enabled);
+
+        inputs.update(0b11);
+        let state = actuator.update_state(&inputs.read(actuator.input_config()), off);
+        assert!(state.enabled);
+        assert_eq!(state.duty_cycle, 10);
+    }
+
+    #[test]
+    fn fn_actuator_default_holds_state() {
+        let mut inputs = InputArray::new();
+        let mut actuator: FnActuator<DualInput> = inputs.make_actuator(Configuration::Tc3).unwrap();
+        let on = State {
+            enabled: true,
+            duty_cycle: 10,
+        };
+
+        inputs.update(0);
+        let state = actuator.update_state(&inputs.read(actuator.input_config()), on);
+        assert_eq!(state, on);
+    }
+
+    #[test]
+    fn counter_fires_on_threshold() {
+        let mut inputs = InputArray::new();
+        let mut spinner = inputs
+            .make_actuator::<SingleInput, Counter>(Configuration::Tc3)
+            .unwrap()
+            .with_threshold(3)
+            .with_burst(2, 100);
+        let off = State {
+            enabled: false,
+            duty_cycle: 0,
+        };
+
+        let mut fired = [false; 8];
+        for (i, fired) in fired.iter_mut().enumerate() {
+            inputs.update((i % 2 == 0) as u16);
+            *fired = spinner
+                .update_state(&inputs.read(spinner.input_config()), off)
+                .enabled;
+        }
+
+        // Rising edges on 0, 2 and 4; the third fires for two cycles.
+        assert_eq!(
+            fired,
+            [false, false, false, false, true, true, false, false]
+        );
+        assert_eq!(spinner.count(), 1);
+    }
+
+    #[test]
+    fn flipper_power_hold_repower() {
+        let mut inputs = InputArray::new();
+        let mut flipper = inputs
+            .make_actuator::<DualInput, Flipper>(Configuration::Tc3)
+            .unwrap()
+            .with_duty(100, 25)
+            .with_repower(2);
+        let off = State {
+            enabled: false,
+            duty_cycle: 0,
+        };
+
+        let mut duties = [None; 8];
+        // button, button + EOS, button + EOS, button (knocked back) x3, button + EOS, off
+        let raw = [0b01, 0b11, 0b11, 0b01, 0b01, 0b01, 0b11, 0b00];
+        for (duty, &raw) in duties.iter_mut().zip(raw.iter()) {
+            inputs.update(raw);
+            let state = flipper.update_state(&inputs.read(flipper.input_config()), off);
+            *duty = if state.enabled {
+                Some(state.duty_cycle)
+            } else {
+                None
+            };
+        }
+
+        assert_eq!(
+            duties,
+            [
+                Some(100),
+                Some(25),
+                Some(25),
+                Some(100),
+                Some(100),
+                Some(25),
+                Some(25),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn pid_regulates_towards_setpoint() {
+        let mut inputs = InputArray::new();
+        let mut magnet = inputs
+            .make_actuator::<DualInput, PidActuator<DualInput>>(Configuration::Tc3)
+            .unwrap()
+            .with_gains(Gains {
+                kp: 1 << GAIN_SHIFT,
+                ki: 1 << (GAIN_SHIFT - 2),
+                kd: 0,
+            })
+            .with_setpoint(0b01, 100)
+            .with_setpoint(0b10, 400);
+        let off = State {
+            enabled: false,
+            duty_cycle: 0,
+        };
+
+        inputs.update(0b00);
+        magnet.set_feedback(0);
+        let state = magnet.update_state(&inputs.read(magnet.input_config()), off);
+        assert!(!state.enabled);
+
+        // Feedback follows duty exactly, so the loop should settle on the setpoint.
+        let mut feedback = 0;
+        inputs.update(0b01);
+        for _ in 0..100 {
+            magnet.set_feedback(feedback);
+            let state = magnet.update_state(&inputs.read(magnet.input_config()), off);
+            assert!(state.enabled);
+            feedback = state.duty_cycle as i32 / 2;
+        }
+        assert!((feedback - 100).abs() <= 2, "settled at {}", feedback);
+
+        inputs.update(0b10);
+        for _ in 0..100 {
+            magnet.set_feedback(feedback);
+            let state = magnet.update_state(&inputs.read(magnet.input_config()), off);
+            feedback = state.duty_cycle as i32 / 2;
+        }
+        assert!((feedback - 400).abs() <= 2, "settled at {}", feedback);
+    }
+
+    #[test]
+    fn velocity_scales_with_time_between_switches() {
+        let mut inputs = InputArray::new();
+        let mut kicker = inputs
+            .make_actuator::<DualInput, Velocity>(Configuration::Tc3)
+            .unwrap()
+            .with_window(10, 50)
+            .with_duty(1000, 200)
+            .with_timeout(100);
+        assert_eq!(kicker.duty_for(30), 600);
+        let off = State {
+            enabled: false,
+            duty_cycle: 0,
+        };
+
+        let mut kick = |raw, time| {
+            inputs.update_at(raw, time);
+            let state = kicker.update_state(&inputs.read(kicker.input_config()), off);
+            if state.enabled {
+                Some(state.duty_cycle)
+            } else {
+                None
+            }
+        };
+
+        assert_eq!(kick(0b01, 100), None);
+        assert_eq!(kick(0b00, 104), None);
+        assert_eq!(kick(0b10, 105), Some(1000));
+        assert_eq!(kick(0b00, 106), None);
+
+        assert_eq!(kick(0b01, 200), None);
+        assert_eq!(kick(0b10, 230), Some(600));
+        assert_eq!(kick(0b00, 240), None);
+
+        // Too slow, the first switch has timed out.
+        assert_eq!(kick(0b01, 300), None);
+        assert_eq!(kick(0b00, 350), None);
+        assert_eq!(kick(0b10, 401), None);
+    }
+
+    #[test]
+    fn chime_queues_strikes() {
+        let mut inputs = InputArray::new();
+        let mut chime = inputs
+            .make_actuator::<SingleInput, Chime>(Configuration::Tc3)
+            .unwrap()
+            .with_strike(2, 100)
+            .with_recock(3)
+            .with_queue(1);
+        let off = State {
+            enabled: false,
+            duty_cycle: 0,
+        };
+
+        // Three quick triggers: one plays, one queues, one is dropped.
+        let raw = [1, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0];
+        let mut fired = [false; 12];
+        for (fired, &raw) in fired.iter_mut().zip(raw.iter()) {
+            inputs.update(raw);
+            *fired = chime
+                .update_state(&inputs.read(chime.input_config()), off)
+                .enabled;
+        }
+
+        assert_eq!(
+            fired,
+            [true, true, false, false, false, true, true, false, false, false, false, false]
+        );
+        assert_eq!(chime.queued(), 0);
+    }
+
+    #[test]
+    fn chime_queue_saturates() {
+        let mut inputs = InputArray::new();
+        let mut chime = inputs
+            .make_actuator::<SingleInput, Chime>(Configuration::Tc3)
+            .unwrap()
+            .with_strike(200, 100)
+            .with_queue(255);
+        let off = State {
+            enabled: false,
+            duty_cycle: 0,
+        };
+
+        // More triggers than the queue holds, all while the first strike plays.
+        for raw in (0..600).map(|i| i % 2) {
+            inputs.update(raw);
+            chime.update_state(&inputs.read(chime.input_config()), off);
+        }
+        assert_eq!(chime.queued(), 255);
+    }
+}
@@ -0,0 +1,22 @@
+use cortex_m::peripheral::SCB;
+
+/// Key a `command::Command::EnterBootloader` must carry, so line noise or a stray frame
+/// can't knock a board off the bus.
+pub const BOOTLOADER_KEY: u32 = 0xB007_10AD;
+
+// What the UF2 bootloader looks for at reset to stay in bootloader mode, as if reset had
+// been double-tapped.
+const DOUBLE_TAP_MAGIC: u32 = 0xF016_69EF;
+// The last word of the SAMD21G18's 32K of RAM, where the bootloader looks for it.
+const DOUBLE_TAP_ADDRESS: *mut u32 = 0x2000_7FFC as *mut u32;
+
+/// Resets into the UF2 bootloader, so a board buried in a cabinet can be reflashed over USB
+/// without reaching its reset button. Nothing is shut down first: turn the outputs off
+/// before calling it, as `controller::Controller` does when it accepts the command.
+pub fn enter() -> ! {
+    cortex_m::interrupt::disable();
+    unsafe {
+        core::ptr::write_volatile(DOUBLE_TAP_ADDRESS, DOUBLE_TAP_MAGIC);
+    }
+    SCB::sys_reset()
+}
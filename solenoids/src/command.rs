@@ -0,0 +1,364 @@
+use bitflags::bitflags;
+use heapless::{consts::*, Vec};
+
+use crate::Param;
+
+/// First byte of every command frame from the master.
+pub const COMMAND_FRAME_ID: u8 = 0x43;
+
+/// Destination address every board on the bus accepts, see `is_for`.
+pub const BROADCAST_ADDRESS: u8 = 0xFF;
+
+pub(crate) const OP_FIRE: u8 = 0x01;
+pub(crate) const OP_CONFIGURE: u8 = 0x02;
+const OP_IDENTIFY: u8 = 0x03;
+const OP_ALL_OFF: u8 = 0x04;
+const OP_ATTRACT: u8 = 0x05;
+const OP_ENTER_BOOTLOADER: u8 = 0x06;
+const OP_TEST_MODE: u8 = 0x07;
+const OP_SET_INPUT: u8 = 0x08;
+const OP_SAVE_CONFIG: u8 = 0x09;
+
+/// Whether a frame sent to `destination` is for the board at `address`: its own frames
+/// and broadcasts. Boards act on broadcasts but never answer them, so a whole bus can be
+/// told something at once without the replies colliding.
+pub fn is_for(destination: u8, address: u8) -> bool {
+    destination == address || destination == BROADCAST_ADDRESS
+}
+
+/// A request from the master board, carried over palantir as a command frame:
+/// `COMMAND_FRAME_ID`, an opcode, then the arguments little-endian.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    /// Fire actuator `id`, its registration order on the controller, for `millis` at
+    /// `strength` percent of the duty it asks for. The actuator's input 1 is held active
+    /// for that long, so the actuator and its decorators (on-time limits, cooldowns) run
+    /// exactly as if its switch had closed.
+    Fire { id: u8, strength: u8, millis: u16 },
+    /// Set `param` of actuator `id` to `value`: a duty cycle, or a time in the units the
+    /// parameter's docs give.
+    Configure { id: u8, param: Param, value: u32 },
+    /// Ask the board to describe itself; answered with a `Reply::Identity`.
+    Identify,
+    /// Turn every output off now, dropping remote fires and scheduled actions. Meant to be
+    /// broadcast, e.g. at the end of a ball.
+    AllOff,
+    /// Enter or leave attract mode, in which every output is held off and the switches
+    /// are ignored, so nothing on the playfield fires between games.
+    Attract { enabled: bool },
+    /// Turn every output off and reset into the UF2 bootloader. Ignored with
+    /// `Error::BadKey` unless `key` is `bootloader::BOOTLOADER_KEY`.
+    EnterBootloader { key: u32 },
+    /// Enter test mode for assembly-line and service testing, or leave it with a
+    /// `timeout_ms` of zero. While testing, fires are capped at `max_strength` percent and
+    /// inputs can be forced with `SetInput`. The board drops back to normal operation by
+    /// itself once `timeout_ms` passes without a command.
+    TestMode { timeout_ms: u16, max_strength: u8 },
+    /// Force input `bit` to a level, or hand it back to its switch with `None`. Only
+    /// allowed in test mode.
+    SetInput { bit: u8, level: Option<bool> },
+    /// Store the address and the parameters set by `Configure` in flash, so they're
+    /// loaded again at boot.
+    SaveConfig,
+}
+
+impl Command {
+    pub fn to_bytes(&self) -> Vec<u8, U16> {
+        let mut bytes = Vec::new();
+        match *self {
+            Command::Fire {
+                id,
+                strength,
+                millis,
+            } => {
+                let millis = millis.to_le_bytes();
+                let _ = bytes.extend_from_slice(&[
+                    COMMAND_FRAME_ID,
+                    OP_FIRE,
+                    id,
+                    strength,
+                    millis[0],
+                    millis[1],
+                ]);
+            }
+            Command::Configure { id, param, value } => {
+                let _ = bytes.extend_from_slice(&[COMMAND_FRAME_ID, OP_CONFIGURE, id, param as u8]);
+                let _ = bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            Command::Identify => {
+                let _ = bytes.extend_from_slice(&[COMMAND_FRAME_ID, OP_IDENTIFY]);
+            }
+            Command::AllOff => {
+                let _ = bytes.extend_from_slice(&[COMMAND_FRAME_ID, OP_ALL_OFF]);
+            }
+            Command::Attract { enabled } => {
+                let _ = bytes.extend_from_slice(&[COMMAND_FRAME_ID, OP_ATTRACT, enabled as u8]);
+            }
+            Command::EnterBootloader { key } => {
+                let _ = bytes.extend_from_slice(&[COMMAND_FRAME_ID, OP_ENTER_BOOTLOADER]);
+                let _ = bytes.extend_from_slice(&key.to_le_bytes());
+            }
+            Command::TestMode {
+                timeout_ms,
+                max_strength,
+            } => {
+                let timeout_ms = timeout_ms.to_le_bytes();
+                let _ = bytes.extend_from_slice(&[
+                    COMMAND_FRAME_ID,
+                    OP_TEST_MODE,
+                    timeout_ms[0],
+                    timeout_ms[1],
+                    max_strength,
+                ]);
+            }
+            Command::SetInput { bit, level } => {
+                let level = match level {
+                    Some(level) => level as u8,
+                    None => 2,
+                };
+                let _ = bytes.extend_from_slice(&[COMMAND_FRAME_ID, OP_SET_INPUT, bit, level]);
+            }
+            Command::SaveConfig => {
+                let _ = bytes.extend_from_slice(&[COMMAND_FRAME_ID, OP_SAVE_CONFIG]);
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [COMMAND_FRAME_ID, OP_FIRE, id, strength, lo, hi] => Some(Command::Fire {
+                id: *id,
+                strength: *strength,
+                millis: u16::from_le_bytes([*lo, *hi]),
+            }),
+            [COMMAND_FRAME_ID, OP_CONFIGURE, id, param, b0, b1, b2, b3] => {
+                Some(Command::Configure {
+                    id: *id,
+                    param: Param::from_u8(*param)?,
+                    value: u32::from_le_bytes([*b0, *b1, *b2, *b3]),
+                })
+            }
+            [COMMAND_FRAME_ID, OP_IDENTIFY] => Some(Command::Identify),
+            [COMMAND_FRAME_ID, OP_ALL_OFF] => Some(Command::AllOff),
+            [COMMAND_FRAME_ID, OP_ATTRACT, enabled @ 0..=1] => Some(Command::Attract {
+                enabled: *enabled != 0,
+            }),
+            [COMMAND_FRAME_ID, OP_ENTER_BOOTLOADER, b0, b1, b2, b3] => {
+                Some(Command::EnterBootloader {
+                    key: u32::from_le_bytes([*b0, *b1, *b2, *b3]),
+                })
+            }
+            [COMMAND_FRAME_ID, OP_TEST_MODE, lo, hi, max_strength] => Some(Command::TestMode {
+                timeout_ms: u16::from_le_bytes([*lo, *hi]),
+                max_strength: *max_strength,
+            }),
+            [COMMAND_FRAME_ID, OP_SET_INPUT, bit, level @ 0..=2] => Some(Command::SetInput {
+                bit: *bit,
+                level: match level {
+                    2 => None,
+                    level => Some(*level != 0),
+                },
+            }),
+            [COMMAND_FRAME_ID, OP_SAVE_CONFIG] => Some(Command::SaveConfig),
+            _ => None,
+        }
+    }
+}
+
+/// First byte of every identity frame.
+pub const IDENTITY_FRAME_ID: u8 = 0x49;
+
+bitflags! {
+    /// What a board can do beyond driving its actuators from its own switches.
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    pub struct Capabilities: u8 {
+        /// Understands `Command::Fire`.
+        const FIRE = 1 << 0;
+        /// Understands `Command::Configure`.
+        const CONFIGURE = 1 << 1;
+        /// Built with the `usb` feature, so it has a USB console.
+        const USB = 1 << 2;
+        /// Understands `Command::EnterBootloader`.
+        const BOOTLOADER = 1 << 3;
+    }
+}
+
+impl Capabilities {
+    /// Everything this build of the firmware supports.
+    pub fn supported() -> Self {
+        let mut capabilities =
+            Capabilities::FIRE | Capabilities::CONFIGURE | Capabilities::BOOTLOADER;
+        capabilities.set(Capabilities::USB, cfg!(feature = "usb"));
+        capabilities
+    }
+}
+
+/// A board's answer to `Command::Identify`, so the master can discover what each
+/// solenoid board on the bus offers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Identity {
+    /// The board's palantir address.
+    pub address: u8,
+    /// Firmware version as major, minor, patch.
+    pub version: [u8; 3],
+    /// Registered actuators, the highest valid `id` in commands being one less.
+    pub actuators: u8,
+    /// Input bits allocated to actuators.
+    pub inputs: u8,
+    /// Width of the input word in bits.
+    pub input_width: u8,
+    pub capabilities: Capabilities,
+}
+
+impl Identity {
+    /// The version of this crate, which the firmware is built from.
+    pub fn firmware_version() -> [u8; 3] {
+        [
+            env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap_or(0),
+            env!("CARGO_PKG_VERSION_MINOR").parse().unwrap_or(0),
+            env!("CARGO_PKG_VERSION_PATCH").parse().unwrap_or(0),
+        ]
+    }
+
+    pub fn to_bytes(&self) -> [u8; 9] {
+        [
+            IDENTITY_FRAME_ID,
+            self.address,
+            self.version[0],
+            self.version[1],
+            self.version[2],
+            self.actuators,
+            self.inputs,
+            self.input_width,
+            self.capabilities.bits(),
+        ]
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [IDENTITY_FRAME_ID, address, major, minor, patch, actuators, inputs, input_width, capabilities] => {
+                Some(Self {
+                    address: *address,
+                    version: [*major, *minor, *patch],
+                    actuators: *actuators,
+                    inputs: *inputs,
+                    input_width: *input_width,
+                    capabilities: Capabilities::from_bits(*capabilities)?,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// What the board sends back for a command that asks for an answer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reply {
+    Identity(Identity),
+}
+
+impl Reply {
+    pub fn to_bytes(&self) -> Vec<u8, U16> {
+        let mut bytes = Vec::new();
+        match self {
+            Reply::Identity(identity) => {
+                let _ = bytes.extend_from_slice(&identity.to_bytes());
+            }
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::command::{
+        is_for, Capabilities, Command, Identity, Reply, BROADCAST_ADDRESS, COMMAND_FRAME_ID,
+        IDENTITY_FRAME_ID,
+    };
+    use crate::Param;
+
+    #[test]
+    fn fire_round_trips() {
+        let fire = Command::Fire {
+            id: 3,
+            strength: 80,
+            millis: 300,
+        };
+        let bytes = fire.to_bytes();
+        assert_eq!(&bytes[..], &[COMMAND_FRAME_ID, 0x01, 3, 80, 0x2C, 0x01]);
+        assert_eq!(Command::from_bytes(&bytes), Some(fire));
+        assert_eq!(Command::from_bytes(&bytes[..5]), None);
+        assert_eq!(Command::from_bytes(&[0x5A, 0x01, 3, 80, 0x2C, 0x01]), None);
+    }
+
+    #[test]
+    fn identity_round_trips() {
+        let identify = Command::Identify.to_bytes();
+        assert_eq!(Command::from_bytes(&identify), Some(Command::Identify));
+
+        let identity = Identity {
+            address: 2,
+            version: Identity::firmware_version(),
+            actuators: 6,
+            inputs: 12,
+            input_width: 16,
+            capabilities: Capabilities::supported(),
+        };
+        assert_eq!(identity.version, [0, 1, 0]);
+        let bytes = Reply::Identity(identity).to_bytes();
+        assert_eq!(bytes[0], IDENTITY_FRAME_ID);
+        assert_eq!(Identity::from_bytes(&bytes), Some(identity));
+        assert_eq!(Identity::from_bytes(&bytes[..8]), None);
+    }
+
+    #[test]
+    fn broadcasts_reach_every_board() {
+        assert!(is_for(BROADCAST_ADDRESS, 2));
+        assert!(is_for(2, 2));
+        assert!(!is_for(3, 2));
+
+        let commands = [
+            Command::AllOff,
+            Command::Attract { enabled: true },
+            Command::EnterBootloader { key: 0xB007_10AD },
+            Command::TestMode {
+                timeout_ms: 5000,
+                max_strength: 20,
+            },
+            Command::SetInput {
+                bit: 3,
+                level: Some(true),
+            },
+            Command::SetInput {
+                bit: 3,
+                level: None,
+            },
+            Command::SaveConfig,
+        ];
+        for &command in commands.iter() {
+            assert_eq!(Command::from_bytes(&command.to_bytes()), Some(command));
+        }
+        assert_eq!(Command::from_bytes(&[COMMAND_FRAME_ID, 0x05, 2]), None);
+    }
+
+    #[test]
+    fn configure_round_trips() {
+        let configure = Command::Configure {
+            id: 1,
+            param: Param::HoldDuty,
+            value: 0x1234_5678,
+        };
+        let bytes = configure.to_bytes();
+        assert_eq!(
+            &bytes[..],
+            &[COMMAND_FRAME_ID, 0x02, 1, 1, 0x78, 0x56, 0x34, 0x12]
+        );
+        assert_eq!(Command::from_bytes(&bytes), Some(configure));
+        assert_eq!(
+            Command::from_bytes(&[COMMAND_FRAME_ID, 0x02, 1, 9, 0, 0, 0, 0]),
+            None
+        );
+    }
+}
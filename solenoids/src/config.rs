@@ -0,0 +1,298 @@
+use feather_m0 as hal;
+use hal::pac::NVMCTRL;
+use heapless::{consts::*, Vec};
+
+use crate::framing::crc16;
+use crate::{Error, InputConfig, InputType, Param};
+
+/// Most actuator settings a `Config` remembers.
+pub const MAX_SETTINGS: usize = 32;
+
+// Magic, format version, address and polarity, then a setting count and 6 bytes per setting,
+// then the CRC of all of it.
+const MAGIC: [u8; 2] = *b"SC";
+const VERSION: u8 = 1;
+const HEADER: usize = 13;
+const SETTING_SIZE: usize = 6;
+const CONFIG_SIZE: usize = HEADER + SETTING_SIZE * MAX_SETTINGS + 2;
+
+/// One actuator parameter, as set by `command::Command::Configure`.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Setting {
+    pub id: u8,
+    pub param: Param,
+    pub value: u32,
+}
+
+/// Config is the part of a board's setup that changes per machine rather than per
+/// firmware build: the bus address, which inputs are active-low, and the actuator
+/// parameters the master has tuned. It's kept in flash through a `Storage`, loaded at boot
+/// and saved again on `command::Command::SaveConfig`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    pub address: u8,
+    /// Active-low bits of the input word, see `InputConfig::with_inverted`.
+    pub inverted: u64,
+    settings: Vec<Setting, U32>,
+}
+
+impl Config {
+    pub fn settings(&self) -> &[Setting] {
+        &self.settings
+    }
+
+    /// Remembers `value` for the actuator's parameter, replacing any earlier value.
+    /// `Error::Storage` if `MAX_SETTINGS` are already kept.
+    pub fn set(&mut self, id: u8, param: Param, value: u32) -> Result<(), Error> {
+        let setting = Setting { id, param, value };
+        match self
+            .settings
+            .iter_mut()
+            .find(|s| s.id == id && s.param == param)
+        {
+            Some(existing) => *existing = setting,
+            None => self.settings.push(setting).map_err(|_| Error::Storage)?,
+        }
+        Ok(())
+    }
+
+    /// Whether `set` would take a value for the actuator's parameter: there's room, or it
+    /// already has one.
+    pub fn has_room_for(&self, id: u8, param: Param) -> bool {
+        self.settings.len() < MAX_SETTINGS
+            || self.settings.iter().any(|s| s.id == id && s.param == param)
+    }
+
+    /// Forgets every actuator setting.
+    pub fn clear_settings(&mut self) {
+        self.settings.clear();
+    }
+
+    /// Applies the stored polarity to an input as it's made, e.g.
+    /// `config.polarity(inputs.input::<DualInput>()?)`.
+    pub fn polarity<I: InputType>(&self, input: InputConfig<I>) -> InputConfig<I> {
+        let mask = (self.inverted >> input.start_offset()) as u16;
+        input.with_inverted(mask)
+    }
+
+    /// Little-endian record: magic, version, address, polarity, setting count, then id,
+    /// parameter and value for each setting, and the CRC-16 of everything before it.
+    pub fn to_bytes(&self) -> Vec<u8, U256> {
+        let mut bytes = Vec::new();
+        let _ = bytes.extend_from_slice(&MAGIC);
+        let _ = bytes.extend_from_slice(&[VERSION, self.address]);
+        let _ = bytes.extend_from_slice(&self.inverted.to_le_bytes());
+        let _ = bytes.push(self.settings.len() as u8);
+        for setting in self.settings.iter() {
+            let _ = bytes.extend_from_slice(&[setting.id, setting.param as u8]);
+            let _ = bytes.extend_from_slice(&setting.value.to_le_bytes());
+        }
+        let crc = crc16(&bytes);
+        let _ = bytes.extend_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    /// Parses a record, ignoring anything after it. `None` for blank or damaged flash and
+    /// for records from another format version.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < HEADER || bytes[..2] != MAGIC || bytes[2] != VERSION {
+            return None;
+        }
+        let count = bytes[12] as usize;
+        let end = HEADER + SETTING_SIZE * count;
+        if count > MAX_SETTINGS || bytes.len() < end + 2 {
+            return None;
+        }
+        if crc16(&bytes[..end]) != u16::from_le_bytes([bytes[end], bytes[end + 1]]) {
+            return None;
+        }
+
+        let mut inverted = [0; 8];
+        inverted.copy_from_slice(&bytes[4..12]);
+        let mut settings = Vec::new();
+        for entry in bytes[HEADER..end].chunks(SETTING_SIZE) {
+            let _ = settings.push(Setting {
+                id: entry[0],
+                param: Param::from_u8(entry[1])?,
+                value: u32::from_le_bytes([entry[2], entry[3], entry[4], entry[5]]),
+            });
+        }
+        Some(Self {
+            address: bytes[3],
+            inverted: u64::from_le_bytes(inverted),
+            settings,
+        })
+    }
+
+    /// Reads the stored configuration, or `None` if there isn't a valid one (a new board,
+    /// or a save cut short by a power loss).
+    pub fn load<S: Storage>(storage: &mut S) -> Option<Self> {
+        let mut bytes = [0; CONFIG_SIZE];
+        match storage.read(&mut bytes) {
+            Ok(()) => Self::from_bytes(&bytes),
+            Err(_e) => {
+                warn!("config read failed: {}", _e);
+                None
+            }
+        }
+    }
+
+    pub fn save<S: Storage>(&self, storage: &mut S) -> Result<(), Error> {
+        let result = storage.write(&self.to_bytes());
+        if let Err(_e) = result {
+            warn!("config write failed: {}", _e);
+        }
+        result
+    }
+}
+
+/// Non-volatile memory a `Config` is kept in.
+pub trait Storage {
+    /// Fills `buffer` from the start of the store.
+    fn read(&mut self, buffer: &mut [u8]) -> Result<(), Error>;
+    /// Replaces the store's contents with `data`.
+    fn write(&mut self, data: &[u8]) -> Result<(), Error>;
+}
+
+// The last 1K of the SAMD21G18's 256K of flash, the EEPROM emulation area when the EEPROM
+// fuses reserve one. Rows are erased as a whole and written a page at a time.
+const STORE_ADDRESS: u32 = 0x0003_FC00;
+/// Bytes a `Flash` can hold.
+pub const FLASH_STORE_SIZE: usize = 1024;
+const ROW_SIZE: usize = 256;
+const PAGE_SIZE: usize = 64;
+
+const CMDEX_KEY: u16 = 0xA5 << 8;
+const CMD_ERASE_ROW: u16 = 0x02;
+const CMD_WRITE_PAGE: u16 = 0x04;
+const CMD_PAGE_BUFFER_CLEAR: u16 = 0x44;
+const CTRLB_MANW: u32 = 1 << 7;
+const INTFLAG_READY: u8 = 1 << 0;
+// PROGE, LOCKE and NVME.
+const STATUS_ERRORS: u16 = 0b111 << 2;
+
+/// Flash is a `Storage` in the last four rows of the SAMD21's flash, written through
+/// NVMCTRL. The CPU stalls while rows are erased and written, several milliseconds a row, so
+/// only save with the outputs off; `controller::Controller` turns them off when it
+/// accepts the save command.
+pub struct Flash {
+    nvmctrl: NVMCTRL,
+}
+
+impl Flash {
+    pub fn new(nvmctrl: NVMCTRL) -> Self {
+        Self { nvmctrl }
+    }
+
+    pub fn free(self) -> NVMCTRL {
+        self.nvmctrl
+    }
+
+    // Runs an NVMCTRL command on the row or page at `address` and waits for it to finish.
+    fn command(&mut self, command: u16, address: u32) -> Result<(), Error> {
+        unsafe {
+            self.nvmctrl.status.write(|w| w.bits(STATUS_ERRORS));
+            // ADDR counts 16-bit words.
+            self.nvmctrl.addr.write(|w| w.bits(address / 2));
+            self.nvmctrl.ctrla.write(|w| w.bits(CMDEX_KEY | command));
+        }
+        while self.nvmctrl.intflag.read().bits() & INTFLAG_READY == 0 {}
+        if self.nvmctrl.status.read().bits() & STATUS_ERRORS != 0 {
+            return Err(Error::Storage);
+        }
+        Ok(())
+    }
+}
+
+impl Storage for Flash {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        if buffer.len() > FLASH_STORE_SIZE {
+            return Err(Error::Storage);
+        }
+        let store = STORE_ADDRESS as *const u8;
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = unsafe { core::ptr::read_volatile(store.add(i)) };
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        if data.len() > FLASH_STORE_SIZE {
+            return Err(Error::Storage);
+        }
+        // Manual writes, so a page is only written on CMD_WRITE_PAGE.
+        self.nvmctrl
+            .ctrlb
+            .modify(|r, w| unsafe { w.bits(r.bits() | CTRLB_MANW) });
+        // Every row is erased, so nothing of a longer earlier record is left behind.
+        for row in 0..FLASH_STORE_SIZE / ROW_SIZE {
+            self.command(CMD_ERASE_ROW, STORE_ADDRESS + (row * ROW_SIZE) as u32)?;
+        }
+        for (page, chunk) in data.chunks(PAGE_SIZE).enumerate() {
+            let address = STORE_ADDRESS + (page * PAGE_SIZE) as u32;
+            self.command(CMD_PAGE_BUFFER_CLEAR, address)?;
+            // The page buffer only takes 16 and 32-bit writes.
+            let words = address as *mut u32;
+            for (i, bytes) in chunk.chunks(4).enumerate() {
+                let mut word = [0xFF; 4];
+                word[..bytes.len()].copy_from_slice(bytes);
+                unsafe { core::ptr::write_volatile(words.add(i), u32::from_le_bytes(word)) };
+            }
+            self.command(CMD_WRITE_PAGE, address)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::{Config, Storage};
+    use crate::{DualInput, Error, InputArray, Param};
+
+    struct Ram([u8; 256]);
+
+    impl Storage for Ram {
+        fn read(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+            buffer.copy_from_slice(&self.0[..buffer.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+            self.0 = [0xFF; 256];
+            self.0[..data.len()].copy_from_slice(data);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn configuration_survives_a_power_cycle() {
+        let mut flash = Ram([0xFF; 256]);
+        assert_eq!(Config::load(&mut flash), None);
+
+        let mut config = Config {
+            address: 4,
+            inverted: 0b1100,
+            ..Config::default()
+        };
+        config.set(0, Param::KickDuty, 80).unwrap();
+        config.set(1, Param::Cooldown, 200).unwrap();
+        config.set(0, Param::KickDuty, 60).unwrap();
+        assert_eq!(config.settings().len(), 2);
+        config.save(&mut flash).unwrap();
+
+        let loaded = Config::load(&mut flash).unwrap();
+        assert_eq!(loaded, config);
+        assert_eq!(loaded.settings()[0].value, 60);
+
+        // Inputs pick up their share of the polarity mask.
+        let mut inputs = InputArray::<u16>::new();
+        let first = loaded.polarity(inputs.input::<DualInput>().unwrap());
+        let second = loaded.polarity(inputs.input::<DualInput>().unwrap());
+        assert_eq!((first.inverted(), second.inverted()), (0b00, 0b11));
+
+        // A damaged record is ignored rather than half applied.
+        flash.0[14] ^= 0x01;
+        assert_eq!(Config::load(&mut flash), None);
+    }
+}
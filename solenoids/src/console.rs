@@ -0,0 +1,341 @@
+use core::fmt::{self, Write};
+use embedded_hal::{serial, watchdog::Watchdog};
+use heapless::{consts::*, String, Vec};
+
+use crate::command::Command;
+use crate::controller::{Controllable, Controller, Evaluate};
+use crate::output::OutputDriver;
+use crate::Word;
+
+/// A byte stream the console can run over, so the same shell works over the debug UART
+/// during bring-up and over USB in the installed cabinet.
+pub trait Transport {
+    type Error;
+
+    /// Reads one byte if one is available.
+    fn read(&mut self) -> nb::Result<u8, Self::Error>;
+
+    /// Writes as much of `bytes` as the transport accepts and returns how many bytes were
+    /// written. Transports that can't make progress without being polled elsewhere (USB)
+    /// may write less than requested rather than block.
+    fn write(&mut self, bytes: &[u8]) -> Result<usize, Self::Error>;
+}
+
+/// Adapts any embedded-hal serial port, such as a SERCOM UART, into a `Transport`.
+pub struct UartTransport<S> {
+    serial: S,
+}
+
+impl<S> UartTransport<S> {
+    pub fn new(serial: S) -> Self {
+        Self { serial }
+    }
+
+    pub fn free(self) -> S {
+        self.serial
+    }
+}
+
+impl<S, E> Transport for UartTransport<S>
+where
+    S: serial::Read<u8, Error = E> + serial::Write<u8, Error = E>,
+{
+    type Error = E;
+
+    fn read(&mut self) -> nb::Result<u8, E> {
+        self.serial.read()
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<usize, E> {
+        for &b in bytes {
+            nb::block!(self.serial.write(b))?;
+        }
+        Ok(bytes.len())
+    }
+}
+
+#[cfg(feature = "usb")]
+impl<B: usb_device::bus::UsbBus> Transport for usbd_serial::SerialPort<'_, B> {
+    type Error = usb_device::UsbError;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        let mut buf = [0u8; 1];
+        match usbd_serial::SerialPort::read(self, &mut buf) {
+            Ok(0) | Err(usb_device::UsbError::WouldBlock) => Err(nb::Error::WouldBlock),
+            Ok(_) => Ok(buf[0]),
+            Err(e) => Err(nb::Error::Other(e)),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<usize, Self::Error> {
+        match usbd_serial::SerialPort::write(self, bytes) {
+            Err(usb_device::UsbError::WouldBlock) => Ok(0),
+            result => result,
+        }
+    }
+}
+
+pub type Line = String<U64>;
+
+/// Console is a line-oriented shell over a `Transport`. It echoes typed characters,
+/// handles backspace, and hands back complete lines; output goes through `fmt::Write`.
+pub struct Console<T: Transport> {
+    transport: T,
+    line: Vec<u8, U64>,
+    last_cr: bool,
+}
+
+impl<T: Transport> Console<T> {
+    pub fn new(transport: T) -> Self {
+        Self {
+            transport,
+            line: Vec::new(),
+            last_cr: false,
+        }
+    }
+
+    pub fn transport_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    pub fn free(self) -> T {
+        self.transport
+    }
+
+    /// Consumes every byte the transport has available and returns the first complete
+    /// line, without its terminator. Characters beyond the line capacity are dropped, as
+    /// are lines that aren't valid UTF-8.
+    pub fn read_line(&mut self) -> nb::Result<Line, T::Error> {
+        loop {
+            let byte = self.transport.read()?;
+            let after_cr = core::mem::replace(&mut self.last_cr, byte == b'\r');
+            match byte {
+                // Treat "\r\n" as one terminator rather than an extra empty line.
+                b'\n' if after_cr => {}
+                b'\r' | b'\n' => {
+                    self.transport.write(b"\r\n").map_err(nb::Error::Other)?;
+                    let line = core::str::from_utf8(&self.line).ok().map(String::from);
+                    self.line.clear();
+                    if let Some(line) = line {
+                        return Ok(line);
+                    }
+                }
+                0x08 | 0x7F => {
+                    if self.line.pop().is_some() {
+                        self.transport
+                            .write(b"\x08 \x08")
+                            .map_err(nb::Error::Other)?;
+                    }
+                }
+                _ => {
+                    if self.line.push(byte).is_ok() {
+                        self.transport.write(&[byte]).map_err(nb::Error::Other)?;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A bench command for `Console::serve`, so a board can be brought up over USB without the
+/// RS-485 master attached.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Request {
+    Help,
+    /// The raw input word and its timestamp.
+    Inputs,
+    /// Every actuator's output, in registration order.
+    States,
+    /// The current faults and the fault log.
+    Faults,
+    /// Fires an actuator as a `Command::Fire` from the master would.
+    Fire {
+        id: u8,
+        millis: u16,
+        strength: u8,
+    },
+}
+
+/// How long a console fire lasts when no time is given.
+pub const DEFAULT_FIRE_MILLIS: u16 = 30;
+
+const HELP: &str = "inputs | states | faults | fire <id> [millis] [strength]\r\n";
+
+impl Request {
+    /// Parses `help`, `inputs`, `states`, `faults` or `fire <id> [millis] [strength]`. A
+    /// fire without a time lasts `DEFAULT_FIRE_MILLIS` and without a strength runs at 100
+    /// percent.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace();
+        let request = match words.next()? {
+            "help" | "?" => Request::Help,
+            "inputs" => Request::Inputs,
+            "states" => Request::States,
+            "faults" => Request::Faults,
+            "fire" => Request::Fire {
+                id: words.next()?.parse().ok()?,
+                millis: match words.next() {
+                    Some(word) => word.parse().ok()?,
+                    None => DEFAULT_FIRE_MILLIS,
+                },
+                strength: match words.next() {
+                    Some(word) => word.parse().ok()?,
+                    None => 100,
+                },
+            },
+            _ => return None,
+        };
+        match words.next() {
+            Some(_) => None,
+            None => Some(request),
+        }
+    }
+}
+
+impl<T: Transport> Console<T> {
+    /// Reads a line and answers it from `controller`. Returns `WouldBlock` until a whole
+    /// line has arrived; a line that isn't a `Request` gets the help text. Output that the
+    /// transport doesn't take is dropped rather than waited for.
+    pub fn serve<S, A, D, W, WD>(
+        &mut self,
+        controller: &mut Controller<S, A, D, W, WD>,
+    ) -> nb::Result<(), T::Error>
+    where
+        S: Controllable<W>,
+        A: Evaluate<W>,
+        D: OutputDriver,
+        W: Word,
+        WD: Watchdog,
+    {
+        let line = self.read_line()?;
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+
+        let _ = match Request::parse(&line) {
+            None | Some(Request::Help) => self.write_str(HELP),
+            Some(Request::Inputs) => {
+                let inputs = controller.inputs();
+                write!(
+                    self,
+                    "inputs {:#x} at {}\r\n",
+                    inputs.raw().widen(),
+                    inputs.timestamp()
+                )
+            }
+            Some(Request::States) => {
+                let snapshot = controller.snapshot();
+                for (id, state) in snapshot.states.iter().enumerate() {
+                    let _ = match state.enabled {
+                        true => write!(self, "{}: on {:#x}\r\n", id, state.duty_cycle),
+                        false => write!(self, "{}: off\r\n", id),
+                    };
+                }
+                Ok(())
+            }
+            Some(Request::Faults) => {
+                let _ = write!(self, "faults {:?}\r\n", controller.faults());
+                for record in controller.fault_log().records() {
+                    let _ = write!(self, "  {} {:?}\r\n", record.timestamp, record.faults);
+                }
+                Ok(())
+            }
+            Some(Request::Fire {
+                id,
+                millis,
+                strength,
+            }) => match controller.dispatch(Command::Fire {
+                id,
+                strength,
+                millis,
+            }) {
+                Ok(_) => self.write_str("ok\r\n"),
+                Err(e) => write!(self, "error {:?}\r\n", e),
+            },
+        };
+        Ok(())
+    }
+}
+
+impl<T: Transport> fmt::Write for Console<T> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.transport
+            .write(s.as_bytes())
+            .map(|_| ())
+            .map_err(|_| fmt::Error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::console::{Console, Request, DEFAULT_FIRE_MILLIS};
+    use crate::testing::{basic_controller, Loopback};
+    use core::fmt::Write;
+    use heapless::{consts::*, Vec};
+
+    fn console(input: &[u8]) -> Console<Loopback> {
+        Console::new(Loopback::new(input))
+    }
+
+    #[test]
+    fn reads_lines_with_backspace() {
+        let mut console = console(b"fire 3x\x08\r\nst");
+        assert_eq!(console.read_line().unwrap().as_str(), "fire 3");
+        assert!(console.read_line().is_err());
+        assert_eq!(&console.transport_mut().tx[..], b"fire 3x\x08 \x08\r\nst");
+    }
+
+    #[test]
+    fn formats_output() {
+        let mut console = console(b"");
+        write!(console, "inputs {:04x}", 0x12).unwrap();
+        assert_eq!(&console.transport_mut().tx[..], b"inputs 0012");
+    }
+
+    #[test]
+    fn parses_requests() {
+        assert_eq!(Request::parse(" states "), Some(Request::States));
+        assert_eq!(
+            Request::parse("fire 2"),
+            Some(Request::Fire {
+                id: 2,
+                millis: DEFAULT_FIRE_MILLIS,
+                strength: 100,
+            })
+        );
+        assert_eq!(
+            Request::parse("fire 2 15 50"),
+            Some(Request::Fire {
+                id: 2,
+                millis: 15,
+                strength: 50,
+            })
+        );
+        assert_eq!(Request::parse("fire"), None);
+        assert_eq!(Request::parse("fire x"), None);
+        assert_eq!(Request::parse("states now"), None);
+    }
+
+    #[test]
+    fn serves_the_controller() {
+        let mut controller = basic_controller();
+        controller.tick().unwrap();
+
+        let mut console = console(b"fire 0\rfire 1\rstates\rinputs\rbogus\r");
+        for _ in 0..3 {
+            console.serve(&mut controller).unwrap();
+            controller.tick().unwrap();
+        }
+        console.serve(&mut controller).unwrap();
+        console.serve(&mut controller).unwrap();
+        assert!(console.serve(&mut controller).is_err());
+
+        let tx = core::str::from_utf8(&console.transport_mut().tx).unwrap();
+        let replies: Vec<&str, U16> = tx.split("\r\n").collect();
+        assert_eq!(replies[1], "ok");
+        assert_eq!(replies[3], "error InvalidMapping");
+        assert_eq!(replies[5], "0: on 0xffffffff");
+        assert_eq!(replies[7], "inputs 0x1 at 4");
+        assert!(replies[9].starts_with("inputs | states"));
+    }
+}
@@ -0,0 +1,3758 @@
+use bitflags::bitflags;
+use core::marker::PhantomData;
+use core::ptr::{addr_of, addr_of_mut};
+use core::sync::atomic::{AtomicBool, Ordering};
+use embedded_hal::{
+    adc::{Channel, OneShot},
+    blocking::{
+        delay::DelayUs,
+        i2c::{Read, Write, WriteRead},
+        spi::Transfer,
+    },
+    digital::v2::{InputPin, OutputPin},
+    spi::{FullDuplex, Mode, Phase, Polarity},
+    timer::{CountDown, Periodic},
+    watchdog::Watchdog,
+};
+use feather_m0 as hal;
+use hal::pac::{DMAC, SERCOM0, SERCOM1, SERCOM2, SERCOM3, SERCOM4, SERCOM5};
+use heapless::{consts::*, Vec};
+
+use crate::bootloader;
+use crate::command::{self, Capabilities, Command, Identity, Reply};
+use crate::config::Config;
+use crate::dma::{self, INPUT_RX_CHANNEL, INPUT_TX_CHANNEL};
+use crate::output::OutputDriver;
+use crate::power::{CoilLimit, Overflow, PowerBudget};
+use crate::pwm;
+use crate::rules::{Action, Rule};
+use crate::schedule::Scheduler;
+use crate::time::{Duration, Instant};
+use crate::watchdog::OnTimeLimits;
+use crate::{Actuator, Error, InputArray, InputType, Param, Word};
+
+/// A source of raw input words for an `InputArray`.
+pub trait Controllable<W: Word = u16> {
+    /// Reads the inputs. A failed read is an error rather than a word of open switches,
+    /// so the caller can hold the last good inputs or stop the machine.
+    fn load_data(&mut self) -> Result<W, Error>;
+
+    /// Makes progress on a read without waiting for the hardware, for superloops that
+    /// interleave the scan with other work; `WouldBlock` until the word is ready. Sources
+    /// that can't read without blocking just do a whole `load_data`.
+    fn poll_data(&mut self) -> nb::Result<W, Error> {
+        self.load_data().map_err(nb::Error::Other)
+    }
+}
+
+/// Evaluate is the dyn-compatible face of an `Actuator`: it reads its own inputs from the
+/// array, so actuators with different `InputType`s and concrete types can sit side by side
+/// in one `Controller`. Wrap an actuator with `Erased::new` (or `ActuatorExt::erased`) to
+/// get one; for a mixed machine register `&'static mut dyn Evaluate<W>`s, e.g. actuators
+/// moved into statics during init.
+pub trait Evaluate<W: Word = u16> {
+    fn pwm_config(&self) -> &pwm::Configuration;
+    /// Reads the actuator's inputs and returns its next state.
+    fn evaluate(&mut self, inputs: &InputArray<W>, curr_state: pwm::State) -> pwm::State;
+    fn hold_capable(&self) -> bool;
+    /// Bit of the input word the actuator's input 1 is read from.
+    fn input_offset(&self) -> u16;
+    /// Whether input 1 is active low, see `InputConfig::active_low`.
+    fn input_inverted(&self) -> bool;
+    /// Every bit of the input word the actuator reads, zero-extended.
+    fn input_mask(&self) -> u64;
+    /// See `Actuator::set_param`.
+    fn set_param(&mut self, param: Param, value: u32) -> Result<(), Error>;
+}
+
+/// Erased pins down the `InputType` of an actuator so it can be used as an `Evaluate`.
+pub struct Erased<I, A> {
+    actuator: A,
+    _type: PhantomData<I>,
+}
+
+impl<I, A> Erased<I, A>
+where
+    I: InputType,
+    A: Actuator<I>,
+{
+    pub fn new(actuator: A) -> Self {
+        Self {
+            actuator,
+            _type: PhantomData,
+        }
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.actuator
+    }
+
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.actuator
+    }
+
+    pub fn into_inner(self) -> A {
+        self.actuator
+    }
+}
+
+impl<I, A, W> Evaluate<W> for Erased<I, A>
+where
+    I: InputType,
+    A: Actuator<I>,
+    W: Word,
+{
+    fn pwm_config(&self) -> &pwm::Configuration {
+        self.actuator.pwm_config()
+    }
+
+    fn evaluate(&mut self, inputs: &InputArray<W>, curr_state: pwm::State) -> pwm::State {
+        let data = inputs.read(self.actuator.input_config());
+        self.actuator.update_state(&data, curr_state)
+    }
+
+    fn hold_capable(&self) -> bool {
+        self.actuator.hold_capable()
+    }
+
+    fn input_offset(&self) -> u16 {
+        self.actuator.input_config().start_offset()
+    }
+
+    fn input_inverted(&self) -> bool {
+        self.actuator.input_config().inverted() & 1 != 0
+    }
+
+    fn input_mask(&self) -> u64 {
+        InputArray::<W>::mask_of(self.actuator.input_config()).widen()
+    }
+
+    fn set_param(&mut self, param: Param, value: u32) -> Result<(), Error> {
+        self.actuator.set_param(param, value)
+    }
+}
+
+impl<W: Word, E: Evaluate<W> + ?Sized> Evaluate<W> for &mut E {
+    fn pwm_config(&self) -> &pwm::Configuration {
+        (**self).pwm_config()
+    }
+
+    fn evaluate(&mut self, inputs: &InputArray<W>, curr_state: pwm::State) -> pwm::State {
+        (**self).evaluate(inputs, curr_state)
+    }
+
+    fn hold_capable(&self) -> bool {
+        (**self).hold_capable()
+    }
+
+    fn input_offset(&self) -> u16 {
+        (**self).input_offset()
+    }
+
+    fn input_inverted(&self) -> bool {
+        (**self).input_inverted()
+    }
+
+    fn input_mask(&self) -> u64 {
+        (**self).input_mask()
+    }
+
+    fn set_param(&mut self, param: Param, value: u32) -> Result<(), Error> {
+        (**self).set_param(param, value)
+    }
+}
+
+/// Controller ties a machine together: it owns the input source, the `InputArray` and the
+/// registered actuators, each mapped to the `OutputDriver` it drives, and `tick` runs one
+/// whole cycle of read inputs, evaluate actuators, apply states.
+///
+/// Actuators are made from `inputs_mut()` (e.g. with `make_actuator`) and then registered
+/// as `Evaluate`s. Each one is handed the state its output is really in, read back from
+/// the driver, so actuator logic never depends on what kind of output it is mapped to.
+///
+/// A failed read keeps the last good inputs. After `failure_limit` failures in a row the
+/// controller turns every output off and stops evaluating actuators until a read succeeds.
+///
+/// Switch rules (`add_rule`) drive an actuator from another switch's edges within the tick
+/// that reads them, before the actuators are evaluated. They don't run while the outputs
+/// are held off.
+///
+/// With `with_watchdog`, the watchdog is fed only by cycles that read fresh inputs and
+/// evaluated every actuator, so a hung loop or a dead input bus ends in a reset, which
+/// leaves every output pin, and so every coil, off.
+pub struct Controller<S, A, D, W = u16, WD = Unwatched>
+where
+    A: Evaluate<W>,
+    D: OutputDriver,
+    W: Word,
+{
+    source: S,
+    inputs: InputArray<W>,
+    actuators: Vec<(A, D), U16>,
+    ticks: u32,
+    failures: u32,
+    failure_limit: u32,
+    scheduler: Scheduler<usize>,
+    fires: Vec<Fire, U16>,
+    rules: Vec<Rule, U32>,
+    config: Config,
+    save_requested: bool,
+    attract: bool,
+    inhibited: bool,
+    bootloader_requested: bool,
+    test: Option<TestMode>,
+    fault_log: FaultLog,
+    max_on: Option<OnTimeLimits>,
+    // When each output was last seen turning on, while it stays on.
+    on_since: [Option<u32>; 16],
+    // Outputs the max-on supervisor has latched off, a bit per actuator.
+    overtime: u16,
+    power_budget: Option<PowerBudget>,
+    // Actuators the power budget serves first, highest priority first.
+    priorities: Vec<u8, U16>,
+    coil_limit: Option<CoilLimit>,
+    // High-power outputs the coil limit let on, a bit per actuator.
+    powered: u16,
+    // Outputs waiting for the coil limit, oldest first.
+    queued: Vec<u8, U16>,
+    // Outputs whose activation the coil limit turned away.
+    rejected: u16,
+    watchdog: WD,
+}
+
+// A remote `Command::Fire`, or a rule's action, in progress.
+#[derive(Clone, Copy)]
+struct Fire {
+    index: usize,
+    bit: u8,
+    idle_level: bool,
+    // Held active for `length` from `start`, which moves up while the coil limit queues it.
+    start: u32,
+    length: u32,
+    strength: u8,
+    // How long an enabling rule kicks before it drops to its hold strength, and that
+    // strength.
+    hold: Option<(u32, u8)>,
+}
+
+impl Fire {
+    fn until(&self) -> u32 {
+        self.start.wrapping_add(self.length)
+    }
+
+    fn strength_at(&self, now: u32) -> u8 {
+        match self.hold {
+            Some((after, strength))
+                if Instant::from_millis(now)
+                    >= Instant::from_millis(self.start.wrapping_add(after)) =>
+            {
+                strength
+            }
+            _ => self.strength,
+        }
+    }
+}
+
+// How long `enable`, or an enabling rule, keeps its actuator on without a disable: as far
+// ahead as a wrap-safe comparison allows, about 24 days.
+const ENABLED_MS: u32 = i32::MAX as u32;
+
+// Remote test mode in progress, see `Command::TestMode`.
+#[derive(Clone, Copy)]
+struct TestMode {
+    timeout: u32,
+    until: u32,
+    max_strength: u8,
+    // Input bits forced by `Command::SetInput`.
+    overrides: u64,
+}
+
+/// The watchdog of a `Controller` without one.
+pub struct Unwatched;
+
+impl Watchdog for Unwatched {
+    fn feed(&mut self) {}
+}
+
+bitflags! {
+    /// Problems a `Controller` is riding out or has given in to.
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    pub struct Faults: u8 {
+        /// The last read failed; the previous inputs are still in use.
+        const READ_FAILED = 1 << 0;
+        /// Too many reads failed in a row and every output is held off.
+        const OUTPUTS_OFF = 1 << 1;
+        /// An output stayed on past the max-on time and is held off until
+        /// `Controller::clear_overtime`.
+        const OVERTIME = 1 << 2;
+    }
+}
+
+/// A change in a `Controller`'s faults.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FaultRecord {
+    /// Input timestamp when the faults changed. Failed reads don't advance it, so read
+    /// faults carry the time of the last good read.
+    pub timestamp: u32,
+    /// The faults from then on; empty when the controller recovered.
+    pub faults: Faults,
+}
+
+/// The last eight fault changes, oldest first, for diagnosing a board after the fact.
+#[derive(Clone, Debug)]
+pub struct FaultLog {
+    records: Vec<FaultRecord, U8>,
+    current: Faults,
+}
+
+impl FaultLog {
+    /// Records `faults` if they differ from the last ones, dropping the oldest record
+    /// when the log is full.
+    pub fn record(&mut self, timestamp: u32, faults: Faults) {
+        if faults == self.current {
+            return;
+        }
+        self.current = faults;
+        if faults.is_empty() {
+            info!("faults cleared at {}", timestamp);
+        } else {
+            warn!("faults {} at {}", faults, timestamp);
+        }
+        if self.records.len() == self.records.capacity() {
+            self.records.rotate_left(1);
+            self.records.pop();
+        }
+        let _ = self.records.push(FaultRecord { timestamp, faults });
+    }
+
+    pub fn records(&self) -> &[FaultRecord] {
+        &self.records
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+}
+
+impl Default for FaultLog {
+    fn default() -> Self {
+        Self {
+            records: Vec::new(),
+            current: Faults::empty(),
+        }
+    }
+}
+
+/// First byte of every snapshot frame.
+pub const SNAPSHOT_FRAME_ID: u8 = 0x53;
+
+/// Size of a snapshot frame with no actuators; each actuator adds 5 bytes.
+const SNAPSHOT_HEADER: usize = 15;
+
+/// A compact copy of a `Controller`'s state, see `Controller::snapshot`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub ticks: u32,
+    /// The raw input word, zero-extended.
+    pub raw: u64,
+    pub faults: Faults,
+    /// The state of each actuator's output, in registration order.
+    pub states: Vec<pwm::State, U16>,
+}
+
+impl Snapshot {
+    /// Little-endian frame: ID, ticks, raw inputs, faults, actuator count, then enabled
+    /// and duty for each actuator.
+    pub fn to_bytes(&self) -> Vec<u8, U96> {
+        let mut bytes = Vec::new();
+        let _ = bytes.push(SNAPSHOT_FRAME_ID);
+        let _ = bytes.extend_from_slice(&self.ticks.to_le_bytes());
+        let _ = bytes.extend_from_slice(&self.raw.to_le_bytes());
+        let _ = bytes.push(self.faults.bits());
+        let _ = bytes.push(self.states.len() as u8);
+        for state in self.states.iter() {
+            let _ = bytes.push(state.enabled as u8);
+            let _ = bytes.extend_from_slice(&state.duty_cycle.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < SNAPSHOT_HEADER || bytes[0] != SNAPSHOT_FRAME_ID {
+            return None;
+        }
+        let count = bytes[14] as usize;
+        if count > 16 || bytes.len() != SNAPSHOT_HEADER + 5 * count {
+            return None;
+        }
+
+        let mut ticks = [0; 4];
+        ticks.copy_from_slice(&bytes[1..5]);
+        let mut raw = [0; 8];
+        raw.copy_from_slice(&bytes[5..13]);
+        let mut states = Vec::new();
+        for entry in bytes[SNAPSHOT_HEADER..].chunks(5) {
+            let mut duty = [0; 4];
+            duty.copy_from_slice(&entry[1..]);
+            let _ = states.push(pwm::State {
+                enabled: entry[0] != 0,
+                duty_cycle: u32::from_le_bytes(duty),
+            });
+        }
+        Some(Self {
+            ticks: u32::from_le_bytes(ticks),
+            raw: u64::from_le_bytes(raw),
+            faults: Faults::from_bits(bytes[13])?,
+            states,
+        })
+    }
+}
+
+/// Consecutive failed reads a `Controller` rides out before it turns everything off.
+pub const DEFAULT_FAILURE_LIMIT: u32 = 3;
+
+const OFF: pwm::State = pwm::State {
+    enabled: false,
+    duty_cycle: 0,
+};
+
+// Whether an output in `state` draws current.
+fn is_on(state: &pwm::State) -> bool {
+    state.enabled && state.duty_cycle > 0
+}
+
+impl<S, A, D, W> Controller<S, A, D, W>
+where
+    S: Controllable<W>,
+    A: Evaluate<W>,
+    D: OutputDriver,
+    W: Word,
+{
+    pub fn new(source: S, inputs: InputArray<W>) -> Self {
+        Self {
+            source,
+            inputs,
+            actuators: Vec::new(),
+            ticks: 0,
+            failures: 0,
+            failure_limit: DEFAULT_FAILURE_LIMIT,
+            scheduler: Scheduler::new(),
+            fires: Vec::new(),
+            rules: Vec::new(),
+            config: Config::default(),
+            save_requested: false,
+            attract: false,
+            inhibited: false,
+            bootloader_requested: false,
+            test: None,
+            fault_log: FaultLog::default(),
+            max_on: None,
+            on_since: [None; 16],
+            overtime: 0,
+            power_budget: None,
+            priorities: Vec::new(),
+            coil_limit: None,
+            powered: 0,
+            queued: Vec::new(),
+            rejected: 0,
+            watchdog: Unwatched,
+        }
+    }
+
+    /// Feeds `watchdog` from good cycles. It should already be running, with a timeout
+    /// of a few tick periods.
+    pub fn with_watchdog<WD: Watchdog>(self, watchdog: WD) -> Controller<S, A, D, W, WD> {
+        Controller {
+            source: self.source,
+            inputs: self.inputs,
+            actuators: self.actuators,
+            ticks: self.ticks,
+            failures: self.failures,
+            failure_limit: self.failure_limit,
+            scheduler: self.scheduler,
+            fires: self.fires,
+            rules: self.rules,
+            config: self.config,
+            save_requested: self.save_requested,
+            attract: self.attract,
+            inhibited: self.inhibited,
+            bootloader_requested: self.bootloader_requested,
+            test: self.test,
+            fault_log: self.fault_log,
+            max_on: self.max_on,
+            on_since: self.on_since,
+            overtime: self.overtime,
+            power_budget: self.power_budget,
+            priorities: self.priorities,
+            coil_limit: self.coil_limit,
+            powered: self.powered,
+            queued: self.queued,
+            rejected: self.rejected,
+            watchdog,
+        }
+    }
+}
+
+impl<S, A, D, W, WD> Controller<S, A, D, W, WD>
+where
+    S: Controllable<W>,
+    A: Evaluate<W>,
+    D: OutputDriver,
+    W: Word,
+    WD: Watchdog,
+{
+    /// Sets how many reads in a row may fail before every output is turned off. Zero is
+    /// treated as one.
+    pub fn with_failure_limit(mut self, limit: u32) -> Self {
+        self.failure_limit = limit.max(1);
+        self
+    }
+
+    /// Sets the longest any output may stay on, in the units of the input timestamps,
+    /// whatever its actuator, a fire or a schedule asks for: `limits.hold` for hold-capable
+    /// actuators and `limits.coil` for the rest. An output on for longer is turned off and
+    /// held off, raising `Faults::OVERTIME`, until `clear_overtime`. It's the last line of
+    /// defence behind the actuators' own limits (`wrappers::MaxOnTime`).
+    pub fn with_max_on_time(mut self, limits: OnTimeLimits) -> Self {
+        self.max_on = Some(limits);
+        self
+    }
+
+    /// Caps the summed duty of the outputs, see `power::PowerBudget`. `priorities` lists
+    /// actuators by registration index, highest priority first; the rest follow in
+    /// registration order. Lower priority outputs are deferred or derated every tick
+    /// before anything is driven.
+    pub fn with_power_budget(mut self, budget: PowerBudget, priorities: &[u8]) -> Self {
+        self.set_power_budget(Some(budget), priorities);
+        self
+    }
+
+    /// Caps how many high-power outputs may be on at once, see `power::CoilLimit`.
+    pub fn with_coil_limit(mut self, limit: CoilLimit) -> Self {
+        self.set_coil_limit(Some(limit));
+        self
+    }
+
+    /// Sets the bus address the board reports in its `Identity`.
+    pub fn with_address(mut self, address: u8) -> Self {
+        self.config.address = address;
+        self
+    }
+
+    /// Takes on a stored configuration: its address, and its settings applied to the
+    /// registered actuators as if the master had sent them. Call it once every actuator is
+    /// registered. Stops at the first setting an actuator rejects.
+    pub fn apply_config(&mut self, config: &Config) -> Result<(), Error> {
+        self.config.address = config.address;
+        for setting in config.settings() {
+            self.dispatch(Command::Configure {
+                id: setting.id,
+                param: setting.param,
+                value: setting.value,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The configuration to save: the address and every parameter set since boot, by
+    /// `apply_config` or the master. Polarity isn't known to the controller; set
+    /// `Config::inverted` before saving if it should change.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Registers `actuator` to drive `output`.
+    pub fn register(&mut self, actuator: A, output: D) -> Result<(), Error> {
+        self.actuators
+            .push((actuator, output))
+            .map_err(|_| Error::TooManyActuators)
+    }
+
+    /// Sets the output of the actuator registered `index`th to `state` on the first cycle
+    /// at or after `at`, in the units of the input timestamps. It applies after the
+    /// actuators are evaluated, so for that cycle it overrides whatever the actuator chose.
+    pub fn schedule(&mut self, at: u32, index: usize, state: pwm::State) -> Result<(), Error> {
+        if index >= self.actuators.len() {
+            return Err(Error::InvalidMapping);
+        }
+        self.scheduler.schedule(at, index, state)
+    }
+
+    /// Pending scheduled actions, e.g. to cancel them.
+    pub fn scheduler_mut(&mut self) -> &mut Scheduler<usize> {
+        &mut self.scheduler
+    }
+
+    /// Carries out a command from the master. Fires hold the actuator's input active from
+    /// now until `millis` later, in the units of the input timestamps, and scale the duty
+    /// it asks for meanwhile. A new fire for the same actuator replaces the old one.
+    ///
+    /// Configure changes a setting of the actuator, or of one of its decorators, before it
+    /// next runs. Dispatch from the receive side holds the controller as `tick` does, so a
+    /// change always lands between two ticks and never mid-evaluation. Settings the
+    /// actuator doesn't have are `Error::Unsupported`.
+    ///
+    /// Identify is answered with the board's `identity`; it's the only command with a
+    /// reply for now. AllOff and Attract act through `all_off` and `set_attract`.
+    ///
+    /// TestMode and SetInput are for bench and service testing; any command keeps test
+    /// mode alive, and when none arrives for its timeout the controller leaves it.
+    ///
+    /// Configure changes are remembered in `config()`. SaveConfig turns every output off,
+    /// since writing flash stalls the CPU, and raises `take_save_request`.
+    ///
+    /// EnterBootloader with the right key puts the board in attract mode, so every output
+    /// is off and stays off, and raises `bootloader_requested`. The application resets once
+    /// it has finished with the bus, by calling `bootloader::enter`.
+    pub fn dispatch(&mut self, command: Command) -> Result<Option<Reply>, Error> {
+        debug!("command {}", command);
+        let now = self.inputs.timestamp();
+        if let Some(test) = self.test.as_mut() {
+            test.until = now.wrapping_add(test.timeout);
+        }
+        match command {
+            Command::Fire {
+                id,
+                strength,
+                millis,
+            } => {
+                self.fire(id as usize, millis as u32, strength, None)?;
+                Ok(None)
+            }
+            Command::Configure { id, param, value } => {
+                let (actuator, _) = self
+                    .actuators
+                    .get_mut(id as usize)
+                    .ok_or(Error::InvalidMapping)?;
+                // Refused before the actuator changes, so what runs is always what's saved.
+                if !self.config.has_room_for(id, param) {
+                    return Err(Error::Storage);
+                }
+                let result = actuator.set_param(param, value);
+                if let Err(_e) = result {
+                    warn!("actuator {} rejected {}: {}", id, param, _e);
+                }
+                result?;
+                self.config.set(id, param, value).map(|_| None)
+            }
+            Command::Identify => Ok(Some(Reply::Identity(self.identity()))),
+            Command::AllOff => {
+                self.all_off();
+                Ok(None)
+            }
+            Command::Attract { enabled } => {
+                self.set_attract(enabled);
+                Ok(None)
+            }
+            Command::EnterBootloader { key } => {
+                if key != bootloader::BOOTLOADER_KEY {
+                    return Err(Error::BadKey);
+                }
+                self.set_attract(true);
+                self.bootloader_requested = true;
+                Ok(None)
+            }
+            Command::TestMode {
+                timeout_ms: 0,
+                max_strength: _,
+            } => {
+                self.end_test();
+                Ok(None)
+            }
+            Command::TestMode {
+                timeout_ms,
+                max_strength,
+            } => {
+                let overrides = self.test.map_or(0, |test| test.overrides);
+                self.test = Some(TestMode {
+                    timeout: timeout_ms as u32,
+                    until: now.wrapping_add(timeout_ms as u32),
+                    max_strength: max_strength.min(100),
+                    overrides,
+                });
+                Ok(None)
+            }
+            Command::SetInput { bit, level } => {
+                let test = self.test.as_mut().ok_or(Error::WrongMode)?;
+                if bit >= W::BITS {
+                    return Err(Error::InvalidMapping);
+                }
+                match level {
+                    Some(level) => {
+                        test.overrides |= 1 << bit;
+                        self.inputs.override_bit(bit, level);
+                    }
+                    None => {
+                        test.overrides &= !(1 << bit);
+                        self.inputs.release_override(bit);
+                    }
+                }
+                Ok(None)
+            }
+            Command::SaveConfig => {
+                self.all_off();
+                self.save_requested = true;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Dispatches a command sent to `destination` if it's for this board, see
+    /// `command::is_for`. Replies to broadcasts are dropped.
+    pub fn receive(&mut self, destination: u8, command: Command) -> Result<Option<Reply>, Error> {
+        if !command::is_for(destination, self.config.address) {
+            return Ok(None);
+        }
+        let reply = self.dispatch(command)?;
+        match destination {
+            command::BROADCAST_ADDRESS => Ok(None),
+            _ => Ok(reply),
+        }
+    }
+
+    /// Turns every output off and drops remote fires and scheduled actions. Actuators are
+    /// evaluated again from the next tick, so a held switch turns its coil back on.
+    pub fn all_off(&mut self) {
+        for fire in self.fires.iter() {
+            self.inputs.override_bit(fire.bit, fire.idle_level);
+            self.inputs.release_override(fire.bit);
+        }
+        self.fires.clear();
+        self.scheduler.clear();
+        for (_, output) in self.actuators.iter_mut() {
+            output.apply(&OFF);
+        }
+    }
+
+    /// In attract mode every output is held off and the actuators aren't evaluated. The
+    /// watchdog is still fed by good reads.
+    pub fn set_attract(&mut self, attract: bool) {
+        if attract && !self.attract {
+            self.all_off();
+        }
+        self.attract = attract;
+    }
+
+    pub fn is_attract(&self) -> bool {
+        self.attract
+    }
+
+    /// Holds every output off like attract mode, but for the application's own lockouts,
+    /// e.g. `mpf::Platform`'s host watchdog, so neither the master's `Command::Attract` nor
+    /// the lockout can lift the other.
+    pub fn set_inhibit(&mut self, inhibited: bool) {
+        if inhibited && !self.inhibited {
+            self.all_off();
+        }
+        self.inhibited = inhibited;
+    }
+
+    pub fn is_inhibited(&self) -> bool {
+        self.inhibited
+    }
+
+    /// Whether the master has the board in test mode, see `Command::TestMode`.
+    pub fn is_testing(&self) -> bool {
+        self.test.is_some()
+    }
+
+    // Fires are held to the test mode's cap while testing.
+    fn max_strength(&self) -> u8 {
+        self.test.map_or(100, |test| test.max_strength)
+    }
+
+    // Back to normal operation: the switches take over again and nothing keeps firing.
+    fn end_test(&mut self) {
+        if let Some(test) = self.test.take() {
+            for bit in (0..64).filter(|bit| test.overrides & (1 << bit) != 0) {
+                self.inputs.release_override(bit);
+            }
+            self.all_off();
+        }
+    }
+
+    /// Whether the master has asked for the configuration to be saved since the last
+    /// call. The application then writes `config()` out, e.g. with `Config::save`.
+    pub fn take_save_request(&mut self) -> bool {
+        core::mem::replace(&mut self.save_requested, false)
+    }
+
+    /// Whether a keyed `Command::EnterBootloader` has been accepted.
+    pub fn bootloader_requested(&self) -> bool {
+        self.bootloader_requested
+    }
+
+    /// What the board offers, for the master to discover it by.
+    pub fn identity(&self) -> Identity {
+        Identity {
+            address: self.config.address,
+            version: Identity::firmware_version(),
+            actuators: self.actuators.len() as u8,
+            inputs: self.inputs.bits_used(),
+            input_width: W::BITS,
+            capabilities: Capabilities::supported(),
+        }
+    }
+
+    /// Whether a remote fire is holding the `index`th actuator on.
+    pub fn is_firing(&self, index: usize) -> bool {
+        self.fires.iter().any(|f| f.index == index)
+    }
+
+    /// Holds the `index`th actuator's input active at `strength` until `disable`, as a
+    /// `Command::Fire` does for its `millis`; for host adapters whose coils stay on until the
+    /// host says otherwise. On-time limits, faults and attract mode still apply.
+    pub fn enable(&mut self, index: usize, strength: u8) -> Result<(), Error> {
+        self.fire(index, ENABLED_MS, strength, None)
+    }
+
+    /// Like `enable`, at `strength` for `pulse_millis` and then at `hold_strength`, as a
+    /// `rules::Action::Enable` does.
+    pub fn enable_with_hold(
+        &mut self,
+        index: usize,
+        pulse_millis: u32,
+        strength: u8,
+        hold_strength: u8,
+    ) -> Result<(), Error> {
+        self.fire(
+            index,
+            ENABLED_MS,
+            strength,
+            Some((pulse_millis, hold_strength)),
+        )
+    }
+
+    /// Ends whatever fire, enable or rule action has the `index`th actuator on; its switch
+    /// takes over again from the next update.
+    pub fn disable(&mut self, index: usize) {
+        if let Some(i) = self.fires.iter().position(|f| f.index == index) {
+            self.end_fire(i);
+        }
+    }
+
+    // Holds the actuator's input active from now for `length`, replacing any fire it already
+    // has.
+    fn fire(
+        &mut self,
+        index: usize,
+        length: u32,
+        strength: u8,
+        hold: Option<(u32, u8)>,
+    ) -> Result<(), Error> {
+        let (actuator, _) = self.actuators.get(index).ok_or(Error::InvalidMapping)?;
+        let max_strength = self.max_strength();
+        let fire = Fire {
+            index,
+            bit: actuator.input_offset() as u8,
+            idle_level: actuator.input_inverted(),
+            start: self.inputs.timestamp(),
+            length,
+            strength: strength.min(max_strength),
+            hold: hold.map(|(after, strength)| (after, strength.min(max_strength))),
+        };
+        if let Some(i) = self.fires.iter().position(|f| f.index == index) {
+            self.fires[i] = fire;
+        } else {
+            self.fires.push(fire).map_err(|_| Error::ScheduleFull)?;
+        }
+        self.inputs.override_bit(fire.bit, !fire.idle_level);
+        Ok(())
+    }
+
+    // Ends the `i`th fire by driving its input idle; the switch takes over again from the
+    // next update.
+    fn end_fire(&mut self, i: usize) {
+        let fire = self.fires.swap_remove(i);
+        // A bit the test master has forced stays forced.
+        let forced = self.test.map_or(0, |test| test.overrides);
+        if forced & (1 << fire.bit) == 0 {
+            self.inputs.override_bit(fire.bit, fire.idle_level);
+            self.inputs.release_override(fire.bit);
+        }
+    }
+
+    fn expire_fires(&mut self) {
+        let now = Instant::from_millis(self.inputs.timestamp());
+        let mut i = 0;
+        while i < self.fires.len() {
+            if now < Instant::from_millis(self.fires[i].until()) {
+                i += 1;
+            } else {
+                self.end_fire(i);
+            }
+        }
+    }
+
+    /// Adds a switch rule, replacing any with the same switch, edge and actuator. The
+    /// switch must be a bit of the input word outside the actuator's own inputs, or it's
+    /// `Error::InvalidMapping`; `Error::TooManyRules` once `rules::MAX_RULES` are held.
+    pub fn add_rule(&mut self, rule: Rule) -> Result<(), Error> {
+        let (actuator, _) = self
+            .actuators
+            .get(rule.actuator as usize)
+            .ok_or(Error::InvalidMapping)?;
+        if rule.switch >= W::BITS || actuator.input_mask() & (1 << rule.switch) != 0 {
+            return Err(Error::InvalidMapping);
+        }
+        let same = |r: &Rule| {
+            r.switch == rule.switch && r.edge == rule.edge && r.actuator == rule.actuator
+        };
+        match self.rules.iter_mut().find(|r| same(r)) {
+            Some(existing) => *existing = rule,
+            None => self.rules.push(rule).map_err(|_| Error::TooManyRules)?,
+        }
+        Ok(())
+    }
+
+    /// Removes every rule binding `switch` to `actuator`, returning how many there were.
+    /// Whatever they started carries on; a held actuator needs its own `Command::Fire`
+    /// with zero `millis` to end it.
+    pub fn remove_rules(&mut self, switch: u8, actuator: u8) -> usize {
+        let mut removed = 0;
+        let mut i = 0;
+        while i < self.rules.len() {
+            if self.rules[i].switch == switch && self.rules[i].actuator == actuator {
+                self.rules.swap_remove(i);
+                removed += 1;
+            } else {
+                i += 1;
+            }
+        }
+        removed
+    }
+
+    pub fn clear_rules(&mut self) {
+        self.rules.clear();
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    // Carries out the rules triggered by the bits that just `changed`.
+    fn run_rules(&mut self, changed: u64) {
+        if changed == 0 {
+            return;
+        }
+        let raw = self.inputs.raw().widen();
+        for i in 0..self.rules.len() {
+            let rule = self.rules[i];
+            if !rule.is_triggered(changed, raw) {
+                continue;
+            }
+            let index = rule.actuator as usize;
+            let result = match rule.action {
+                Action::Pulse { millis, strength } => {
+                    self.fire(index, millis as u32, strength, None)
+                }
+                Action::Enable {
+                    pulse_millis,
+                    strength,
+                    hold_strength,
+                } => self.enable_with_hold(index, pulse_millis as u32, strength, hold_strength),
+                Action::Disable => {
+                    self.disable(index);
+                    Ok(())
+                }
+            };
+            if let Err(_e) = result {
+                warn!("rule on switch {} failed: {}", rule.switch, _e);
+            }
+        }
+    }
+
+    /// Runs one cycle, timestamped by update count. Returns the input bits that changed,
+    /// or the read error.
+    pub fn tick(&mut self) -> Result<W, Error> {
+        let result = self.source.load_data().map(|data| self.inputs.update(data));
+        self.finish(result)
+    }
+
+    /// Like `tick`, but built on `poll_data`: until the source has a word ready this
+    /// returns `WouldBlock` and nothing is evaluated.
+    pub fn poll(&mut self) -> nb::Result<W, Error> {
+        let result = match self.source.poll_data() {
+            Ok(data) => Ok(self.inputs.update(data)),
+            Err(nb::Error::WouldBlock) => return Err(nb::Error::WouldBlock),
+            Err(nb::Error::Other(e)) => Err(e),
+        };
+        self.finish(result).map_err(nb::Error::Other)
+    }
+
+    /// Runs one cycle with inputs timestamped `timestamp`, see `InputArray::update_at`.
+    pub fn tick_at(&mut self, timestamp: u32) -> Result<W, Error> {
+        let result = self
+            .source
+            .load_data()
+            .map(|data| self.inputs.update_at(data, timestamp));
+        self.finish(result)
+    }
+
+    /// Evaluates and applies just the actuators in `mask`, a bit per actuator, against the
+    /// inputs as they stand, without reading the source or feeding the watchdog. For inputs
+    /// that change between ticks, e.g. `direct::DirectInputs` from its interrupt, so their
+    /// actuators don't wait for the next tick.
+    ///
+    /// Nothing is driven while the outputs are held off, latched outputs stay off, and the
+    /// power budget counts every other output as it is. A high-power output the coil limit
+    /// hasn't let on yet waits for the next tick to take its turn.
+    pub fn refresh(&mut self, mask: u16) {
+        if self.is_failed() || self.attract || self.inhibited {
+            return;
+        }
+        let now = self.inputs.timestamp();
+        let mut states = Vec::<pwm::State, U16>::new();
+        for (index, (actuator, output)) in self.actuators.iter_mut().enumerate() {
+            let mut next = output.state();
+            if mask & (1 << index) != 0 {
+                next = actuator.evaluate(&self.inputs, next);
+                if let Some(fire) = self.fires.iter().find(|f| f.index == index) {
+                    let strength = fire.strength_at(now) as u64;
+                    next.duty_cycle = (next.duty_cycle as u64 * strength / 100) as u32;
+                }
+            }
+            let _ = states.push(next);
+        }
+        for (index, next) in states.iter_mut().enumerate() {
+            if self.overtime & (1 << index) != 0 {
+                *next = OFF;
+            }
+        }
+        self.budget_power(&mut states);
+        if let Some(limit) = self.coil_limit {
+            for (index, next) in states.iter_mut().enumerate() {
+                if limit.is_high_power(index) && self.powered & (1 << index) == 0 {
+                    *next = OFF;
+                }
+            }
+        }
+        for (index, ((_, output), next)) in self.actuators.iter_mut().zip(states.iter()).enumerate()
+        {
+            if mask & (1 << index) != 0 {
+                output.apply(next);
+            }
+        }
+        self.track_on_time();
+    }
+
+    fn finish(&mut self, result: Result<W, Error>) -> Result<W, Error> {
+        match result {
+            Ok(_) => self.failures = 0,
+            Err(_e) => {
+                debug!("input read failed: {}", _e);
+                self.failures = self.failures.saturating_add(1);
+            }
+        }
+
+        self.expire_fires();
+        if let Some(test) = self.test {
+            let now = Instant::from_millis(self.inputs.timestamp());
+            if now >= Instant::from_millis(test.until) {
+                info!("test mode timed out");
+                self.end_test();
+            }
+        }
+
+        // Actions that come due while the outputs are forced off are dropped, not
+        // replayed on recovery.
+        let failed = self.is_failed();
+        let held_off = self.attract || self.inhibited;
+        if let Ok(changed) = result {
+            if !failed && !held_off {
+                self.run_rules(changed.widen());
+            }
+        }
+        self.check_on_time();
+        let now = self.inputs.timestamp();
+        if failed || held_off {
+            for (_, output) in self.actuators.iter_mut() {
+                output.apply(&OFF);
+            }
+            self.scheduler.run(now, |_, _| ());
+            // Nothing is on, so nothing keeps a place under the coil limit or waits for one.
+            self.powered = 0;
+            self.queued.clear();
+            self.rejected = 0;
+            if !failed && result.is_ok() {
+                self.watchdog.feed();
+            }
+        } else {
+            // Every state is settled before any is applied, so an output the supervisor or
+            // the coil limit holds off is never driven on, even for part of a tick.
+            let mut states = Vec::<pwm::State, U16>::new();
+            for (index, (actuator, output)) in self.actuators.iter_mut().enumerate() {
+                let mut next = actuator.evaluate(&self.inputs, output.state());
+                if let Some(fire) = self.fires.iter().find(|f| f.index == index) {
+                    let strength = fire.strength_at(now) as u64;
+                    next.duty_cycle = (next.duty_cycle as u64 * strength / 100) as u32;
+                }
+                let _ = states.push(next);
+            }
+            self.scheduler.run(now, |&index, state| {
+                if let Some(next) = states.get_mut(index) {
+                    *next = *state;
+                }
+            });
+            for (index, next) in states.iter_mut().enumerate() {
+                if self.overtime & (1 << index) != 0 {
+                    *next = OFF;
+                }
+            }
+            self.budget_power(&mut states);
+            self.limit_coils(&mut states);
+            for ((_, output), next) in self.actuators.iter_mut().zip(states.iter()) {
+                output.apply(next);
+            }
+            if result.is_ok() {
+                self.watchdog.feed();
+            }
+        }
+        self.track_on_time();
+        self.fault_log
+            .record(self.inputs.timestamp(), self.faults());
+        self.ticks = self.ticks.wrapping_add(1);
+        result
+    }
+
+    // Holds `states` to the power budget, taking the prioritised actuators first and the
+    // rest in registration order.
+    fn budget_power(&self, states: &mut [pwm::State]) {
+        let budget = match self.power_budget {
+            Some(budget) => budget,
+            None => return,
+        };
+        let mut order = Vec::<usize, U16>::new();
+        let mut seen = 0u16;
+        let listed = self.priorities.iter().map(|&index| index as usize);
+        for index in listed.chain(0..states.len()) {
+            if index < states.len() && seen & (1 << index) == 0 {
+                seen |= 1 << index;
+                let _ = order.push(index);
+            }
+        }
+        let mut ordered: Vec<pwm::State, U16> = order.iter().map(|&index| states[index]).collect();
+        budget.arbitrate(&mut ordered);
+        for (&index, state) in order.iter().zip(ordered.iter()) {
+            states[index] = *state;
+        }
+    }
+
+    // Turns off the high-power `states` past the coil limit. Outputs the limit let on before
+    // keep their place, then queued ones go in order, then new ones in registration order.
+    // A queued output's fire starts over each tick it waits, so its whole pulse comes out
+    // once it's let on.
+    fn limit_coils(&mut self, states: &mut [pwm::State]) {
+        let limit = match self.coil_limit {
+            Some(limit) => limit,
+            None => return,
+        };
+        let mut wanted = 0u16;
+        for (index, state) in states.iter().enumerate() {
+            if limit.is_high_power(index) && is_on(state) {
+                wanted |= 1 << index;
+            }
+        }
+        // A turned away activation stays off until its actuator lets go.
+        self.rejected &= wanted;
+
+        let mut granted = self.powered & wanted;
+        let mut count = granted.count_ones();
+        let mut queued = Vec::<u8, U16>::new();
+        let mut waiting = 0u16;
+        for &index in self.queued.iter().filter(|&&i| wanted & (1 << i) != 0) {
+            if count < limit.max() as u32 {
+                granted |= 1 << index;
+                count += 1;
+            } else {
+                let _ = queued.push(index);
+                waiting |= 1 << index;
+            }
+        }
+        let new = wanted & !granted & !waiting & !self.rejected;
+        for index in (0..16u8).filter(|&i| new & (1 << i) != 0) {
+            if count < limit.max() as u32 {
+                granted |= 1 << index;
+                count += 1;
+                continue;
+            }
+            debug!("output {} held back by the coil limit", index);
+            match limit.overflow() {
+                Overflow::Queue => {
+                    let _ = queued.push(index);
+                }
+                Overflow::Reject => self.rejected |= 1 << index,
+            }
+        }
+
+        for (index, state) in states.iter_mut().enumerate() {
+            if wanted & !granted & (1 << index) != 0 {
+                *state = OFF;
+            }
+        }
+        let now = self.inputs.timestamp();
+        for fire in self.fires.iter_mut() {
+            if queued.iter().any(|&index| index as usize == fire.index) {
+                fire.start = now;
+            }
+        }
+        self.powered = granted;
+        self.queued = queued;
+    }
+
+    // Latches off every output that has been on for its max-on time. It runs before
+    // anything is applied, so a latched output is written off instead of on and never
+    // pulses.
+    fn check_on_time(&mut self) {
+        let limits = match self.max_on {
+            Some(limits) => limits,
+            None => return,
+        };
+        let now = Instant::from_millis(self.inputs.timestamp());
+        for (index, (actuator, _)) in self.actuators.iter().enumerate() {
+            let since = match self.on_since[index] {
+                Some(since) if self.overtime & (1 << index) == 0 => since,
+                _ => continue,
+            };
+            let limit = Duration::from_millis(limits.limit(actuator.hold_capable()));
+            if now.duration_since(Instant::from_millis(since)) >= limit {
+                warn!("output {} on too long, latched off", index);
+                self.overtime |= 1 << index;
+            }
+        }
+    }
+
+    // Notes when each output turned on, once this tick's states are applied.
+    fn track_on_time(&mut self) {
+        if self.max_on.is_none() {
+            return;
+        }
+        let now = self.inputs.timestamp();
+        for (index, (_, output)) in self.actuators.iter().enumerate() {
+            if is_on(&output.state()) {
+                self.on_since[index].get_or_insert(now);
+            } else {
+                self.on_since[index] = None;
+            }
+        }
+    }
+
+    /// Failed reads since the last good one.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Whether enough reads have failed in a row that every output is held off.
+    pub fn is_failed(&self) -> bool {
+        self.failures >= self.failure_limit
+    }
+
+    /// What the controller is currently riding out or has given in to.
+    pub fn faults(&self) -> Faults {
+        let mut faults = Faults::empty();
+        faults.set(Faults::READ_FAILED, self.failures > 0);
+        faults.set(Faults::OUTPUTS_OFF, self.is_failed());
+        faults.set(Faults::OVERTIME, self.overtime != 0);
+        faults
+    }
+
+    /// Changes or, with `None`, lifts the power budget, see `with_power_budget`.
+    pub fn set_power_budget(&mut self, budget: Option<PowerBudget>, priorities: &[u8]) {
+        self.power_budget = budget;
+        self.priorities = priorities.iter().copied().take(16).collect();
+    }
+
+    /// Changes or, with `None`, lifts the coil limit. Outputs already on keep their place
+    /// under a lower limit; the limit only holds back activations.
+    pub fn set_coil_limit(&mut self, limit: Option<CoilLimit>) {
+        self.coil_limit = limit;
+        self.queued.clear();
+        self.rejected = 0;
+    }
+
+    /// Outputs the coil limit is holding back for a free place, oldest first.
+    pub fn queued(&self) -> &[u8] {
+        &self.queued
+    }
+
+    /// Outputs latched off by the max-on time, a bit per actuator in registration order.
+    pub fn overtime(&self) -> u16 {
+        self.overtime
+    }
+
+    /// Lets outputs latched off by the max-on time turn on again, once whatever held them
+    /// on has been dealt with.
+    pub fn clear_overtime(&mut self) {
+        self.overtime = 0;
+    }
+
+    /// Recent changes in `faults`.
+    pub fn fault_log(&self) -> &FaultLog {
+        &self.fault_log
+    }
+
+    pub fn fault_log_mut(&mut self) -> &mut FaultLog {
+        &mut self.fault_log
+    }
+
+    /// Completed cycles.
+    pub fn ticks(&self) -> u32 {
+        self.ticks
+    }
+
+    pub fn inputs(&self) -> &InputArray<W> {
+        &self.inputs
+    }
+
+    pub fn inputs_mut(&mut self) -> &mut InputArray<W> {
+        &mut self.inputs
+    }
+
+    /// Registered actuators with their outputs, in registration order.
+    pub fn actuators(&self) -> &[(A, D)] {
+        &self.actuators
+    }
+
+    pub fn actuators_mut(&mut self) -> &mut [(A, D)] {
+        &mut self.actuators
+    }
+
+    pub fn source_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    /// The controller's state at a glance, for diagnostics.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut states = Vec::new();
+        for (_, output) in self.actuators.iter() {
+            let _ = states.push(output.state());
+        }
+        Snapshot {
+            ticks: self.ticks,
+            raw: self.inputs.raw().widen(),
+            faults: self.faults(),
+            states,
+        }
+    }
+
+    pub fn watchdog_mut(&mut self) -> &mut WD {
+        &mut self.watchdog
+    }
+
+    pub fn free(self) -> (S, InputArray<W>, WD) {
+        (self.source, self.inputs, self.watchdog)
+    }
+
+    /// Starts `timer` at `rate` and hands back a controller that ticks once per period,
+    /// from `on_tick` in the timer's interrupt. Enabling that interrupt is left to the
+    /// HAL, e.g. `TimerCounter::enable_interrupt`.
+    pub fn run_at<T, R>(self, mut timer: T, rate: R) -> ScheduledController<S, A, D, T, W, WD>
+    where
+        T: CountDown + Periodic,
+        R: Into<T::Time>,
+    {
+        timer.start(rate);
+        ScheduledController {
+            controller: self,
+            timer,
+        }
+    }
+}
+
+/// A `Controller` paced by a periodic timer, see `Controller::run_at`.
+pub struct ScheduledController<S, A, D, T, W = u16, WD = Unwatched>
+where
+    A: Evaluate<W>,
+    D: OutputDriver,
+    W: Word,
+{
+    controller: Controller<S, A, D, W, WD>,
+    timer: T,
+}
+
+impl<S, A, D, T, W, WD> ScheduledController<S, A, D, T, W, WD>
+where
+    S: Controllable<W>,
+    A: Evaluate<W>,
+    D: OutputDriver,
+    T: CountDown + Periodic,
+    W: Word,
+    WD: Watchdog,
+{
+    /// Call from the timer's interrupt. Runs one cycle if a period has elapsed, which also
+    /// clears the timer's flag, and returns its result; None for a spurious call.
+    pub fn on_tick(&mut self) -> Option<Result<W, Error>> {
+        match self.timer.wait() {
+            Ok(()) => Some(self.controller.tick()),
+            Err(_) => None,
+        }
+    }
+
+    pub fn controller(&self) -> &Controller<S, A, D, W, WD> {
+        &self.controller
+    }
+
+    pub fn controller_mut(&mut self) -> &mut Controller<S, A, D, W, WD> {
+        &mut self.controller
+    }
+
+    /// Hands back the controller and the timer, which is left running.
+    pub fn free(self) -> (Controller<S, A, D, W, WD>, T) {
+        (self.controller, self.timer)
+    }
+}
+
+/// Most 74HC165s that can be chained and read into one `InputArray` word.
+pub const MAX_REGISTERS: u8 = 8;
+
+/// How the bytes read from a register chain are assembled into an input word.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ByteOrder {
+    /// The first byte clocked in, from the register nearest the MCU, is bits 0-7.
+    LittleEndian,
+    /// The first byte clocked in is the most significant byte.
+    BigEndian,
+}
+
+/// Which register input each bit of a byte came from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BitOrder {
+    /// Bit 7 is input H, which a 74HC165 shifts out first. This matches an MSB-first bus.
+    MsbFirst,
+    /// Bit 0 is input H, for registers wired in reverse or an LSB-first bus.
+    LsbFirst,
+}
+
+/// Timing for reading 74HC165-style shift registers. The defaults suit short runs on the
+/// board itself; long unshielded cable runs to the backbox want `ShiftTiming::conservative`
+/// or slower.
+#[derive(Clone, Copy)]
+pub struct ShiftTiming {
+    /// SPI clock frequency in Hz. This has to be applied when the bus is created, along with
+    /// `mode()`.
+    pub frequency: u32,
+    /// Clock idle level. Idling high samples on the falling edge, half a clock away from
+    /// the register shifting, which leaves the most margin on long runs.
+    pub clock_idle: Polarity,
+    /// How long the load pin is held low to latch the parallel inputs.
+    pub latch_setup_us: u16,
+    /// How long to wait after releasing the load pin before clocking data out.
+    pub latch_hold_us: u16,
+    /// Gap between each byte transferred.
+    pub byte_gap_us: u16,
+}
+
+impl ShiftTiming {
+    pub fn conservative() -> Self {
+        Self {
+            frequency: 100_000,
+            clock_idle: Polarity::IdleHigh,
+            latch_setup_us: 5,
+            latch_hold_us: 5,
+            byte_gap_us: 10,
+        }
+    }
+
+    /// SPI mode to create the bus with.
+    pub fn mode(&self) -> Mode {
+        Mode {
+            polarity: self.clock_idle,
+            phase: Phase::CaptureOnFirstTransition,
+        }
+    }
+}
+
+impl Default for ShiftTiming {
+    fn default() -> Self {
+        Self {
+            frequency: 1_000_000,
+            clock_idle: Polarity::IdleLow,
+            latch_setup_us: 1,
+            latch_hold_us: 1,
+            byte_gap_us: 0,
+        }
+    }
+}
+
+pub struct ControllerBuilder;
+
+impl ControllerBuilder {
+    /// Reads inputs from shift registers on an SPI bus, latched by `load_pin`.
+    pub fn new_spi<SPI, LOAD, DELAY>(
+        bus: SPI,
+        load_pin: LOAD,
+        delay: DELAY,
+    ) -> SPIControllerBuilder<SPI, LOAD, DELAY>
+    where
+        SPI: Transfer<u8>,
+        LOAD: OutputPin,
+        DELAY: DelayUs<u16>,
+    {
+        SPIControllerBuilder {
+            bus,
+            load_pin,
+            delay,
+            timing: ShiftTiming::default(),
+            registers: 2,
+            byte_order: ByteOrder::LittleEndian,
+            bit_order: BitOrder::MsbFirst,
+        }
+    }
+
+    /// Reads inputs straight from a tuple of GPIO input pins, the first pin into bit 0.
+    /// The pins must already be configured as inputs, with pull-ups if needed.
+    pub fn new_gpio<PINS: PinSet>(pins: PINS) -> GPIOControllerBuilder<PINS> {
+        GPIOControllerBuilder {
+            pins,
+            active_low: false,
+        }
+    }
+
+    /// Reads inputs from ADC channels, each decoded into one bit with a pair of
+    /// thresholds. `channels` is a tuple of up to 16 pins; element 0 becomes bit 0.
+    pub fn new_adc<ADC, CH, A>(adc: ADC, channels: CH) -> ADCControllerBuilder<ADC, CH, A>
+    where
+        CH: ChannelSet<A, ADC>,
+    {
+        ADCControllerBuilder {
+            _peripheral: PhantomData,
+            adc,
+            channels,
+            thresholds: [Threshold::default(); MAX_CHANNELS],
+            values: [0; MAX_CHANNELS],
+            levels: 0,
+        }
+    }
+
+    /// Reads inputs from MCP23017 expanders on an I2C bus. Each address in `addresses`
+    /// supplies the next 16 bits of the input word, GPIOA then GPIOB; addresses past
+    /// `MAX_EXPANDERS` are ignored.
+    pub fn new_mcp23017<I2C, E>(bus: I2C, addresses: &[u8]) -> MCP23017ControllerBuilder<I2C>
+    where
+        I2C: Write<Error = E> + WriteRead<Error = E>,
+    {
+        let mut expanders = Vec::new();
+        for &address in addresses.iter().take(MAX_EXPANDERS) {
+            let _ = expanders.push(address);
+        }
+        MCP23017ControllerBuilder {
+            bus,
+            addresses: expanders,
+            pull_ups: true,
+            active_low: true,
+        }
+    }
+
+    /// Reads input bytes from any I2C device that returns its switch state as plain bytes,
+    /// such as a simple expander or a companion MCU. Two bytes are read by default,
+    /// little-endian, with no register address written first.
+    pub fn new_i2c<I2C, E>(bus: I2C, address: u8) -> I2CControllerBuilder<I2C>
+    where
+        I2C: Read<Error = E> + WriteRead<Error = E>,
+    {
+        I2CControllerBuilder {
+            bus,
+            address,
+            register: None,
+            bytes: 2,
+            byte_order: ByteOrder::LittleEndian,
+        }
+    }
+
+    /// Takes inputs pushed from serial frames instead of sampling local hardware, for
+    /// boards whose switches are read by another MCU. Whatever handles the frames calls
+    /// `push_bytes` or `push_word`; `load_data` returns the latest word.
+    pub fn new_remote<W: Word>() -> RemoteControllerBuilder<W> {
+        RemoteControllerBuilder {
+            word: W::ZERO,
+            age: 0,
+            timeout: None,
+        }
+    }
+
+    /// Combines several sources into one input word. `sources` is a tuple of up to 8
+    /// `(source, bits)` pairs: each source supplies its low `bits` bits, placed after the
+    /// bits of the sources before it, so the global numbering only depends on the order
+    /// and widths given here.
+    pub fn new_composite<SRC: SourceSet>(sources: SRC) -> CompositeControllerBuilder<SRC> {
+        CompositeControllerBuilder { sources }
+    }
+}
+
+/// A fixed set of input pins sampled together. Implemented for tuples of up to 16
+/// `InputPin`s, which may all be different types, and for arrays of one pin type (e.g.
+/// pins downgraded to a common type) of up to 64; element 0 becomes bit 0.
+pub trait PinSet {
+    /// One bit per pin, set when the pin is high, or low if `active_low`. Fails with
+    /// `Error::Pin` if any pin can't be read.
+    fn sample(&self, active_low: bool) -> Result<u64, Error>;
+}
+
+macro_rules! impl_pin_set {
+    ($($pin:ident $idx:tt),+) => {
+        impl<$($pin: InputPin),+> PinSet for ($($pin,)+) {
+            fn sample(&self, active_low: bool) -> Result<u64, Error> {
+                let mut value = 0;
+                $(
+                    if self.$idx.is_high().map_err(|_| Error::Pin)? != active_low {
+                        value |= 1 << $idx;
+                    }
+                )+
+                Ok(value)
+            }
+        }
+    };
+}
+
+impl_pin_set!(P0 0);
+impl_pin_set!(P0 0, P1 1);
+impl_pin_set!(P0 0, P1 1, P2 2);
+impl_pin_set!(P0 0, P1 1, P2 2, P3 3);
+impl_pin_set!(P0 0, P1 1, P2 2, P3 3, P4 4);
+impl_pin_set!(P0 0, P1 1, P2 2, P3 3, P4 4, P5 5);
+impl_pin_set!(P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6);
+impl_pin_set!(P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7);
+impl_pin_set!(P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8);
+impl_pin_set!(P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9);
+impl_pin_set!(P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9, P10 10);
+impl_pin_set!(P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9, P10 10, P11 11);
+impl_pin_set!(
+    P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9, P10 10, P11 11, P12 12
+);
+impl_pin_set!(
+    P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9, P10 10, P11 11, P12 12, P13 13
+);
+impl_pin_set!(
+    P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9, P10 10, P11 11, P12 12, P13 13,
+    P14 14
+);
+impl_pin_set!(
+    P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9, P10 10, P11 11, P12 12, P13 13,
+    P14 14, P15 15
+);
+
+impl<P: InputPin, const N: usize> PinSet for [P; N] {
+    fn sample(&self, active_low: bool) -> Result<u64, Error> {
+        let mut value = 0;
+        for (i, pin) in self.iter().take(64).enumerate() {
+            if pin.is_high().map_err(|_| Error::Pin)? != active_low {
+                value |= 1 << i;
+            }
+        }
+        Ok(value)
+    }
+}
+
+/// Samples GPIO inputs directly, for small builds without shift registers or expanders.
+pub struct GPIOControllerBuilder<PINS> {
+    pins: PINS,
+    active_low: bool,
+}
+
+impl<PINS> GPIOControllerBuilder<PINS> {
+    /// Inverts every pin, for switches to ground with pull-ups.
+    pub fn active_low(mut self, inverted: bool) -> Self {
+        self.active_low = inverted;
+        self
+    }
+
+    pub fn free(self) -> PINS {
+        self.pins
+    }
+}
+
+impl<PINS: PinSet, W: Word> Controllable<W> for GPIOControllerBuilder<PINS> {
+    fn load_data(&mut self) -> Result<W, Error> {
+        self.pins.sample(self.active_low).map(W::truncate)
+    }
+}
+
+/// Most ADC channels an `ADCControllerBuilder` samples.
+pub const MAX_CHANNELS: usize = 16;
+
+/// A fixed set of ADC channels converted one after another by `D`. Implemented for tuples
+/// of up to 16 pins, which may all be different types; element 0 becomes bit 0.
+pub trait ChannelSet<ADC, D> {
+    const LEN: usize;
+
+    /// Converts channel `index`, blocking until the result is ready. None if the
+    /// conversion failed or the index is out of range.
+    fn convert(&mut self, adc: &mut D, index: usize) -> Option<u16>;
+}
+
+macro_rules! impl_channel_set {
+    ($len:expr; $($pin:ident $idx:tt),+) => {
+        impl<ADC, D, $($pin: Channel<ADC>),+> ChannelSet<ADC, D> for ($($pin,)+)
+        where
+            $(D: OneShot<ADC, u16, $pin>,)+
+        {
+            const LEN: usize = $len;
+
+            fn convert(&mut self, adc: &mut D, index: usize) -> Option<u16> {
+                match index {
+                    $($idx => nb::block!(adc.read(&mut self.$idx)).ok(),)+
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+impl_channel_set!(1; P0 0);
+impl_channel_set!(2; P0 0, P1 1);
+impl_channel_set!(3; P0 0, P1 1, P2 2);
+impl_channel_set!(4; P0 0, P1 1, P2 2, P3 3);
+impl_channel_set!(5; P0 0, P1 1, P2 2, P3 3, P4 4);
+impl_channel_set!(6; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5);
+impl_channel_set!(7; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6);
+impl_channel_set!(8; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7);
+impl_channel_set!(9; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8);
+impl_channel_set!(10; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9);
+impl_channel_set!(11; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9, P10 10);
+impl_channel_set!(
+    12; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9, P10 10, P11 11
+);
+impl_channel_set!(
+    13; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9, P10 10, P11 11, P12 12
+);
+impl_channel_set!(
+    14; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9, P10 10, P11 11, P12 12,
+    P13 13
+);
+impl_channel_set!(
+    15; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9, P10 10, P11 11, P12 12,
+    P13 13, P14 14
+);
+impl_channel_set!(
+    16; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9, P10 10, P11 11, P12 12,
+    P13 13, P14 14, P15 15
+);
+
+/// Decodes an analog reading into a bit with hysteresis: the bit sets once the reading
+/// reaches `high` and clears once it falls to `low`, holding its level in between.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Threshold {
+    pub low: u16,
+    pub high: u16,
+}
+
+impl Threshold {
+    pub fn new(low: u16, high: u16) -> Self {
+        Self {
+            low: low.min(high),
+            high,
+        }
+    }
+
+    pub fn decode(&self, value: u16, was_high: bool) -> bool {
+        if value >= self.high {
+            true
+        } else if value <= self.low {
+            false
+        } else {
+            was_high
+        }
+    }
+}
+
+impl Default for Threshold {
+    /// A quarter and three quarters of a 12-bit ADC's range.
+    fn default() -> Self {
+        Self::new(0x400, 0xC00)
+    }
+}
+
+/// Samples ADC channels and turns them into input bits, for analog plungers and coil
+/// current comparators. The ADC must be configured (reference, resolution, gain) before
+/// it is handed over; thresholds are in its result units. `A` is the ADC peripheral the
+/// channels belong to.
+pub struct ADCControllerBuilder<ADC, CH, A> {
+    _peripheral: PhantomData<A>,
+    adc: ADC,
+    channels: CH,
+    thresholds: [Threshold; MAX_CHANNELS],
+    values: [u16; MAX_CHANNELS],
+    levels: u64,
+}
+
+impl<ADC, CH, A> ADCControllerBuilder<ADC, CH, A> {
+    /// Uses `threshold` for every channel.
+    pub fn thresholds(mut self, threshold: Threshold) -> Self {
+        self.thresholds = [threshold; MAX_CHANNELS];
+        self
+    }
+
+    /// Uses `threshold` for one channel; out of range channels are ignored.
+    pub fn threshold(mut self, channel: usize, threshold: Threshold) -> Self {
+        if let Some(t) = self.thresholds.get_mut(channel) {
+            *t = threshold;
+        }
+        self
+    }
+
+    /// The last reading of `channel`, e.g. the plunger position.
+    pub fn value(&self, channel: usize) -> Option<u16> {
+        self.values.get(channel).copied()
+    }
+
+    pub fn free(self) -> (ADC, CH) {
+        (self.adc, self.channels)
+    }
+}
+
+impl<ADC, CH, A> ADCControllerBuilder<ADC, CH, A>
+where
+    CH: ChannelSet<A, ADC>,
+{
+    /// Converts every channel and decodes the readings against the previous levels.
+    /// A failed conversion keeps its channel's previous level, and once every channel
+    /// has been tried the read fails with `Error::Bus`.
+    pub fn load_channels(&mut self) -> Result<u64, Error> {
+        let mut failed = false;
+        for i in 0..CH::LEN.min(MAX_CHANNELS) {
+            let converted = self.channels.convert(&mut self.adc, i);
+            failed |= converted.is_none();
+            if let Some(value) = converted {
+                self.values[i] = value;
+                let high = self.thresholds[i].decode(value, self.levels & 1 << i != 0);
+                if high {
+                    self.levels |= 1 << i;
+                } else {
+                    self.levels &= !(1 << i);
+                }
+            }
+        }
+        if failed {
+            Err(Error::Bus)
+        } else {
+            Ok(self.levels)
+        }
+    }
+}
+
+impl<ADC, CH, A, W> Controllable<W> for ADCControllerBuilder<ADC, CH, A>
+where
+    CH: ChannelSet<A, ADC>,
+    W: Word,
+{
+    fn load_data(&mut self) -> Result<W, Error> {
+        self.load_channels().map(W::truncate)
+    }
+}
+
+/// Most MCP23017s that fit in a 64-bit input word.
+pub const MAX_EXPANDERS: usize = 4;
+
+// MCP23017 registers, IOCON.BANK = 0 (the power-on default) so A/B pairs are adjacent.
+const MCP_IPOLA: u8 = 0x02;
+const MCP_GPPUA: u8 = 0x0C;
+const MCP_GPIOA: u8 = 0x12;
+
+/// Reads MCP23017 port expanders. By default every pin gets the internal pull-up and
+/// reads inverted, so switches wired to ground read high when closed.
+pub struct MCP23017ControllerBuilder<I2C> {
+    bus: I2C,
+    addresses: Vec<u8, U4>,
+    pull_ups: bool,
+    active_low: bool,
+}
+
+impl<I2C> MCP23017ControllerBuilder<I2C> {
+    pub fn pull_ups(mut self, enabled: bool) -> Self {
+        self.pull_ups = enabled;
+        self
+    }
+
+    /// Whether the expanders invert their inputs (IPOL) so a pin pulled to ground reads 1.
+    pub fn active_low(mut self, inverted: bool) -> Self {
+        self.active_low = inverted;
+        self
+    }
+
+    pub fn addresses(&self) -> &[u8] {
+        &self.addresses
+    }
+
+    pub fn free(self) -> I2C {
+        self.bus
+    }
+}
+
+impl<I2C, E> MCP23017ControllerBuilder<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+{
+    /// Writes the pull-up and polarity settings to every expander. All pins are inputs
+    /// from power-on, so this is the only set-up they need.
+    pub fn init(&mut self) -> Result<(), E> {
+        let pull_ups = if self.pull_ups { 0xFF } else { 0x00 };
+        let polarity = if self.active_low { 0xFF } else { 0x00 };
+        for &address in self.addresses.iter() {
+            self.bus.write(address, &[MCP_GPPUA, pull_ups, pull_ups])?;
+            self.bus.write(address, &[MCP_IPOLA, polarity, polarity])?;
+        }
+        Ok(())
+    }
+
+    /// Reads every expander into a word, the first address in the lowest 16 bits.
+    pub fn load_expanders(&mut self) -> Result<u64, E> {
+        let mut value = 0;
+        for (i, &address) in self.addresses.iter().enumerate() {
+            let mut ports = [0u8; 2];
+            self.bus.write_read(address, &[MCP_GPIOA], &mut ports)?;
+            value |= (u16::from_le_bytes(ports) as u64) << (i * 16);
+        }
+        Ok(value)
+    }
+}
+
+impl<I2C, E, W> Controllable<W> for MCP23017ControllerBuilder<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+    W: Word,
+{
+    fn load_data(&mut self) -> Result<W, Error> {
+        self.load_expanders()
+            .map(W::truncate)
+            .map_err(|_| Error::Bus)
+    }
+}
+
+/// Reads raw input bytes from an I2C device. Bytes are combined into the input word the
+/// same way as a shift register chain, without any bit reordering.
+pub struct I2CControllerBuilder<I2C> {
+    bus: I2C,
+    address: u8,
+    register: Option<u8>,
+    bytes: u8,
+    byte_order: ByteOrder,
+}
+
+impl<I2C> I2CControllerBuilder<I2C> {
+    /// Writes `register` before every read, for devices that need to be told where to
+    /// start reading.
+    pub fn register(mut self, register: u8) -> Self {
+        self.register = Some(register);
+        self
+    }
+
+    /// Sets how many bytes are read, up to `MAX_REGISTERS`.
+    pub fn bytes(mut self, count: u8) -> Self {
+        self.bytes = count.clamp(1, MAX_REGISTERS);
+        self
+    }
+
+    pub fn byte_order(mut self, order: ByteOrder) -> Self {
+        self.byte_order = order;
+        self
+    }
+
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    pub fn free(self) -> I2C {
+        self.bus
+    }
+}
+
+impl<I2C, E> I2CControllerBuilder<I2C>
+where
+    I2C: Read<Error = E> + WriteRead<Error = E>,
+{
+    /// Reads the device into a word. A failed read returns the error so callers can tell
+    /// a missing device from open switches.
+    pub fn load_bytes(&mut self) -> Result<u64, E> {
+        let count = self.bytes as usize;
+        let mut buf = [0u8; MAX_REGISTERS as usize];
+        match self.register {
+            Some(register) => self
+                .bus
+                .write_read(self.address, &[register], &mut buf[..count])?,
+            None => self.bus.read(self.address, &mut buf[..count])?,
+        }
+
+        Ok(buf[..count]
+            .iter()
+            .enumerate()
+            .fold(0, |value, (i, &byte)| {
+                let index = match self.byte_order {
+                    ByteOrder::LittleEndian => i,
+                    ByteOrder::BigEndian => count - 1 - i,
+                };
+                value | (byte as u64) << (index * 8)
+            }))
+    }
+}
+
+impl<I2C, E, W> Controllable<W> for I2CControllerBuilder<I2C>
+where
+    I2C: Read<Error = E> + WriteRead<Error = E>,
+    W: Word,
+{
+    fn load_data(&mut self) -> Result<W, Error> {
+        self.load_bytes().map(W::truncate).map_err(|_| Error::Bus)
+    }
+}
+
+/// Holds the input word last received from another MCU. With a timeout, loads fail with
+/// `Error::Timeout` once that many pass without a new frame, so a dead link is noticed
+/// rather than leaving the last inputs held forever.
+pub struct RemoteControllerBuilder<W: Word = u16> {
+    word: W,
+    age: u32,
+    timeout: Option<u32>,
+}
+
+impl<W: Word> RemoteControllerBuilder<W> {
+    pub fn timeout(mut self, loads: u32) -> Self {
+        self.timeout = Some(loads);
+        self
+    }
+
+    pub fn push_word(&mut self, word: W) {
+        self.word = word;
+        self.age = 0;
+    }
+
+    /// Takes a little-endian frame payload. Bytes past the width of the word are dropped.
+    pub fn push_bytes(&mut self, bytes: &[u8]) {
+        let value = bytes
+            .iter()
+            .take(8)
+            .enumerate()
+            .fold(0, |value, (i, &byte)| value | (byte as u64) << (i * 8));
+        self.push_word(W::truncate(value));
+    }
+
+    /// Loads since the last frame arrived.
+    pub fn age(&self) -> u32 {
+        self.age
+    }
+
+    pub fn is_stale(&self) -> bool {
+        matches!(self.timeout, Some(timeout) if self.age > timeout)
+    }
+}
+
+impl<W: Word> Controllable<W> for RemoteControllerBuilder<W> {
+    fn load_data(&mut self) -> Result<W, Error> {
+        self.age = self.age.saturating_add(1);
+        if self.is_stale() {
+            Err(Error::Timeout)
+        } else {
+            Ok(self.word)
+        }
+    }
+}
+
+/// Several input sources read as one. Implemented for tuples of up to 8 `(source, bits)`
+/// pairs, where every source is a `Controllable<u64>`.
+pub trait SourceSet {
+    /// Reads every source in order. Fails with the first source's error.
+    fn load(&mut self) -> Result<u64, Error>;
+
+    /// The first bit of source `index` in the combined word.
+    fn offset(&self, index: usize) -> Option<u32>;
+
+    /// Total width of all sources; bits past 64 are dropped.
+    fn bits(&self) -> u32;
+}
+
+// Adds the low `bits` bits of `word` to `value` at `offset`, and moves `offset` past them.
+fn stack(value: &mut u64, offset: &mut u32, word: u64, bits: u8) {
+    let bits = u32::from(bits.min(64));
+    let mask = u64::MAX.checked_shr(64 - bits).unwrap_or(0);
+    *value |= (word & mask).checked_shl(*offset).unwrap_or(0);
+    *offset += bits;
+}
+
+macro_rules! impl_source_set {
+    ($($src:ident $idx:tt),+) => {
+        impl<$($src: Controllable<u64>),+> SourceSet for ($(($src, u8),)+) {
+            fn load(&mut self) -> Result<u64, Error> {
+                let mut value = 0;
+                let mut offset = 0;
+                $(stack(&mut value, &mut offset, self.$idx.0.load_data()?, self.$idx.1);)+
+                Ok(value)
+            }
+
+            fn offset(&self, index: usize) -> Option<u32> {
+                let widths = [$(u32::from(self.$idx.1),)+];
+                if index < widths.len() {
+                    Some(widths[..index].iter().sum())
+                } else {
+                    None
+                }
+            }
+
+            fn bits(&self) -> u32 {
+                0 $(+ u32::from(self.$idx.1))+
+            }
+        }
+    };
+}
+
+impl_source_set!(S0 0);
+impl_source_set!(S0 0, S1 1);
+impl_source_set!(S0 0, S1 1, S2 2);
+impl_source_set!(S0 0, S1 1, S2 2, S3 3);
+impl_source_set!(S0 0, S1 1, S2 2, S3 3, S4 4);
+impl_source_set!(S0 0, S1 1, S2 2, S3 3, S4 4, S5 5);
+impl_source_set!(S0 0, S1 1, S2 2, S3 3, S4 4, S5 5, S6 6);
+impl_source_set!(S0 0, S1 1, S2 2, S3 3, S4 4, S5 5, S6 6, S7 7);
+
+/// Reads a `SourceSet` into one word, e.g. an SPI chain, a few direct GPIOs and an
+/// expander feeding a single `InputArray<u64>`.
+pub struct CompositeControllerBuilder<SRC> {
+    sources: SRC,
+}
+
+impl<SRC: SourceSet> CompositeControllerBuilder<SRC> {
+    /// The first bit of source `index` in the input word.
+    pub fn offset(&self, index: usize) -> Option<u32> {
+        self.sources.offset(index)
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.sources.bits()
+    }
+
+    pub fn sources_mut(&mut self) -> &mut SRC {
+        &mut self.sources
+    }
+
+    pub fn free(self) -> SRC {
+        self.sources
+    }
+}
+
+impl<SRC: SourceSet, W: Word> Controllable<W> for CompositeControllerBuilder<SRC> {
+    fn load_data(&mut self) -> Result<W, Error> {
+        self.sources.load().map(W::truncate)
+    }
+}
+
+/// Reads a chain of 74HC165s. The default is two registers, little-endian, MSB first,
+/// filling a 16-bit word.
+pub struct SPIControllerBuilder<SPI, LOAD, DELAY> {
+    bus: SPI,
+    load_pin: LOAD,
+    delay: DELAY,
+    timing: ShiftTiming,
+    registers: u8,
+    byte_order: ByteOrder,
+    bit_order: BitOrder,
+}
+
+impl<SPI, LOAD, DELAY> SPIControllerBuilder<SPI, LOAD, DELAY> {
+    /// Sets the latch and byte timing. The bus must already have been created with the
+    /// frequency and mode from the same `ShiftTiming`.
+    pub fn timing(mut self, timing: ShiftTiming) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    pub fn get_timing(&self) -> &ShiftTiming {
+        &self.timing
+    }
+
+    /// Sets how many registers are daisy-chained, up to `MAX_REGISTERS`. Bytes beyond the
+    /// width of the `InputArray` word are read but dropped.
+    pub fn registers(mut self, count: u8) -> Self {
+        self.registers = count.clamp(1, MAX_REGISTERS);
+        self
+    }
+
+    pub fn byte_order(mut self, order: ByteOrder) -> Self {
+        self.byte_order = order;
+        self
+    }
+
+    pub fn bit_order(mut self, order: BitOrder) -> Self {
+        self.bit_order = order;
+        self
+    }
+
+    pub fn free(self) -> (SPI, LOAD, DELAY) {
+        (self.bus, self.load_pin, self.delay)
+    }
+
+    /// Reads the chain a byte at a time through `FullDuplex` instead, so `poll_data`
+    /// never waits on the bus. Only the latch delays still block.
+    pub fn nb(self) -> NbSPIControllerBuilder<SPI, LOAD, DELAY> {
+        NbSPIControllerBuilder {
+            inner: self,
+            step: Step::Idle,
+            read: 0,
+            buf: [0; MAX_REGISTERS as usize],
+        }
+    }
+
+    /// Reads the chain by DMA instead, through `sercom`, which must be the SERCOM `bus`
+    /// was made from. The byte gap isn't used: the bytes are clocked out back to back.
+    ///
+    /// Every DMA reader shares one receive buffer and the input DMAC channels, so only one
+    /// can exist at a time: while another hasn't been `free`d this is
+    /// `Error::ChannelInUse`.
+    pub fn dma(self, sercom: Sercom) -> Result<DMASPIControllerBuilder<SPI, LOAD, DELAY>, Error> {
+        // thumbv6m has no atomic swap, so the check and the claim share a critical section.
+        let taken = cortex_m::interrupt::free(|_| {
+            let taken = DMA_READER_TAKEN.load(Ordering::Relaxed);
+            DMA_READER_TAKEN.store(true, Ordering::Relaxed);
+            taken
+        });
+        if taken {
+            return Err(Error::ChannelInUse);
+        }
+        dma::enable(unsafe { &*DMAC::ptr() });
+        Ok(DMASPIControllerBuilder {
+            inner: self,
+            sercom,
+            busy: false,
+            latest: 0,
+        })
+    }
+}
+
+impl<SPI, LOAD, DELAY> SPIControllerBuilder<SPI, LOAD, DELAY>
+where
+    SPI: Transfer<u8>,
+    LOAD: OutputPin,
+    DELAY: DelayUs<u16>,
+{
+    /// Latches and reads the whole chain. Without a byte gap the chain is read in a single
+    /// transfer.
+    /// Fails with `Error::Pin` if the load pin can't be driven and `Error::Bus` if a
+    /// transfer fails.
+    pub fn load_chain(&mut self) -> Result<u64, Error> {
+        self.latch()?;
+
+        let count = self.registers as usize;
+        let mut buf = [0u8; MAX_REGISTERS as usize];
+        if self.timing.byte_gap_us == 0 {
+            let mut words = [0u8; MAX_REGISTERS as usize];
+            let read = self
+                .bus
+                .transfer(&mut words[..count])
+                .map_err(|_| Error::Bus)?;
+            buf[..count].copy_from_slice(read);
+        } else {
+            for (i, byte) in buf[..count].iter_mut().enumerate() {
+                if i > 0 {
+                    self.delay.delay_us(self.timing.byte_gap_us);
+                }
+                let mut word = [0u8];
+                *byte = self.bus.transfer(&mut word).map_err(|_| Error::Bus)?[0];
+            }
+        }
+
+        Ok(assemble(&buf[..count], self.byte_order, self.bit_order))
+    }
+
+    fn latch(&mut self) -> Result<(), Error> {
+        self.load_pin.set_low().map_err(|_| Error::Pin)?;
+        self.delay.delay_us(self.timing.latch_setup_us);
+        self.load_pin.set_high().map_err(|_| Error::Pin)?;
+        self.delay.delay_us(self.timing.latch_hold_us);
+        Ok(())
+    }
+}
+
+// Combines the bytes read from a chain into a word.
+fn assemble(bytes: &[u8], byte_order: ByteOrder, bit_order: BitOrder) -> u64 {
+    bytes.iter().enumerate().fold(0, |value, (i, &byte)| {
+        let byte = match bit_order {
+            BitOrder::MsbFirst => byte,
+            BitOrder::LsbFirst => byte.reverse_bits(),
+        };
+        let index = match byte_order {
+            ByteOrder::LittleEndian => i,
+            ByteOrder::BigEndian => bytes.len() - 1 - i,
+        };
+        value | (byte as u64) << (index * 8)
+    })
+}
+
+impl<SPI, LOAD, DELAY, W> Controllable<W> for SPIControllerBuilder<SPI, LOAD, DELAY>
+where
+    SPI: Transfer<u8>,
+    LOAD: OutputPin,
+    DELAY: DelayUs<u16>,
+    W: Word,
+{
+    fn load_data(&mut self) -> Result<W, Error> {
+        self.load_chain().map(W::truncate)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Step {
+    Idle,
+    Send,
+    Receive,
+}
+
+/// Reads a chain of 74HC165s without blocking on the bus, see `SPIControllerBuilder::nb`.
+pub struct NbSPIControllerBuilder<SPI, LOAD, DELAY> {
+    inner: SPIControllerBuilder<SPI, LOAD, DELAY>,
+    step: Step,
+    read: usize,
+    buf: [u8; MAX_REGISTERS as usize],
+}
+
+impl<SPI, LOAD, DELAY> NbSPIControllerBuilder<SPI, LOAD, DELAY>
+where
+    SPI: Transfer<u8> + FullDuplex<u8>,
+    LOAD: OutputPin,
+    DELAY: DelayUs<u16>,
+{
+    /// Moves the read along as far as the bus allows. A new read is latched when the
+    /// previous one has been returned. An error abandons the read.
+    pub fn poll_chain(&mut self) -> nb::Result<u64, Error> {
+        let result = self.advance();
+        if let Err(nb::Error::Other(_)) = result {
+            self.step = Step::Idle;
+        }
+        result
+    }
+
+    /// Whether a read is part way through.
+    pub fn is_busy(&self) -> bool {
+        self.step != Step::Idle
+    }
+
+    /// Abandons any read in progress and goes back to blocking reads.
+    pub fn free(self) -> SPIControllerBuilder<SPI, LOAD, DELAY> {
+        self.inner
+    }
+
+    fn advance(&mut self) -> nb::Result<u64, Error> {
+        let count = self.inner.registers as usize;
+        loop {
+            match self.step {
+                Step::Idle => {
+                    self.inner.latch()?;
+                    self.read = 0;
+                    self.step = Step::Send;
+                }
+                Step::Send => {
+                    FullDuplex::send(&mut self.inner.bus, 0).map_err(bus_error)?;
+                    self.step = Step::Receive;
+                }
+                Step::Receive => {
+                    self.buf[self.read] =
+                        FullDuplex::read(&mut self.inner.bus).map_err(bus_error)?;
+                    self.read += 1;
+                    if self.read < count {
+                        self.step = Step::Send;
+                    } else {
+                        self.step = Step::Idle;
+                        let bytes = &self.buf[..count];
+                        return Ok(assemble(bytes, self.inner.byte_order, self.inner.bit_order));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn bus_error<E>(e: nb::Error<E>) -> nb::Error<Error> {
+    match e {
+        nb::Error::WouldBlock => nb::Error::WouldBlock,
+        nb::Error::Other(_) => nb::Error::Other(Error::Bus),
+    }
+}
+
+impl<SPI, LOAD, DELAY, W> Controllable<W> for NbSPIControllerBuilder<SPI, LOAD, DELAY>
+where
+    SPI: Transfer<u8> + FullDuplex<u8>,
+    LOAD: OutputPin,
+    DELAY: DelayUs<u16>,
+    W: Word,
+{
+    fn load_data(&mut self) -> Result<W, Error> {
+        nb::block!(self.poll_chain()).map(W::truncate)
+    }
+
+    fn poll_data(&mut self) -> nb::Result<W, Error> {
+        self.poll_chain().map(W::truncate)
+    }
+}
+
+/// The SERCOM an SPI bus runs on, for DMA reads.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sercom {
+    Sercom0,
+    Sercom1,
+    Sercom2,
+    Sercom3,
+    Sercom4,
+    Sercom5,
+}
+
+impl Sercom {
+    fn index(self) -> u32 {
+        self as u32
+    }
+
+    fn data(self) -> u32 {
+        let base = match self {
+            Sercom::Sercom0 => SERCOM0::ptr() as u32,
+            Sercom::Sercom1 => SERCOM1::ptr() as u32,
+            Sercom::Sercom2 => SERCOM2::ptr() as u32,
+            Sercom::Sercom3 => SERCOM3::ptr() as u32,
+            Sercom::Sercom4 => SERCOM4::ptr() as u32,
+            Sercom::Sercom5 => SERCOM5::ptr() as u32,
+        };
+        base + SERCOM_SPI_DATA
+    }
+}
+
+// SERCOM SPI DATA register offset.
+const SERCOM_SPI_DATA: u32 = 0x28;
+
+// DMAC channel and descriptor fields for byte transfers paced by the SERCOM.
+const CHCTRLA_SWRST: u8 = 1 << 0;
+const CHCTRLA_ENABLE: u8 = 1 << 1;
+const CHCTRLB_TRIGSRC: u32 = 8;
+const CHCTRLB_TRIGACT_BEAT: u32 = 2 << 22;
+const CHINT_TCMPL: u8 = 1 << 1;
+const BTCTRL_VALID: u16 = 1 << 0;
+const BTCTRL_DSTINC: u16 = 1 << 11;
+
+// SERCOMn RX and TX trigger sources are 1 + 2n and 2 + 2n.
+const TRIGSRC_SERCOM_RX: u32 = 0x01;
+const TRIGSRC_SERCOM_TX: u32 = 0x02;
+
+// What the DMA reads land in, and the zero bytes clocked out to get them.
+static mut RX_BUF: [u8; MAX_REGISTERS as usize] = [0; MAX_REGISTERS as usize];
+static TX_ZERO: u8 = 0;
+
+// Whether a `DMASPIControllerBuilder` owns `RX_BUF` and the input DMAC channels.
+static DMA_READER_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Reads a chain of 74HC165s by DMA so the scan doesn't hold up the task that starts it.
+/// `start` latches the chain and arms the transfer, then `poll` returns the word once
+/// the DMAC has finished, which is also flagged through the DMAC interrupt after
+/// `listen`.
+///
+/// As a `Controllable`, each load returns the newest completed scan and starts the next
+/// one, so inputs lag by at most one load. Until the first scan completes, every input
+/// reads clear. Only one DMA reader can exist, see `SPIControllerBuilder::dma`, and it must
+/// be used from the same priority as `Waveforms`, which shares the DMAC.
+pub struct DMASPIControllerBuilder<SPI, LOAD, DELAY> {
+    inner: SPIControllerBuilder<SPI, LOAD, DELAY>,
+    sercom: Sercom,
+    busy: bool,
+    latest: u64,
+}
+
+impl<SPI, LOAD, DELAY> DMASPIControllerBuilder<SPI, LOAD, DELAY>
+where
+    SPI: Transfer<u8>,
+    LOAD: OutputPin,
+    DELAY: DelayUs<u16>,
+{
+    /// Latches the chain and starts reading it. Does nothing if a read is in progress.
+    pub fn start(&mut self) -> Result<(), Error> {
+        if self.busy {
+            return Ok(());
+        }
+        self.inner.latch()?;
+
+        let count = self.inner.registers as u16;
+        let data = self.sercom.data();
+        unsafe {
+            *dma::descriptor(INPUT_RX_CHANNEL as usize) = dma::Descriptor {
+                btctrl: BTCTRL_VALID | BTCTRL_DSTINC,
+                btcnt: count,
+                srcaddr: data,
+                // With DSTINC the destination address is the end of the block.
+                dstaddr: (addr_of_mut!(RX_BUF) as *mut u8).add(count as usize) as u32,
+                descaddr: 0,
+            };
+            *dma::descriptor(INPUT_TX_CHANNEL as usize) = dma::Descriptor {
+                btctrl: BTCTRL_VALID,
+                btcnt: count,
+                srcaddr: addr_of!(TX_ZERO) as u32,
+                dstaddr: data,
+                descaddr: 0,
+            };
+        }
+
+        let sercom = self.sercom.index();
+        // Receive first, so no byte can arrive before its channel is ready.
+        self.arm(INPUT_RX_CHANNEL, TRIGSRC_SERCOM_RX + 2 * sercom);
+        self.arm(INPUT_TX_CHANNEL, TRIGSRC_SERCOM_TX + 2 * sercom);
+        self.busy = true;
+        Ok(())
+    }
+
+    /// Whether the last started read has finished.
+    pub fn is_complete(&self) -> bool {
+        let dmac = unsafe { &*DMAC::ptr() };
+        dmac.chid.write(|w| unsafe { w.bits(INPUT_RX_CHANNEL) });
+        dmac.chintflag.read().bits() & CHINT_TCMPL != 0
+    }
+
+    /// The word from a finished read, starting one first if none is running.
+    pub fn poll(&mut self) -> nb::Result<u64, Error> {
+        if !self.busy {
+            self.start()?;
+            return Err(nb::Error::WouldBlock);
+        }
+        if !self.is_complete() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let dmac = unsafe { &*DMAC::ptr() };
+        dmac.chid.write(|w| unsafe { w.bits(INPUT_RX_CHANNEL) });
+        dmac.chintflag.write(|w| unsafe { w.bits(CHINT_TCMPL) });
+        self.busy = false;
+
+        let count = self.inner.registers as usize;
+        let bytes = unsafe { &(&*addr_of!(RX_BUF))[..count] };
+        self.latest = assemble(bytes, self.inner.byte_order, self.inner.bit_order);
+        Ok(self.latest)
+    }
+
+    /// Raises the DMAC interrupt when a read finishes, or stops raising it.
+    pub fn listen(&mut self, enabled: bool) {
+        let dmac = unsafe { &*DMAC::ptr() };
+        dmac.chid.write(|w| unsafe { w.bits(INPUT_RX_CHANNEL) });
+        if enabled {
+            dmac.chintenset.write(|w| unsafe { w.bits(CHINT_TCMPL) });
+        } else {
+            dmac.chintenclr.write(|w| unsafe { w.bits(CHINT_TCMPL) });
+        }
+    }
+
+    /// Stops any read in progress and goes back to blocking reads.
+    pub fn free(self) -> SPIControllerBuilder<SPI, LOAD, DELAY> {
+        let dmac = unsafe { &*DMAC::ptr() };
+        for &channel in [INPUT_TX_CHANNEL, INPUT_RX_CHANNEL].iter() {
+            dmac.chid.write(|w| unsafe { w.bits(channel) });
+            dmac.chctrla.write(|w| unsafe { w.bits(0) });
+            while dmac.chctrla.read().bits() & CHCTRLA_ENABLE != 0 {}
+            dmac.chctrla.write(|w| unsafe { w.bits(CHCTRLA_SWRST) });
+        }
+        DMA_READER_TAKEN.store(false, Ordering::Relaxed);
+        self.inner
+    }
+
+    fn arm(&mut self, channel: u8, trigger: u32) {
+        let dmac = unsafe { &*DMAC::ptr() };
+        dmac.chid.write(|w| unsafe { w.bits(channel) });
+        dmac.chintflag.write(|w| unsafe { w.bits(CHINT_TCMPL) });
+        dmac.chctrlb
+            .write(|w| unsafe { w.bits(trigger << CHCTRLB_TRIGSRC | CHCTRLB_TRIGACT_BEAT) });
+        dmac.chctrla.write(|w| unsafe { w.bits(CHCTRLA_ENABLE) });
+    }
+}
+
+impl<SPI, LOAD, DELAY, W> Controllable<W> for DMASPIControllerBuilder<SPI, LOAD, DELAY>
+where
+    SPI: Transfer<u8>,
+    LOAD: OutputPin,
+    DELAY: DelayUs<u16>,
+    W: Word,
+{
+    fn load_data(&mut self) -> Result<W, Error> {
+        match self.poll() {
+            Ok(_) => self.start()?,
+            Err(nb::Error::WouldBlock) => {}
+            Err(nb::Error::Other(e)) => return Err(e),
+        }
+        Ok(W::truncate(self.latest))
+    }
+
+    fn poll_data(&mut self) -> nb::Result<W, Error> {
+        self.poll().map(W::truncate)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::actuators::{Basic, Flipper, FnActuator};
+    use crate::bootloader::BOOTLOADER_KEY;
+    use crate::command::{Command, Reply, BROADCAST_ADDRESS};
+    use crate::controller::{
+        BitOrder, ByteOrder, Controllable, Controller, ControllerBuilder, Erased, Evaluate,
+        FaultRecord, Faults, ShiftTiming, Snapshot, Threshold,
+    };
+    use crate::output::{ChannelOutput, OutputDriver};
+    use crate::power::{CoilLimit, Overflow, Overload, PowerBudget};
+    use crate::pwm::{self, Configuration, Execute, State};
+    use crate::rules::{Action, Rule};
+    use crate::testing::{FlakyWord16, Word16};
+    use crate::time::Duration;
+    use crate::watchdog::OnTimeLimits;
+    use crate::wrappers::{ActuatorExt, Hold};
+    use crate::{
+        Actuator, DualInput, Edge, Error, InputArray, InputData, InputType, Param, SingleInput,
+    };
+    use core::cell::{Cell, RefCell};
+    use core::convert::Infallible;
+    use embedded_hal::{
+        adc::{Channel, OneShot},
+        blocking::{
+            delay::DelayUs,
+            i2c::{Read, Write, WriteRead},
+            spi::{transfer, Transfer},
+        },
+        digital::v2::{InputPin, OutputPin},
+        spi::FullDuplex,
+        timer::{CountDown, Periodic},
+        watchdog::Watchdog,
+    };
+    use heapless::{consts::*, Vec};
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Load(bool),
+        Delay(u16),
+        Byte,
+    }
+
+    type Log = RefCell<Vec<Event, U16>>;
+
+    struct Bus<'a>(&'a Log, &'a [u8], usize);
+    struct Pin<'a>(&'a Log);
+    struct Delay<'a>(&'a Log);
+
+    impl Transfer<u8> for Bus<'_> {
+        type Error = Infallible;
+
+        fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Infallible> {
+            for word in words.iter_mut() {
+                self.0.borrow_mut().push(Event::Byte).unwrap();
+                *word = self.1[self.2];
+                self.2 += 1;
+            }
+            Ok(words)
+        }
+    }
+
+    struct SlowBus<'a>(&'a [u8], usize, bool);
+
+    impl FullDuplex<u8> for SlowBus<'_> {
+        type Error = Infallible;
+
+        fn read(&mut self) -> nb::Result<u8, Infallible> {
+            // Every byte takes two polls to arrive.
+            self.2 = !self.2;
+            if self.2 {
+                return Err(nb::Error::WouldBlock);
+            }
+            self.1 += 1;
+            Ok(self.0[self.1 - 1])
+        }
+
+        fn send(&mut self, _word: u8) -> nb::Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    impl transfer::Default<u8> for SlowBus<'_> {}
+
+    #[test]
+    fn nb_read_does_not_block() {
+        let log = Log::default();
+        let mut controller =
+            ControllerBuilder::new_spi(SlowBus(&[0x34, 0x12], 0, false), Pin(&log), Delay(&log))
+                .nb();
+
+        assert_eq!(
+            Controllable::<u16>::poll_data(&mut controller),
+            Err(nb::Error::WouldBlock)
+        );
+        assert!(controller.is_busy());
+        assert_eq!(
+            Controllable::<u16>::poll_data(&mut controller),
+            Err(nb::Error::WouldBlock)
+        );
+        assert_eq!(Controllable::<u16>::poll_data(&mut controller), Ok(0x1234));
+        assert!(!controller.is_busy());
+        assert_eq!(
+            log.borrow()
+                .iter()
+                .filter(|e| **e == Event::Load(false))
+                .count(),
+            1
+        );
+    }
+
+    impl OutputPin for Pin<'_> {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            self.0.borrow_mut().push(Event::Load(false)).unwrap();
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            self.0.borrow_mut().push(Event::Load(true)).unwrap();
+            Ok(())
+        }
+    }
+
+    impl DelayUs<u16> for Delay<'_> {
+        fn delay_us(&mut self, us: u16) {
+            self.0.borrow_mut().push(Event::Delay(us)).unwrap();
+        }
+    }
+
+    #[test]
+    fn spi_load_follows_timing() {
+        let log = Log::default();
+        let mut controller =
+            ControllerBuilder::new_spi(Bus(&log, &[0x34, 0x12], 0), Pin(&log), Delay(&log))
+                .timing(ShiftTiming::conservative());
+
+        assert_eq!(Controllable::<u16>::load_data(&mut controller), Ok(0x1234));
+        assert_eq!(
+            &log.borrow()[..],
+            &[
+                Event::Load(false),
+                Event::Delay(5),
+                Event::Load(true),
+                Event::Delay(5),
+                Event::Byte,
+                Event::Delay(10),
+                Event::Byte,
+            ]
+        );
+    }
+
+    #[test]
+    fn chained_registers_fill_wide_word() {
+        let log = Log::default();
+        let mut controller =
+            ControllerBuilder::new_spi(Bus(&log, &[0x01, 0x80, 0x0F], 0), Pin(&log), Delay(&log))
+                .registers(3)
+                .byte_order(ByteOrder::BigEndian)
+                .bit_order(BitOrder::LsbFirst);
+
+        // Bit-reversed to 0x80 0x01 0xF0, first byte most significant.
+        assert_eq!(
+            Controllable::<u32>::load_data(&mut controller),
+            Ok(0x0080_01F0)
+        );
+
+        // No byte gap, so the whole chain is one transfer.
+        assert_eq!(
+            &log.borrow()[..],
+            &[
+                Event::Load(false),
+                Event::Delay(1),
+                Event::Load(true),
+                Event::Delay(1),
+                Event::Byte,
+                Event::Byte,
+                Event::Byte,
+            ]
+        );
+    }
+
+    struct I2c<'a>(&'a RefCell<Vec<(u8, u8), U8>>);
+
+    impl Write for I2c<'_> {
+        type Error = Infallible;
+
+        fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Infallible> {
+            self.0.borrow_mut().push((address, bytes[0])).unwrap();
+            Ok(())
+        }
+    }
+
+    impl WriteRead for I2c<'_> {
+        type Error = Infallible;
+
+        fn write_read(
+            &mut self,
+            address: u8,
+            bytes: &[u8],
+            buffer: &mut [u8],
+        ) -> Result<(), Infallible> {
+            assert_eq!(bytes, &[0x12]);
+            buffer.copy_from_slice(&[address, 0x80]);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn mcp23017_expanders_fill_word() {
+        let writes = RefCell::new(Vec::new());
+        let mut controller = ControllerBuilder::new_mcp23017(I2c(&writes), &[0x20, 0x21]);
+        controller.init().unwrap();
+        assert_eq!(
+            &writes.borrow()[..],
+            &[(0x20, 0x0C), (0x20, 0x02), (0x21, 0x0C), (0x21, 0x02)]
+        );
+
+        assert_eq!(
+            Controllable::<u32>::load_data(&mut controller),
+            Ok(0x8021_8020)
+        );
+        assert_eq!(Controllable::<u16>::load_data(&mut controller), Ok(0x8020));
+    }
+
+    struct Companion(Option<u8>);
+
+    impl Read for Companion {
+        type Error = ();
+
+        fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), ()> {
+            if address != 0x42 {
+                return Err(());
+            }
+            for (i, byte) in buffer.iter_mut().enumerate() {
+                *byte = i as u8 + 1;
+            }
+            Ok(())
+        }
+    }
+
+    impl WriteRead for Companion {
+        type Error = ();
+
+        fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), ()> {
+            self.0 = Some(bytes[0]);
+            self.read(address, buffer)
+        }
+    }
+
+    #[test]
+    fn i2c_bytes_fill_word() {
+        let mut controller = ControllerBuilder::new_i2c(Companion(None), 0x42);
+        assert_eq!(Controllable::<u32>::load_data(&mut controller), Ok(0x0201));
+
+        let mut controller = controller
+            .register(0x10)
+            .bytes(3)
+            .byte_order(ByteOrder::BigEndian);
+        assert_eq!(controller.load_bytes(), Ok(0x01_0203));
+        assert_eq!(controller.free().0, Some(0x10));
+
+        let mut missing = ControllerBuilder::new_i2c(Companion(None), 0x43);
+        assert_eq!(missing.load_bytes(), Err(()));
+        assert_eq!(
+            Controllable::<u16>::load_data(&mut missing),
+            Err(Error::Bus)
+        );
+    }
+
+    #[test]
+    fn remote_frames_time_out() {
+        let mut controller = ControllerBuilder::new_remote::<u32>().timeout(2);
+        controller.push_bytes(&[0x01, 0x80, 0x02]);
+        assert_eq!(controller.load_data(), Ok(0x02_8001));
+        assert_eq!(controller.load_data(), Ok(0x02_8001));
+        assert_eq!(controller.load_data(), Err(Error::Timeout));
+        assert!(controller.is_stale());
+
+        controller.push_word(0x10);
+        assert_eq!(controller.load_data(), Ok(0x10));
+        assert_eq!(controller.age(), 1);
+    }
+
+    struct Level(bool);
+    struct Broken;
+
+    impl InputPin for Level {
+        type Error = Infallible;
+
+        fn is_high(&self) -> Result<bool, Infallible> {
+            Ok(self.0)
+        }
+
+        fn is_low(&self) -> Result<bool, Infallible> {
+            Ok(!self.0)
+        }
+    }
+
+    impl InputPin for Broken {
+        type Error = ();
+
+        fn is_high(&self) -> Result<bool, ()> {
+            Err(())
+        }
+
+        fn is_low(&self) -> Result<bool, ()> {
+            Err(())
+        }
+    }
+
+    #[test]
+    fn gpio_pins_map_to_bits() {
+        let mut controller =
+            ControllerBuilder::new_gpio((Level(true), Level(false), Level(false), Level(true)));
+        assert_eq!(Controllable::<u16>::load_data(&mut controller), Ok(0b1001));
+
+        let mut controller = controller.active_low(true);
+        assert_eq!(Controllable::<u16>::load_data(&mut controller), Ok(0b0110));
+
+        let mut controller = ControllerBuilder::new_gpio((Level(true), Broken));
+        assert_eq!(
+            Controllable::<u16>::load_data(&mut controller),
+            Err(Error::Pin)
+        );
+    }
+
+    #[test]
+    fn gpio_pin_array() {
+        let mut controller =
+            ControllerBuilder::new_gpio([Level(false), Level(true), Level(true)]).active_low(true);
+        assert_eq!(Controllable::<u16>::load_data(&mut controller), Ok(0b001));
+    }
+
+    struct Adc<'a>(&'a Cell<[u16; 2]>);
+    struct Analog;
+    struct Ch0;
+    struct Ch1;
+
+    impl Channel<Analog> for Ch0 {
+        type ID = u8;
+
+        fn channel() -> u8 {
+            0
+        }
+    }
+
+    impl Channel<Analog> for Ch1 {
+        type ID = u8;
+
+        fn channel() -> u8 {
+            1
+        }
+    }
+
+    impl<P: Channel<Analog, ID = u8>> OneShot<Analog, u16, P> for Adc<'_> {
+        type Error = Infallible;
+
+        fn read(&mut self, _pin: &mut P) -> nb::Result<u16, Infallible> {
+            Ok(self.0.get()[P::channel() as usize])
+        }
+    }
+
+    #[test]
+    fn adc_thresholds_have_hysteresis() {
+        let readings = Cell::new([0, 0]);
+        let mut controller = ControllerBuilder::new_adc(Adc(&readings), (Ch0, Ch1))
+            .thresholds(Threshold::new(1000, 3000))
+            .threshold(1, Threshold::new(100, 200));
+
+        readings.set([2000, 150]);
+        assert_eq!(controller.load_channels(), Ok(0b00));
+        readings.set([3000, 250]);
+        assert_eq!(controller.load_channels(), Ok(0b11));
+        readings.set([2000, 150]);
+        assert_eq!(controller.load_channels(), Ok(0b11));
+        assert_eq!(controller.value(0), Some(2000));
+        readings.set([1000, 50]);
+        assert_eq!(Controllable::<u16>::load_data(&mut controller), Ok(0b00));
+    }
+
+    struct Timer(Cell<u32>);
+
+    impl CountDown for Timer {
+        type Time = u32;
+
+        fn start<T: Into<u32>>(&mut self, count: T) {
+            self.0.set(count.into());
+        }
+
+        fn wait(&mut self) -> nb::Result<(), void::Void> {
+            match self.0.get() {
+                0 => Err(nb::Error::WouldBlock),
+                _ => {
+                    self.0.set(0);
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    impl Periodic for Timer {}
+
+    // Remembers whether each state it was given was on.
+    struct Recorder<'a> {
+        log: &'a RefCell<Vec<bool, U256>>,
+        state: State,
+    }
+
+    impl<'a> Recorder<'a> {
+        fn new(log: &'a RefCell<Vec<bool, U256>>) -> Self {
+            let state = State {
+                enabled: false,
+                duty_cycle: 0,
+            };
+            Self { log, state }
+        }
+    }
+
+    impl OutputDriver for Recorder<'_> {
+        fn apply(&mut self, state: &State) {
+            let _ = self.log.borrow_mut().push(state.enabled);
+            self.state = *state;
+        }
+
+        fn state(&self) -> State {
+            self.state
+        }
+    }
+
+    struct Channels([State; 13]);
+
+    // Thirteen PWM channels, all off.
+    fn channels() -> RefCell<Channels> {
+        let off = State {
+            enabled: false,
+            duty_cycle: 0,
+        };
+        RefCell::new(Channels([off; 13]))
+    }
+
+    // A controller reading `source`, with one actuator made on TC3 and set up by `wrap`,
+    // driving TC3 in `channels`.
+    fn on_tc3<'a, S, I, T, A>(
+        channels: &'a RefCell<Channels>,
+        source: S,
+        wrap: impl FnOnce(T) -> A,
+    ) -> Controller<S, A, ChannelOutput<'a, Channels>>
+    where
+        S: Controllable<u16>,
+        I: InputType,
+        T: Actuator<I>,
+        A: Evaluate<u16>,
+    {
+        let mut controller = Controller::new(source, InputArray::new());
+        let actuator: T = controller
+            .inputs_mut()
+            .make_actuator(Configuration::Tc3)
+            .unwrap();
+        controller
+            .register(
+                wrap(actuator),
+                ChannelOutput::new(channels, Configuration::Tc3),
+            )
+            .unwrap();
+        controller
+    }
+
+    impl Execute for Channels {
+        fn apply(&mut self, config: &Configuration, state: &State) {
+            self.0[config.index() as usize] = *state;
+        }
+
+        fn state(&self, config: &Configuration) -> State {
+            self.0[config.index() as usize]
+        }
+    }
+
+    #[test]
+    fn tick_reads_evaluates_and_applies() {
+        let channels = channels();
+        let mut controller = Controller::new(Word16(0), InputArray::new());
+        for &channel in [pwm::Channel::_0, pwm::Channel::_1].iter() {
+            let config = Configuration::Tcc1(channel);
+            let basic: Basic = controller.inputs_mut().make_actuator(config).unwrap();
+            controller
+                .register(Erased::new(basic), ChannelOutput::new(&channels, config))
+                .unwrap();
+        }
+
+        controller.source_mut().0 = 0b10;
+        assert_eq!(controller.tick(), Ok(0b10));
+        assert!(!channels.borrow().0[4].enabled);
+        assert!(channels.borrow().0[5].enabled);
+        assert_eq!(controller.ticks(), 1);
+    }
+
+    #[test]
+    fn scheduled_actions_override_on_their_cycle() {
+        let channels = channels();
+        let mut controller = on_tc3(&channels, Word16(0), |basic: Basic| basic.erased());
+
+        let kick = State {
+            enabled: true,
+            duty_cycle: 100,
+        };
+        assert_eq!(controller.schedule(15, 1, kick), Err(Error::InvalidMapping));
+        controller.schedule(15, 0, kick).unwrap();
+
+        controller.tick_at(10).unwrap();
+        assert!(!channels.borrow().0[12].enabled);
+        controller.tick_at(15).unwrap();
+        assert_eq!(channels.borrow().0[12], kick);
+        assert!(controller.scheduler_mut().is_empty());
+        controller.tick_at(20).unwrap();
+        assert!(!channels.borrow().0[12].enabled);
+    }
+
+    #[test]
+    fn fire_command_drives_through_the_actuator() {
+        let channels = channels();
+        let mut controller = on_tc3(&channels, Word16(0), |basic: Basic| {
+            basic.max_on_for(Duration::from_millis(20)).erased()
+        });
+        controller.tick_at(100).unwrap();
+
+        let fire = |id| Command::Fire {
+            id,
+            strength: 50,
+            millis: 30,
+        };
+        assert_eq!(controller.dispatch(fire(1)), Err(Error::InvalidMapping));
+        controller.dispatch(fire(0)).unwrap();
+        assert!(controller.is_firing(0));
+
+        controller.tick_at(101).unwrap();
+        assert_eq!(
+            channels.borrow().0[12],
+            State {
+                enabled: true,
+                duty_cycle: u32::MAX / 2,
+            }
+        );
+        // The on-time limit still applies to remote fires.
+        controller.tick_at(125).unwrap();
+        assert!(!channels.borrow().0[12].enabled);
+
+        controller.tick_at(130).unwrap();
+        assert!(!controller.is_firing(0));
+        assert_eq!(controller.inputs().override_mask(), 0);
+
+        // Released, so the limit has re-armed for the next fire.
+        controller.dispatch(fire(0)).unwrap();
+        controller.tick_at(131).unwrap();
+        assert!(channels.borrow().0[12].enabled);
+    }
+
+    #[test]
+    fn enable_holds_until_disabled() {
+        let channels = channels();
+        let mut controller = on_tc3(&channels, Word16(0), |basic: Basic| basic.erased());
+        controller.tick_at(0).unwrap();
+
+        assert_eq!(controller.enable(1, 100), Err(Error::InvalidMapping));
+        controller.enable_with_hold(0, 10, 100, 50).unwrap();
+        controller.tick_at(1).unwrap();
+        assert_eq!(channels.borrow().0[12].duty_cycle, u32::MAX);
+        // Well past the longest `Command::Fire`, with nothing renewing it.
+        controller.tick_at(100_000).unwrap();
+        assert!(controller.is_firing(0));
+        assert_eq!(channels.borrow().0[12].duty_cycle, u32::MAX / 2);
+
+        controller.disable(0);
+        controller.tick_at(100_001).unwrap();
+        assert!(!channels.borrow().0[12].enabled);
+        assert_eq!(controller.inputs().override_mask(), 0);
+    }
+
+    #[test]
+    fn switch_rules_act_within_the_tick() {
+        let channels = channels();
+        let mut controller = on_tc3(&channels, Word16(0), |basic: Basic| basic.erased());
+        controller.tick_at(0).unwrap();
+
+        let enable = Rule {
+            switch: 1,
+            edge: Edge::Rising,
+            actuator: 0,
+            action: Action::Enable {
+                pulse_millis: 10,
+                strength: 100,
+                hold_strength: 25,
+            },
+        };
+        let release = Rule {
+            edge: Edge::Falling,
+            action: Action::Disable,
+            ..enable
+        };
+        // Neither the actuator's own input nor a missing actuator can be bound.
+        let own = Rule {
+            switch: 0,
+            ..enable
+        };
+        assert_eq!(controller.add_rule(own), Err(Error::InvalidMapping));
+        let missing = Rule {
+            actuator: 1,
+            ..enable
+        };
+        assert_eq!(controller.add_rule(missing), Err(Error::InvalidMapping));
+        let mut flippers = Controller::new(Word16(0), InputArray::new());
+        let flipper: Flipper = flippers
+            .inputs_mut()
+            .make_actuator(Configuration::Tc3)
+            .unwrap();
+        flippers
+            .register(
+                Erased::new(flipper),
+                ChannelOutput::new(&channels, Configuration::Tc3),
+            )
+            .unwrap();
+        let eos = Rule {
+            switch: 1,
+            ..enable
+        };
+        assert_eq!(flippers.add_rule(eos), Err(Error::InvalidMapping));
+        flippers
+            .add_rule(Rule {
+                switch: 2,
+                ..enable
+            })
+            .unwrap();
+        controller.add_rule(enable).unwrap();
+        controller.add_rule(release).unwrap();
+        controller.add_rule(enable).unwrap();
+        assert_eq!(controller.rules().len(), 2);
+
+        controller.source_mut().0 = 0b10;
+        controller.tick_at(1).unwrap();
+        assert_eq!(channels.borrow().0[12].duty_cycle, u32::MAX);
+        controller.tick_at(11).unwrap();
+        assert_eq!(channels.borrow().0[12].duty_cycle, u32::MAX / 4);
+
+        controller.source_mut().0 = 0;
+        controller.tick_at(12).unwrap();
+        assert!(!channels.borrow().0[12].enabled);
+        assert!(!controller.is_firing(0));
+
+        assert_eq!(controller.remove_rules(1, 0), 2);
+        controller.source_mut().0 = 0b10;
+        controller.tick_at(13).unwrap();
+        assert!(!channels.borrow().0[12].enabled);
+    }
+
+    #[test]
+    fn max_on_time_latches_a_stuck_output_off() {
+        let mut inputs = InputArray::new();
+        let coil: Basic = inputs.make_actuator(Configuration::Tc3).unwrap();
+        let post: Hold<Basic> = inputs
+            .make_actuator(Configuration::Tcc0(pwm::Channel::_1))
+            .unwrap();
+        let (mut coil, mut post) = (coil.erased(), post.erased());
+        let (coil_log, post_log) = (RefCell::new(Vec::new()), RefCell::new(Vec::new()));
+        let mut coil_out = Recorder::new(&coil_log);
+        let mut post_out = Recorder::new(&post_log);
+        let mut controller: Controller<_, &mut dyn Evaluate, &mut dyn OutputDriver> =
+            Controller::new(Word16(0b11), inputs).with_max_on_time(OnTimeLimits::new(50, 200));
+        controller.register(&mut coil, &mut coil_out).unwrap();
+        controller.register(&mut post, &mut post_out).unwrap();
+
+        for now in 0..50 {
+            controller.tick_at(now).unwrap();
+        }
+        assert!(controller.faults().is_empty());
+        controller.tick_at(50).unwrap();
+        assert_eq!(controller.overtime(), 0b01);
+        assert_eq!(controller.faults(), Faults::OVERTIME);
+        assert_eq!(controller.fault_log().records()[0].timestamp, 50);
+
+        // Still held on, but never written on again until cleared. The hold-capable post
+        // has the longer limit.
+        for now in 51..=200 {
+            controller.tick_at(now).unwrap();
+        }
+        assert!(coil_log.borrow()[..50].iter().all(|&on| on));
+        assert!(coil_log.borrow()[50..].iter().all(|&on| !on));
+        assert!(post_log.borrow()[..200].iter().all(|&on| on));
+        assert!(!post_log.borrow()[200]);
+        assert_eq!(controller.overtime(), 0b11);
+
+        controller.source_mut().0 = 0;
+        controller.tick_at(201).unwrap();
+        controller.clear_overtime();
+        controller.source_mut().0 = 0b01;
+        controller.tick_at(202).unwrap();
+        assert!(coil_log.borrow()[202]);
+        assert!(controller.faults().is_empty());
+    }
+
+    #[test]
+    fn power_budget_defers_or_derates_low_priority_outputs() {
+        let channels = channels();
+        let mut controller = Controller::new(Word16(0b11), InputArray::new());
+        for &channel in [pwm::Channel::_0, pwm::Channel::_1].iter() {
+            let config = Configuration::Tcc1(channel);
+            let basic: Basic = controller.inputs_mut().make_actuator(config).unwrap();
+            controller
+                .register(basic.erased(), ChannelOutput::new(&channels, config))
+                .unwrap();
+        }
+        // Room for one coil at full duty, and the second actuator comes first.
+        let mut controller =
+            controller.with_power_budget(PowerBudget::full_duty(1, Overload::Defer), &[1]);
+
+        controller.tick().unwrap();
+        assert!(!channels.borrow().0[4].enabled);
+        assert_eq!(channels.borrow().0[5].duty_cycle, u32::MAX);
+
+        let budget = PowerBudget::new(u32::MAX as u64 * 3 / 2, Overload::Derate);
+        controller.set_power_budget(Some(budget), &[1]);
+        controller.tick().unwrap();
+        assert_eq!(channels.borrow().0[4].duty_cycle, u32::MAX / 2);
+        assert_eq!(channels.borrow().0[5].duty_cycle, u32::MAX);
+
+        // Without priorities, registration order decides.
+        controller.set_power_budget(Some(PowerBudget::full_duty(1, Overload::Defer)), &[]);
+        controller.tick().unwrap();
+        assert!(channels.borrow().0[4].enabled);
+        assert!(!channels.borrow().0[5].enabled);
+    }
+
+    #[test]
+    fn refresh_applies_only_the_masked_actuators() {
+        let channels = channels();
+        let mut controller = Controller::new(FlakyWord16(Some(0)), InputArray::new());
+        for &channel in [pwm::Channel::_0, pwm::Channel::_1].iter() {
+            let config = Configuration::Tcc1(channel);
+            let basic: Basic = controller.inputs_mut().make_actuator(config).unwrap();
+            controller
+                .register(basic.erased(), ChannelOutput::new(&channels, config))
+                .unwrap();
+        }
+        controller.tick().unwrap();
+
+        controller.inputs_mut().update(0b11);
+        controller.refresh(0b10);
+        assert!(!channels.borrow().0[4].enabled);
+        assert!(channels.borrow().0[5].enabled);
+
+        // Nothing comes on while failed reads hold the outputs off.
+        controller.source_mut().0 = None;
+        while !controller.is_failed() {
+            let _ = controller.tick();
+        }
+        controller.refresh(0b11);
+        assert!(!channels.borrow().0[4].enabled);
+        assert!(!channels.borrow().0[5].enabled);
+    }
+
+    #[test]
+    fn coil_limit_queues_or_rejects_activations() {
+        let logs = [
+            RefCell::new(Vec::new()),
+            RefCell::new(Vec::new()),
+            RefCell::new(Vec::new()),
+        ];
+        let mut controller = Controller::new(Word16(0b011), InputArray::new())
+            .with_coil_limit(CoilLimit::new(2, Overflow::Queue));
+        for log in logs.iter() {
+            let basic: Basic = controller
+                .inputs_mut()
+                .make_actuator(Configuration::Tc3)
+                .unwrap();
+            controller
+                .register(basic.erased(), Recorder::new(log))
+                .unwrap();
+        }
+        let on = || {
+            let last = |i: usize| *logs[i].borrow().last().unwrap();
+            [last(0), last(1), last(2)]
+        };
+
+        controller.tick_at(0).unwrap();
+        controller.source_mut().0 = 0b111;
+        controller.tick_at(1).unwrap();
+        assert_eq!(on(), [true, true, false]);
+        assert_eq!(controller.queued(), &[2]);
+        controller.source_mut().0 = 0b110;
+        controller.tick_at(2).unwrap();
+        assert_eq!(on(), [false, true, true]);
+        assert!(controller.queued().is_empty());
+
+        // Turned away, and stays off while its switch is held.
+        controller.set_coil_limit(Some(CoilLimit::new(2, Overflow::Reject)));
+        controller.source_mut().0 = 0b111;
+        controller.tick_at(3).unwrap();
+        controller.source_mut().0 = 0b101;
+        controller.tick_at(4).unwrap();
+        assert_eq!(on(), [false, false, true]);
+        controller.source_mut().0 = 0b100;
+        controller.tick_at(5).unwrap();
+        controller.source_mut().0 = 0b101;
+        controller.tick_at(6).unwrap();
+        assert_eq!(on(), [true, false, true]);
+        // Held back outputs were never written on, even for part of a tick.
+        assert_eq!(&logs[2].borrow()[..2], &[false, false]);
+        assert_eq!(&logs[0].borrow()[3..6], &[false, false, false]);
+
+        // A queued fire's pulse only starts once it's let on, and comes out whole.
+        controller.source_mut().0 = 0;
+        controller.tick_at(7).unwrap();
+        controller.set_coil_limit(Some(CoilLimit::new(1, Overflow::Queue)));
+        controller.source_mut().0 = 0b001;
+        controller.tick_at(8).unwrap();
+        let fire = Command::Fire {
+            id: 1,
+            strength: 100,
+            millis: 30,
+        };
+        controller.dispatch(fire).unwrap();
+        for now in 9..20 {
+            controller.tick_at(now).unwrap();
+        }
+        assert_eq!(controller.queued(), &[1]);
+        controller.source_mut().0 = 0;
+        for now in 20..60 {
+            controller.tick_at(now).unwrap();
+        }
+        let log = logs[1].borrow();
+        assert!(log[8..20].iter().all(|&on| !on));
+        assert!(log[20..49].iter().all(|&on| on));
+        assert!(log[49..].iter().all(|&on| !on));
+
+        // Outputs outside the high-power set are never held back.
+        drop(log);
+        controller.set_coil_limit(Some(CoilLimit::new(1, Overflow::Queue).high_power(0b001)));
+        controller.source_mut().0 = 0b111;
+        controller.tick_at(60).unwrap();
+        assert_eq!(on(), [true, true, true]);
+    }
+
+    #[test]
+    fn test_mode_times_out_to_normal_operation() {
+        let channels = channels();
+        let mut controller = on_tc3(&channels, Word16(0), |basic: Basic| basic.erased());
+        controller.tick_at(100).unwrap();
+
+        let set = |level| Command::SetInput { bit: 0, level };
+        assert_eq!(controller.dispatch(set(Some(true))), Err(Error::WrongMode));
+        controller
+            .dispatch(Command::TestMode {
+                timeout_ms: 1000,
+                max_strength: 25,
+            })
+            .unwrap();
+        assert!(controller.is_testing());
+
+        // A forced switch drives its actuator like the real one.
+        controller.dispatch(set(Some(true))).unwrap();
+        controller.tick_at(101).unwrap();
+        assert!(channels.borrow().0[12].enabled);
+        controller.dispatch(set(None)).unwrap();
+        controller.tick_at(102).unwrap();
+        assert!(!channels.borrow().0[12].enabled);
+
+        // Fires are held to the test strength.
+        controller
+            .dispatch(Command::Fire {
+                id: 0,
+                strength: 100,
+                millis: 30,
+            })
+            .unwrap();
+        controller.tick_at(103).unwrap();
+        assert_eq!(channels.borrow().0[12].duty_cycle, u32::MAX / 4);
+
+        // Each command keeps test mode alive; silence ends it and releases the input.
+        controller.dispatch(set(Some(true))).unwrap();
+        controller.tick_at(1102).unwrap();
+        assert!(controller.is_testing());
+        assert!(channels.borrow().0[12].enabled);
+        controller.tick_at(1103).unwrap();
+        assert!(!controller.is_testing());
+        assert_eq!(controller.inputs().override_mask(), 0);
+        controller.tick_at(1104).unwrap();
+        assert!(!channels.borrow().0[12].enabled);
+    }
+
+    #[test]
+    fn configure_command_retunes_between_ticks() {
+        let channels = channels();
+        // Button and EOS both closed: the flipper is holding.
+        let mut controller = on_tc3(&channels, Word16(0b11), |flipper: Flipper| {
+            flipper.cooldown(10).erased()
+        });
+        controller.tick().unwrap();
+        assert_eq!(channels.borrow().0[12].duty_cycle, u32::MAX / 4);
+
+        let configure = |id, param, value| Command::Configure { id, param, value };
+        controller
+            .dispatch(configure(0, Param::HoldDuty, 1000))
+            .unwrap();
+        controller
+            .dispatch(configure(0, Param::Cooldown, 20))
+            .unwrap();
+        assert_eq!(
+            controller.dispatch(configure(0, Param::PulseTime, 3)),
+            Err(Error::Unsupported)
+        );
+        assert_eq!(
+            controller.dispatch(configure(1, Param::HoldDuty, 0)),
+            Err(Error::InvalidMapping)
+        );
+        controller.tick().unwrap();
+        assert_eq!(
+            channels.borrow().0[12],
+            State {
+                enabled: true,
+                duty_cycle: 1000,
+            }
+        );
+
+        // Accepted settings are kept for saving; saving turns the outputs off first.
+        assert_eq!(controller.config().settings().len(), 2);
+        assert!(!controller.take_save_request());
+        controller.dispatch(Command::SaveConfig).unwrap();
+        assert!(!channels.borrow().0[12].enabled);
+        assert!(controller.take_save_request());
+        assert!(!controller.take_save_request());
+
+        // A board booting with the saved config comes up tuned the same way.
+        let saved = controller.config().clone();
+        let mut controller = on_tc3(&channels, Word16(0b11), |flipper: Flipper| {
+            flipper.cooldown(10).erased()
+        });
+        controller.apply_config(&saved).unwrap();
+        controller.tick().unwrap();
+        assert_eq!(channels.borrow().0[12].duty_cycle, 1000);
+
+        // With no room left to remember a new setting, the actuator isn't changed either.
+        for id in 100..130 {
+            controller.config.set(id, Param::KickDuty, 0).unwrap();
+        }
+        controller
+            .dispatch(configure(0, Param::HoldDuty, 2000))
+            .unwrap();
+        assert_eq!(
+            controller.dispatch(configure(0, Param::KickDuty, 3000)),
+            Err(Error::Storage)
+        );
+        controller.tick().unwrap();
+        assert_eq!(channels.borrow().0[12].duty_cycle, 2000);
+        controller.source_mut().0 = 0;
+        for _ in 0..25 {
+            controller.tick().unwrap();
+        }
+        controller.source_mut().0 = 0b01;
+        controller.tick().unwrap();
+        assert_eq!(channels.borrow().0[12].duty_cycle, u32::MAX);
+    }
+
+    #[test]
+    fn broadcasts_hold_every_output_off() {
+        let channels = channels();
+        let mut controller =
+            on_tc3(&channels, Word16(1), |basic: Basic| basic.erased()).with_address(2);
+        controller.tick().unwrap();
+        assert!(channels.borrow().0[12].enabled);
+
+        // Another board's frame is ignored; a broadcast identify isn't answered.
+        assert_eq!(controller.receive(3, Command::AllOff), Ok(None));
+        assert!(channels.borrow().0[12].enabled);
+        assert_eq!(
+            controller.receive(BROADCAST_ADDRESS, Command::Identify),
+            Ok(None)
+        );
+        assert!(controller.receive(2, Command::Identify).unwrap().is_some());
+
+        controller
+            .receive(BROADCAST_ADDRESS, Command::Attract { enabled: true })
+            .unwrap();
+        assert!(!channels.borrow().0[12].enabled);
+        controller.tick().unwrap();
+        assert!(!channels.borrow().0[12].enabled);
+
+        controller
+            .receive(BROADCAST_ADDRESS, Command::Attract { enabled: false })
+            .unwrap();
+        controller.tick().unwrap();
+        assert!(channels.borrow().0[12].enabled);
+        controller
+            .receive(BROADCAST_ADDRESS, Command::AllOff)
+            .unwrap();
+        assert!(!channels.borrow().0[12].enabled);
+
+        let enter = |key| Command::EnterBootloader { key };
+        assert_eq!(controller.dispatch(enter(0)), Err(Error::BadKey));
+        assert!(!controller.bootloader_requested());
+        controller.dispatch(enter(BOOTLOADER_KEY)).unwrap();
+        assert!(controller.bootloader_requested());
+        assert!(controller.is_attract());
+    }
+
+    #[test]
+    fn identify_describes_the_board() {
+        let channels = channels();
+        let mut controller =
+            on_tc3(&channels, Word16(0), |flipper: Flipper| flipper.erased()).with_address(2);
+
+        let identity = controller.identity();
+        assert_eq!(
+            controller.dispatch(Command::Identify),
+            Ok(Some(Reply::Identity(identity)))
+        );
+        assert_eq!(identity.address, 2);
+        assert_eq!(identity.actuators, 1);
+        assert_eq!(identity.inputs, 2);
+        assert_eq!(identity.input_width, 16);
+    }
+
+    #[test]
+    fn mixed_actuators_share_a_tick() {
+        let channels = channels();
+        let mut inputs = InputArray::new();
+        let basic: Basic = inputs.make_actuator(Configuration::Tc3).unwrap();
+        let both: FnActuator<DualInput> = inputs
+            .make_fn_actuator(
+                Configuration::Tcc0(pwm::Channel::_0),
+                |data: &InputData<DualInput>, _| State {
+                    enabled: data.is_input1_high() && data.is_input2_high(),
+                    duty_cycle: u32::MAX,
+                },
+            )
+            .unwrap();
+        let mut basic = basic.erased();
+        let mut both = both.erased();
+        let mut tc3 = ChannelOutput::new(&channels, Configuration::Tc3);
+        let mut tcc0 = ChannelOutput::new(&channels, Configuration::Tcc0(pwm::Channel::_0));
+
+        let mut controller: Controller<_, &mut dyn Evaluate, &mut dyn OutputDriver> =
+            Controller::new(Word16(0b011), inputs);
+        controller.register(&mut basic, &mut tc3).unwrap();
+        controller.register(&mut both, &mut tcc0).unwrap();
+
+        controller.tick().unwrap();
+        assert!(channels.borrow().0[12].enabled);
+        assert!(!channels.borrow().0[0].enabled);
+        controller.source_mut().0 = 0b110;
+        controller.tick().unwrap();
+        assert!(!channels.borrow().0[12].enabled);
+        assert!(channels.borrow().0[0].enabled);
+    }
+
+    #[test]
+    fn failed_reads_turn_outputs_off() {
+        let channels = channels();
+        let mut controller = on_tc3(&channels, FlakyWord16(Some(1)), |basic: Basic| {
+            basic.erased()
+        })
+        .with_failure_limit(2);
+
+        controller.tick().unwrap();
+        assert!(channels.borrow().0[12].enabled);
+
+        controller.source_mut().0 = None;
+        assert_eq!(controller.tick(), Err(Error::Bus));
+        assert!(channels.borrow().0[12].enabled);
+        assert_eq!(controller.tick(), Err(Error::Bus));
+        assert!(controller.is_failed());
+        assert!(!channels.borrow().0[12].enabled);
+
+        controller.source_mut().0 = Some(1);
+        controller.tick().unwrap();
+        assert!(!controller.is_failed());
+        assert!(channels.borrow().0[12].enabled);
+
+        let record = |timestamp, faults| FaultRecord { timestamp, faults };
+        assert_eq!(
+            controller.fault_log().records(),
+            &[
+                record(1, Faults::READ_FAILED),
+                record(1, Faults::READ_FAILED | Faults::OUTPUTS_OFF),
+                record(2, Faults::empty()),
+            ]
+        );
+    }
+
+    #[test]
+    fn composite_sources_stack_in_order() {
+        let mut remote = ControllerBuilder::new_remote::<u64>();
+        remote.push_word(0xFFAB);
+        let gpio = ControllerBuilder::new_gpio((Level(true), Level(false), Level(true)));
+        let mut controller = ControllerBuilder::new_composite((
+            (remote, 8),
+            (gpio, 3),
+            (ControllerBuilder::new_i2c(Companion(None), 0x42), 16),
+        ));
+
+        assert_eq!(controller.offset(1), Some(8));
+        assert_eq!(controller.offset(2), Some(11));
+        assert_eq!(controller.offset(3), None);
+        assert_eq!(controller.bits(), 27);
+        assert_eq!(
+            Controllable::<u32>::load_data(&mut controller),
+            Ok(0x0201 << 11 | 0b101 << 8 | 0xAB)
+        );
+
+        controller.sources_mut().2 .0 = ControllerBuilder::new_i2c(Companion(None), 0x43);
+        assert_eq!(
+            Controllable::<u32>::load_data(&mut controller),
+            Err(Error::Bus)
+        );
+    }
+
+    #[test]
+    fn scheduled_ticks_on_timer() {
+        let channels = channels();
+        let controller = on_tc3(&channels, Word16(1), |basic: Basic| basic.erased());
+
+        let mut scheduled = controller.run_at(Timer(Cell::new(0)), 1000u32);
+        assert_eq!(scheduled.on_tick(), Some(Ok(1)));
+        assert!(channels.borrow().0[12].enabled);
+        assert_eq!(scheduled.on_tick(), None);
+        assert_eq!(scheduled.controller().ticks(), 1);
+    }
+
+    struct Fed(u32);
+
+    impl Watchdog for Fed {
+        fn feed(&mut self) {
+            self.0 += 1;
+        }
+    }
+
+    #[test]
+    fn watchdog_fed_only_by_good_cycles() {
+        let mut controller: Controller<
+            _,
+            Erased<SingleInput, Basic>,
+            ChannelOutput<Channels>,
+            _,
+            _,
+        > = Controller::new(FlakyWord16(Some(0)), InputArray::new())
+            .with_failure_limit(1)
+            .with_watchdog(Fed(0));
+
+        controller.tick().unwrap();
+        assert_eq!(controller.watchdog_mut().0, 1);
+        controller.source_mut().0 = None;
+        assert!(controller.tick().is_err());
+        assert_eq!(controller.watchdog_mut().0, 1);
+        controller.source_mut().0 = Some(0);
+        assert_eq!(controller.poll(), Ok(0));
+        assert_eq!(controller.watchdog_mut().0, 2);
+    }
+
+    #[test]
+    fn snapshot_round_trips() {
+        let channels = channels();
+        let mut controller = Controller::new(FlakyWord16(Some(0x8001)), InputArray::new());
+        for &config in [Configuration::Tc3, Configuration::Tcc0(pwm::Channel::_1)].iter() {
+            let basic: Basic = controller.inputs_mut().make_actuator(config).unwrap();
+            controller
+                .register(basic.erased(), ChannelOutput::new(&channels, config))
+                .unwrap();
+        }
+        controller.tick().unwrap();
+        controller.source_mut().0 = None;
+        assert!(controller.tick().is_err());
+
+        let snapshot = controller.snapshot();
+        assert_eq!(snapshot.ticks, 2);
+        assert_eq!(snapshot.raw, 0x8001);
+        assert_eq!(snapshot.faults, Faults::READ_FAILED);
+        assert!(snapshot.states[0].enabled);
+        assert!(!snapshot.states[1].enabled);
+
+        let bytes = snapshot.to_bytes();
+        assert_eq!(bytes.len(), 25);
+        assert_eq!(Snapshot::from_bytes(&bytes), Some(snapshot));
+        assert_eq!(Snapshot::from_bytes(&bytes[..24]), None);
+    }
+}
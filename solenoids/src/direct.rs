@@ -0,0 +1,141 @@
+use feather_m0 as hal;
+use hal::{
+    clock::GenericClockController,
+    gpio::Port,
+    pac::{EIC, PORT},
+};
+
+use crate::{InputArray, Word};
+
+// EIC.CONFIG sense and filter settings, one nibble per line.
+const SENSE_BOTH: u32 = 0x3;
+const FILTEN: u32 = 0x8;
+const CTRL_ENABLE: u8 = 1 << 1;
+const STATUS_SYNCBUSY: u8 = 1 << 7;
+
+// PORT.WRCONFIG fields.
+const WRCONFIG_PMUXEN: u32 = 1 << 16;
+const WRCONFIG_INEN: u32 = 1 << 17;
+const WRCONFIG_PULLEN: u32 = 1 << 18;
+const WRCONFIG_WRPMUX: u32 = 1 << 28;
+const WRCONFIG_WRPINCFG: u32 = 1 << 30;
+const WRCONFIG_HWSEL: u32 = 1 << 31;
+
+/// A button wired straight to an EXTINT-capable PA pin, to ground, using the internal
+/// pull-up. A pressed button reads as a high input bit.
+#[derive(Clone, Copy, Debug)]
+pub struct Line {
+    /// PA pin number, e.g. 16 for PA16.
+    pub pin: u8,
+    /// The EXTINT line the pin is wired to.
+    pub extint: u8,
+    /// Bit of the `InputArray` word the button is fed into.
+    pub bit: u8,
+}
+
+/// DirectInputs routes two flipper buttons through the EIC instead of the shift register
+/// scan, which is the single biggest latency win for flippers.
+///
+/// Each line senses both edges with the EIC's majority filter enabled. The application
+/// binds the EIC interrupt at a priority above the bus and scan tasks and, in it, calls
+/// `handle`, updates the flipper actuators and writes their duty. That path is a handful
+/// of register accesses plus the actuator update, so a press reaches the coil well under a
+/// millisecond after the filter settles, independent of the scan rate.
+pub struct DirectInputs {
+    eic: EIC,
+    lines: [Line; 2],
+}
+
+impl DirectInputs {
+    /// Configures the pins, the EIC clock and both lines, and claims the lines' bits in
+    /// `inputs` so the bulk scan no longer overwrites them. `_port` is only taken to show
+    /// the pins are no longer in use elsewhere.
+    pub fn new<W: Word>(
+        clocks: &mut GenericClockController,
+        eic: EIC,
+        _port: &mut Port,
+        inputs: &mut InputArray<W>,
+        left: Line,
+        right: Line,
+    ) -> Self {
+        let gclk0 = clocks.gclk0();
+        clocks.eic(&gclk0).unwrap();
+
+        let lines = [left, right];
+        let port = unsafe { &*PORT::ptr() };
+        for line in lines.iter() {
+            let hwsel = if line.pin >= 16 { WRCONFIG_HWSEL } else { 0 };
+            // Peripheral function A (EIC) with input and pull enabled; OUT selects pull-up.
+            port.outset0.write(|w| unsafe { w.bits(1 << line.pin) });
+            port.wrconfig0.write(|w| unsafe {
+                w.bits(
+                    hwsel
+                        | WRCONFIG_WRPINCFG
+                        | WRCONFIG_WRPMUX
+                        | WRCONFIG_PMUXEN
+                        | WRCONFIG_INEN
+                        | WRCONFIG_PULLEN
+                        | (1 << (line.pin % 16)),
+                )
+            });
+
+            let shift = (line.extint as u32 % 8) * 4;
+            eic.config[line.extint as usize / 8].modify(|r, w| unsafe {
+                w.bits(r.bits() & !(0xF << shift) | (SENSE_BOTH | FILTEN) << shift)
+            });
+        }
+
+        let direct = Self { eic, lines };
+        direct
+            .eic
+            .intenset
+            .write(|w| unsafe { w.bits(direct.extint_mask()) });
+        direct
+            .eic
+            .ctrl
+            .modify(|r, w| unsafe { w.bits(r.bits() | CTRL_ENABLE) });
+        while direct.eic.status.read().bits() & STATUS_SYNCBUSY != 0 {}
+
+        inputs.claim_direct(direct.input_mask());
+        direct.sample(inputs);
+        direct
+    }
+
+    pub fn lines(&self) -> &[Line; 2] {
+        &self.lines
+    }
+
+    /// Bits of the input word driven by these lines.
+    pub fn input_mask<W: Word>(&self) -> W {
+        self.lines.iter().fold(W::ZERO, |m, l| m | W::bit(l.bit))
+    }
+
+    fn extint_mask(&self) -> u32 {
+        self.lines.iter().fold(0, |m, l| m | 1 << l.extint)
+    }
+
+    /// Call from the EIC interrupt. Clears this path's interrupt flags and copies the
+    /// button levels into `inputs`. Returns false if neither line had fired.
+    pub fn handle<W: Word>(&mut self, inputs: &mut InputArray<W>) -> bool {
+        let flags = self.eic.intflag.read().bits() & self.extint_mask();
+        self.eic.intflag.write(|w| unsafe { w.bits(flags) });
+        self.sample(inputs);
+        flags != 0
+    }
+
+    /// Copies the current button levels into `inputs` without touching the interrupt
+    /// flags.
+    pub fn sample<W: Word>(&self, inputs: &mut InputArray<W>) {
+        let levels = unsafe { (*PORT::ptr()).in0.read().bits() };
+        let pressed = self
+            .lines
+            .iter()
+            .filter(|l| levels & (1 << l.pin) == 0)
+            .fold(W::ZERO, |m, l| m | W::bit(l.bit));
+        inputs.set_direct(self.input_mask(), pressed);
+    }
+
+    pub fn free(self) -> EIC {
+        self.eic
+    }
+}
@@ -0,0 +1,60 @@
+use feather_m0 as hal;
+use hal::pac::dmac;
+
+// DMA channels are split between users so they can share the one descriptor table.
+/// Channels used by `pwm::waveform::Waveforms`.
+pub const WAVEFORM_CHANNELS: usize = 4;
+/// Channels used by DMA input reads: one receiving the chain, one clocking it out.
+pub const INPUT_RX_CHANNEL: u8 = 4;
+pub const INPUT_TX_CHANNEL: u8 = 5;
+const CHANNELS: usize = 6;
+
+const CTRL_DMAENABLE: u16 = 1 << 1;
+const CTRL_LVLEN_ALL: u16 = 0xF << 8;
+
+#[derive(Clone, Copy)]
+#[repr(C, align(16))]
+pub struct Descriptor {
+    pub btctrl: u16,
+    pub btcnt: u16,
+    pub srcaddr: u32,
+    pub dstaddr: u32,
+    pub descaddr: u32,
+}
+
+const EMPTY: Descriptor = Descriptor {
+    btctrl: 0,
+    btcnt: 0,
+    srcaddr: 0,
+    dstaddr: 0,
+    descaddr: 0,
+};
+
+// The DMAC reads the first descriptor of each channel from BASEADDR and writes its
+// progress to WRBADDR; both must be 16-byte aligned and live as long as it runs.
+static mut DESCRIPTORS: [Descriptor; CHANNELS] = [EMPTY; CHANNELS];
+static mut WRITEBACK: [Descriptor; CHANNELS] = [EMPTY; CHANNELS];
+
+/// Points the DMAC at the descriptor table and enables it, unless a previous user
+/// already has.
+pub fn enable(dmac: &dmac::RegisterBlock) {
+    if dmac.ctrl.read().bits() & CTRL_DMAENABLE != 0 {
+        return;
+    }
+    unsafe {
+        dmac.baseaddr
+            .write(|w| w.bits(core::ptr::addr_of!(DESCRIPTORS) as u32));
+        dmac.wrbaddr
+            .write(|w| w.bits(core::ptr::addr_of!(WRITEBACK) as u32));
+        dmac.ctrl.write(|w| w.bits(CTRL_DMAENABLE | CTRL_LVLEN_ALL));
+    }
+}
+
+/// The first descriptor of `channel`.
+///
+/// # Safety
+///
+/// The channel must be disabled, and only its owner may touch it.
+pub unsafe fn descriptor(channel: usize) -> &'static mut Descriptor {
+    &mut (*core::ptr::addr_of_mut!(DESCRIPTORS))[channel]
+}
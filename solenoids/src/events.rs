@@ -0,0 +1,127 @@
+use heapless::{consts::*, Vec};
+
+use crate::{Edge, InputArray, InputEvent, Word};
+
+/// First byte of every switch event frame.
+pub const EVENT_FRAME_ID: u8 = 0x45;
+
+/// Most events carried by one frame.
+pub const EVENTS_PER_FRAME: usize = 8;
+
+// ID, missed count and event count, then 6 bytes per event.
+const EVENT_HEADER: usize = 3;
+const EVENT_SIZE: usize = 6;
+
+/// Debounced switch transitions pushed to the master, so game logic reacts to a switch as
+/// soon as the board has committed it instead of waiting to poll. Build one with `drain`
+/// after each update and send it whenever it comes back `Some`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EventFrame {
+    /// `InputArray::missed_events`, saturated to a byte. A change between frames tells the
+    /// master it lost transitions and should re-read the switches.
+    pub missed: u8,
+    pub events: Vec<InputEvent, U8>,
+}
+
+impl EventFrame {
+    /// Takes up to `EVENTS_PER_FRAME` queued events, oldest first, or `None` if there are
+    /// none. Anything left over goes in the next frame.
+    pub fn drain<W: Word>(inputs: &mut InputArray<W>) -> Option<Self> {
+        let mut events = Vec::new();
+        while events.len() < EVENTS_PER_FRAME {
+            match inputs.pop_event() {
+                Some(event) => {
+                    let _ = events.push(event);
+                }
+                None => break,
+            }
+        }
+        if events.is_empty() {
+            return None;
+        }
+        Some(Self {
+            missed: inputs.missed_events().min(u8::MAX as u32) as u8,
+            events,
+        })
+    }
+
+    /// Little-endian frame: ID, missed count, event count, then bit, edge (1 rising, 0
+    /// falling) and timestamp for each event. The sample count stays on the board.
+    pub fn to_bytes(&self) -> Vec<u8, U64> {
+        let mut bytes = Vec::new();
+        let _ = bytes.extend_from_slice(&[EVENT_FRAME_ID, self.missed, self.events.len() as u8]);
+        for event in self.events.iter() {
+            let _ = bytes.push(event.bit);
+            let _ = bytes.push((event.edge == Edge::Rising) as u8);
+            let _ = bytes.extend_from_slice(&event.timestamp.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Parses a frame; events come back with a `sample` of zero.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < EVENT_HEADER || bytes[0] != EVENT_FRAME_ID {
+            return None;
+        }
+        let count = bytes[2] as usize;
+        if count > EVENTS_PER_FRAME || bytes.len() != EVENT_HEADER + EVENT_SIZE * count {
+            return None;
+        }
+
+        let mut events = Vec::new();
+        for entry in bytes[EVENT_HEADER..].chunks(EVENT_SIZE) {
+            let edge = match entry[1] {
+                0 => Edge::Falling,
+                1 => Edge::Rising,
+                _ => return None,
+            };
+            let mut timestamp = [0; 4];
+            timestamp.copy_from_slice(&entry[2..]);
+            let _ = events.push(InputEvent {
+                bit: entry[0],
+                edge,
+                sample: 0,
+                timestamp: u32::from_le_bytes(timestamp),
+            });
+        }
+        Some(Self {
+            missed: bytes[1],
+            events,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::events::{EventFrame, EVENTS_PER_FRAME};
+    use crate::{Edge, InputArray};
+
+    #[test]
+    fn transitions_are_pushed_in_frames() {
+        let mut inputs = InputArray::new();
+        assert_eq!(EventFrame::drain(&mut inputs), None);
+
+        for i in 0..10u32 {
+            inputs.update_at((i & 1) as u16, 1000 + i);
+        }
+        let frame = EventFrame::drain(&mut inputs).unwrap();
+        assert_eq!(frame.events.len(), EVENTS_PER_FRAME);
+        assert_eq!(frame.missed, 0);
+        assert_eq!(frame.events[0].edge, Edge::Rising);
+        assert_eq!(frame.events[0].timestamp, 1001);
+
+        let parsed = EventFrame::from_bytes(&frame.to_bytes()).unwrap();
+        assert_eq!(parsed.events.len(), EVENTS_PER_FRAME);
+        for (sent, received) in frame.events.iter().zip(parsed.events.iter()) {
+            assert_eq!((sent.bit, sent.edge), (received.bit, received.edge));
+            assert_eq!(sent.timestamp, received.timestamp);
+        }
+
+        // The ninth transition follows in its own frame.
+        let rest = EventFrame::drain(&mut inputs).unwrap();
+        assert_eq!(rest.events.len(), 1);
+        assert_eq!(rest.events[0].timestamp, 1009);
+        assert_eq!(EventFrame::drain(&mut inputs), None);
+        assert_eq!(EventFrame::from_bytes(&rest.to_bytes()[..8]), None);
+    }
+}
@@ -0,0 +1,172 @@
+use core::convert::TryFrom;
+use core::marker::PhantomData;
+use embedded_hal::PwmPin;
+
+use crate::pwm::{self, State};
+use crate::{Actuator, InputArray, InputData, InputType, Word};
+
+/// PinExecutor drives an `embedded_hal::PwmPin` from an actuator, so actuator logic can be
+/// reused on any HAL, or on outputs that aren't SAMD21 timer channels (`output::DigitalPin`,
+/// expander PWM, ...). The actuator's `pwm_config` is ignored; the pin is the output.
+pub struct PinExecutor<I, A, P>
+where
+    I: InputType,
+    A: Actuator<I>,
+    P: PwmPin,
+{
+    actuator: A,
+    pin: P,
+    state: State,
+    slew_limit: u32,
+    _type: PhantomData<I>,
+}
+
+impl<I, A, P> PinExecutor<I, A, P>
+where
+    I: InputType,
+    A: Actuator<I>,
+    P: PwmPin,
+    P::Duty: Copy + Into<u32> + TryFrom<u32>,
+{
+    /// Takes `pin` and turns it off.
+    pub fn new(actuator: A, mut pin: P) -> Self {
+        let state = State {
+            enabled: false,
+            duty_cycle: 0,
+        };
+        pwm::drive(&mut pin, &state);
+        Self {
+            actuator,
+            pin,
+            state,
+            slew_limit: u32::MAX,
+            _type: PhantomData,
+        }
+    }
+
+    /// Limits how far the duty may move per update, see `pwm::Controller::set_slew_limit`.
+    pub fn with_slew_limit(mut self, max_step: u32) -> Self {
+        self.slew_limit = max_step;
+        self
+    }
+
+    /// Updates the actuator from `data` and drives the pin with the result, slew limited.
+    pub fn update(&mut self, data: &InputData<I>) -> State {
+        let next = self.actuator.update_state(data, self.state);
+        self.state = next.slewed(&self.state, self.slew_limit);
+        pwm::drive(&mut self.pin, &self.state);
+        self.state
+    }
+
+    /// Reads the actuator's input from `inputs`, then behaves like `update`.
+    pub fn run<W: Word>(&mut self, inputs: &InputArray<W>) -> State {
+        let data = inputs.read(self.actuator.input_config());
+        self.update(&data)
+    }
+
+    /// The state last written to the pin.
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn pin(&self) -> &P {
+        &self.pin
+    }
+
+    pub fn actuator(&self) -> &A {
+        &self.actuator
+    }
+
+    pub fn actuator_mut(&mut self) -> &mut A {
+        &mut self.actuator
+    }
+
+    /// Turns the pin off and hands back both halves.
+    pub fn free(mut self) -> (A, P) {
+        self.state.enabled = false;
+        pwm::drive(&mut self.pin, &self.state);
+        (self.actuator, self.pin)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::actuators::Basic;
+    use crate::executor::PinExecutor;
+    use crate::pwm::Configuration;
+    use crate::InputArray;
+    use embedded_hal::PwmPin;
+
+    struct Pin {
+        enabled: bool,
+        duty: u16,
+    }
+
+    impl PwmPin for Pin {
+        type Duty = u16;
+
+        fn disable(&mut self) {
+            self.enabled = false;
+        }
+
+        fn enable(&mut self) {
+            self.enabled = true;
+        }
+
+        fn get_duty(&self) -> u16 {
+            self.duty
+        }
+
+        fn get_max_duty(&self) -> u16 {
+            0x3FF
+        }
+
+        fn set_duty(&mut self, duty: u16) {
+            self.duty = duty;
+        }
+    }
+
+    #[test]
+    fn drives_pin_from_actuator() {
+        let mut inputs = InputArray::new();
+        let basic: Basic = inputs.make_actuator(Configuration::Tc3).unwrap();
+        let pin = Pin {
+            enabled: true,
+            duty: 5,
+        };
+        let mut executor = PinExecutor::new(basic, pin);
+
+        assert!(!executor.pin().enabled);
+        assert_eq!(executor.pin().duty, 0);
+
+        inputs.update(0b1);
+        assert!(executor.run(&inputs).enabled);
+        assert!(executor.pin().enabled);
+        assert_eq!(executor.pin().duty, 0x3FF);
+
+        let (_, pin) = executor.free();
+        assert!(!pin.enabled);
+        assert_eq!(pin.duty, 0);
+    }
+
+    #[test]
+    fn slew_limit_spreads_turn_on() {
+        let mut inputs = InputArray::new();
+        let basic: Basic = inputs.make_actuator(Configuration::Tc3).unwrap();
+        let pin = Pin {
+            enabled: false,
+            duty: 0,
+        };
+        let mut executor = PinExecutor::new(basic, pin).with_slew_limit(u32::MAX / 2 + 1);
+
+        inputs.update(0b1);
+        executor.run(&inputs);
+        assert_eq!(executor.pin().duty, 0x200);
+        inputs.update(0b1);
+        executor.run(&inputs);
+        assert_eq!(executor.pin().duty, 0x3FF);
+        inputs.update(0b0);
+        executor.run(&inputs);
+        assert!(!executor.pin().enabled);
+    }
+}
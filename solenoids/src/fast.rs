@@ -0,0 +1,435 @@
+use core::fmt::Write;
+use embedded_hal::watchdog::Watchdog;
+use heapless::{consts::*, String, Vec};
+
+use crate::command::{Command, Identity};
+use crate::console::{Transport, DEFAULT_FIRE_MILLIS};
+use crate::controller::{Controllable, Controller, Evaluate};
+use crate::output::OutputDriver;
+use crate::time::Instant;
+use crate::Word;
+
+/// Most drivers the adapter tracks, as many as a `Controller` can register.
+pub const MAX_DRIVERS: usize = 16;
+
+/// How a driver behaves when triggered, set with `DL:`. Times are milliseconds and powers
+/// run from 00 (off) to FF (full), all in hex as on the wire.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DriverMode {
+    /// Mode 10: pulse for `millis` at `power`. Enabled, it stays on at `power`.
+    Pulse { millis: u8, power: u8 },
+    /// Mode 18: pulse for `millis` at `power`, then hold at `hold_power` while enabled.
+    PulseHold {
+        millis: u8,
+        power: u8,
+        hold_power: u8,
+    },
+    /// Mode 20: while enabled, on for `on_millis` at `power`, then off for `off_millis`.
+    Patter {
+        on_millis: u8,
+        off_millis: u8,
+        power: u8,
+    },
+}
+
+/// What `TL:` asks of a driver.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trigger {
+    /// 00: hand the driver back to its switch, ending any manual drive.
+    Auto,
+    /// 01: one pulse, as configured.
+    Pulse,
+    /// 02: off.
+    Off,
+    /// 03: on until turned off, as configured.
+    On,
+}
+
+/// A command line from the host. Numbers are hex, fields are separated by commas and
+/// lines end with a carriage return.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Request {
+    /// `ID:` - answered with `ID:SOL <version>`.
+    Id,
+    /// `DL:<driver>,<control>,<switch>,<mode>,<p1>,<p2>,<p3>`. The control and switch
+    /// fields are accepted but unused: this board wires switches to drivers itself.
+    Configure { driver: u8, mode: DriverMode },
+    /// `TL:<driver>,<control>`.
+    Trigger { driver: u8, trigger: Trigger },
+}
+
+impl Request {
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.trim().splitn(2, ':');
+        let name = parts.next()?;
+        let mut fields = parts.next()?.split(',').filter(|f| !f.is_empty());
+        let mut hex = || u8::from_str_radix(fields.next()?.trim(), 16).ok();
+        let request = match name {
+            "ID" => Request::Id,
+            "DL" => {
+                let driver = hex()?;
+                let (_control, _switch) = (hex()?, hex()?);
+                let mode = hex()?;
+                let (p1, p2, p3) = (hex().unwrap_or(0), hex().unwrap_or(0), hex().unwrap_or(0));
+                let mode = match mode {
+                    0x10 => DriverMode::Pulse {
+                        millis: p1,
+                        power: p2,
+                    },
+                    0x18 => DriverMode::PulseHold {
+                        millis: p1,
+                        power: p2,
+                        hold_power: p3,
+                    },
+                    0x20 => DriverMode::Patter {
+                        on_millis: p1,
+                        off_millis: p2,
+                        power: p3,
+                    },
+                    _ => return None,
+                };
+                Request::Configure { driver, mode }
+            }
+            "TL" => Request::Trigger {
+                driver: hex()?,
+                trigger: match hex()? {
+                    0x00 => Trigger::Auto,
+                    0x01 => Trigger::Pulse,
+                    0x02 => Trigger::Off,
+                    0x03 => Trigger::On,
+                    _ => return None,
+                },
+            },
+            _ => return None,
+        };
+        Some(request)
+    }
+}
+
+// A driver's configuration and what it's doing now.
+#[derive(Clone, Copy)]
+struct Driver {
+    mode: DriverMode,
+    // While pattering, when the next fire is due.
+    next: Option<u32>,
+}
+
+impl Default for Driver {
+    fn default() -> Self {
+        Self {
+            mode: DriverMode::Pulse {
+                millis: DEFAULT_FIRE_MILLIS as u8,
+                power: 0xFF,
+            },
+            next: None,
+        }
+    }
+}
+
+// A FAST power byte as a `Command::Fire` strength.
+fn strength(power: u8) -> u8 {
+    ((power as u16 * 100 + 127) / 255) as u8
+}
+
+fn fire(driver: u8, millis: u32, power: u8) -> Command {
+    Command::Fire {
+        id: driver,
+        strength: strength(power),
+        millis: millis.min(u16::MAX as u32) as u16,
+    }
+}
+
+/// Fast lets host software written for FAST-style serial controllers, such as a game
+/// framework's FAST platform, drive the board: it answers a subset of that ASCII protocol
+/// with driver configuration (`DL:`) and triggers (`TL:`) for pulse, enable and patter.
+/// Every driver command goes through the controller as a `Command::Fire`, or as an
+/// `enable` while enabled, so on-time limits and faults still apply.
+///
+/// Call `serve` for each line and `poll` every tick, which runs patter. Drivers are numbered
+/// in registration order. Each command is answered with `XX:P` when carried out or `XX:F`
+/// when not.
+pub struct Fast<T: Transport> {
+    transport: T,
+    line: Vec<u8, U64>,
+    drivers: [Driver; MAX_DRIVERS],
+}
+
+impl<T: Transport> Fast<T> {
+    pub fn new(transport: T) -> Self {
+        Self {
+            transport,
+            line: Vec::new(),
+            drivers: [Driver::default(); MAX_DRIVERS],
+        }
+    }
+
+    pub fn transport_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    pub fn free(self) -> T {
+        self.transport
+    }
+
+    /// Reads a line and carries it out on `controller`. Returns `WouldBlock` until a whole
+    /// line has arrived. Unlike the console nothing is echoed, and replies the transport
+    /// doesn't take are dropped.
+    pub fn serve<S, A, D, W, WD>(
+        &mut self,
+        controller: &mut Controller<S, A, D, W, WD>,
+    ) -> nb::Result<(), T::Error>
+    where
+        S: Controllable<W>,
+        A: Evaluate<W>,
+        D: OutputDriver,
+        W: Word,
+        WD: Watchdog,
+    {
+        let line = loop {
+            match self.transport.read()? {
+                b'\r' | b'\n' => {
+                    let line = core::str::from_utf8(&self.line)
+                        .ok()
+                        .map(String::<U64>::from);
+                    self.line.clear();
+                    match line {
+                        Some(line) if !line.trim().is_empty() => break line,
+                        _ => {}
+                    }
+                }
+                byte => {
+                    let _ = self.line.push(byte);
+                }
+            }
+        };
+
+        let name = line.trim().get(..2).unwrap_or("XX");
+        let mut reply: String<U32> = String::new();
+        let _ = match Request::parse(&line) {
+            Some(Request::Id) => write!(reply, "ID:SOL {}", version()),
+            Some(request) => {
+                let passed = self.carry_out(request, controller).is_ok();
+                write!(reply, "{}:{}", name, if passed { "P" } else { "F" })
+            }
+            None => write!(reply, "{}:F", name),
+        };
+        let _ = reply.push('\r');
+        self.transport
+            .write(reply.as_bytes())
+            .map_err(nb::Error::Other)?;
+        Ok(())
+    }
+
+    fn carry_out<S, A, D, W, WD>(
+        &mut self,
+        request: Request,
+        controller: &mut Controller<S, A, D, W, WD>,
+    ) -> Result<(), crate::Error>
+    where
+        S: Controllable<W>,
+        A: Evaluate<W>,
+        D: OutputDriver,
+        W: Word,
+        WD: Watchdog,
+    {
+        let now = controller.inputs().timestamp();
+        match request {
+            Request::Id => Ok(()),
+            Request::Configure { driver, mode } => {
+                let slot = self
+                    .drivers
+                    .get_mut(driver as usize)
+                    .ok_or(crate::Error::InvalidMapping)?;
+                slot.mode = mode;
+                Ok(())
+            }
+            Request::Trigger { driver, trigger } => {
+                let slot = self
+                    .drivers
+                    .get_mut(driver as usize)
+                    .ok_or(crate::Error::InvalidMapping)?;
+                slot.next = None;
+                let command = match (trigger, slot.mode) {
+                    (Trigger::Auto, _) | (Trigger::Off, _) => fire(driver, 0, 0),
+                    (Trigger::Pulse, DriverMode::Pulse { millis, power })
+                    | (Trigger::Pulse, DriverMode::PulseHold { millis, power, .. }) => {
+                        fire(driver, millis as u32, power)
+                    }
+                    (
+                        Trigger::Pulse,
+                        DriverMode::Patter {
+                            on_millis, power, ..
+                        },
+                    ) => fire(driver, on_millis as u32, power),
+                    (Trigger::On, DriverMode::Pulse { power, .. }) => {
+                        return controller.enable(driver as usize, strength(power));
+                    }
+                    (
+                        Trigger::On,
+                        DriverMode::PulseHold {
+                            millis,
+                            power,
+                            hold_power,
+                        },
+                    ) => {
+                        return controller.enable_with_hold(
+                            driver as usize,
+                            millis as u32,
+                            strength(power),
+                            strength(hold_power),
+                        );
+                    }
+                    (Trigger::On, DriverMode::Patter { .. }) => {
+                        slot.next = Some(now);
+                        return self.patter(driver as usize, now, controller);
+                    }
+                };
+                controller.dispatch(command).map(|_| ())
+            }
+        }
+    }
+
+    /// Runs patter on the drivers enabled in that mode. Call every tick.
+    pub fn poll<S, A, D, W, WD>(&mut self, controller: &mut Controller<S, A, D, W, WD>)
+    where
+        S: Controllable<W>,
+        A: Evaluate<W>,
+        D: OutputDriver,
+        W: Word,
+        WD: Watchdog,
+    {
+        let now = controller.inputs().timestamp();
+        for index in 0..MAX_DRIVERS {
+            let due = match self.drivers[index].next {
+                Some(next) => Instant::from_millis(now) >= Instant::from_millis(next),
+                None => false,
+            };
+            if due {
+                let _ = self.patter(index, now, controller);
+            }
+        }
+    }
+
+    // Issues a pattering driver's next fire and works out when the one after is due.
+    fn patter<S, A, D, W, WD>(
+        &mut self,
+        index: usize,
+        now: u32,
+        controller: &mut Controller<S, A, D, W, WD>,
+    ) -> Result<(), crate::Error>
+    where
+        S: Controllable<W>,
+        A: Evaluate<W>,
+        D: OutputDriver,
+        W: Word,
+        WD: Watchdog,
+    {
+        let driver = &mut self.drivers[index];
+        let (command, wait) = match driver.mode {
+            DriverMode::Patter {
+                on_millis,
+                off_millis,
+                power,
+            } => (
+                fire(index as u8, on_millis as u32, power),
+                (on_millis as u32 + off_millis as u32).max(1),
+            ),
+            _ => {
+                // Reconfigured out of patter since it was enabled.
+                driver.next = None;
+                return Ok(());
+            }
+        };
+        driver.next = Some(now.wrapping_add(wait));
+        let result = controller.dispatch(command);
+        if result.is_err() {
+            // Nothing to keep going, e.g. no such actuator.
+            self.drivers[index].next = None;
+        }
+        result.map(|_| ())
+    }
+}
+
+fn version() -> String<U16> {
+    let [major, minor, patch] = Identity::firmware_version();
+    let mut version = String::new();
+    let _ = write!(version, "{}.{}.{}", major, minor, patch);
+    version
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fast::{DriverMode, Fast, Request, Trigger};
+    use crate::testing::{basic_controller, is_on, Loopback};
+    use heapless::{consts::*, Vec};
+
+    #[test]
+    fn parses_driver_commands() {
+        assert_eq!(
+            Request::parse("DL:3,81,00,18,1A,FF,40\r"),
+            Some(Request::Configure {
+                driver: 3,
+                mode: DriverMode::PulseHold {
+                    millis: 0x1A,
+                    power: 0xFF,
+                    hold_power: 0x40,
+                },
+            })
+        );
+        assert_eq!(
+            Request::parse("TL:0A,03"),
+            Some(Request::Trigger {
+                driver: 10,
+                trigger: Trigger::On,
+            })
+        );
+        assert_eq!(Request::parse("ID:"), Some(Request::Id));
+        assert_eq!(Request::parse("DL:3,81,00,99"), None);
+        assert_eq!(Request::parse("TL:3"), None);
+        assert_eq!(Request::parse("WD:1"), None);
+    }
+
+    #[test]
+    fn drives_pulses_holds_and_patter() {
+        let mut controller = basic_controller();
+        controller.tick_at(0).unwrap();
+
+        let mut fast = Fast::new(Loopback::new(
+            b"DL:0,01,00,18,0A,FF,80\rTL:0,03\rTL:5,01\rDL:0,01,00,20,05,0F,FF\rTL:0,03\r",
+        ));
+
+        // Pulse then hold: full power for 10ms, then half.
+        fast.serve(&mut controller).unwrap();
+        fast.serve(&mut controller).unwrap();
+        controller.tick_at(1).unwrap();
+        assert!(is_on(&controller));
+        for now in 2..=12 {
+            fast.poll(&mut controller);
+            controller.tick_at(now).unwrap();
+        }
+        assert!(controller.is_firing(0));
+        assert_eq!(controller.actuators()[0].1 .0.duty_cycle, u32::MAX / 2);
+
+        // A driver that isn't there fails.
+        fast.serve(&mut controller).unwrap();
+
+        // Patter: 5ms on, 15ms off.
+        fast.serve(&mut controller).unwrap();
+        fast.serve(&mut controller).unwrap();
+        let mut rising: Vec<u32, U4> = Vec::new();
+        let mut was_on = is_on(&controller);
+        for now in 13..60 {
+            fast.poll(&mut controller);
+            controller.tick_at(now).unwrap();
+            if is_on(&controller) && !was_on {
+                rising.push(now).unwrap();
+            }
+            was_on = is_on(&controller);
+        }
+        // The hold carries straight into the first patter pulse; then one every 20ms.
+        assert_eq!(&rising[..], [33, 53]);
+
+        let replies = core::str::from_utf8(&fast.transport_mut().tx).unwrap();
+        assert_eq!(replies, "DL:P\rTL:P\rTL:F\rDL:P\rTL:P\r");
+    }
+}
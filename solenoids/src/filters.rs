@@ -0,0 +1,186 @@
+use crate::time::{Duration, Instant};
+use crate::{InputArray, InputData, InputType, Word};
+
+/// DoubleTap reports when input 1 of an input is activated twice within `window`, measured
+/// in the units of the input timestamps (update cycles when using `InputArray::update`).
+/// A detected double-tap starts a fresh sequence, so a third quick tap doesn't count again.
+///
+/// This is meant for secondary actions on a single button, e.g. tapping a flipper button
+/// twice to fire an upper flipper or a magna-save.
+pub struct DoubleTap {
+    window: u32,
+    first_tap: Option<Instant>,
+}
+
+impl DoubleTap {
+    pub fn new(window: u32) -> Self {
+        Self {
+            window,
+            first_tap: None,
+        }
+    }
+
+    pub fn window(&self) -> u32 {
+        self.window
+    }
+
+    pub fn set_window(&mut self, window: u32) {
+        self.window = window;
+    }
+
+    /// Feed every read of the input. Returns true on the update where the second tap
+    /// lands inside the window.
+    pub fn update<I: InputType>(&mut self, data: &InputData<I>) -> bool {
+        if data.rose() & 1 == 0 {
+            return false;
+        }
+
+        let now = Instant::from_millis(data.timestamp());
+        let window = Duration::from_millis(self.window);
+        match self.first_tap {
+            Some(first) if matches!(now.checked_duration_since(first), Some(d) if d <= window) => {
+                self.first_tap = None;
+                true
+            }
+            _ => {
+                self.first_tap = Some(now);
+                false
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.first_tap = None;
+    }
+}
+
+/// StuckSwitches flags input bits that have been high for longer than `timeout`, in the
+/// units of the input timestamps. With masking on, flagged bits are also disabled in the
+/// `InputArray` so a stuck slingshot switch can't keep firing its coil; they are enabled
+/// again as soon as the switch opens.
+pub struct StuckSwitches<W: Word = u16> {
+    timeout: u32,
+    watched: W,
+    masking: bool,
+    stuck: W,
+}
+
+impl<W: Word> StuckSwitches<W> {
+    /// Watches every bit, without masking.
+    pub fn new(timeout: u32) -> Self {
+        Self {
+            timeout,
+            watched: !W::ZERO,
+            masking: false,
+            stuck: W::ZERO,
+        }
+    }
+
+    /// Only supervises the bits in `mask`, e.g. to leave hold buttons and optos alone.
+    pub fn watch(mut self, mask: W) -> Self {
+        self.watched = mask;
+        self
+    }
+
+    pub fn with_masking(mut self, masking: bool) -> Self {
+        self.masking = masking;
+        self
+    }
+
+    pub fn set_timeout(&mut self, timeout: u32) {
+        self.timeout = timeout;
+    }
+
+    /// Bits currently flagged as stuck.
+    pub fn stuck(&self) -> W {
+        self.stuck
+    }
+
+    /// Checks every watched bit; call after each update. Returns the bits newly flagged
+    /// by this check.
+    pub fn check(&mut self, inputs: &mut InputArray<W>) -> W {
+        let now = Instant::from_millis(inputs.timestamp());
+        let timeout = Duration::from_millis(self.timeout);
+        let raw = inputs.raw();
+        let mut flagged = W::ZERO;
+        let mut released = W::ZERO;
+        for bit in (0..W::BITS).filter(|&bit| self.watched.is_set(bit)) {
+            let high = raw.is_set(bit);
+            if self.stuck.is_set(bit) {
+                if !high {
+                    released |= W::bit(bit);
+                }
+                continue;
+            }
+
+            let since = inputs
+                .stats(bit)
+                .map_or(now, |s| Instant::from_millis(s.last_change));
+            if high && now.duration_since(since) > timeout {
+                flagged |= W::bit(bit);
+            }
+        }
+
+        self.stuck = (self.stuck | flagged) & !released;
+        if self.masking {
+            let disabled = inputs.disabled_mask();
+            inputs.set_disabled_mask((disabled | flagged) & !released);
+        }
+        flagged
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::filters::{DoubleTap, StuckSwitches};
+    use crate::{InputArray, SingleInput};
+
+    #[test]
+    fn double_tap_within_window() {
+        let mut inputs = InputArray::new();
+        let button = inputs.input::<SingleInput>().unwrap();
+        let mut tap = DoubleTap::new(20);
+
+        let mut feed = |raw, at| {
+            inputs.update_at(raw, at);
+            tap.update(&inputs.read(&button))
+        };
+
+        assert!(!feed(1, 0));
+        assert!(!feed(0, 5));
+        assert!(feed(1, 15));
+        assert!(!feed(0, 18));
+        // The third tap starts a new sequence.
+        assert!(!feed(1, 25));
+        assert!(!feed(0, 30));
+        // Too slow.
+        assert!(!feed(1, 60));
+        assert!(!feed(0, 65));
+        assert!(feed(1, 70));
+    }
+
+    #[test]
+    fn stuck_switch_masked_until_released() {
+        let mut inputs = InputArray::new();
+        let sling = inputs.input::<SingleInput>().unwrap();
+        let hold = inputs.input::<SingleInput>().unwrap();
+        let mut stuck = StuckSwitches::new(50).watch(0b01).with_masking(true);
+
+        inputs.update_at(0b11, 0);
+        assert_eq!(stuck.check(&mut inputs), 0);
+        inputs.update_at(0b11, 60);
+        assert_eq!(stuck.check(&mut inputs), 0b01);
+        assert!(!inputs.read(&sling).is_input1_high());
+        assert!(inputs.read(&hold).is_input1_high());
+
+        inputs.update_at(0b11, 70);
+        assert_eq!(stuck.check(&mut inputs), 0);
+        assert_eq!(stuck.stuck(), 0b01);
+
+        inputs.update_at(0b10, 80);
+        stuck.check(&mut inputs);
+        assert_eq!(stuck.stuck(), 0);
+        inputs.update_at(0b11, 90);
+        assert!(inputs.read(&sling).is_input1_high());
+    }
+}
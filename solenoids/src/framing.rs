@@ -0,0 +1,281 @@
+use heapless::{consts::*, Vec};
+
+use crate::time::{Duration, Instant};
+use crate::Error;
+
+/// Payload of a reply accepting the frame with the same sequence number.
+pub const ACK: u8 = 0x06;
+/// Payload of a reply asking for the frame in flight again, sent for a frame that failed
+/// its CRC. Its sequence number means nothing, since the bad frame's can't be trusted.
+pub const NAK: u8 = 0x15;
+
+/// Largest payload a frame can carry.
+pub const MAX_PAYLOAD: usize = 125;
+
+pub type Frame = Vec<u8, U128>;
+
+/// CRC-16/CCITT-FALSE: polynomial 0x1021, initial value 0xFFFF.
+pub fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for &byte in bytes {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Wraps `payload` (a command, reply, snapshot or event frame) for the wire: the sequence
+/// number, the payload, then the CRC of both, little-endian. A coil firing next to the bus
+/// can flip bits; the CRC makes sure a damaged command is dropped rather than acted on.
+/// `Error::TooLong` for payloads longer than `MAX_PAYLOAD`, which are never sent in part.
+pub fn seal(sequence: u8, payload: &[u8]) -> Result<Frame, Error> {
+    if payload.len() > MAX_PAYLOAD {
+        return Err(Error::TooLong);
+    }
+    let mut frame = Frame::new();
+    let _ = frame.push(sequence);
+    let _ = frame.extend_from_slice(payload);
+    let crc = crc16(&frame);
+    let _ = frame.extend_from_slice(&crc.to_le_bytes());
+    Ok(frame)
+}
+
+/// Checks a sealed frame and returns its sequence number and payload, or `None` if it's
+/// too short or fails the CRC.
+pub fn open(frame: &[u8]) -> Option<(u8, &[u8])> {
+    if frame.len() < 3 {
+        return None;
+    }
+    let (body, crc) = frame.split_at(frame.len() - 2);
+    if crc16(body) != u16::from_le_bytes([crc[0], crc[1]]) {
+        return None;
+    }
+    Some((body[0], &body[1..]))
+}
+
+/// What a `Receiver` made of a frame, and what to reply with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Received<'a> {
+    /// A frame not seen before: act on the payload.
+    New { sequence: u8, payload: &'a [u8] },
+    /// A retry of the last frame, whose acknowledgement must have been lost. Don't act on
+    /// it again, just acknowledge it.
+    Duplicate { sequence: u8 },
+    /// The frame failed its CRC.
+    Corrupt,
+}
+
+impl Received<'_> {
+    /// The frame to send back: `ACK` with the sequence number, or a `NAK`.
+    pub fn reply(&self) -> Frame {
+        let (sequence, payload) = match *self {
+            Received::New { sequence, .. } | Received::Duplicate { sequence } => (sequence, ACK),
+            Received::Corrupt => (0, NAK),
+        };
+        // A single byte always fits.
+        seal(sequence, &[payload]).unwrap_or_default()
+    }
+}
+
+/// Receiver is the board's end of a link: it checks each frame and suppresses the
+/// duplicates a sender's retries produce, so a command is carried out once however many
+/// times it had to be sent.
+#[derive(Clone, Debug, Default)]
+pub struct Receiver {
+    last: Option<u8>,
+}
+
+impl Receiver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn receive<'a>(&mut self, frame: &'a [u8]) -> Received<'a> {
+        match open(frame) {
+            None => Received::Corrupt,
+            Some((sequence, _)) if self.last == Some(sequence) => Received::Duplicate { sequence },
+            Some((sequence, payload)) => {
+                self.last = Some(sequence);
+                Received::New { sequence, payload }
+            }
+        }
+    }
+}
+
+/// Sender is the master's end of a link: one frame in flight at a time, resent on a `NAK`
+/// or when no acknowledgement arrives within the timeout, and given up on after a number
+/// of tries. Times are in whatever units `now` is given in, normally milliseconds.
+pub struct Sender {
+    sequence: u8,
+    timeout: Duration,
+    max_tries: u8,
+    pending: Option<Pending>,
+}
+
+struct Pending {
+    frame: Frame,
+    sent_at: Instant,
+    tries: u8,
+}
+
+impl Sender {
+    /// Waits `timeout` for each acknowledgement and sends each frame at most `max_tries`
+    /// times.
+    pub fn new<D: Into<Duration>>(timeout: D, max_tries: u8) -> Self {
+        Self {
+            sequence: 0,
+            timeout: timeout.into(),
+            max_tries: max_tries.max(1),
+            pending: None,
+        }
+    }
+
+    /// Whether the last frame has been acknowledged or given up on.
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_none()
+    }
+
+    /// Seals `payload` with the next sequence number and returns the frame to send.
+    /// `Error::Busy` while the previous frame is still waiting for its acknowledgement, and
+    /// `Error::TooLong` for a payload that doesn't fit a frame.
+    pub fn send(&mut self, payload: &[u8], now: u32) -> Result<Frame, Error> {
+        if self.pending.is_some() {
+            return Err(Error::Busy);
+        }
+        let frame = seal(self.sequence.wrapping_add(1), payload)?;
+        self.sequence = self.sequence.wrapping_add(1);
+        self.pending = Some(Pending {
+            frame: frame.clone(),
+            sent_at: Instant::from_millis(now),
+            tries: 1,
+        });
+        Ok(frame)
+    }
+
+    /// Handles a reply from the far end. Returns the frame to resend after a `NAK`, or
+    /// `Err(Error::Timeout)` if that was the last try.
+    pub fn on_reply(&mut self, reply: &[u8], now: u32) -> Result<Option<Frame>, Error> {
+        let pending = match self.pending.as_ref() {
+            Some(pending) => pending,
+            None => return Ok(None),
+        };
+        match open(reply) {
+            Some((sequence, [ACK])) if sequence == pending.frame[0] => {
+                self.pending = None;
+                Ok(None)
+            }
+            Some((_, [NAK])) => self.resend(now),
+            _ => Ok(None),
+        }
+    }
+
+    /// Call regularly: returns the frame to resend once the acknowledgement is overdue,
+    /// or `Err(Error::Timeout)` when the frame has had all its tries.
+    pub fn poll(&mut self, now: u32) -> Result<Option<Frame>, Error> {
+        match self.pending.as_ref() {
+            Some(pending)
+                if Instant::from_millis(now).duration_since(pending.sent_at) >= self.timeout =>
+            {
+                self.resend(now)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn resend(&mut self, now: u32) -> Result<Option<Frame>, Error> {
+        let max_tries = self.max_tries;
+        let pending = match self.pending.as_mut() {
+            Some(pending) => pending,
+            None => return Ok(None),
+        };
+        if pending.tries >= max_tries {
+            self.pending = None;
+            return Err(Error::Timeout);
+        }
+        pending.tries += 1;
+        pending.sent_at = Instant::from_millis(now);
+        Ok(Some(pending.frame.clone()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::command::Command;
+    use crate::framing::{crc16, open, seal, Received, Receiver, Sender, MAX_PAYLOAD};
+    use crate::time::Duration;
+    use crate::Error;
+
+    #[test]
+    fn crc_catches_corruption() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+
+        let fire = Command::Fire {
+            id: 2,
+            strength: 100,
+            millis: 40,
+        }
+        .to_bytes();
+        let mut frame = seal(7, &fire).unwrap();
+        assert_eq!(open(&frame), Some((7, &fire[..])));
+        frame[3] ^= 0x04;
+        assert_eq!(open(&frame), None);
+        assert_eq!(open(&frame[..2]), None);
+    }
+
+    #[test]
+    fn oversize_payloads_are_refused() {
+        let payload = [0x55; MAX_PAYLOAD + 1];
+        assert_eq!(seal(1, &payload), Err(Error::TooLong));
+        let fits = seal(1, &payload[..MAX_PAYLOAD]).unwrap();
+        assert_eq!(open(&fits), Some((1, &payload[..MAX_PAYLOAD])));
+
+        let mut sender = Sender::new(Duration::from_millis(20), 3);
+        assert_eq!(sender.send(&payload, 0), Err(Error::TooLong));
+        assert!(sender.is_idle());
+        let frame = sender.send(&payload[..4], 1).unwrap();
+        assert_eq!(frame[0], 1);
+    }
+
+    #[test]
+    fn retries_are_acted_on_once() {
+        let mut sender = Sender::new(Duration::from_millis(20), 3);
+        let mut receiver = Receiver::new();
+        let payload = Command::AllOff.to_bytes();
+
+        let frame = sender.send(&payload, 0).unwrap();
+        assert_eq!(sender.send(&payload, 1), Err(Error::Busy));
+
+        // Damaged on the way: NAKed and resent.
+        let mut damaged = frame.clone();
+        damaged[1] ^= 0xFF;
+        let received = receiver.receive(&damaged);
+        assert_eq!(received, Received::Corrupt);
+        let resent = sender.on_reply(&received.reply(), 5).unwrap().unwrap();
+        assert_eq!(resent, frame);
+
+        // Arrives, but the ACK is lost: the retry is a duplicate.
+        match receiver.receive(&resent) {
+            Received::New { payload: p, .. } => assert_eq!(p, &payload[..]),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(sender.poll(24), Ok(None));
+        let retry = sender.poll(25).unwrap().unwrap();
+        let received = receiver.receive(&retry);
+        assert!(matches!(received, Received::Duplicate { .. }));
+        assert_eq!(sender.on_reply(&received.reply(), 26), Ok(None));
+        assert!(sender.is_idle());
+
+        // Nothing ever comes back.
+        sender.send(&payload, 100).unwrap();
+        assert!(sender.poll(120).unwrap().is_some());
+        assert!(sender.poll(140).unwrap().is_some());
+        assert_eq!(sender.poll(160), Err(Error::Timeout));
+        assert!(sender.is_idle());
+    }
+}
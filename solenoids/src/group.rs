@@ -0,0 +1,152 @@
+use core::marker::PhantomData;
+use heapless::{ArrayLength, Vec};
+
+use crate::pwm::State;
+use crate::{Actuator, Error, InputArray, InputType, Word};
+
+/// ExclusiveGroup wraps several actuators and guarantees that at most one of them
+/// produces an enabled `State` per update. Members are prioritised in the order they
+/// were pushed, so the first member has the highest priority.
+///
+/// This is intended for mechanisms that must never energize together, such as two
+/// opposing diverters driving the same gate.
+pub struct ExclusiveGroup<I, A, N>
+where
+    I: InputType,
+    A: Actuator<I>,
+    N: ArrayLength<A>,
+{
+    members: Vec<A, N>,
+    _type: PhantomData<I>,
+}
+
+impl<I, A, N> ExclusiveGroup<I, A, N>
+where
+    I: InputType,
+    A: Actuator<I>,
+    N: ArrayLength<A>,
+{
+    pub fn new() -> Self {
+        Self {
+            members: Vec::new(),
+            _type: PhantomData,
+        }
+    }
+
+    /// Adds an actuator to the group with a lower priority than every member already
+    /// in it.
+    pub fn push(&mut self, actuator: A) -> Result<(), Error> {
+        self.members
+            .push(actuator)
+            .map_err(|_| Error::TooManyActuators)
+    }
+
+    pub fn members(&self) -> &[A] {
+        &self.members
+    }
+
+    /// Updates every member in priority order. `states` holds the current state of each
+    /// member, indexed the same way as `members()`, and is overwritten with the next
+    /// states. Once a member is enabled, any lower priority member that also wants to be
+    /// enabled is forced off instead. `Error::InvalidMapping`, with nothing updated, if
+    /// there isn't exactly one state per member.
+    pub fn update_states<W: Word>(
+        &mut self,
+        inputs: &InputArray<W>,
+        states: &mut [State],
+    ) -> Result<(), Error> {
+        if states.len() != self.members.len() {
+            return Err(Error::InvalidMapping);
+        }
+        let mut granted = false;
+        for (actuator, state) in self.members.iter_mut().zip(states.iter_mut()) {
+            let data = inputs.read(actuator.input_config());
+            let mut next = actuator.update_state(&data, *state);
+            if next.enabled {
+                if granted {
+                    next.enabled = false;
+                }
+                granted = true;
+            }
+            *state = next;
+        }
+        Ok(())
+    }
+}
+
+impl<I, A, N> Default for ExclusiveGroup<I, A, N>
+where
+    I: InputType,
+    A: Actuator<I>,
+    N: ArrayLength<A>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::actuators::Basic;
+    use crate::group::ExclusiveGroup;
+    use crate::pwm::{Channel, Configuration, State};
+    use crate::{Error, InputArray, SingleInput};
+    use heapless::consts::*;
+
+    fn off() -> State {
+        State {
+            enabled: false,
+            duty_cycle: 0,
+        }
+    }
+
+    #[test]
+    fn highest_priority_wins() {
+        let mut inputs = InputArray::new();
+        let mut group: ExclusiveGroup<SingleInput, Basic, U2> = ExclusiveGroup::new();
+        group
+            .push(inputs.make_actuator(Configuration::Tc3).unwrap())
+            .unwrap();
+        group
+            .push(
+                inputs
+                    .make_actuator(Configuration::Tcc0(Channel::_0))
+                    .unwrap(),
+            )
+            .unwrap();
+
+        let mut states = [off(), off()];
+
+        inputs.update(1 << 1);
+        group.update_states(&inputs, &mut states).unwrap();
+        assert!(!states[0].enabled);
+        assert!(states[1].enabled);
+
+        inputs.update(1 << 0 | 1 << 1);
+        group.update_states(&inputs, &mut states).unwrap();
+        assert!(states[0].enabled);
+        assert!(!states[1].enabled);
+
+        inputs.update(0);
+        group.update_states(&inputs, &mut states).unwrap();
+        assert!(!states[0].enabled);
+        assert!(!states[1].enabled);
+
+        assert_eq!(
+            group.update_states(&inputs, &mut states[..1]),
+            Err(Error::InvalidMapping)
+        );
+    }
+
+    #[test]
+    fn push_past_capacity() {
+        let mut inputs = InputArray::new();
+        let mut group: ExclusiveGroup<SingleInput, Basic, U1> = ExclusiveGroup::new();
+        group
+            .push(inputs.make_actuator(Configuration::Tc3).unwrap())
+            .unwrap();
+        assert!(group
+            .push(inputs.make_actuator(Configuration::Tc3).unwrap())
+            .is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use heapless::{consts::*, spsc::Queue, Vec};
+
+use crate::command::{Command, Identity, COMMAND_FRAME_ID, OP_CONFIGURE, OP_FIRE};
+
+/// Registers of the I2C command interface. A host writes the register address followed by
+/// its payload, or writes the address and then reads.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Register {
+    /// Read: the board's `Identity` frame.
+    Identity = 0x00,
+    /// Read: the input word as 8 bytes little-endian, then its 4-byte timestamp.
+    Inputs = 0x10,
+    /// Write: id, strength, millis low, millis high, as `Command::Fire`.
+    Fire = 0x20,
+    /// Write: id, parameter, value as 4 bytes little-endian, as `Command::Configure`.
+    Configure = 0x21,
+}
+
+impl Register {
+    pub fn from_u8(address: u8) -> Option<Self> {
+        match address {
+            0x00 => Some(Register::Identity),
+            0x10 => Some(Register::Inputs),
+            0x20 => Some(Register::Fire),
+            0x21 => Some(Register::Configure),
+            _ => None,
+        }
+    }
+
+    // Opcode of the command frame a write to this register stands for.
+    fn opcode(self) -> Option<u8> {
+        match self {
+            Register::Fire => Some(OP_FIRE),
+            Register::Configure => Some(OP_CONFIGURE),
+            _ => None,
+        }
+    }
+}
+
+/// RegisterMap lets the board be driven as an I2C slave, e.g. by a Raspberry Pi running
+/// the game logic, instead of over palantir. The SERCOM slave interrupt feeds it the bus
+/// events (`on_write`, `on_read`, `on_stop`); the scan loop calls `refresh` with the
+/// controller's latest inputs and dispatches whatever `pop_command` hands back, so
+/// commands land between ticks just like ones from the master.
+///
+/// Reads past the end of a register return 0xFF. Writes to unknown or read-only registers,
+/// and writes with the wrong payload length, are ignored.
+pub struct RegisterMap {
+    register: Option<Register>,
+    payload: Vec<u8, U8>,
+    read_at: usize,
+    identity: [u8; 9],
+    inputs: [u8; 12],
+    commands: Queue<Command, U4>,
+    dropped: u32,
+}
+
+impl RegisterMap {
+    pub fn new(identity: Identity) -> Self {
+        Self {
+            register: None,
+            payload: Vec::new(),
+            read_at: 0,
+            identity: identity.to_bytes(),
+            inputs: [0; 12],
+            commands: Queue::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Updates what the read registers return.
+    pub fn refresh(&mut self, identity: Identity, raw: u64, timestamp: u32) {
+        self.identity = identity.to_bytes();
+        self.inputs[..8].copy_from_slice(&raw.to_le_bytes());
+        self.inputs[8..].copy_from_slice(&timestamp.to_le_bytes());
+    }
+
+    /// A byte written by the host: the register address first, then payload.
+    pub fn on_write(&mut self, byte: u8) {
+        // A write after a read in the same transfer isn't part of the map. Bytes beyond the
+        // buffer are dropped, which leaves the payload too long to parse.
+        if self.read_at > 0 {
+            return;
+        }
+        if self.payload.is_empty() {
+            self.register = Register::from_u8(byte);
+        }
+        let _ = self.payload.push(byte);
+    }
+
+    /// The next byte for the host to read from the addressed register.
+    pub fn on_read(&mut self) -> u8 {
+        let bytes: &[u8] = match self.register {
+            Some(Register::Identity) => &self.identity,
+            Some(Register::Inputs) => &self.inputs,
+            _ => &[],
+        };
+        let byte = bytes.get(self.read_at).copied().unwrap_or(0xFF);
+        self.read_at += 1;
+        byte
+    }
+
+    /// The host ended the transfer. A complete write to a command register is queued.
+    pub fn on_stop(&mut self) {
+        if let Some(opcode) = self.register.and_then(Register::opcode) {
+            let mut frame: Vec<u8, U16> = Vec::new();
+            let _ = frame.extend_from_slice(&[COMMAND_FRAME_ID, opcode]);
+            let _ = frame.extend_from_slice(&self.payload[1..]);
+            if let Some(command) = Command::from_bytes(&frame) {
+                if self.commands.enqueue(command).is_err() {
+                    warn!("i2c command queue full, dropped {}", command);
+                    self.dropped = self.dropped.saturating_add(1);
+                }
+            }
+        }
+        self.register = None;
+        self.payload.clear();
+        self.read_at = 0;
+    }
+
+    /// Takes the oldest command the host has written.
+    pub fn pop_command(&mut self) -> Option<Command> {
+        self.commands.dequeue()
+    }
+
+    /// Commands lost because the queue was full.
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::command::{Capabilities, Command, Identity};
+    use crate::i2c_slave::{Register, RegisterMap};
+    use crate::Param;
+
+    fn identity() -> Identity {
+        Identity {
+            address: 0x42,
+            version: [0, 1, 0],
+            actuators: 2,
+            inputs: 3,
+            input_width: 16,
+            capabilities: Capabilities::supported(),
+        }
+    }
+
+    fn write(map: &mut RegisterMap, bytes: &[u8]) {
+        for &byte in bytes {
+            map.on_write(byte);
+        }
+        map.on_stop();
+    }
+
+    fn read(map: &mut RegisterMap, register: Register, len: usize) -> [u8; 12] {
+        map.on_write(register as u8);
+        let mut bytes = [0; 12];
+        for byte in bytes.iter_mut().take(len) {
+            *byte = map.on_read();
+        }
+        map.on_stop();
+        bytes
+    }
+
+    #[test]
+    fn register_writes_become_commands() {
+        let mut map = RegisterMap::new(identity());
+        write(&mut map, &[0x20, 1, 100, 0x2C, 0x01]);
+        write(&mut map, &[0x21, 0, 3, 20, 0, 0, 0]);
+        // Too short, read-only and unknown.
+        write(&mut map, &[0x20, 1, 100]);
+        write(&mut map, &[0x10, 1]);
+        write(&mut map, &[0x7F, 1, 2, 3, 4]);
+
+        assert_eq!(
+            map.pop_command(),
+            Some(Command::Fire {
+                id: 1,
+                strength: 100,
+                millis: 300,
+            })
+        );
+        assert_eq!(
+            map.pop_command(),
+            Some(Command::Configure {
+                id: 0,
+                param: Param::Cooldown,
+                value: 20,
+            })
+        );
+        assert_eq!(map.pop_command(), None);
+    }
+
+    #[test]
+    fn reads_serve_the_latest_state() {
+        let mut map = RegisterMap::new(identity());
+        let bytes = read(&mut map, Register::Identity, 10);
+        assert_eq!(Identity::from_bytes(&bytes[..9]), Some(identity()));
+        assert_eq!(bytes[9], 0xFF);
+
+        map.refresh(identity(), 0x0105, 7);
+        let bytes = read(&mut map, Register::Inputs, 12);
+        assert_eq!(bytes, [0x05, 0x01, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0]);
+    }
+}
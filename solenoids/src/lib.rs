@@ -1,15 +1,114 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
 use core::marker::PhantomData;
-use heapless::{consts::*, Vec};
+use core::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
+use heapless::{consts::*, spsc::Queue, Vec};
+
+#[macro_use]
+mod log;
 
 pub mod actuators;
+pub mod bootloader;
+pub mod command;
+pub mod config;
+pub mod console;
+pub mod controller;
+pub mod direct;
+mod dma;
+pub mod events;
+pub mod executor;
+#[cfg(feature = "fast")]
+pub mod fast;
+pub mod filters;
+pub mod framing;
+pub mod group;
+pub mod i2c_slave;
+pub mod machine;
+pub mod mpf;
+pub mod opto;
+pub mod output;
+pub mod power;
+/// The traits and decorators most applications need, plus aliases for common stacks.
+pub mod prelude;
 pub mod pwm;
+pub mod restart;
+pub mod rules;
+pub mod safety;
+pub mod schedule;
+pub mod soft_pwm;
+pub mod status;
+pub mod sysclock;
+pub mod telemetry;
+#[cfg(test)]
+mod testing;
+pub mod thermal;
+pub mod time;
+pub mod watchdog;
+pub mod wrappers;
 
-#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Error {
     TooManyInputs,
     InvalidInputType,
+    TooManyActuators,
+    InvalidMapping,
+    /// A PWM channel was claimed by more than one actuator, or the DMA input reader is
+    /// already in use.
+    ChannelInUse,
+    /// The hardware can't do what was asked, e.g. DMA waveforms on TC3.
+    Unsupported,
+    /// A bus transfer or conversion failed while reading inputs (SPI, I2C, ADC).
+    Bus,
+    /// A GPIO could not be read or written.
+    Pin,
+    /// An input source stopped receiving data.
+    Timeout,
+    /// A `schedule::Scheduler` already holds as many actions as it can.
+    ScheduleFull,
+    /// A protected command carried the wrong key.
+    BadKey,
+    /// A `framing::Sender` is still waiting for the last frame to be acknowledged.
+    Busy,
+    /// The command isn't allowed in the current mode, e.g. `SetInput` outside test mode.
+    WrongMode,
+    /// Non-volatile storage couldn't be written, or a `config::Config` is full.
+    Storage,
+    /// A `machine::MachineConfig` couldn't be encoded or decoded, or is from another
+    /// version.
+    Encoding,
+    /// A `controller::Controller` already holds `rules::MAX_RULES` switch rules.
+    TooManyRules,
+    /// A payload doesn't fit a `framing` frame.
+    TooLong,
+}
+
+/// A setting that can be changed on a running actuator through `Actuator::set_param`, e.g.
+/// from a `command::Command::Configure` sent by the master.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Param {
+    /// Duty of the initial, full power part of an activation.
+    KickDuty = 0,
+    /// Duty once the actuator drops to holding.
+    HoldDuty = 1,
+    /// How long the kick lasts, in update cycles.
+    PulseTime = 2,
+    /// Off-time after each activation, in the `wrappers::Cooldown`'s timebase.
+    Cooldown = 3,
+}
+
+impl Param {
+    pub fn from_u8(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Param::KickDuty),
+            1 => Some(Param::HoldDuty),
+            2 => Some(Param::PulseTime),
+            3 => Some(Param::Cooldown),
+            _ => None,
+        }
+    }
 }
 
 pub trait InputType {
@@ -24,7 +123,7 @@ impl InputType for SingleInput {
     }
 
     fn size(&self) -> u8 {
-        0
+        1
     }
 }
 
@@ -35,7 +134,7 @@ impl InputType for DualInput {
     }
 
     fn size(&self) -> u8 {
-        1
+        2
     }
 }
 
@@ -46,75 +145,684 @@ impl InputType for TriInput {
     }
 
     fn size(&self) -> u8 {
-        2
+        3
     }
 }
 
+/// An input of `N` bits, for mechanisms with more switches than `TriInput` covers, such
+/// as a bank of four drop targets. `N` runs from 1 to 16; any other width fails the build.
+pub struct MultiInput<const N: usize>;
+
+impl<const N: usize> MultiInput<N> {
+    // Evaluated when `new` or `size` is instantiated for an `N`, so a bad width is a
+    // compile error rather than a panic on the board.
+    const VALID: () = assert!(N >= 1 && N <= 16, "MultiInput must be 1 to 16 bits wide");
+}
+
+impl<const N: usize> InputType for MultiInput<N> {
+    fn new() -> Self {
+        let () = Self::VALID;
+        MultiInput
+    }
+
+    fn size(&self) -> u8 {
+        let () = Self::VALID;
+        N as u8
+    }
+}
+
+pub type QuadInput = MultiInput<4>;
+
 #[derive(Clone)]
 pub struct InputConfig<I: InputType> {
     start_offset: u16,
     input_type: I,
+    inverted: u16,
+}
+
+impl<I: InputType> InputConfig<I> {
+    /// Bit of the input word that input 1 is read from.
+    pub fn start_offset(&self) -> u16 {
+        self.start_offset
+    }
+
+    /// Marks every bit of the input as active-low, for normally-closed and opto switches,
+    /// so reads report activation rather than the electrical level.
+    pub fn active_low(self) -> Self {
+        let all = ((1u32 << self.input_type.size()) - 1) as u16;
+        self.with_inverted(all)
+    }
+
+    /// Opto switch mode: every bit is active-low, since a blocked beam turns the receiver
+    /// off. Pair with `opto::OptoCheck` to catch dead optos at power-on.
+    pub fn opto(self) -> Self {
+        self.active_low()
+    }
+
+    /// Marks the bits in `mask` (input 1 as the least significant bit) as active-low and
+    /// the rest as active-high. Only reads through this config are affected; the raw word
+    /// and `InputEvent`s keep electrical levels.
+    pub fn with_inverted(mut self, mask: u16) -> Self {
+        self.inverted = mask & ((1u32 << self.input_type.size()) - 1) as u16;
+        self
+    }
+
+    /// The active-low bits, laid out like `InputData::bits`.
+    pub fn inverted(&self) -> u16 {
+        self.inverted
+    }
 }
 
 pub struct InputData<I: InputType> {
-    start_offset: u16,
     data: u16,
+    prev: u16,
+    timestamp: u32,
+    // When each bit of the input last changed, input 1 first.
+    changed_at: [u32; 16],
     _type: PhantomData<I>,
 }
 
 impl<I: InputType> InputData<I> {
-    fn new(config: &InputConfig<I>, data: u16) -> Self {
+    fn new<W: Word>(
+        config: &InputConfig<I>,
+        data: W,
+        prev: W,
+        disabled: W,
+        timestamp: u32,
+        changed_at: &[u32],
+    ) -> Self {
+        let size = config.input_type.size();
+        let enabled = !disabled.field(config.start_offset, size);
+        let mut own = [0; 16];
+        own[..changed_at.len()].copy_from_slice(changed_at);
         Self {
-            start_offset: config.start_offset,
-            data,
+            data: (data.field(config.start_offset, size) ^ config.inverted) & enabled,
+            prev: (prev.field(config.start_offset, size) ^ config.inverted) & enabled,
+            timestamp,
+            changed_at: own,
             _type: PhantomData,
         }
     }
 
+    /// When the data was sampled, see `InputArray::update_at`.
+    pub fn timestamp(&self) -> u32 {
+        self.timestamp
+    }
+
     pub fn is_input1_high(&self) -> bool {
-        self.data & (1 << self.start_offset) != 0
+        self.data & 1 != 0
+    }
+
+    /// How long input 1 has been continuously active, in the units of the timestamps
+    /// (update cycles when using `InputArray::update`). Zero while it is inactive.
+    pub fn held_for(&self) -> u32 {
+        self.held_for_input(0)
+    }
+
+    /// Like `held_for`, for input `n + 1` of the input: `held_for_input(1)` is input 2.
+    /// Zero for bits the input doesn't have.
+    pub fn held_for_input(&self, n: usize) -> u32 {
+        match self.changed_at.get(n) {
+            Some(&at) if self.data & (1 << n) != 0 => self.timestamp.wrapping_sub(at),
+            _ => 0,
+        }
+    }
+
+    /// The bits belonging to this input, with input 1 as the least significant bit.
+    pub fn bits(&self) -> u16 {
+        self.data
+    }
+
+    /// Whether any of the bits belonging to this input are high.
+    pub fn is_any_high(&self) -> bool {
+        self.bits() != 0
+    }
+
+    /// The bits belonging to this input before the last update, laid out like `bits`.
+    pub fn prev_bits(&self) -> u16 {
+        self.prev
+    }
+
+    /// Bits that went from low to high in the last update, laid out like `bits`.
+    pub fn rose(&self) -> u16 {
+        self.bits() & !self.prev_bits()
+    }
+
+    /// Bits that went from high to low in the last update, laid out like `bits`.
+    pub fn fell(&self) -> u16 {
+        !self.bits() & self.prev_bits()
+    }
+
+    /// Bits that changed in either direction in the last update, laid out like `bits`.
+    pub fn changed(&self) -> u16 {
+        self.bits() ^ self.prev_bits()
     }
 }
 
 impl InputData<DualInput> {
     pub fn is_input2_high(&self) -> bool {
-        self.data & (1 << (1 + self.start_offset)) != 0
+        self.data & (1 << 1) != 0
     }
 }
 
 impl InputData<TriInput> {
     pub fn is_input2_high(&self) -> bool {
-        self.data & (1 << (1 + self.start_offset)) != 0
+        self.data & (1 << 1) != 0
     }
 
     pub fn is_input3_high(&self) -> bool {
-        self.data & (1 << (2 + self.start_offset)) != 0
+        self.data & (1 << 2) != 0
+    }
+}
+
+impl<const N: usize> InputData<MultiInput<N>> {
+    /// Whether input `n` is high, counting from 1 like `is_input1_high`. Inputs outside
+    /// `1..=N` read low.
+    pub fn is_input_high(&self, n: usize) -> bool {
+        n >= 1 && n <= N && self.data & (1 << (n - 1)) != 0
+    }
+}
+
+/// Storage for the raw input bits of an `InputArray`. Implemented for `u16`, `u32` and
+/// `u64`; pick the narrowest one that fits the machine's switches.
+pub trait Word:
+    Copy
+    + PartialEq
+    + Not<Output = Self>
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + BitXor<Output = Self>
+    + BitAndAssign
+    + BitOrAssign
+    + BitXorAssign
+{
+    const BITS: u8;
+    const ZERO: Self;
+
+    /// A word with only bit `n` set.
+    fn bit(n: u8) -> Self;
+
+    /// `len` bits starting at `offset`, shifted down to bit 0.
+    fn field(self, offset: u16, len: u8) -> u16;
+
+    /// The low `BITS` bits of `value`.
+    fn truncate(value: u64) -> Self;
+
+    /// The word zero-extended to 64 bits.
+    fn widen(self) -> u64;
+
+    fn is_set(self, n: u8) -> bool {
+        self & Self::bit(n) != Self::ZERO
     }
 }
 
-// (start_offset, len)
-type InputLayout = Vec<(u8, u8), U6>;
+macro_rules! impl_word {
+    ($($t:ty),*) => {$(
+        impl Word for $t {
+            const BITS: u8 = (core::mem::size_of::<$t>() * 8) as u8;
+            const ZERO: Self = 0;
+
+            fn bit(n: u8) -> Self {
+                1 << n
+            }
+
+            fn field(self, offset: u16, len: u8) -> u16 {
+                let mask = (1u64 << len.min(16)) - 1;
+                ((self >> offset) as u64 & mask) as u16
+            }
+
+            fn truncate(value: u64) -> Self {
+                value as $t
+            }
+
+            fn widen(self) -> u64 {
+                self as u64
+            }
+        }
+    )*};
+}
+
+impl_word!(u16, u32, u64);
+
+// Per-bit debounce state is kept for the widest supported word.
+const MAX_BITS: usize = 64;
+
+/// Marks a logical bit as not connected to any bus bit in `InputArray::set_wiring`.
+pub const UNWIRED: u8 = 0xFF;
+
+// (start_offset, len); every input takes at least one bit, so MAX_BITS entries always fit.
+type InputLayout = Vec<(u8, u8), U64>;
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edge {
+    Rising,
+    Falling,
+}
+
+/// A single committed transition of one input bit.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InputEvent {
+    /// Bit of the input word that changed.
+    pub bit: u8,
+    pub edge: Edge,
+    /// Number of updates the array had seen when the change was committed.
+    pub sample: u32,
+    /// Timestamp of the update that committed the change, see `update_at`.
+    pub timestamp: u32,
+}
 
-pub struct InputArray {
-    raw: u16,
+/// Wear statistics for one input bit, see `InputArray::stats`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InputStats {
+    /// Committed low-to-high transitions. For active-low switches this counts releases,
+    /// which only differs from the closure count by one.
+    pub closures: u32,
+    /// Timestamp of the last committed change in either direction.
+    pub last_change: u32,
+}
+
+/// InputArray holds the latest level of every input bit, `W::BITS` of them. The default
+/// 16-bit array is made with `new`; wider ones with `Default`, e.g.
+/// `InputArray::<u64>::default()`.
+pub struct InputArray<W: Word = u16> {
+    raw: W,
+    prev: W,
+    direct: W,
+    latching: W,
+    latched: W,
+    disabled: W,
+    overridden: W,
+    override_levels: W,
+    // Physical bus bit feeding each logical bit, when `remapped`.
+    wiring: [u8; MAX_BITS],
+    remapped: bool,
+    timestamp: u32,
     layout: InputLayout,
+    // Samples a bit must differ from `raw` for before the change is committed, and how
+    // many it has differed for so far.
+    debounce: [u8; MAX_BITS],
+    unstable_for: [u8; MAX_BITS],
+    // Majority-vote window of each bit in `voting`, and its last raw samples, newest in
+    // bit 0.
+    voting: W,
+    vote_window: [u8; MAX_BITS],
+    vote_history: [u8; MAX_BITS],
+    // Timestamp of each bit's last committed change.
+    changed_at: [u32; MAX_BITS],
+    closures: [u32; MAX_BITS],
+    samples: u32,
+    events: Queue<InputEvent, U32>,
+    missed_events: u32,
 }
 
 impl InputArray {
     pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<W: Word> Default for InputArray<W> {
+    fn default() -> Self {
         Self {
-            raw: 0,
+            raw: W::ZERO,
+            prev: W::ZERO,
+            direct: W::ZERO,
+            latching: W::ZERO,
+            latched: W::ZERO,
+            disabled: W::ZERO,
+            overridden: W::ZERO,
+            override_levels: W::ZERO,
+            wiring: [UNWIRED; MAX_BITS],
+            remapped: false,
+            timestamp: 0,
             layout: Vec::new(),
+            debounce: [1; MAX_BITS],
+            unstable_for: [0; MAX_BITS],
+            voting: W::ZERO,
+            vote_window: [1; MAX_BITS],
+            vote_history: [0; MAX_BITS],
+            changed_at: [0; MAX_BITS],
+            closures: [0; MAX_BITS],
+            samples: 0,
+            events: Queue::new(),
+            missed_events: 0,
+        }
+    }
+}
+
+impl<W: Word> InputArray<W> {
+    /// Updates the inputs without a time source. The timestamp advances by one per update,
+    /// so it counts samples. Returns the bits that changed, see `update_at`.
+    pub fn update(&mut self, data: W) -> W {
+        self.update_at(data, self.timestamp.wrapping_add(1))
+    }
+
+    /// Updates the inputs, recording when they were sampled in whatever time unit the
+    /// application uses (e.g. milliseconds). Timestamps are expected to wrap.
+    ///
+    /// Returns the bits whose committed level changed, so callers can skip inputs that
+    /// didn't; test it against `mask` for a particular input.
+    pub fn update_at(&mut self, data: W, timestamp: u32) -> W {
+        let data = if self.remapped {
+            self.apply_wiring(data)
+        } else {
+            data
+        };
+        let data = self.vote(data);
+        let data = (data & !self.overridden) | (self.override_levels & self.overridden);
+        let mut changed = (data ^ self.raw) & !(self.direct & !self.overridden);
+        for bit in 0..W::BITS {
+            let i = bit as usize;
+            if !changed.is_set(bit) {
+                self.unstable_for[i] = 0;
+                continue;
+            }
+
+            self.unstable_for[i] = self.unstable_for[i].saturating_add(1);
+            if self.unstable_for[i] < self.debounce[i] && !self.overridden.is_set(bit) {
+                changed &= !W::bit(bit);
+            } else {
+                self.unstable_for[i] = 0;
+            }
+        }
+        self.prev = self.raw;
+        self.raw ^= changed;
+        self.latched |= self.raw & self.latching;
+        self.timestamp = timestamp;
+        self.mark_changed(changed);
+        self.samples = self.samples.wrapping_add(1);
+        self.queue_events(changed);
+        changed
+    }
+
+    fn vote(&mut self, data: W) -> W {
+        let mut voted = data;
+        let voting = self.voting;
+        for bit in (0..W::BITS).filter(|&bit| voting.is_set(bit)) {
+            let i = bit as usize;
+            let window = self.vote_window[i];
+            self.vote_history[i] = self.vote_history[i] << 1 | data.is_set(bit) as u8;
+            let window_mask = ((1u16 << window) - 1) as u8;
+            let highs = (self.vote_history[i] & window_mask).count_ones() as u8;
+            let high = if highs * 2 == window {
+                self.raw.is_set(bit)
+            } else {
+                highs * 2 > window
+            };
+            if high {
+                voted |= W::bit(bit);
+            } else {
+                voted &= !W::bit(bit);
+            }
+        }
+        voted
+    }
+
+    fn mark_changed(&mut self, changed: W) {
+        for bit in (0..W::BITS).filter(|&bit| changed.is_set(bit)) {
+            self.changed_at[bit as usize] = self.timestamp;
+            if self.raw.is_set(bit) {
+                self.closures[bit as usize] = self.closures[bit as usize].wrapping_add(1);
+            }
+        }
+    }
+
+    /// The committed electrical level of every bit, before polarity, latching and the
+    /// enable mask are applied.
+    pub fn raw(&self) -> W {
+        self.raw
+    }
+
+    /// Timestamp of the latest update.
+    pub fn timestamp(&self) -> u32 {
+        self.timestamp
+    }
+
+    /// Closure count and last change time of bit `index`, for spotting worn or dead
+    /// switches from diagnostics. `None` past the end of the word.
+    pub fn stats(&self, index: u8) -> Option<InputStats> {
+        if index >= W::BITS {
+            return None;
+        }
+        Some(InputStats {
+            closures: self.closures[index as usize],
+            last_change: self.changed_at[index as usize],
+        })
+    }
+
+    /// Zeroes every closure count. Change times are kept, since hold durations rely on
+    /// them.
+    pub fn reset_stats(&mut self) {
+        self.closures = [0; MAX_BITS];
+    }
+
+    fn queue_events(&mut self, changed: W) {
+        for bit in (0..W::BITS).filter(|&bit| changed.is_set(bit)) {
+            let edge = if self.raw.is_set(bit) {
+                Edge::Rising
+            } else {
+                Edge::Falling
+            };
+            let event = InputEvent {
+                bit,
+                edge,
+                sample: self.samples,
+                timestamp: self.timestamp,
+            };
+            if self.events.enqueue(event).is_err() {
+                if self.missed_events == 0 {
+                    warn!("input event queue full, dropping events");
+                }
+                self.missed_events = self.missed_events.saturating_add(1);
+            }
+        }
+    }
+
+    fn apply_wiring(&self, data: W) -> W {
+        (0..W::BITS)
+            .filter(|&bit| {
+                let from = self.wiring[bit as usize];
+                from < W::BITS && data.is_set(from)
+            })
+            .fold(W::ZERO, |word, bit| word | W::bit(bit))
+    }
+
+    /// Rewires the bus at runtime: `table[n]` is the physical bit of the bus word that
+    /// logical bit `n`, the one input configs were laid out on, reads from. Logical bits
+    /// past the end of the table, or mapped to `UNWIRED`, read low. This lets one firmware
+    /// serve different playfield wiring, e.g. with a table received over the bus.
+    pub fn set_wiring(&mut self, table: &[u8]) -> Result<(), Error> {
+        if table.len() > W::BITS as usize || table.iter().any(|&b| b != UNWIRED && b >= W::BITS) {
+            return Err(Error::InvalidMapping);
+        }
+
+        let mut wiring = [UNWIRED; MAX_BITS];
+        wiring[..table.len()].copy_from_slice(table);
+        self.wiring = wiring;
+        self.remapped = true;
+        Ok(())
+    }
+
+    /// Moves a single input so that it reads the physical bits starting at `physical`.
+    /// The rest of the wiring is kept, starting from one-to-one if none was set.
+    pub fn remap_input<I: InputType>(
+        &mut self,
+        input_config: &InputConfig<I>,
+        physical: u8,
+    ) -> Result<(), Error> {
+        let size = input_config.input_type.size();
+        if physical as u16 + size as u16 > W::BITS as u16 {
+            return Err(Error::InvalidMapping);
+        }
+
+        if !self.remapped {
+            for (bit, from) in self.wiring.iter_mut().enumerate() {
+                *from = bit as u8;
+            }
+            self.remapped = true;
+        }
+        for i in 0..size {
+            self.wiring[(input_config.start_offset as u8 + i) as usize] = physical + i;
+        }
+        Ok(())
+    }
+
+    /// Goes back to reading every bit where the bus puts it.
+    pub fn clear_wiring(&mut self) {
+        self.wiring = [UNWIRED; MAX_BITS];
+        self.remapped = false;
+    }
+
+    /// Takes the oldest transition off the event queue. Every committed change, scanned or
+    /// direct, is queued in order, so transitions aren't lost when several happen between
+    /// reads of the current levels.
+    pub fn pop_event(&mut self) -> Option<InputEvent> {
+        self.events.dequeue()
+    }
+
+    /// Drains the event queue, oldest first.
+    pub fn events(&mut self) -> impl Iterator<Item = InputEvent> + '_ {
+        core::iter::from_fn(move || self.pop_event())
+    }
+
+    /// Events dropped because the queue was full when they happened.
+    pub fn missed_events(&self) -> u32 {
+        self.missed_events
+    }
+
+    /// Debounces the bits of `input_config`: a change is only committed once the new level
+    /// has been sampled `samples` times in a row. 0 and 1 both commit changes immediately,
+    /// which is the default. Directly driven bits are never debounced here.
+    pub fn set_debounce<I: InputType>(&mut self, input_config: &InputConfig<I>, samples: u8) {
+        let start = input_config.start_offset as usize;
+        let end = start + input_config.input_type.size() as usize;
+        for bit in start..end.min(W::BITS as usize) {
+            self.debounce[bit] = samples;
+            self.unstable_for[bit] = 0;
+        }
+    }
+
+    /// Filters the bits of `input_config` by majority vote over their last `samples` raw
+    /// samples (at most 8), before debouncing. This rejects single-sample glitches, like
+    /// those coils induce on long switch harness runs, that debouncing alone would only
+    /// delay. A tie keeps the current level, so odd windows are best. 0 and 1 turn the
+    /// filter off, which is the default.
+    pub fn set_majority_vote<I: InputType>(&mut self, input_config: &InputConfig<I>, samples: u8) {
+        let samples = samples.clamp(1, 8);
+        let start = input_config.start_offset as usize;
+        let end = start + input_config.input_type.size() as usize;
+        for bit in start..end.min(W::BITS as usize) {
+            self.vote_window[bit] = samples;
+            self.vote_history[bit] = if self.raw.is_set(bit as u8) { 0xFF } else { 0 };
+            if samples > 1 {
+                self.voting |= W::bit(bit as u8);
+            } else {
+                self.voting &= !W::bit(bit as u8);
+            }
+        }
+    }
+
+    /// Marks the bits in `mask` as driven directly (e.g. from pin interrupts) rather than by
+    /// the bulk input source, so `update` leaves them alone.
+    pub fn claim_direct(&mut self, mask: W) {
+        self.direct |= mask;
+    }
+
+    /// Sets the directly driven bits in `mask` to `levels`. Edges on these bits are
+    /// reported until the next `update`.
+    pub fn set_direct(&mut self, mask: W, levels: W) {
+        self.force(mask & self.direct & !self.overridden, levels);
+    }
+
+    // Sets the bits in `mask` to `levels` immediately, outside of an update.
+    fn force(&mut self, mask: W, levels: W) {
+        let changed = (self.raw ^ levels) & mask;
+        self.prev = (self.prev & !mask) | (self.raw & mask);
+        self.raw ^= changed;
+        self.latched |= self.raw & self.latching;
+        self.mark_changed(changed);
+        self.queue_events(changed);
+    }
+
+    /// Forces input bit `index` to `level`, taking precedence over bus data, direct
+    /// inputs and debouncing until released. The change applies immediately, so a service
+    /// mode or remote command can fire an actuator without touching its switch. Indexes
+    /// past the end of the word are ignored.
+    pub fn override_bit(&mut self, index: u8, level: bool) {
+        if index >= W::BITS {
+            return;
+        }
+        let bit = W::bit(index);
+        self.overridden |= bit;
+        if level {
+            self.override_levels |= bit;
+        } else {
+            self.override_levels &= !bit;
+        }
+        self.force(bit, self.override_levels);
+    }
+
+    /// Hands bit `index` back to its source. It keeps the overridden level until the next
+    /// `update` (or `set_direct` for a direct bit).
+    pub fn release_override(&mut self, index: u8) {
+        if index < W::BITS {
+            self.overridden &= !W::bit(index);
         }
     }
 
-    pub fn update(&mut self, data: u16) {
-        self.raw = data;
+    pub fn release_overrides(&mut self) {
+        self.overridden = W::ZERO;
+    }
+
+    /// Bits currently overridden.
+    pub fn override_mask(&self) -> W {
+        self.overridden
+    }
+
+    /// The bits of the word that `input_config` reads.
+    pub fn mask<I: InputType>(&self, input_config: &InputConfig<I>) -> W {
+        Self::mask_of(input_config)
+    }
+
+    fn mask_of<I: InputType>(input_config: &InputConfig<I>) -> W {
+        let start = input_config.start_offset as u8;
+        (start..start + input_config.input_type.size())
+            .filter(|&bit| bit < W::BITS)
+            .fold(W::ZERO, |mask, bit| mask | W::bit(bit))
+    }
+
+    /// Latches the bits of `input_config`: once a bit reads high it keeps reading high,
+    /// even after the switch opens, until it is acknowledged. This keeps fast rollovers
+    /// and other closures shorter than the actuator update period from being missed.
+    /// Latching applies to electrical levels, so use it on active-high inputs.
+    pub fn set_latching<I: InputType>(&mut self, input_config: &InputConfig<I>, latching: bool) {
+        let mask = Self::mask_of(input_config);
+        if latching {
+            self.latching |= mask;
+        } else {
+            self.latching &= !mask;
+            self.latched &= !mask;
+        }
+    }
+
+    /// Releases the latched bits of `input_config`. Bits whose switch is still closed stay
+    /// high as normal.
+    pub fn acknowledge<I: InputType>(&mut self, input_config: &InputConfig<I>) {
+        self.latched &= !Self::mask_of(input_config);
+    }
+
+    /// Reads `input_config` and acknowledges it in one step.
+    pub fn take<I: InputType>(&mut self, input_config: &InputConfig<I>) -> InputData<I> {
+        let data = self.read(input_config);
+        self.acknowledge(input_config);
+        data
     }
 
     fn get_input<I: InputType>(&mut self, input: I) -> Result<InputConfig<I>, Error> {
-        let size_used = self.layout.iter().map(|t| t.1).sum();
-        if size_used >= 16 {
+        let size_used = self.bits_used();
+        if size_used + input.size() > W::BITS {
             return Err(Error::TooManyInputs);
         }
 
@@ -126,11 +834,57 @@ impl InputArray {
         Ok(InputConfig {
             start_offset: size_used as u16,
             input_type: input,
+            inverted: 0,
         })
     }
 
+    /// How many bits have been allocated to inputs so far.
+    pub fn bits_used(&self) -> u8 {
+        self.layout.iter().map(|t| t.1).sum()
+    }
+
+    /// Allocates the next free bits for an input without making an actuator, so the config
+    /// can be adjusted (e.g. `active_low`) before passing it to `Actuator::new`.
+    pub fn input<I: InputType>(&mut self) -> Result<InputConfig<I>, Error> {
+        self.get_input(I::new())
+    }
+
     pub fn read<I: InputType>(&self, input_config: &InputConfig<I>) -> InputData<I> {
-        InputData::new(input_config, self.raw)
+        let start = input_config.start_offset as usize;
+        InputData::new(
+            input_config,
+            self.raw | self.latched,
+            self.prev,
+            self.disabled,
+            self.timestamp,
+            &self.changed_at[start..start + input_config.input_type.size() as usize],
+        )
+    }
+
+    /// Enables or disables the bits of `input_config`. A disabled input always reads
+    /// inactive, whatever the switch or its polarity, and reports no edges. Inputs start
+    /// enabled.
+    pub fn set_enabled<I: InputType>(&mut self, input_config: &InputConfig<I>, enabled: bool) {
+        let mask = Self::mask_of(input_config);
+        if enabled {
+            self.disabled &= !mask;
+        } else {
+            self.disabled |= mask;
+        }
+    }
+
+    pub fn is_enabled<I: InputType>(&self, input_config: &InputConfig<I>) -> bool {
+        self.disabled & Self::mask_of(input_config) == W::ZERO
+    }
+
+    /// Replaces the whole disable mask at once, e.g. to drop every flipper button on tilt.
+    /// Set bits are disabled.
+    pub fn set_disabled_mask(&mut self, mask: W) {
+        self.disabled = mask;
+    }
+
+    pub fn disabled_mask(&self) -> W {
+        self.disabled
     }
 
     pub fn make_actuator<I: InputType, A: Actuator<I>>(
@@ -139,6 +893,20 @@ impl InputArray {
     ) -> Result<A, Error> {
         Ok(A::new(self.get_input(I::new())?, channel_config))
     }
+
+    /// Makes an actuator whose behaviour is the given function, for one-off behaviours
+    /// that don't warrant their own `Actuator` implementation.
+    pub fn make_fn_actuator<I: InputType>(
+        &mut self,
+        channel_config: pwm::Configuration,
+        update: actuators::UpdateFn<I>,
+    ) -> Result<actuators::FnActuator<I>, Error> {
+        Ok(actuators::FnActuator::from_fn(
+            self.get_input(I::new())?,
+            channel_config,
+            update,
+        ))
+    }
 }
 
 /// BasicActuator checks input pin 1 for state. The actuator will be turned on at max
@@ -150,105 +918,482 @@ where
     fn new(input_config: InputConfig<I>, pwm_config: pwm::Configuration) -> Self;
     fn input_config(&self) -> &InputConfig<I>;
     fn pwm_config(&self) -> &pwm::Configuration;
-    fn update_state(&self, data: &InputData<I>, curr_state: pwm::State) -> pwm::State;
+    fn update_state(&mut self, data: &InputData<I>, curr_state: pwm::State) -> pwm::State;
+
+    /// Whether this actuator intentionally holds its output on for long periods (up-posts,
+    /// magnets, gates). On-time enforcement applies the hold limit to these instead of the
+    /// coil limit.
+    fn hold_capable(&self) -> bool {
+        false
+    }
+
+    /// Changes `param` to `value` from the next update. Actuators without that setting
+    /// return `Error::Unsupported` and are left as they were.
+    fn set_param(&mut self, _param: Param, _value: u32) -> Result<(), Error> {
+        Err(Error::Unsupported)
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{DualInput, InputArray, InputType, SingleInput};
+    use crate::{
+        DualInput, Edge, InputArray, InputEvent, InputStats, MultiInput, QuadInput, SingleInput,
+        TriInput,
+    };
 
     #[test]
     fn borrow_checking() {
         let mut inputs = InputArray::new();
-        let data = match inputs.get_input(SingleInput) {
-            Ok(data) => data,
+        let config = match inputs.get_input(SingleInput) {
+            Ok(config) => config,
             Err(e) => panic!("failed to get data: {:?}", e),
         };
 
         // core::mem::drop(inputs);
 
-        data.input1_is_high();
+        inputs.read(&config).is_input1_high();
     }
 
     #[test]
     fn adding_single_input() {
         let mut inputs = InputArray::new();
-        let data = match inputs.get_input(SingleInput) {
-            Ok(data) => data,
+        let config = match inputs.get_input(SingleInput) {
+            Ok(config) => config,
             Err(e) => panic!("failed to get data: {:?}", e),
         };
 
-        assert_eq!(data.input1_is_high().is_some(), true);
-        assert!(data.input2_is_high().is_none());
-        assert!(data.input3_is_high().is_none());
-
-        assert_eq!(data.input1_is_high().unwrap(), false);
+        assert_eq!(inputs.read(&config).is_input1_high(), false);
         inputs.update(1);
-        assert_eq!(data.input1_is_high().unwrap(), true);
+        assert_eq!(inputs.read(&config).is_input1_high(), true);
     }
 
     #[test]
     fn add_double_input() {
         let mut inputs = InputArray::new();
-        let data = match inputs.get_input(DualInput) {
-            Ok(data) => data,
+        let config = match inputs.get_input(DualInput) {
+            Ok(config) => config,
             Err(e) => panic!("failed to get data: {:?}", e),
         };
 
-        assert!(data.input1_is_high().is_some());
-        assert!(data.input2_is_high().is_some());
-        assert!(data.input3_is_high().is_none());
-
-        assert_eq!(data.input1_is_high().unwrap(), false);
-        assert_eq!(data.input2_is_high().unwrap(), false);
+        assert_eq!(inputs.read(&config).is_input1_high(), false);
+        assert_eq!(inputs.read(&config).is_input2_high(), false);
         inputs.update(1);
-        assert_eq!(data.input1_is_high().unwrap(), true);
-        assert_eq!(data.input2_is_high().unwrap(), false);
+        assert_eq!(inputs.read(&config).is_input1_high(), true);
+        assert_eq!(inputs.read(&config).is_input2_high(), false);
 
         inputs.update(0);
 
-        assert_eq!(data.input1_is_high().unwrap(), false);
-        assert_eq!(data.input2_is_high().unwrap(), false);
+        assert_eq!(inputs.read(&config).is_input1_high(), false);
+        assert_eq!(inputs.read(&config).is_input2_high(), false);
         inputs.update(1 << 1);
-        assert_eq!(data.input1_is_high().unwrap(), false);
-        assert_eq!(data.input2_is_high().unwrap(), true);
+        assert_eq!(inputs.read(&config).is_input1_high(), false);
+        assert_eq!(inputs.read(&config).is_input2_high(), true);
     }
 
     #[test]
     fn add_single_double_inputs() {
         let mut inputs = InputArray::new();
-        let single_data = match inputs.get_input(SingleInput) {
-            Ok(d) => d,
+        let single = match inputs.get_input(SingleInput) {
+            Ok(c) => c,
             Err(e) => panic!("failed to get data: {:?}", e),
         };
-        let double_data = match inputs.get_input(DualInput) {
-            Ok(d) => d,
+        let double = match inputs.get_input(DualInput) {
+            Ok(c) => c,
             Err(e) => panic!("failed to get data: {:?}", e),
         };
 
         inputs.update(1 << 0);
-        assert!(single_data.input1_is_high().unwrap());
-        assert!(!double_data.input1_is_high().unwrap());
-        assert!(!double_data.input2_is_high().unwrap());
+        assert!(inputs.read(&single).is_input1_high());
+        assert!(!inputs.read(&double).is_input1_high());
+        assert!(!inputs.read(&double).is_input2_high());
 
         inputs.update(1 << 1);
-        assert!(!single_data.input1_is_high().unwrap());
-        assert!(double_data.input1_is_high().unwrap());
-        assert!(!double_data.input2_is_high().unwrap());
+        assert!(!inputs.read(&single).is_input1_high());
+        assert!(inputs.read(&double).is_input1_high());
+        assert!(!inputs.read(&double).is_input2_high());
 
         inputs.update(1 << 2);
-        assert!(!single_data.input1_is_high().unwrap());
-        assert!(!double_data.input1_is_high().unwrap());
-        assert!(double_data.input2_is_high().unwrap());
+        assert!(!inputs.read(&single).is_input1_high());
+        assert!(!inputs.read(&double).is_input1_high());
+        assert!(inputs.read(&double).is_input2_high());
 
         inputs.update(1 << 0 | 1 << 1);
-        assert!(single_data.input1_is_high().unwrap());
-        assert!(double_data.input1_is_high().unwrap());
-        assert!(!double_data.input2_is_high().unwrap());
+        assert!(inputs.read(&single).is_input1_high());
+        assert!(inputs.read(&double).is_input1_high());
+        assert!(!inputs.read(&double).is_input2_high());
 
         inputs.update(1 << 0 | 1 << 1 | 1 << 2);
-        assert!(single_data.input1_is_high().unwrap());
-        assert!(double_data.input1_is_high().unwrap());
-        assert!(double_data.input2_is_high().unwrap());
+        assert!(inputs.read(&single).is_input1_high());
+        assert!(inputs.read(&double).is_input1_high());
+        assert!(inputs.read(&double).is_input2_high());
+    }
+
+    #[test]
+    fn direct_bits_survive_update() {
+        let mut inputs = InputArray::new();
+        let button = inputs.get_input(SingleInput).unwrap();
+        let switch = inputs.get_input(SingleInput).unwrap();
+        inputs.claim_direct(1 << 0);
+
+        inputs.set_direct(1 << 0 | 1 << 1, 1 << 0 | 1 << 1);
+        assert!(inputs.read(&button).is_input1_high());
+        assert!(!inputs.read(&switch).is_input1_high());
+
+        inputs.update(1 << 1);
+        assert!(inputs.read(&button).is_input1_high());
+        assert!(inputs.read(&switch).is_input1_high());
+
+        inputs.set_direct(1 << 0, 0);
+        assert!(!inputs.read(&button).is_input1_high());
+    }
+
+    #[test]
+    fn debounce_waits_for_stable_samples() {
+        let mut inputs = InputArray::new();
+        let leaf = inputs.get_input(SingleInput).unwrap();
+        let clean = inputs.get_input(SingleInput).unwrap();
+        inputs.set_debounce(&leaf, 3);
+
+        // Bouncing never settles long enough to commit.
+        for &raw in [1, 0, 1, 1, 0, 1].iter() {
+            inputs.update(raw | raw << 1);
+            assert!(!inputs.read(&leaf).is_input1_high());
+            assert_eq!(inputs.read(&clean).is_input1_high(), raw == 1);
+        }
+
+        inputs.update(1);
+        inputs.update(1);
+        assert!(inputs.read(&leaf).is_input1_high());
+
+        inputs.update(0);
+        inputs.update(0);
+        assert!(inputs.read(&leaf).is_input1_high());
+        inputs.update(0);
+        assert!(!inputs.read(&leaf).is_input1_high());
+    }
+
+    #[test]
+    fn edges_since_last_update() {
+        let mut inputs = InputArray::new();
+        let single = inputs.get_input(SingleInput).unwrap();
+        let double = inputs.get_input(DualInput).unwrap();
+
+        inputs.update(1 << 0 | 1 << 2);
+        assert_eq!(inputs.read(&single).rose(), 1);
+        assert_eq!(inputs.read(&double).rose(), 0b10);
+        assert_eq!(inputs.read(&double).fell(), 0);
+
+        inputs.update(1 << 0 | 1 << 1);
+        assert_eq!(inputs.read(&single).changed(), 0);
+        assert_eq!(inputs.read(&double).rose(), 0b01);
+        assert_eq!(inputs.read(&double).fell(), 0b10);
+        assert_eq!(inputs.read(&double).changed(), 0b11);
+
+        inputs.update(1 << 0 | 1 << 1);
+        assert_eq!(inputs.read(&double).changed(), 0);
+    }
+
+    #[test]
+    fn events_keep_every_transition() {
+        let mut inputs = InputArray::new();
+        let _leaf = inputs.get_input(SingleInput).unwrap();
+        let button = inputs.get_input(SingleInput).unwrap();
+        inputs.claim_direct(1 << button.start_offset());
+
+        inputs.update(1);
+        inputs.set_direct(1 << 1, 1 << 1);
+        inputs.update(0);
+        inputs.set_direct(1 << 1, 1 << 1);
+
+        let mut events = inputs.events();
+        let event = |bit, edge, sample| {
+            Some(InputEvent {
+                bit,
+                edge,
+                sample,
+                timestamp: sample,
+            })
+        };
+        assert_eq!(events.next(), event(0, Edge::Rising, 1));
+        assert_eq!(events.next(), event(1, Edge::Rising, 1));
+        assert_eq!(events.next(), event(0, Edge::Falling, 2));
+        assert_eq!(events.next(), None);
+    }
+
+    #[test]
+    fn full_event_queue_counts_misses() {
+        let mut inputs = InputArray::new();
+        for i in 0..40 {
+            inputs.update(i & 1);
+        }
+        assert!(inputs.missed_events() > 0);
+        assert_eq!(inputs.pop_event().map(|e| e.sample), Some(2));
+    }
+
+    #[test]
+    fn wide_array() {
+        let mut inputs = InputArray::<u64>::default();
+        for _ in 0..5 {
+            inputs.get_input(TriInput).unwrap();
+        }
+        let last = inputs.get_input(TriInput).unwrap();
+        assert_eq!(last.start_offset(), 15);
+
+        inputs.update(1 << 16 | 1 << 17);
+        assert!(!inputs.read(&last).is_input1_high());
+        assert!(inputs.read(&last).is_input2_high());
+        assert!(inputs.read(&last).is_input3_high());
+        assert_eq!(inputs.pop_event().map(|e| e.bit), Some(16));
+    }
+
+    #[test]
+    fn majority_vote_rejects_glitches() {
+        let mut inputs = InputArray::new();
+        let sling = inputs.input::<SingleInput>().unwrap();
+        let other = inputs.input::<SingleInput>().unwrap();
+        inputs.set_majority_vote(&sling, 3);
+
+        // A one-sample glitch on the filtered bit is ignored, the other passes through.
+        assert_eq!(inputs.update(0b11), 0b10);
+        assert_eq!(inputs.update(0b00), 0b10);
+        inputs.update(0b00);
+        assert!(!inputs.read(&sling).is_input1_high());
+
+        // A real closure is committed once most of the window agrees.
+        inputs.update(0b01);
+        assert!(!inputs.read(&sling).is_input1_high());
+        inputs.update(0b01);
+        assert!(inputs.read(&sling).is_input1_high());
+        inputs.update(0b00);
+        assert!(inputs.read(&sling).is_input1_high());
+        inputs.update(0b00);
+        assert!(!inputs.read(&sling).is_input1_high());
+        assert!(!inputs.read(&other).is_input1_high());
+    }
+
+    #[test]
+    fn one_input_per_bit() {
+        let mut inputs = InputArray::new();
+        for i in 0..16 {
+            let input = inputs.input::<SingleInput>().unwrap();
+            assert_eq!(input.start_offset(), i);
+        }
+        assert!(matches!(
+            inputs.input::<SingleInput>(),
+            Err(crate::Error::TooManyInputs)
+        ));
+
+        let mut inputs = InputArray::<u64>::default();
+        for _ in 0..64 {
+            inputs.input::<SingleInput>().unwrap();
+        }
+        assert!(inputs.input::<SingleInput>().is_err());
+    }
+
+    #[test]
+    fn active_low_reads_activation() {
+        let mut inputs = InputArray::new();
+        let opto = inputs.input::<SingleInput>().unwrap().active_low();
+        let flipper = inputs.input::<DualInput>().unwrap().with_inverted(0b10);
+
+        // Opto beam unbroken, button released, NC end-of-stroke switch closed.
+        inputs.update(1 << 0 | 1 << 2);
+        assert!(!inputs.read(&opto).is_input1_high());
+        assert!(!inputs.read(&flipper).is_input1_high());
+        assert!(!inputs.read(&flipper).is_input2_high());
+
+        // Beam broken, button pressed, EOS opened by the stroke.
+        inputs.update(1 << 1);
+        assert!(inputs.read(&opto).is_input1_high());
+        assert_eq!(inputs.read(&opto).rose(), 1);
+        assert_eq!(inputs.read(&flipper).bits(), 0b11);
+    }
+
+    #[test]
+    fn latched_pulse_held_until_acknowledged() {
+        let mut inputs = InputArray::new();
+        let rollover = inputs.input::<SingleInput>().unwrap();
+        let plain = inputs.input::<SingleInput>().unwrap();
+        inputs.set_latching(&rollover, true);
+
+        inputs.update(0b11);
+        inputs.update(0);
+        assert!(inputs.read(&rollover).is_input1_high());
+        assert!(!inputs.read(&plain).is_input1_high());
+
+        assert!(inputs.take(&rollover).is_input1_high());
+        assert!(!inputs.read(&rollover).is_input1_high());
+
+        inputs.update(1);
+        inputs.acknowledge(&rollover);
+        assert!(inputs.read(&rollover).is_input1_high());
+    }
+
+    #[test]
+    fn disabled_inputs_read_inactive() {
+        let mut inputs = InputArray::new();
+        let button = inputs.input::<SingleInput>().unwrap();
+        let opto = inputs.input::<SingleInput>().unwrap().active_low();
+
+        inputs.set_enabled(&button, false);
+        inputs.set_enabled(&opto, false);
+        assert!(!inputs.is_enabled(&button));
+
+        inputs.update(1 << 0);
+        assert!(!inputs.read(&button).is_input1_high());
+        assert_eq!(inputs.read(&button).rose(), 0);
+        assert!(!inputs.read(&opto).is_input1_high());
+
+        inputs.set_disabled_mask(0);
+        assert!(inputs.read(&button).is_input1_high());
+        assert!(inputs.read(&opto).is_input1_high());
+    }
+
+    #[test]
+    fn multi_input_bits() {
+        let mut inputs = InputArray::new();
+        let _single = inputs.input::<SingleInput>().unwrap();
+        let bank = inputs.input::<QuadInput>().unwrap();
+        let wide = inputs.input::<MultiInput<11>>().unwrap();
+        assert_eq!(wide.start_offset(), 5);
+        assert!(inputs.input::<SingleInput>().is_err());
+
+        inputs.update(0b1010 << 1 | 1 << 15);
+        let data = inputs.read(&bank);
+        assert!(!data.is_input_high(1));
+        assert!(data.is_input_high(2));
+        assert!(data.is_input_high(4));
+        assert!(!data.is_input_high(5));
+        assert_eq!(data.bits(), 0b1010);
+        assert!(inputs.read(&wide).is_input_high(11));
+    }
+
+    #[test]
+    fn rewired_inputs() {
+        let mut inputs = InputArray::new();
+        let left = inputs.input::<SingleInput>().unwrap();
+        let right = inputs.input::<SingleInput>().unwrap();
+        let bank = inputs.input::<DualInput>().unwrap();
+
+        inputs.set_wiring(&[1, 0]).unwrap();
+        inputs.update(1 << 1 | 1 << 2);
+        assert!(inputs.read(&left).is_input1_high());
+        assert!(!inputs.read(&right).is_input1_high());
+        assert_eq!(inputs.read(&bank).bits(), 0);
+
+        inputs.clear_wiring();
+        inputs.remap_input(&bank, 8).unwrap();
+        inputs.update(1 << 0 | 1 << 9);
+        assert!(inputs.read(&left).is_input1_high());
+        assert_eq!(inputs.read(&bank).bits(), 0b10);
+
+        assert!(inputs.set_wiring(&[16]).is_err());
+        assert!(inputs.remap_input(&bank, 15).is_err());
+    }
+
+    #[test]
+    fn overrides_beat_bus_and_direct() {
+        let mut inputs = InputArray::new();
+        let coil = inputs.input::<SingleInput>().unwrap();
+        let button = inputs.input::<SingleInput>().unwrap();
+        inputs.claim_direct(1 << 1);
+        inputs.set_debounce(&coil, 5);
+
+        inputs.override_bit(0, true);
+        inputs.override_bit(1, true);
+        assert!(inputs.read(&coil).is_input1_high());
+        assert_eq!(inputs.read(&coil).rose(), 1);
+
+        inputs.update(0);
+        inputs.set_direct(1 << 1, 0);
+        assert!(inputs.read(&coil).is_input1_high());
+        assert!(inputs.read(&button).is_input1_high());
+
+        inputs.override_bit(0, false);
+        assert!(!inputs.read(&coil).is_input1_high());
+        assert_eq!(inputs.override_mask(), 0b11);
+
+        inputs.release_overrides();
+        inputs.set_direct(1 << 1, 0);
+        assert!(!inputs.read(&button).is_input1_high());
+        inputs.update(1);
+        assert!(!inputs.read(&coil).is_input1_high());
+    }
+
+    #[test]
+    fn update_reports_changed_bits() {
+        let mut inputs = InputArray::new();
+        let single = inputs.input::<SingleInput>().unwrap();
+        let double = inputs.input::<DualInput>().unwrap();
+        assert_eq!(inputs.mask(&double), 0b110);
+
+        let changed = inputs.update(0b101);
+        assert_eq!(changed, 0b101);
+        assert!(changed & inputs.mask(&single) != 0);
+
+        let changed = inputs.update(0b001);
+        assert_eq!(changed & inputs.mask(&single), 0);
+        assert_eq!(changed & inputs.mask(&double), 0b100);
+        assert_eq!(inputs.update(0b001), 0);
+    }
+
+    #[test]
+    fn hold_duration() {
+        let mut inputs = InputArray::new();
+        let plunger = inputs.input::<SingleInput>().unwrap();
+        let opto = inputs.input::<SingleInput>().unwrap().active_low();
+
+        inputs.update_at(0b10, 100);
+        assert_eq!(inputs.read(&plunger).held_for(), 0);
+        inputs.update_at(0b11, 110);
+        inputs.update_at(0b11, 150);
+        assert_eq!(inputs.read(&plunger).held_for(), 40);
+        assert_eq!(inputs.read(&opto).held_for(), 0);
+
+        inputs.update_at(0b00, 160);
+        assert_eq!(inputs.read(&plunger).held_for(), 0);
+        inputs.update_at(0b00, 175);
+        assert_eq!(inputs.read(&opto).held_for(), 15);
+    }
+
+    #[test]
+    fn hold_duration_of_each_input() {
+        let mut inputs = InputArray::new();
+        let _single = inputs.input::<SingleInput>().unwrap();
+        let flipper = inputs.input::<DualInput>().unwrap();
+
+        inputs.update_at(0b010, 100);
+        inputs.update_at(0b110, 120);
+        inputs.update_at(0b110, 150);
+        let data = inputs.read(&flipper);
+        assert_eq!(data.held_for(), 50);
+        assert_eq!(data.held_for_input(1), 30);
+        assert_eq!(data.held_for_input(2), 0);
+
+        inputs.update_at(0b100, 160);
+        let data = inputs.read(&flipper);
+        assert_eq!(data.held_for(), 0);
+        assert_eq!(data.held_for_input(1), 40);
+    }
+
+    #[test]
+    fn closure_statistics() {
+        let mut inputs = InputArray::new();
+        for (i, &raw) in [1, 0, 1, 3, 2, 0].iter().enumerate() {
+            inputs.update_at(raw, i as u32 * 10);
+        }
+
+        assert_eq!(
+            inputs.stats(0),
+            Some(InputStats {
+                closures: 2,
+                last_change: 40,
+            })
+        );
+        assert_eq!(inputs.stats(1).map(|s| s.closures), Some(1));
+        assert_eq!(inputs.stats(16), None);
+
+        inputs.reset_stats();
+        assert_eq!(inputs.stats(0).map(|s| s.closures), Some(0));
     }
 }
@@ -0,0 +1,29 @@
+//! Logging over RTT through `defmt` with the `defmt` feature; without it the macros expand
+//! to nothing and their arguments aren't evaluated.
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "defmt")]
+        {
+            defmt::debug!($($arg)*);
+        }
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "defmt")]
+        {
+            defmt::info!($($arg)*);
+        }
+    };
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "defmt")]
+        {
+            defmt::warn!($($arg)*);
+        }
+    };
+}
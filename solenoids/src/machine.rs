@@ -0,0 +1,384 @@
+use heapless::{consts::*, Vec};
+
+use crate::config::Config;
+use crate::controller::DEFAULT_FAILURE_LIMIT;
+use crate::pwm::{Configuration, State};
+use crate::restart::RestartPolicy;
+#[cfg(feature = "serde")]
+use crate::{config::Storage, framing::crc16};
+use crate::{Error, Param};
+
+/// Static description of a machine's actuators. Firmware normally doesn't build this by
+/// hand; the board's build script generates it from a playfield file at compile time so
+/// nothing has to be parsed on the MCU.
+pub struct MachineDesc {
+    pub actuators: &'static [ActuatorDesc],
+}
+
+pub struct ActuatorDesc {
+    pub name: &'static str,
+    pub kind: ActuatorKind,
+    pub input: InputKind,
+    pub pwm: Configuration,
+    /// Whether the driver switches on a low output, see `pwm::Controller::set_inverted`.
+    pub inverted: bool,
+    /// What to do after a soft reset if the actuator was active.
+    pub restart: RestartPolicy,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ActuatorKind {
+    Basic,
+    /// `Flipper` with its button on a direct EIC input and EOS on the scanned inputs.
+    Flipper,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputKind {
+    Single,
+    Dual,
+    Tri,
+}
+
+impl InputKind {
+    /// Number of input bits an actuator of this kind is bound to.
+    pub fn size(self) -> u8 {
+        match self {
+            InputKind::Single => 1,
+            InputKind::Dual => 2,
+            InputKind::Tri => 3,
+        }
+    }
+}
+
+/// A registered actuator as seen by diagnostics and host tools: its position in the
+/// machine description, its static description, where its inputs were bound and the
+/// state it last produced.
+#[derive(Clone, Copy)]
+pub struct ActuatorInfo<'a> {
+    pub id: usize,
+    pub desc: &'a ActuatorDesc,
+    pub input_offset: u16,
+    pub state: State,
+}
+
+impl<'a> ActuatorInfo<'a> {
+    /// Bits of the input word the actuator reads.
+    pub fn input_mask(&self) -> u64 {
+        ((1 << self.desc.input.size()) - 1) << self.input_offset
+    }
+}
+
+impl MachineDesc {
+    pub fn find(&self, name: &str) -> Option<&ActuatorDesc> {
+        self.actuators.iter().find(|a| a.name == name)
+    }
+}
+
+/// Version of the `MachineConfig` layout. Bump it whenever a field is added, removed or
+/// reordered: postcard isn't self-describing, so an old record would decode as garbage.
+pub const MACHINE_CONFIG_VERSION: u16 = 1;
+
+/// Largest encoded `MachineConfig`, which fits a `config::Flash`.
+pub const MAX_MACHINE_CONFIG_SIZE: usize = 1024;
+
+/// First byte of a frame carrying a `MachineConfig`.
+pub const MACHINE_CONFIG_FRAME_ID: u8 = 0x4D;
+
+/// MachineConfig is the runtime counterpart of `MachineDesc`: the actuators, inputs and
+/// limits of one machine, sent by the master and kept in flash. With the `serde` feature it
+/// encodes with postcard, the one format for the wire and for flash, so a host tool can
+/// build it with the same struct.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct MachineConfig {
+    /// `MACHINE_CONFIG_VERSION` of the firmware that wrote it; decoding rejects others.
+    pub version: u16,
+    pub address: u8,
+    pub actuators: Vec<ActuatorConfig, U16>,
+    pub inputs: InputsConfig,
+    pub limits: Limits,
+}
+
+/// One actuator, in registration order.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ActuatorConfig {
+    pub kind: ActuatorKind,
+    pub input: InputKind,
+    pub pwm: Configuration,
+    /// Whether the driver switches on a low output.
+    pub inverted: bool,
+    pub restart: RestartPolicy,
+    /// Parameters for `Actuator::set_param`; `None` keeps the actuator's default.
+    pub kick_duty: Option<u32>,
+    pub hold_duty: Option<u32>,
+    pub pulse_time: Option<u32>,
+    pub cooldown: Option<u32>,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InputsConfig {
+    /// Active-low bits of the input word, see `InputConfig::with_inverted`.
+    pub inverted: u64,
+    /// Samples an input must hold before it's committed, see `InputArray::set_debounce`.
+    pub debounce: u8,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    /// See `controller::Controller::with_failure_limit`.
+    pub failure_limit: u32,
+    /// Longest any actuator may stay on, in milliseconds, see `wrappers::MaxOnTime`.
+    pub max_on_ms: Option<u32>,
+}
+
+impl ActuatorConfig {
+    pub fn new(kind: ActuatorKind, input: InputKind, pwm: Configuration) -> Self {
+        Self {
+            kind,
+            input,
+            pwm,
+            inverted: false,
+            restart: RestartPolicy::StayOff,
+            kick_duty: None,
+            hold_duty: None,
+            pulse_time: None,
+            cooldown: None,
+        }
+    }
+
+    /// The parameters that are set, to apply once the actuator is made.
+    pub fn settings(&self) -> Vec<(Param, u32), U4> {
+        let params = [
+            (Param::KickDuty, self.kick_duty),
+            (Param::HoldDuty, self.hold_duty),
+            (Param::PulseTime, self.pulse_time),
+            (Param::Cooldown, self.cooldown),
+        ];
+        params
+            .iter()
+            .filter_map(|&(param, value)| Some((param, value?)))
+            .collect()
+    }
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        Self {
+            version: MACHINE_CONFIG_VERSION,
+            address: 0,
+            actuators: Vec::new(),
+            inputs: InputsConfig {
+                inverted: 0,
+                debounce: 1,
+            },
+            limits: Limits {
+                failure_limit: DEFAULT_FAILURE_LIMIT,
+                max_on_ms: None,
+            },
+        }
+    }
+}
+
+impl MachineConfig {
+    /// The address, polarity and actuator parameters as a `config::Config`, for
+    /// `Controller::apply_config`.
+    pub fn config(&self) -> Result<Config, Error> {
+        let mut config = Config::default();
+        config.address = self.address;
+        config.inverted = self.inputs.inverted;
+        for (id, actuator) in self.actuators.iter().enumerate() {
+            for (param, value) in actuator.settings() {
+                config.set(id as u8, param, value)?;
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl MachineConfig {
+    /// Encodes the config into `buffer` and returns the part used.
+    pub fn encode<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a mut [u8], Error> {
+        postcard::to_slice(self, buffer).map_err(|_| Error::Encoding)
+    }
+
+    /// Decodes a config, ignoring anything after it. `Error::Encoding` if it's malformed
+    /// or another version.
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        Self::take(bytes).map(|(config, _)| config)
+    }
+
+    // Decodes a config and returns it with the bytes after it.
+    fn take(bytes: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (config, rest): (Self, _) =
+            postcard::take_from_bytes(bytes).map_err(|_| Error::Encoding)?;
+        if config.version != MACHINE_CONFIG_VERSION {
+            warn!("machine config is version {}", config.version);
+            return Err(Error::Encoding);
+        }
+        Ok((config, rest))
+    }
+
+    /// The config as a frame for the master link: `MACHINE_CONFIG_FRAME_ID`, then the
+    /// encoded config.
+    pub fn to_frame<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a mut [u8], Error> {
+        let (id, body) = buffer.split_first_mut().ok_or(Error::Encoding)?;
+        *id = MACHINE_CONFIG_FRAME_ID;
+        let len = self.encode(body)?.len();
+        Ok(&mut buffer[..=len])
+    }
+
+    pub fn from_frame(frame: &[u8]) -> Result<Self, Error> {
+        match frame.split_first() {
+            Some((&MACHINE_CONFIG_FRAME_ID, body)) => Self::decode(body),
+            _ => Err(Error::Encoding),
+        }
+    }
+
+    /// Writes the config to `storage` as its encoding followed by a CRC-16 of it.
+    pub fn save<S: Storage>(&self, storage: &mut S) -> Result<(), Error> {
+        let mut buffer = [0; MAX_MACHINE_CONFIG_SIZE];
+        let len = self
+            .encode(&mut buffer[..MAX_MACHINE_CONFIG_SIZE - 2])?
+            .len();
+        let crc = crc16(&buffer[..len]);
+        buffer[len..len + 2].copy_from_slice(&crc.to_le_bytes());
+        storage.write(&buffer[..len + 2])
+    }
+
+    /// Reads the config saved in `storage`, or `None` for blank or damaged flash and for
+    /// configs saved by another version.
+    pub fn load<S: Storage>(storage: &mut S) -> Option<Self> {
+        let mut buffer = [0; MAX_MACHINE_CONFIG_SIZE];
+        storage.read(&mut buffer).ok()?;
+        let (config, rest) = Self::take(&buffer).ok()?;
+        let len = MAX_MACHINE_CONFIG_SIZE - rest.len();
+        match rest {
+            [lo, hi, ..] if crc16(&buffer[..len]) == u16::from_le_bytes([*lo, *hi]) => Some(config),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::machine::{
+        ActuatorConfig, ActuatorDesc, ActuatorInfo, ActuatorKind, InputKind, MachineConfig,
+    };
+    use crate::pwm::{Channel, Configuration, State};
+    use crate::restart::RestartPolicy;
+    use crate::Param;
+
+    #[test]
+    fn input_mask_covers_binding() {
+        let desc = ActuatorDesc {
+            name: "left_flipper",
+            kind: ActuatorKind::Flipper,
+            input: InputKind::Dual,
+            pwm: Configuration::Tc3,
+            inverted: false,
+            restart: RestartPolicy::StayOff,
+        };
+        let info = ActuatorInfo {
+            id: 2,
+            desc: &desc,
+            input_offset: 3,
+            state: State {
+                enabled: false,
+                duty_cycle: 0,
+            },
+        };
+        assert_eq!(info.input_mask(), 0b11000);
+    }
+
+    fn machine() -> MachineConfig {
+        let mut machine = MachineConfig {
+            address: 3,
+            ..MachineConfig::default()
+        };
+        machine.inputs.inverted = 0b100;
+        machine.limits.max_on_ms = Some(500);
+        let mut flipper = ActuatorConfig::new(
+            ActuatorKind::Flipper,
+            InputKind::Dual,
+            Configuration::Tcc0(Channel::_1),
+        );
+        flipper.hold_duty = Some(0x40_0000);
+        let _ = machine.actuators.push(flipper);
+        let mut kicker =
+            ActuatorConfig::new(ActuatorKind::Basic, InputKind::Single, Configuration::Tc3);
+        kicker.restart = RestartPolicy::Home;
+        kicker.pulse_time = Some(12);
+        kicker.cooldown = Some(100);
+        let _ = machine.actuators.push(kicker);
+        machine
+    }
+
+    #[test]
+    fn machine_config_feeds_the_controller() {
+        let config = machine().config().unwrap();
+        assert_eq!((config.address, config.inverted), (3, 0b100));
+        let settings: heapless::Vec<_, heapless::consts::U4> = config
+            .settings()
+            .iter()
+            .map(|s| (s.id, s.param, s.value))
+            .collect();
+        assert_eq!(
+            settings,
+            [
+                (0, Param::HoldDuty, 0x40_0000),
+                (1, Param::PulseTime, 12),
+                (1, Param::Cooldown, 100),
+            ]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn machine_config_round_trips_through_postcard() {
+        use crate::config::Storage;
+        use crate::machine::{MACHINE_CONFIG_VERSION, MAX_MACHINE_CONFIG_SIZE};
+        use crate::Error;
+
+        struct Ram([u8; MAX_MACHINE_CONFIG_SIZE]);
+
+        impl Storage for Ram {
+            fn read(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+                buffer.copy_from_slice(&self.0[..buffer.len()]);
+                Ok(())
+            }
+
+            fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+                self.0 = [0xFF; MAX_MACHINE_CONFIG_SIZE];
+                self.0[..data.len()].copy_from_slice(data);
+                Ok(())
+            }
+        }
+
+        let mut buffer = [0; 128];
+        let frame = machine().to_frame(&mut buffer).unwrap();
+        assert_eq!(MachineConfig::from_frame(frame), Ok(machine()));
+        assert_eq!(MachineConfig::from_frame(&frame[1..]), Err(Error::Encoding));
+
+        let mut flash = Ram([0xFF; MAX_MACHINE_CONFIG_SIZE]);
+        assert_eq!(MachineConfig::load(&mut flash), None);
+        machine().save(&mut flash).unwrap();
+        assert_eq!(MachineConfig::load(&mut flash), Some(machine()));
+        flash.0[4] ^= 0x01;
+        assert_eq!(MachineConfig::load(&mut flash), None);
+
+        // A config from another firmware version isn't misread.
+        let old = MachineConfig {
+            version: MACHINE_CONFIG_VERSION + 1,
+            ..machine()
+        };
+        let encoded = old.encode(&mut buffer).unwrap();
+        assert_eq!(MachineConfig::decode(encoded), Err(Error::Encoding));
+    }
+}
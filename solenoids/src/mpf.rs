@@ -0,0 +1,598 @@
+use embedded_hal::watchdog::Watchdog;
+use heapless::{consts::*, Vec};
+
+use crate::command::Command;
+use crate::controller::{Controllable, Controller, Evaluate};
+use crate::events::EventFrame;
+use crate::output::OutputDriver;
+use crate::rules::{Action, Rule};
+use crate::time::Instant;
+use crate::{Edge, Error, Word};
+
+/// First byte of every platform frame, in either direction.
+pub const PLATFORM_FRAME_ID: u8 = 0x50;
+
+const OP_QUERY_SWITCHES: u8 = 0x01;
+const OP_PULSE: u8 = 0x02;
+const OP_ENABLE: u8 = 0x03;
+const OP_DISABLE: u8 = 0x04;
+const OP_SET_RULE: u8 = 0x05;
+const OP_CLEAR_RULE: u8 = 0x06;
+const OP_WATCHDOG: u8 = 0x07;
+
+const OP_ACK: u8 = 0x80;
+const OP_SWITCHES: u8 = 0x81;
+const OP_NAK: u8 = 0xFF;
+
+/// The shapes of hardware rule a host platform asks for, after MPF's: a switch driving a
+/// coil on the board so the coil doesn't wait on the host.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RuleKind {
+    /// Pulse when the switch closes: slingshots, pop bumpers.
+    PulseOnHit = 0,
+    /// Pulse when the switch closes, cut short if it opens first.
+    PulseOnHitAndRelease = 1,
+    /// Pulse when the switch closes, then hold until it opens: flippers.
+    PulseOnHitAndEnableAndRelease = 2,
+}
+
+impl RuleKind {
+    pub fn from_u8(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(RuleKind::PulseOnHit),
+            1 => Some(RuleKind::PulseOnHitAndRelease),
+            2 => Some(RuleKind::PulseOnHitAndEnableAndRelease),
+            _ => None,
+        }
+    }
+}
+
+/// A message from the host platform: `PLATFORM_FRAME_ID`, an opcode, then the arguments
+/// little-endian. Coils are numbered in registration order and switches by input bit.
+/// Strengths are percentages, as for `Command::Fire`.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Request {
+    /// Read every switch; answered with `Reply::Switches`.
+    QuerySwitches,
+    Pulse {
+        coil: u8,
+        millis: u16,
+        strength: u8,
+    },
+    /// Turn a coil on until `Disable`.
+    Enable {
+        coil: u8,
+        strength: u8,
+    },
+    Disable {
+        coil: u8,
+    },
+    /// Bind `switch` to `coil` on the board.
+    SetRule {
+        switch: u8,
+        coil: u8,
+        kind: RuleKind,
+        pulse_millis: u16,
+        pulse_strength: u8,
+        hold_strength: u8,
+    },
+    ClearRule {
+        switch: u8,
+        coil: u8,
+    },
+    /// Arm or feed the host watchdog: if nothing arrives within `timeout_ms`, every coil
+    /// is turned off until the host is back. Zero disarms it.
+    Watchdog {
+        timeout_ms: u16,
+    },
+}
+
+impl Request {
+    pub fn to_bytes(&self) -> Vec<u8, U16> {
+        let mut bytes = Vec::new();
+        let _ = bytes.push(PLATFORM_FRAME_ID);
+        let _ = match *self {
+            Request::QuerySwitches => bytes.extend_from_slice(&[OP_QUERY_SWITCHES]),
+            Request::Pulse {
+                coil,
+                millis,
+                strength,
+            } => {
+                let millis = millis.to_le_bytes();
+                bytes.extend_from_slice(&[OP_PULSE, coil, millis[0], millis[1], strength])
+            }
+            Request::Enable { coil, strength } => {
+                bytes.extend_from_slice(&[OP_ENABLE, coil, strength])
+            }
+            Request::Disable { coil } => bytes.extend_from_slice(&[OP_DISABLE, coil]),
+            Request::SetRule {
+                switch,
+                coil,
+                kind,
+                pulse_millis,
+                pulse_strength,
+                hold_strength,
+            } => {
+                let millis = pulse_millis.to_le_bytes();
+                bytes.extend_from_slice(&[
+                    OP_SET_RULE,
+                    switch,
+                    coil,
+                    kind as u8,
+                    millis[0],
+                    millis[1],
+                    pulse_strength,
+                    hold_strength,
+                ])
+            }
+            Request::ClearRule { switch, coil } => {
+                bytes.extend_from_slice(&[OP_CLEAR_RULE, switch, coil])
+            }
+            Request::Watchdog { timeout_ms } => {
+                let timeout = timeout_ms.to_le_bytes();
+                bytes.extend_from_slice(&[OP_WATCHDOG, timeout[0], timeout[1]])
+            }
+        };
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [PLATFORM_FRAME_ID, OP_QUERY_SWITCHES] => Some(Request::QuerySwitches),
+            [PLATFORM_FRAME_ID, OP_PULSE, coil, lo, hi, strength] => Some(Request::Pulse {
+                coil: *coil,
+                millis: u16::from_le_bytes([*lo, *hi]),
+                strength: *strength,
+            }),
+            [PLATFORM_FRAME_ID, OP_ENABLE, coil, strength] => Some(Request::Enable {
+                coil: *coil,
+                strength: *strength,
+            }),
+            [PLATFORM_FRAME_ID, OP_DISABLE, coil] => Some(Request::Disable { coil: *coil }),
+            [PLATFORM_FRAME_ID, OP_SET_RULE, switch, coil, kind, lo, hi, pulse, hold] => {
+                Some(Request::SetRule {
+                    switch: *switch,
+                    coil: *coil,
+                    kind: RuleKind::from_u8(*kind)?,
+                    pulse_millis: u16::from_le_bytes([*lo, *hi]),
+                    pulse_strength: *pulse,
+                    hold_strength: *hold,
+                })
+            }
+            [PLATFORM_FRAME_ID, OP_CLEAR_RULE, switch, coil] => Some(Request::ClearRule {
+                switch: *switch,
+                coil: *coil,
+            }),
+            [PLATFORM_FRAME_ID, OP_WATCHDOG, lo, hi] => Some(Request::Watchdog {
+                timeout_ms: u16::from_le_bytes([*lo, *hi]),
+            }),
+            _ => None,
+        }
+    }
+
+    fn opcode(&self) -> u8 {
+        self.to_bytes()[1]
+    }
+}
+
+/// The board's answer to a `Request`. Switch changes aren't replies: `Platform::poll`
+/// hands them over as `events::EventFrame`s to push to the host.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reply {
+    /// The request with this opcode was carried out.
+    Ack { op: u8 },
+    /// The raw input word, electrical levels, and when it was read.
+    Switches { raw: u64, timestamp: u32 },
+    /// The request with this opcode was refused.
+    Nak { op: u8 },
+}
+
+impl Reply {
+    pub fn to_bytes(&self) -> Vec<u8, U16> {
+        let mut bytes = Vec::new();
+        let _ = bytes.push(PLATFORM_FRAME_ID);
+        let _ = match *self {
+            Reply::Ack { op } => bytes.extend_from_slice(&[OP_ACK, op]),
+            Reply::Switches { raw, timestamp } => {
+                let _ = bytes.push(OP_SWITCHES);
+                let _ = bytes.extend_from_slice(&raw.to_le_bytes());
+                bytes.extend_from_slice(&timestamp.to_le_bytes())
+            }
+            Reply::Nak { op } => bytes.extend_from_slice(&[OP_NAK, op]),
+        };
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [PLATFORM_FRAME_ID, OP_ACK, op] => Some(Reply::Ack { op: *op }),
+            [PLATFORM_FRAME_ID, OP_NAK, op] => Some(Reply::Nak { op: *op }),
+            [PLATFORM_FRAME_ID, OP_SWITCHES, rest @ ..] if rest.len() == 12 => {
+                let mut raw = [0; 8];
+                raw.copy_from_slice(&rest[..8]);
+                let mut timestamp = [0; 4];
+                timestamp.copy_from_slice(&rest[8..]);
+                Some(Reply::Switches {
+                    raw: u64::from_le_bytes(raw),
+                    timestamp: u32::from_le_bytes(timestamp),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Platform is the board's side of a host platform driver, e.g. for the Mission Pinball
+/// Framework: the host pulses and enables coils, reads switches and gets every change
+/// pushed to it, and keeps a watchdog fed so a crashed host can't leave a coil on. Coil
+/// requests become `Command::Fire`s and `Controller::enable`s, so the board's own on-time
+/// limits still apply, and hardware rules become the controller's switch rules
+/// (`rules::Rule`).
+///
+/// Hand each request to `handle` and send back the reply; call `poll` every tick and send
+/// whatever switch events it returns.
+pub struct Platform {
+    // Timeout and deadline while armed.
+    watchdog: Option<(u32, u32)>,
+    expired: bool,
+}
+
+impl Default for Platform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Platform {
+    pub fn new() -> Self {
+        Self {
+            watchdog: None,
+            expired: false,
+        }
+    }
+
+    /// Whether the host let the watchdog run out. Coils stay off, and coil requests are
+    /// refused, until it sends `Request::Watchdog` again.
+    pub fn is_expired(&self) -> bool {
+        self.expired
+    }
+
+    pub fn handle<S, A, D, W, WD>(
+        &mut self,
+        request: Request,
+        controller: &mut Controller<S, A, D, W, WD>,
+    ) -> Reply
+    where
+        S: Controllable<W>,
+        A: Evaluate<W>,
+        D: OutputDriver,
+        W: Word,
+        WD: Watchdog,
+    {
+        let op = request.opcode();
+        match self.carry_out(request, controller) {
+            Ok(Some(reply)) => reply,
+            Ok(None) => Reply::Ack { op },
+            Err(_e) => {
+                debug!("platform request {} refused: {}", request, _e);
+                Reply::Nak { op }
+            }
+        }
+    }
+
+    fn carry_out<S, A, D, W, WD>(
+        &mut self,
+        request: Request,
+        controller: &mut Controller<S, A, D, W, WD>,
+    ) -> Result<Option<Reply>, Error>
+    where
+        S: Controllable<W>,
+        A: Evaluate<W>,
+        D: OutputDriver,
+        W: Word,
+        WD: Watchdog,
+    {
+        let now = controller.inputs().timestamp();
+        // Any request shows the host is alive.
+        if let Some((timeout, deadline)) = self.watchdog.as_mut() {
+            *deadline = now.wrapping_add(*timeout);
+        }
+        match request {
+            Request::QuerySwitches => Ok(Some(Reply::Switches {
+                raw: controller.inputs().raw().widen(),
+                timestamp: now,
+            })),
+            Request::Watchdog { timeout_ms: 0 } => {
+                self.watchdog = None;
+                self.revive(controller);
+                Ok(None)
+            }
+            Request::Watchdog { timeout_ms } => {
+                let timeout = timeout_ms as u32;
+                self.watchdog = Some((timeout, now.wrapping_add(timeout)));
+                self.revive(controller);
+                Ok(None)
+            }
+            _ if self.expired => Err(Error::WrongMode),
+            Request::Pulse {
+                coil,
+                millis,
+                strength,
+            } => {
+                controller.dispatch(Command::Fire {
+                    id: coil,
+                    strength,
+                    millis,
+                })?;
+                Ok(None)
+            }
+            Request::Enable { coil, strength } => {
+                controller.enable(coil as usize, strength)?;
+                Ok(None)
+            }
+            Request::Disable { coil } => {
+                controller.dispatch(Command::Fire {
+                    id: coil,
+                    strength: 0,
+                    millis: 0,
+                })?;
+                Ok(None)
+            }
+            Request::SetRule {
+                switch,
+                coil,
+                kind,
+                pulse_millis,
+                pulse_strength,
+                hold_strength,
+            } => {
+                // A hit closes the switch, which for an active-low one is a falling edge.
+                let inverted =
+                    controller.config().inverted & 1u64.checked_shl(switch as u32).unwrap_or(0);
+                let (hit, release) = if inverted != 0 {
+                    (Edge::Falling, Edge::Rising)
+                } else {
+                    (Edge::Rising, Edge::Falling)
+                };
+                let action = match kind {
+                    RuleKind::PulseOnHitAndEnableAndRelease => Action::Enable {
+                        pulse_millis,
+                        strength: pulse_strength,
+                        hold_strength,
+                    },
+                    _ => Action::Pulse {
+                        millis: pulse_millis,
+                        strength: pulse_strength,
+                    },
+                };
+                let rule = Rule {
+                    switch,
+                    edge: hit,
+                    actuator: coil,
+                    action,
+                };
+                controller.remove_rules(switch, coil);
+                controller.add_rule(rule)?;
+                if kind != RuleKind::PulseOnHit {
+                    let cut = Rule {
+                        edge: release,
+                        action: Action::Disable,
+                        ..rule
+                    };
+                    if let Err(e) = controller.add_rule(cut) {
+                        controller.remove_rules(switch, coil);
+                        return Err(e);
+                    }
+                }
+                Ok(None)
+            }
+            Request::ClearRule { switch, coil } => {
+                controller.remove_rules(switch, coil);
+                Ok(None)
+            }
+        }
+    }
+
+    // The host is back after the watchdog ran out.
+    fn revive<S, A, D, W, WD>(&mut self, controller: &mut Controller<S, A, D, W, WD>)
+    where
+        S: Controllable<W>,
+        A: Evaluate<W>,
+        D: OutputDriver,
+        W: Word,
+        WD: Watchdog,
+    {
+        if self.expired {
+            info!("platform host is back");
+            self.expired = false;
+            controller.set_inhibit(false);
+        }
+    }
+
+    /// Runs the watchdog and returns the switch changes to push to
+    /// the host, if any. Call every tick, after the controller's.
+    pub fn poll<S, A, D, W, WD>(
+        &mut self,
+        controller: &mut Controller<S, A, D, W, WD>,
+    ) -> Option<EventFrame>
+    where
+        S: Controllable<W>,
+        A: Evaluate<W>,
+        D: OutputDriver,
+        W: Word,
+        WD: Watchdog,
+    {
+        let now = controller.inputs().timestamp();
+        let is_due = |at: u32| Instant::from_millis(now) >= Instant::from_millis(at);
+
+        if let Some((_, deadline)) = self.watchdog {
+            if !self.expired && is_due(deadline) {
+                warn!("platform watchdog expired, coils off");
+                self.expired = true;
+                controller.set_inhibit(true);
+            }
+        }
+
+        EventFrame::drain(controller.inputs_mut())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mpf::{Platform, Reply, Request, RuleKind};
+    use crate::testing::{basic_controller, is_on};
+    use crate::Edge;
+
+    #[test]
+    fn requests_round_trip() {
+        let requests = [
+            Request::QuerySwitches,
+            Request::Pulse {
+                coil: 1,
+                millis: 25,
+                strength: 100,
+            },
+            Request::Enable {
+                coil: 2,
+                strength: 30,
+            },
+            Request::Disable { coil: 2 },
+            Request::SetRule {
+                switch: 4,
+                coil: 0,
+                kind: RuleKind::PulseOnHitAndEnableAndRelease,
+                pulse_millis: 30,
+                pulse_strength: 100,
+                hold_strength: 25,
+            },
+            Request::ClearRule { switch: 4, coil: 0 },
+            Request::Watchdog { timeout_ms: 1000 },
+        ];
+        for request in requests.iter() {
+            assert_eq!(Request::from_bytes(&request.to_bytes()), Some(*request));
+        }
+        let switches = Reply::Switches {
+            raw: 0x8001,
+            timestamp: 77,
+        };
+        assert_eq!(Reply::from_bytes(&switches.to_bytes()), Some(switches));
+        assert_eq!(Request::from_bytes(&[0x50, 0x02, 1]), None);
+    }
+
+    #[test]
+    fn host_drives_coils_behind_a_watchdog() {
+        let mut controller = basic_controller();
+        controller.tick_at(0).unwrap();
+        let mut platform = Platform::new();
+
+        let watchdog = Request::Watchdog { timeout_ms: 100 };
+        assert_eq!(
+            platform.handle(watchdog, &mut controller),
+            Reply::Ack { op: 0x07 }
+        );
+        let enable = Request::Enable {
+            coil: 0,
+            strength: 100,
+        };
+        assert_eq!(
+            platform.handle(enable, &mut controller),
+            Reply::Ack { op: 0x03 }
+        );
+        assert_eq!(
+            platform.handle(
+                Request::Enable {
+                    coil: 1,
+                    strength: 100
+                },
+                &mut controller
+            ),
+            Reply::Nak { op: 0x03 }
+        );
+
+        // The forced switch is reported like any other.
+        controller.tick_at(1).unwrap();
+        assert!(is_on(&controller));
+        let events = platform.poll(&mut controller).unwrap();
+        assert_eq!(
+            (events.events[0].bit, events.events[0].edge),
+            (0, Edge::Rising)
+        );
+        assert_eq!(
+            platform.handle(Request::QuerySwitches, &mut controller),
+            Reply::Switches {
+                raw: 1,
+                timestamp: 1
+            }
+        );
+
+        // The host goes quiet: coils off and refused until it's back.
+        for now in 2..=101 {
+            controller.tick_at(now).unwrap();
+            platform.poll(&mut controller);
+        }
+        assert!(platform.is_expired());
+        controller.tick_at(102).unwrap();
+        assert!(!is_on(&controller));
+        assert_eq!(
+            platform.handle(enable, &mut controller),
+            Reply::Nak { op: 0x03 }
+        );
+        platform.handle(watchdog, &mut controller);
+        assert!(!platform.is_expired());
+        platform.handle(enable, &mut controller);
+        controller.tick_at(103).unwrap();
+        assert!(is_on(&controller));
+
+        // The lockout and the master's attract mode don't lift each other.
+        controller.set_attract(true);
+        for now in 104..=204 {
+            controller.tick_at(now).unwrap();
+            platform.poll(&mut controller);
+        }
+        assert!(platform.is_expired() && controller.is_inhibited());
+        controller.set_attract(false);
+        controller.tick_at(205).unwrap();
+        assert!(!is_on(&controller));
+        controller.set_attract(true);
+        platform.handle(watchdog, &mut controller);
+        assert!(!controller.is_inhibited() && controller.is_attract());
+        controller.set_attract(false);
+        platform.handle(enable, &mut controller);
+        controller.tick_at(206).unwrap();
+        assert!(is_on(&controller));
+    }
+
+    #[test]
+    fn rules_fire_coils_without_the_host() {
+        let mut controller = basic_controller();
+        controller.tick_at(0).unwrap();
+        let mut platform = Platform::new();
+        let flipper = Request::SetRule {
+            switch: 1,
+            coil: 0,
+            kind: RuleKind::PulseOnHitAndEnableAndRelease,
+            pulse_millis: 30,
+            pulse_strength: 100,
+            hold_strength: 25,
+        };
+        assert_eq!(
+            platform.handle(flipper, &mut controller),
+            Reply::Ack { op: 0x05 }
+        );
+        assert_eq!(controller.rules().len(), 2);
+
+        controller.source_mut().0 = 0b10;
+        controller.tick_at(1).unwrap();
+        assert!(controller.actuators()[0].1 .0.enabled);
+        controller.source_mut().0 = 0;
+        controller.tick_at(2).unwrap();
+        assert!(!controller.actuators()[0].1 .0.enabled);
+
+        let clear = Request::ClearRule { switch: 1, coil: 0 };
+        platform.handle(clear, &mut controller);
+        assert!(controller.rules().is_empty());
+    }
+}
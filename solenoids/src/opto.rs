@@ -0,0 +1,159 @@
+use heapless::{consts::*, Vec};
+
+use crate::{InputArray, InputConfig, InputType, SingleInput, Word};
+
+/// Something that can't be true of correctly working optos.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OptoFault {
+    /// Every opto reads blocked at once, which usually means the opto boards have no
+    /// power or their ribbon cable is off.
+    AllBlocked,
+    /// `bit` reads blocked but `requires`, which must be blocked whenever it is, doesn't.
+    Missing { bit: u8, requires: u8 },
+    /// `bit` and `excludes` read blocked together.
+    Conflict { bit: u8, excludes: u8 },
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Rule {
+    Requires(u8, u8),
+    Excludes(u8, u8),
+}
+
+/// OptoCheck validates opto switches at power-on, before any coil is allowed to act on
+/// them. A failed emitter or an unpowered opto board reads as a blocked beam, so a bad
+/// opto can look like a ball sitting in front of an eject coil; rules about which beams
+/// can be blocked together turn those states into faults.
+///
+/// For example, in a trough with position 1 at the exit, a ball in position 3 requires
+/// balls in 1 and 2, and a diverter's open and closed position optos exclude each other.
+pub struct OptoCheck<W: Word = u16> {
+    optos: W,
+    active_low: W,
+    rules: Vec<Rule, U16>,
+}
+
+impl<W: Word> OptoCheck<W> {
+    pub fn new() -> Self {
+        Self {
+            optos: W::ZERO,
+            active_low: W::ZERO,
+            rules: Vec::new(),
+        }
+    }
+
+    /// Adds the bits of an opto input, with the polarity from its config (normally made
+    /// with `InputConfig::opto`).
+    pub fn opto<I: InputType>(mut self, input_config: &InputConfig<I>) -> Self {
+        let mask = InputArray::<W>::mask_of(input_config);
+        let start = input_config.start_offset as u8;
+        let inverted = (0..input_config.input_type.size())
+            .filter(|&n| input_config.inverted & 1 << n != 0 && start + n < W::BITS)
+            .fold(W::ZERO, |m, n| m | W::bit(start + n));
+        self.optos |= mask;
+        self.active_low = (self.active_low & !mask) | inverted;
+        self
+    }
+
+    /// Whenever `opto` is blocked, `required` must be too. Rules past 16 are ignored.
+    pub fn requires(
+        mut self,
+        opto: &InputConfig<SingleInput>,
+        required: &InputConfig<SingleInput>,
+    ) -> Self {
+        let _ = self.rules.push(Rule::Requires(
+            opto.start_offset as u8,
+            required.start_offset as u8,
+        ));
+        self
+    }
+
+    /// `opto` and `excluded` can't be blocked at the same time. Rules past 16 are ignored.
+    pub fn excludes(
+        mut self,
+        opto: &InputConfig<SingleInput>,
+        excluded: &InputConfig<SingleInput>,
+    ) -> Self {
+        let _ = self.rules.push(Rule::Excludes(
+            opto.start_offset as u8,
+            excluded.start_offset as u8,
+        ));
+        self
+    }
+
+    /// Optos currently reading blocked.
+    pub fn blocked(&self, inputs: &InputArray<W>) -> W {
+        (inputs.raw() ^ self.active_low) & self.optos
+    }
+
+    /// Checks the current levels, normally after the inputs have settled following
+    /// power-on. Returns every fault found; an empty result means the optos look sane.
+    pub fn validate(&self, inputs: &InputArray<W>) -> Vec<OptoFault, U16> {
+        let blocked = self.blocked(inputs);
+        let mut faults = Vec::new();
+        let optos = (0..W::BITS).filter(|&bit| self.optos.is_set(bit)).count();
+        if optos > 1 && blocked == self.optos {
+            let _ = faults.push(OptoFault::AllBlocked);
+        }
+
+        for rule in self.rules.iter() {
+            let fault = match *rule {
+                Rule::Requires(bit, requires)
+                    if blocked.is_set(bit) && !blocked.is_set(requires) =>
+                {
+                    OptoFault::Missing { bit, requires }
+                }
+                Rule::Excludes(bit, excludes)
+                    if blocked.is_set(bit) && blocked.is_set(excludes) =>
+                {
+                    OptoFault::Conflict { bit, excludes }
+                }
+                _ => continue,
+            };
+            let _ = faults.push(fault);
+        }
+        faults
+    }
+}
+
+impl<W: Word> Default for OptoCheck<W> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::opto::{OptoCheck, OptoFault};
+    use crate::{InputArray, SingleInput};
+
+    #[test]
+    fn trough_faults() {
+        let mut inputs = InputArray::new();
+        let trough1 = inputs.input::<SingleInput>().unwrap().opto();
+        let trough2 = inputs.input::<SingleInput>().unwrap().opto();
+        let trough3 = inputs.input::<SingleInput>().unwrap().opto();
+        let check = OptoCheck::new()
+            .opto(&trough1)
+            .opto(&trough2)
+            .opto(&trough3)
+            .requires(&trough3, &trough2)
+            .requires(&trough2, &trough1);
+
+        // Two balls, beams 1 and 2 broken.
+        inputs.update(0b100);
+        assert!(check.validate(&inputs).is_empty());
+
+        inputs.update(0b010);
+        assert_eq!(
+            &check.validate(&inputs)[..],
+            &[OptoFault::Missing {
+                bit: 2,
+                requires: 1
+            }]
+        );
+
+        inputs.update(0b000);
+        assert_eq!(&check.validate(&inputs)[..], &[OptoFault::AllBlocked]);
+    }
+}
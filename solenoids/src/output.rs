@@ -0,0 +1,425 @@
+use core::cell::RefCell;
+use core::convert::TryFrom;
+use embedded_hal::{blocking::spi::Write, digital::v2::OutputPin, PwmPin};
+
+use crate::pwm::{self, Configuration, Execute, State};
+
+const OFF: State = State {
+    enabled: false,
+    duty_cycle: 0,
+};
+
+/// OutputDriver is one physical output an actuator drives, whatever it is wired to: a
+/// timer channel, a software PWM channel, a GPIO or a shift register bit. The `Controller`
+/// maps every actuator to one, so actuators only deal in `State`s.
+pub trait OutputDriver {
+    fn apply(&mut self, state: &State);
+
+    /// The state the output is in, as near to the hardware as the driver can tell.
+    fn state(&self) -> State;
+}
+
+impl<D: OutputDriver + ?Sized> OutputDriver for &mut D {
+    fn apply(&mut self, state: &State) {
+        (**self).apply(state)
+    }
+
+    fn state(&self) -> State {
+        (**self).state()
+    }
+}
+
+/// PwmOutput drives any `PwmPin`: a `pwm::ChannelPin`, a `SoftChannel`, a PCA9685 channel
+/// or a GPIO through `DigitalPin`. `PwmPin` can't report whether it is enabled, so the
+/// last applied state is remembered.
+pub struct PwmOutput<P> {
+    pin: P,
+    state: State,
+}
+
+impl<P> PwmOutput<P>
+where
+    P: PwmPin,
+    P::Duty: Copy + Into<u32> + TryFrom<u32>,
+{
+    /// Wraps `pin` and turns it off.
+    pub fn new(mut pin: P) -> Self {
+        pwm::drive(&mut pin, &OFF);
+        Self { pin, state: OFF }
+    }
+
+    pub fn free(self) -> P {
+        self.pin
+    }
+}
+
+impl<P> OutputDriver for PwmOutput<P>
+where
+    P: PwmPin,
+    P::Duty: Copy + Into<u32> + TryFrom<u32>,
+{
+    fn apply(&mut self, state: &State) {
+        pwm::drive(&mut self.pin, state);
+        self.state = *state;
+    }
+
+    fn state(&self) -> State {
+        self.state
+    }
+}
+
+/// ChannelOutput drives one channel of an `Execute`, normally the shared `pwm::Controller`,
+/// so several outputs can use the same timers. The state is read back from the executor.
+pub struct ChannelOutput<'a, E> {
+    executor: &'a RefCell<E>,
+    config: Configuration,
+}
+
+impl<'a, E: Execute> ChannelOutput<'a, E> {
+    pub fn new(executor: &'a RefCell<E>, config: Configuration) -> Self {
+        Self { executor, config }
+    }
+
+    pub fn config(&self) -> &Configuration {
+        &self.config
+    }
+}
+
+impl<E: Execute> OutputDriver for ChannelOutput<'_, E> {
+    fn apply(&mut self, state: &State) {
+        self.executor.borrow_mut().apply(&self.config, state);
+    }
+
+    fn state(&self) -> State {
+        self.executor.borrow().state(&self.config)
+    }
+}
+
+/// How a digital output drives its line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Drive {
+    /// Driven high when active and low when idle.
+    PushPull,
+    /// Pulled low when active and released when idle, for shared lines and opto-isolated
+    /// driver inputs. The idle level comes from the external pull-up.
+    OpenDrain,
+}
+
+/// DigitalPin drives a plain GPIO as an on/off channel so actuators that don't need PWM
+/// can use the same `PwmPin` interface as the timer channels. Any non-zero duty is
+/// treated as fully on.
+pub struct DigitalPin<P: OutputPin> {
+    pin: P,
+    drive: Drive,
+    enabled: bool,
+    duty: u8,
+}
+
+impl<P: OutputPin> DigitalPin<P> {
+    /// Wraps `pin` and immediately puts it in its idle state.
+    pub fn new(pin: P, drive: Drive) -> Self {
+        let mut out = Self {
+            pin,
+            drive,
+            enabled: false,
+            duty: 0,
+        };
+        out.idle();
+        out
+    }
+
+    pub fn drive(&self) -> Drive {
+        self.drive
+    }
+
+    /// Forces the output off and drives its idle level. Failsafe and emergency-stop paths
+    /// should use this instead of `disable()` so an open-drain line is released rather
+    /// than pulled low.
+    pub fn idle(&mut self) {
+        self.enabled = false;
+        self.duty = 0;
+        self.write(false);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.enabled && self.duty != 0
+    }
+
+    pub fn free(mut self) -> P {
+        self.idle();
+        self.pin
+    }
+
+    fn write(&mut self, active: bool) {
+        // Pin errors can't be reported through `PwmPin`, and most HALs can't fail here.
+        let _ = match (self.drive, active) {
+            (Drive::PushPull, true) | (Drive::OpenDrain, false) => self.pin.set_high(),
+            (Drive::PushPull, false) | (Drive::OpenDrain, true) => self.pin.set_low(),
+        };
+    }
+
+    fn refresh(&mut self) {
+        let active = self.is_active();
+        self.write(active);
+    }
+}
+
+impl<P: OutputPin> PwmPin for DigitalPin<P> {
+    type Duty = u8;
+
+    fn disable(&mut self) {
+        self.enabled = false;
+        self.refresh();
+    }
+
+    fn enable(&mut self) {
+        self.enabled = true;
+        self.refresh();
+    }
+
+    fn get_duty(&self) -> Self::Duty {
+        self.duty
+    }
+
+    fn get_max_duty(&self) -> Self::Duty {
+        1
+    }
+
+    fn set_duty(&mut self, duty: Self::Duty) {
+        self.duty = duty.min(1);
+        self.refresh();
+    }
+}
+
+/// Most 74HC595s a `ShiftOutputs` chain drives.
+pub const MAX_OUTPUT_REGISTERS: u8 = 8;
+
+/// ShiftOutputs drives a chain of 74HC595s, e.g. feeding MOSFET banks, as one on/off word.
+/// Bit 0 is output QA of the register nearest the MCU. Changes are buffered until `flush`
+/// shifts the whole word out and pulses the latch (RCLK), so every output changes at once.
+///
+/// This is for actuators that don't need PWM: `apply` turns a `State` into its bit.
+pub struct ShiftOutputs<SPI, LATCH> {
+    spi: SPI,
+    latch: LATCH,
+    registers: u8,
+    outputs: u64,
+    dirty: bool,
+}
+
+impl<SPI, LATCH, E> ShiftOutputs<SPI, LATCH>
+where
+    SPI: Write<u8, Error = E>,
+    LATCH: OutputPin,
+{
+    /// `registers` is clamped to 1..=MAX_OUTPUT_REGISTERS. Everything starts off, but
+    /// nothing is written until the first `flush`.
+    pub fn new(spi: SPI, mut latch: LATCH, registers: u8) -> Self {
+        let _ = latch.set_low();
+        Self {
+            spi,
+            latch,
+            registers: registers.clamp(1, MAX_OUTPUT_REGISTERS),
+            outputs: 0,
+            dirty: true,
+        }
+    }
+
+    /// Outputs past the end of the chain are ignored.
+    pub fn set(&mut self, bit: u8, on: bool) {
+        if bit >= self.registers * 8 {
+            return;
+        }
+        let outputs = if on {
+            self.outputs | 1 << bit
+        } else {
+            self.outputs & !(1 << bit)
+        };
+        self.dirty |= outputs != self.outputs;
+        self.outputs = outputs;
+    }
+
+    /// Turns `bit` on for an enabled state with any non-zero duty, otherwise off.
+    pub fn apply(&mut self, bit: u8, state: &State) {
+        self.set(bit, state.enabled && state.duty_cycle != 0);
+    }
+
+    pub fn outputs(&self) -> u64 {
+        self.outputs
+    }
+
+    /// Whether there are changes `flush` hasn't written yet.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Turns every output off; still needs a `flush`.
+    pub fn clear(&mut self) {
+        self.dirty |= self.outputs != 0;
+        self.outputs = 0;
+    }
+
+    /// Shifts the word out, farthest register first, and latches it. Writes even when
+    /// nothing changed, which also repairs registers upset by noise.
+    pub fn flush(&mut self) -> Result<(), E> {
+        let bytes = self.outputs.to_le_bytes();
+        let mut chain = [0u8; MAX_OUTPUT_REGISTERS as usize];
+        let chain = &mut chain[..self.registers as usize];
+        for (byte, &out) in chain.iter_mut().rev().zip(bytes.iter()) {
+            *byte = out;
+        }
+        self.spi.write(chain)?;
+        let _ = self.latch.set_high();
+        let _ = self.latch.set_low();
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn free(self) -> (SPI, LATCH) {
+        (self.spi, self.latch)
+    }
+}
+
+/// ShiftBit drives one output of a shared `ShiftOutputs` chain. Applying a state only
+/// buffers the bit; the chain still has to be flushed once the whole update is done.
+pub struct ShiftBit<'a, SPI, LATCH> {
+    outputs: &'a RefCell<ShiftOutputs<SPI, LATCH>>,
+    bit: u8,
+}
+
+impl<'a, SPI, LATCH> ShiftBit<'a, SPI, LATCH> {
+    pub fn new(outputs: &'a RefCell<ShiftOutputs<SPI, LATCH>>, bit: u8) -> Self {
+        Self { outputs, bit }
+    }
+}
+
+impl<SPI, LATCH, E> OutputDriver for ShiftBit<'_, SPI, LATCH>
+where
+    SPI: Write<u8, Error = E>,
+    LATCH: OutputPin,
+{
+    fn apply(&mut self, state: &State) {
+        self.outputs.borrow_mut().apply(self.bit, state);
+    }
+
+    fn state(&self) -> State {
+        if self.outputs.borrow().outputs() & 1 << self.bit != 0 {
+            State {
+                enabled: true,
+                duty_cycle: u32::MAX,
+            }
+        } else {
+            OFF
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::output::{DigitalPin, Drive, OutputDriver, PwmOutput, ShiftBit, ShiftOutputs};
+    use crate::pwm::State;
+    use core::cell::{Cell, RefCell};
+    use core::convert::Infallible;
+    use embedded_hal::{blocking::spi::Write, digital::v2::OutputPin, PwmPin};
+    use heapless::{consts::*, Vec};
+
+    struct MockPin<'a>(&'a Cell<bool>);
+
+    impl OutputPin for MockPin<'_> {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            self.0.set(false);
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            self.0.set(true);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn push_pull_levels() {
+        let high = Cell::new(true);
+        let mut out = DigitalPin::new(MockPin(&high), Drive::PushPull);
+        assert!(!high.get());
+
+        out.set_duty(1);
+        out.enable();
+        assert!(high.get());
+
+        out.idle();
+        assert!(!high.get());
+    }
+
+    #[test]
+    fn open_drain_levels() {
+        let high = Cell::new(false);
+        let mut out = DigitalPin::new(MockPin(&high), Drive::OpenDrain);
+        assert!(high.get());
+
+        out.set_duty(1);
+        out.enable();
+        assert!(!high.get());
+
+        out.disable();
+        assert!(high.get());
+
+        out.enable();
+        out.idle();
+        assert!(high.get());
+        assert!(!out.is_active());
+    }
+
+    struct Spi<'a>(&'a RefCell<Vec<u8, U8>>);
+
+    impl Write<u8> for Spi<'_> {
+        type Error = Infallible;
+
+        fn write(&mut self, words: &[u8]) -> Result<(), Infallible> {
+            self.0.borrow_mut().clear();
+            self.0.borrow_mut().extend_from_slice(words).unwrap();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn shift_register_chain() {
+        let shifted = RefCell::new(Vec::new());
+        let latch = Cell::new(false);
+        let mut out = ShiftOutputs::new(Spi(&shifted), MockPin(&latch), 3);
+
+        out.apply(1, &State::from_percent(50.0));
+        out.set(9, true);
+        out.set(20, true);
+        out.set(24, true);
+        assert_eq!(out.outputs(), 1 << 1 | 1 << 9 | 1 << 20);
+        out.flush().unwrap();
+        assert_eq!(&shifted.borrow()[..], &[0x10, 0x02, 0x02]);
+        assert!(!latch.get());
+        assert!(!out.is_dirty());
+
+        out.apply(1, &State::from_percent(0.0));
+        assert!(out.is_dirty());
+    }
+
+    #[test]
+    fn drivers_report_applied_state() {
+        let high = Cell::new(false);
+        let mut gpio = PwmOutput::new(DigitalPin::new(MockPin(&high), Drive::PushPull));
+        gpio.apply(&State::from_percent(75.0));
+        assert!(high.get());
+        assert!(gpio.state().enabled);
+
+        let latch = Cell::new(false);
+        let shifted = RefCell::new(Vec::new());
+        let chain = RefCell::new(ShiftOutputs::new(Spi(&shifted), MockPin(&latch), 1));
+        let mut bit = ShiftBit::new(&chain, 3);
+        bit.apply(&State::from_percent(100.0));
+        assert_eq!(chain.borrow().outputs(), 1 << 3);
+        assert!(bit.state().enabled);
+        bit.apply(&State::from_percent(0.0));
+        assert!(!bit.state().enabled);
+    }
+}
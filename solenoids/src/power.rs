@@ -0,0 +1,154 @@
+use crate::pwm::State;
+
+/// What to do with a lower priority actuator whose duty would push the total over budget.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overload {
+    /// Disable the actuator for this update. It is reconsidered on the next update.
+    Defer,
+    /// Reduce the actuator's duty to whatever is left of the budget.
+    Derate,
+}
+
+/// PowerBudget caps the summed duty cycle of every enabled actuator sharing a supply. A
+/// `controller::Controller` applies one every tick with `with_power_budget`.
+/// The budget is in the same units as `State::duty_cycle`, so a budget of
+/// `2 * u32::MAX as u64` allows two actuators at full duty.
+#[derive(Clone, Copy, Debug)]
+pub struct PowerBudget {
+    budget: u64,
+    overload: Overload,
+}
+
+impl PowerBudget {
+    pub fn new(budget: u64, overload: Overload) -> Self {
+        Self { budget, overload }
+    }
+
+    /// Budget allowing `coils` actuators to run at full duty at the same time.
+    pub fn full_duty(coils: u32, overload: Overload) -> Self {
+        Self::new(coils as u64 * u32::MAX as u64, overload)
+    }
+
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    /// Applies the budget to `states`, which must be ordered from highest to lowest
+    /// priority. Returns the summed duty of the enabled states after arbitration.
+    pub fn arbitrate(&self, states: &mut [State]) -> u64 {
+        let mut used = 0u64;
+        for state in states.iter_mut().filter(|s| s.enabled) {
+            let remaining = self.budget - used;
+            let duty = state.duty_cycle as u64;
+            if duty <= remaining {
+                used += duty;
+                continue;
+            }
+
+            match self.overload {
+                Overload::Derate if remaining > 0 => {
+                    state.duty_cycle = remaining as u32;
+                    used += remaining;
+                }
+                _ => state.enabled = false,
+            }
+        }
+        used
+    }
+}
+
+/// What to do with an activation that would put more high-power outputs on than a
+/// `CoilLimit` allows.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overflow {
+    /// Hold the output off until enough others turn off, then let it on. Waiting outputs
+    /// go first come, first served. A `command::Command::Fire` or switch rule pulse only
+    /// starts once it's let on; an actuator's own timing keeps running while it waits, so
+    /// actuators that time their own pulses may come out short.
+    Queue,
+    /// Hold the output off until its actuator lets go of it. The activation is lost.
+    Reject,
+}
+
+/// CoilLimit caps how many high-power outputs a `controller::Controller` lets on at once,
+/// so a burst of activations, say during multiball, can't pull the supply down far enough
+/// to brown out the board. Outputs already on keep their place; activations past the
+/// limit are queued or rejected. Unlike `PowerBudget` it counts outputs rather than duty,
+/// since a coil's inrush is much the same whatever duty it's driven at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CoilLimit {
+    max: u8,
+    high_power: u16,
+    overflow: Overflow,
+}
+
+impl CoilLimit {
+    /// At most `max` outputs on at once, every output counting as high power.
+    pub fn new(max: u8, overflow: Overflow) -> Self {
+        Self {
+            max,
+            high_power: u16::MAX,
+            overflow,
+        }
+    }
+
+    /// Only the actuators in `mask`, a bit per actuator in registration order, count; the
+    /// rest, e.g. flasher LEDs, are never held back.
+    pub fn high_power(mut self, mask: u16) -> Self {
+        self.high_power = mask;
+        self
+    }
+
+    pub fn max(&self) -> u8 {
+        self.max
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    pub fn is_high_power(&self, index: usize) -> bool {
+        index < 16 && self.high_power & (1 << index) != 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::power::{Overload, PowerBudget};
+    use crate::pwm::State;
+
+    fn on(duty_cycle: u32) -> State {
+        State {
+            enabled: true,
+            duty_cycle,
+        }
+    }
+
+    #[test]
+    fn within_budget_untouched() {
+        let budget = PowerBudget::new(100, Overload::Defer);
+        let mut states = [on(40), on(60)];
+        assert_eq!(budget.arbitrate(&mut states), 100);
+        assert_eq!(states, [on(40), on(60)]);
+    }
+
+    #[test]
+    fn defer_skips_low_priority() {
+        let budget = PowerBudget::new(100, Overload::Defer);
+        let mut states = [on(70), on(50), on(30)];
+        assert_eq!(budget.arbitrate(&mut states), 100);
+        assert!(states[0].enabled);
+        assert!(!states[1].enabled);
+        assert!(states[2].enabled);
+    }
+
+    #[test]
+    fn derate_scales_low_priority() {
+        let budget = PowerBudget::new(100, Overload::Derate);
+        let mut states = [on(70), on(50), on(30)];
+        assert_eq!(budget.arbitrate(&mut states), 100);
+        assert_eq!(states[0], on(70));
+        assert_eq!(states[1], on(30));
+        assert!(!states[2].enabled);
+    }
+}
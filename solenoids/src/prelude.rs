@@ -0,0 +1,28 @@
+pub use crate::actuators::{Basic, Flipper};
+pub use crate::pwm::{Configuration, State};
+pub use crate::restart::RestartPolicy;
+pub use crate::wrappers::{
+    ActuatorExt, Cooldown, Decorator, Hold, MaxOnTime, Ramp, Randomize, Restart, Timebase,
+};
+pub use crate::{
+    Actuator, DualInput, InputArray, InputType, MultiInput, QuadInput, SingleInput, TriInput, Word,
+};
+
+/// Any actuator with an on-time limit. Every stack below ends in one, so a stuck input
+/// can never hold a coil on.
+pub type Protected<A> = MaxOnTime<A>;
+
+/// Knocker or ball launcher: locked out for a while after each fire.
+pub type Knocker = MaxOnTime<Cooldown<Basic>>;
+
+/// Pop bumper or slingshot with a slightly different kick each time.
+pub type Bumper = MaxOnTime<Randomize<Basic>>;
+
+/// Diverter or motor that soft-starts instead of kicking at full power.
+pub type SoftStart = MaxOnTime<Ramp<Basic>>;
+
+/// Up-post, magnet or gate, checked against the hold limit.
+pub type HoldCoil = MaxOnTime<Hold<Basic>>;
+
+/// Flipper with an on-time limit; `Flipper` is already hold-capable.
+pub type ProtectedFlipper = MaxOnTime<Flipper>;
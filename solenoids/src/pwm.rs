@@ -1,12 +1,77 @@
+use core::convert::TryFrom;
 use embedded_hal::{Pwm, PwmPin};
 use feather_m0 as hal;
 use hal::{
     clock::GenericClockController,
-    pac::{PM, TC3, TCC0, TCC1, TCC2},
+    pac::{tcc0, PM, TC3, TCC0, TCC1, TCC2},
     pwm::{self, Pwm0, Pwm1, Pwm2, Pwm3},
     time::Hertz,
 };
 
+use crate::{Actuator, Error, InputData, InputType};
+
+pub mod pca9685;
+pub mod waveform;
+
+// TCC register fields used directly, for features the HAL doesn't expose.
+const TCC_CTRLA_ENABLE: u32 = 1 << 1;
+const TCC_SYNCBUSY_ENABLE: u32 = 1 << 1;
+const TCC_WEXCTRL_DTIEN: u32 = 8;
+const TCC_WEXCTRL_DTLS: u32 = 16;
+const TCC_WEXCTRL_DTHS: u32 = 24;
+const TCC_CTRLB_ONESHOT: u8 = 1 << 2;
+const TCC_CTRLB_CMD_RETRIGGER: u8 = 1 << 5;
+const TCC_SYNCBUSY_CTRLB: u32 = 1 << 2;
+
+/// Dead-time inserted by TCC0 between complementary outputs, in TCC clock ticks: how long
+/// both outputs stay off after the low side turns off (`low_side`) and after the high
+/// side turns off (`high_side`).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DeadTime {
+    pub low_side: u8,
+    pub high_side: u8,
+}
+
+fn tcc(timer: Timer) -> Option<&'static tcc0::RegisterBlock> {
+    unsafe {
+        match timer {
+            Timer::Tcc0 => Some(&*TCC0::ptr()),
+            Timer::Tcc1 => Some(&*TCC1::ptr()),
+            Timer::Tcc2 => Some(&*TCC2::ptr()),
+            Timer::Tc3 => None,
+        }
+    }
+}
+
+fn wexctrl(complementary: u8, dead_time: DeadTime) -> u32 {
+    (complementary as u32 & 0xF) << TCC_WEXCTRL_DTIEN
+        | (dead_time.low_side as u32) << TCC_WEXCTRL_DTLS
+        | (dead_time.high_side as u32) << TCC_WEXCTRL_DTHS
+}
+
+/// Timer ticks in a pulse of `us` microseconds on a timer running at `frequency_hz` with
+/// `max_duty` ticks per period, or None if the pulse doesn't fit in one period.
+fn pulse_ticks(us: u32, max_duty: u32, frequency_hz: u32) -> Option<u32> {
+    let ticks = us as u64 * max_duty as u64 * frequency_hz as u64 / 1_000_000;
+    if ticks > max_duty as u64 {
+        None
+    } else {
+        Some(ticks as u32)
+    }
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timer {
+    Tcc0,
+    Tcc1,
+    Tcc2,
+    Tc3,
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Configuration {
     Tcc0(Channel),
     Tcc1(Channel),
@@ -14,12 +79,145 @@ pub enum Configuration {
     Tc3,
 }
 
+impl Configuration {
+    /// A unique number for the channel, 0-3 for TCC0 up to 12 for TC3.
+    pub fn index(&self) -> u8 {
+        match *self {
+            Configuration::Tcc0(channel) => channel.index(),
+            Configuration::Tcc1(channel) => 4 + channel.index(),
+            Configuration::Tcc2(channel) => 8 + channel.index(),
+            Configuration::Tc3 => 12,
+        }
+    }
+
+    pub fn timer(&self) -> Timer {
+        match *self {
+            Configuration::Tcc0(_) => Timer::Tcc0,
+            Configuration::Tcc1(_) => Timer::Tcc1,
+            Configuration::Tcc2(_) => Timer::Tcc2,
+            Configuration::Tc3 => Timer::Tc3,
+        }
+    }
+
+    /// The inverse of `index`.
+    pub fn from_index(index: u8) -> Option<Self> {
+        let channel = match index % 4 {
+            0 => Channel::_0,
+            1 => Channel::_1,
+            2 => Channel::_2,
+            _ => Channel::_3,
+        };
+        match index {
+            0..=3 => Some(Configuration::Tcc0(channel)),
+            4..=7 => Some(Configuration::Tcc1(channel)),
+            8..=11 => Some(Configuration::Tcc2(channel)),
+            12 => Some(Configuration::Tc3),
+            _ => None,
+        }
+    }
+}
+
+const OFF: State = State {
+    enabled: false,
+    duty_cycle: 0,
+};
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct State {
     pub enabled: bool,
     pub duty_cycle: u32,
 }
 
-#[derive(Clone, Copy)]
+impl State {
+    /// On at `percent` of full duty, clamped to 0..=100. Zero gives an off state.
+    pub fn from_percent(percent: f32) -> Self {
+        let duty = (percent.clamp(0.0, 100.0) / 100.0 * u32::MAX as f32) as u32;
+        Self {
+            enabled: duty != 0,
+            duty_cycle: duty,
+        }
+    }
+
+    /// On at `num / den` of full duty, clamped to 1. A zero numerator or denominator gives
+    /// an off state.
+    pub fn from_ratio(num: u32, den: u32) -> Self {
+        let duty = if den == 0 {
+            0
+        } else {
+            (num.min(den) as u64 * u32::MAX as u64 / den as u64) as u32
+        };
+        Self {
+            enabled: duty != 0,
+            duty_cycle: duty,
+        }
+    }
+
+    /// `duty_cycle` is a fraction of `u32::MAX`; this scales it to a timer whose
+    /// `get_max_duty()` is `max_duty`, e.g. TC3's 16-bit range, rounding to the nearest
+    /// step. 0 when disabled.
+    pub fn duty_for(&self, max_duty: u32) -> u32 {
+        if !self.enabled {
+            return 0;
+        }
+        let full = u32::MAX as u64;
+        ((self.duty_cycle as u64 * max_duty as u64 + full / 2) / full) as u32
+    }
+
+    /// This state with its duty moved at most `max_step` away from `current`'s, treating
+    /// a disabled `current` as zero duty. Disabled states are returned as they are, so
+    /// turning off is never delayed.
+    pub fn slewed(&self, current: &State, max_step: u32) -> State {
+        if !self.enabled {
+            return *self;
+        }
+        let from = if current.enabled {
+            current.duty_cycle
+        } else {
+            0
+        };
+        let duty_cycle = if self.duty_cycle > from {
+            self.duty_cycle.min(from.saturating_add(max_step))
+        } else {
+            self.duty_cycle.max(from.saturating_sub(max_step))
+        };
+        State {
+            enabled: true,
+            duty_cycle,
+        }
+    }
+
+    // What an active-low channel has to output for this state: always running, low for
+    // the active part of the period and high when off.
+    fn inverted(&self) -> State {
+        State {
+            enabled: true,
+            duty_cycle: if self.enabled {
+                !self.duty_cycle
+            } else {
+                u32::MAX
+            },
+        }
+    }
+
+    /// The inverse of `duty_for`: a state from a timer's raw duty, to the timer's
+    /// resolution.
+    pub fn from_duty(enabled: bool, duty: u32, max_duty: u32) -> Self {
+        let duty_cycle = if max_duty == 0 {
+            0
+        } else {
+            (duty.min(max_duty) as u64 * u32::MAX as u64 / max_duty as u64) as u32
+        };
+        Self {
+            enabled,
+            duty_cycle,
+        }
+    }
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Channel {
     _0,
     _1,
@@ -38,6 +236,17 @@ impl From<pwm::Channel> for Channel {
     }
 }
 
+impl Channel {
+    fn index(self) -> u8 {
+        match self {
+            Channel::_0 => 0,
+            Channel::_1 => 1,
+            Channel::_2 => 2,
+            Channel::_3 => 3,
+        }
+    }
+}
+
 impl Into<pwm::Channel> for Channel {
     fn into(self) -> pwm::Channel {
         match self {
@@ -49,14 +258,75 @@ impl Into<pwm::Channel> for Channel {
     }
 }
 
+/// The PWM frequency of each timer. TCC0/TCC1 and TCC2/TC3 share a generic clock, but
+/// every timer has its own prescaler and period, so solenoids, lamps and servos on
+/// different timers can each run at the frequency they want.
+#[derive(Clone, Copy)]
+pub struct Periods {
+    pub tcc0: Hertz,
+    pub tcc1: Hertz,
+    pub tcc2: Hertz,
+    pub tc3: Hertz,
+}
+
+impl Periods {
+    /// Every timer at `period`.
+    pub fn uniform<F: Into<Hertz>>(period: F) -> Self {
+        let period = period.into();
+        Self {
+            tcc0: period,
+            tcc1: period,
+            tcc2: period,
+            tc3: period,
+        }
+    }
+
+    pub fn tcc0<F: Into<Hertz>>(mut self, period: F) -> Self {
+        self.tcc0 = period.into();
+        self
+    }
+
+    pub fn tcc1<F: Into<Hertz>>(mut self, period: F) -> Self {
+        self.tcc1 = period.into();
+        self
+    }
+
+    pub fn tcc2<F: Into<Hertz>>(mut self, period: F) -> Self {
+        self.tcc2 = period.into();
+        self
+    }
+
+    pub fn tc3<F: Into<Hertz>>(mut self, period: F) -> Self {
+        self.tc3 = period.into();
+        self
+    }
+}
+
 pub struct Controller {
     tcc0: Pwm0,
     tcc1: Pwm1,
     tcc2: Pwm2,
     tc3: Pwm3,
+    periods: Periods,
+    // One bit per channel, TCC0 in bits 0-3 up to TC3 in bit 12.
+    enabled: u16,
+    // The last state applied to each channel at full resolution, for slew limiting.
+    applied: [State; 13],
+    slew_limit: u32,
+    // Active-low channels, same layout as `enabled`.
+    inverted: u16,
+    // TCC0 channels paired with dead-time insertion, one bit per channel.
+    complementary: u8,
+    dead_time: DeadTime,
+    // Channels bound to an actuator, same layout as `enabled`.
+    claimed: u16,
+    disabled: bool,
+    // Timers in one-shot mode, one bit per `Timer`.
+    one_shot: u8,
 }
 
 impl Controller {
+    /// Runs every timer at `period`.
     pub fn new<F: Into<Hertz> + Copy>(
         clocks: &mut GenericClockController,
         period: F,
@@ -65,16 +335,303 @@ impl Controller {
         tcc2: TCC2,
         tc3: TC3,
         pm: &mut PM,
+    ) -> Self {
+        Self::with_periods(clocks, Periods::uniform(period), tcc0, tcc1, tcc2, tc3, pm)
+    }
+
+    /// Like `new`, with a separate frequency for each timer.
+    pub fn with_periods(
+        clocks: &mut GenericClockController,
+        periods: Periods,
+        tcc0: TCC0,
+        tcc1: TCC1,
+        tcc2: TCC2,
+        tc3: TC3,
+        pm: &mut PM,
     ) -> Self {
         let gclk0 = clocks.gclk0();
         let tcc0tcc1clock = clocks.tcc0_tcc1(&gclk0).unwrap();
         let tcc2tc3clock = clocks.tcc2_tc3(&gclk0).unwrap();
         Self {
-            tcc0: Pwm0::new(&tcc0tcc1clock, period, tcc0, pm),
-            tcc1: Pwm1::new(&tcc0tcc1clock, period, tcc1, pm),
-            tcc2: Pwm2::new(&tcc2tc3clock, period, tcc2, pm),
-            tc3: Pwm3::new(&tcc2tc3clock, period, tc3, pm),
+            tcc0: Pwm0::new(&tcc0tcc1clock, periods.tcc0, tcc0, pm),
+            tcc1: Pwm1::new(&tcc0tcc1clock, periods.tcc1, tcc1, pm),
+            tcc2: Pwm2::new(&tcc2tc3clock, periods.tcc2, tcc2, pm),
+            tc3: Pwm3::new(&tcc2tc3clock, periods.tc3, tc3, pm),
+            periods,
+            enabled: 0,
+            applied: [OFF; 13],
+            slew_limit: u32::MAX,
+            inverted: 0,
+            complementary: 0,
+            dead_time: DeadTime::default(),
+            claimed: 0,
+            disabled: false,
+            one_shot: 0,
+        }
+    }
+
+    /// Puts a TCC in one-shot mode: instead of running continuously it stops at the end
+    /// of each period, so a channel on it outputs exactly one pulse per `pulse`. This
+    /// affects every channel on the timer, so dedicate one to pulse actuators. TC3 isn't
+    /// supported.
+    pub fn set_one_shot(&mut self, timer: Timer, one_shot: bool) -> Result<(), Error> {
+        let tcc = tcc(timer).ok_or(Error::Unsupported)?;
+        if one_shot {
+            tcc.ctrlbset.write(|w| unsafe { w.bits(TCC_CTRLB_ONESHOT) });
+            self.one_shot |= 1 << timer as u8;
+        } else {
+            tcc.ctrlbclr.write(|w| unsafe { w.bits(TCC_CTRLB_ONESHOT) });
+            self.one_shot &= !(1 << timer as u8);
         }
+        while tcc.syncbusy.read().bits() & TCC_SYNCBUSY_CTRLB != 0 {}
+        Ok(())
+    }
+
+    /// Fires a single hardware-timed pulse of `us` microseconds on the channel of
+    /// `config`, whose timer must be in one-shot mode. The pulse can be at most one
+    /// period long. Ignored, but still Ok, while `disable_all` is latched.
+    pub fn pulse(&mut self, config: &Configuration, us: u32) -> Result<(), Error> {
+        let timer = config.timer();
+        let tcc = tcc(timer)
+            .filter(|_| self.one_shot & 1 << timer as u8 != 0)
+            .ok_or(Error::Unsupported)?;
+        let frequency = match timer {
+            Timer::Tcc0 => self.periods.tcc0,
+            Timer::Tcc1 => self.periods.tcc1,
+            _ => self.periods.tcc2,
+        };
+        let max_duty = self.max_duty(config);
+        let ticks = pulse_ticks(us, max_duty, frequency.0).ok_or(Error::Unsupported)?;
+
+        self.apply(config, &State::from_duty(true, ticks, max_duty));
+        if !self.disabled {
+            tcc.ctrlbset
+                .write(|w| unsafe { w.bits(TCC_CTRLB_CMD_RETRIGGER) });
+            while tcc.syncbusy.read().bits() & TCC_SYNCBUSY_CTRLB != 0 {}
+        }
+        Ok(())
+    }
+
+    /// Turns every claimed or enabled channel off and keeps them off, whatever is applied,
+    /// until `enable_all`. For tilt, slam tilt and fault handling.
+    pub fn disable_all(&mut self) {
+        self.disabled = true;
+        let channels = self.claimed | self.enabled;
+        for index in (0..13).filter(|&i| channels & 1 << i != 0) {
+            if let Some(config) = Configuration::from_index(index) {
+                self.apply(&config, &OFF);
+            }
+        }
+    }
+
+    /// Clears `disable_all`. Channels stay off until their next `apply`.
+    pub fn enable_all(&mut self) {
+        self.disabled = false;
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
+    /// Records that an actuator drives the channel of `config`, so two actuators can't
+    /// silently fight over its duty. Fails if the channel is already claimed.
+    pub fn claim(&mut self, config: &Configuration) -> Result<(), Error> {
+        let slot = 1 << config.index();
+        if self.claimed & slot != 0 {
+            return Err(Error::ChannelInUse);
+        }
+        self.claimed |= slot;
+        Ok(())
+    }
+
+    pub fn release(&mut self, config: &Configuration) {
+        self.claimed &= !(1 << config.index());
+    }
+
+    pub fn is_claimed(&self, config: &Configuration) -> bool {
+        self.claimed & 1 << config.index() != 0
+    }
+
+    pub fn periods(&self) -> Periods {
+        self.periods
+    }
+
+    /// Changes `timer`'s PWM frequency while running, e.g. to try coil frequencies from
+    /// service mode. The timer's duty range changes with its period, so every channel on
+    /// it is rewritten to keep the same fraction of the period.
+    pub fn set_period<F: Into<Hertz>>(&mut self, timer: Timer, period: F) {
+        let period = period.into();
+        match timer {
+            Timer::Tcc0 => {
+                self.tcc0.set_period(period);
+                self.periods.tcc0 = period;
+            }
+            Timer::Tcc1 => {
+                self.tcc1.set_period(period);
+                self.periods.tcc1 = period;
+            }
+            Timer::Tcc2 => {
+                self.tcc2.set_period(period);
+                self.periods.tcc2 = period;
+            }
+            Timer::Tc3 => {
+                self.tc3.set_period(period);
+                self.periods.tc3 = period;
+            }
+        }
+
+        let channels = self.claimed | self.enabled | self.inverted;
+        for index in (0..13).filter(|&i| channels & 1 << i != 0) {
+            match Configuration::from_index(index) {
+                Some(config) if config.timer() == timer => {
+                    let state = self.applied[index as usize];
+                    self.apply(&config, &state);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Sets the dead-time shared by every complementary TCC0 pair.
+    pub fn set_dead_time(&mut self, dead_time: DeadTime) {
+        self.dead_time = dead_time;
+        self.write_wexctrl();
+    }
+
+    /// Pairs TCC0 `channel`'s output WO[n] (high side) with WO[n+4] (low side) as
+    /// complementary outputs with dead-time between them, for half-bridges driving
+    /// bidirectional motors and magnets. Only TCC0 has dead-time insertion. Both pins
+    /// must be muxed to TCC0 by the application; `apply` then drives the pair as one.
+    pub fn set_complementary(&mut self, channel: Channel, complementary: bool) {
+        if complementary {
+            self.complementary |= 1 << channel.index();
+        } else {
+            self.complementary &= !(1 << channel.index());
+        }
+        self.write_wexctrl();
+    }
+
+    pub fn is_complementary(&self, channel: Channel) -> bool {
+        self.complementary & 1 << channel.index() != 0
+    }
+
+    // WEXCTRL is enable-protected, so TCC0 is stopped for the write.
+    fn write_wexctrl(&mut self) {
+        let value = wexctrl(self.complementary, self.dead_time);
+        let tcc0 = unsafe { &*TCC0::ptr() };
+        tcc0.ctrla
+            .modify(|r, w| unsafe { w.bits(r.bits() & !TCC_CTRLA_ENABLE) });
+        while tcc0.syncbusy.read().bits() & TCC_SYNCBUSY_ENABLE != 0 {}
+        tcc0.wexctrl.write(|w| unsafe { w.bits(value) });
+        tcc0.ctrla
+            .modify(|r, w| unsafe { w.bits(r.bits() | TCC_CTRLA_ENABLE) });
+        while tcc0.syncbusy.read().bits() & TCC_SYNCBUSY_ENABLE != 0 {}
+    }
+
+    /// Marks the channel of `config` as active-low, for gate drivers that switch on a low
+    /// input. An inverted channel idles high and its duty is the fraction of the period
+    /// it spends low; `apply` and `state` keep working in active terms. The channel is
+    /// rewritten straight away so it doesn't sit at the wrong idle level.
+    pub fn set_inverted(&mut self, config: &Configuration, inverted: bool) {
+        let index = config.index();
+        if inverted {
+            self.inverted |= 1 << index;
+        } else {
+            self.inverted &= !(1 << index);
+        }
+        let state = self.applied[index as usize];
+        self.apply(config, &state);
+    }
+
+    pub fn is_inverted(&self, config: &Configuration) -> bool {
+        self.inverted & 1 << config.index() != 0
+    }
+
+    /// Limits how far any channel's duty may move per `apply`, in `State::duty_cycle`
+    /// units, as a global guard independent of each actuator's own ramping. Turning a
+    /// channel off is always immediate. `u32::MAX`, the default, disables the limit.
+    pub fn set_slew_limit(&mut self, max_step: u32) {
+        self.slew_limit = max_step;
+    }
+
+    pub fn slew_limit(&self) -> u32 {
+        self.slew_limit
+    }
+
+    /// Drives the channel of `config` with `state`, scaling its duty to the timer's range
+    /// and applying the slew limit. Disabled states turn the channel off with zero duty,
+    /// as does every state while `disable_all` is latched.
+    pub fn apply(&mut self, config: &Configuration, state: &State) {
+        let index = config.index() as usize;
+        let state = if self.disabled { &OFF } else { state };
+        let state = &state.slewed(&self.applied[index], self.slew_limit);
+        self.applied[index] = *state;
+        let slot = 1 << index;
+        if state.enabled {
+            self.enabled |= slot;
+        } else {
+            self.enabled &= !slot;
+        }
+        let state = &if self.inverted & slot != 0 {
+            state.inverted()
+        } else {
+            *state
+        };
+        match *config {
+            Configuration::Tcc0(channel) => drive(&mut self.tcc0_channel(channel), state),
+            Configuration::Tcc1(channel) => drive(&mut self.tcc1_channel(channel), state),
+            Configuration::Tcc2(channel) => drive(&mut self.tcc2_channel(channel), state),
+            Configuration::Tc3 => drive(&mut self.tc3, state),
+        }
+    }
+
+    /// Runs one update of `actuator`: its current state is read back from its channel, so
+    /// it sees what the hardware is really doing, and the result is applied. Returns the
+    /// state applied.
+    pub fn update<I: InputType, A: Actuator<I>>(
+        &mut self,
+        actuator: &mut A,
+        data: &InputData<I>,
+    ) -> State {
+        let config = *actuator.pwm_config();
+        let next = actuator.update_state(data, self.state(&config));
+        self.apply(&config, &next);
+        self.state(&config)
+    }
+
+    /// The raw duty that drives the channel of `config` fully on, which is what
+    /// `State::duty_for` and DMA waveform tables scale against.
+    pub fn max_duty(&self, config: &Configuration) -> u32 {
+        match *config {
+            Configuration::Tcc0(_) => self.tcc0.get_max_duty(),
+            Configuration::Tcc1(_) => self.tcc1.get_max_duty(),
+            Configuration::Tcc2(_) => self.tcc2.get_max_duty(),
+            Configuration::Tc3 => self.tc3.get_max_duty().into(),
+        }
+    }
+
+    /// The state the channel of `config` is actually in, read back from the timer. Duty is
+    /// in active terms for inverted channels.
+    pub fn state(&self, config: &Configuration) -> State {
+        let enabled = self.enabled & 1 << config.index() != 0;
+        let (duty, max_duty) = match *config {
+            Configuration::Tcc0(channel) => {
+                (self.tcc0.get_duty(channel.into()), self.tcc0.get_max_duty())
+            }
+            Configuration::Tcc1(channel) => {
+                (self.tcc1.get_duty(channel.into()), self.tcc1.get_max_duty())
+            }
+            Configuration::Tcc2(channel) => {
+                (self.tcc2.get_duty(channel.into()), self.tcc2.get_max_duty())
+            }
+            Configuration::Tc3 => (self.tc3.get_duty().into(), self.tc3.get_max_duty().into()),
+        };
+        let duty = if self.is_inverted(config) {
+            max_duty - duty.min(max_duty)
+        } else {
+            duty
+        };
+        State::from_duty(enabled, duty, max_duty)
     }
 
     pub fn tcc0_channel(&mut self, channel: Channel) -> ChannelPin<Pwm0> {
@@ -103,6 +660,23 @@ impl Controller {
     }
 }
 
+/// Something that can drive and read back actuator channels by `Configuration`; the
+/// timers themselves (`Controller`), or a stand-in in tests.
+pub trait Execute {
+    fn apply(&mut self, config: &Configuration, state: &State);
+    fn state(&self, config: &Configuration) -> State;
+}
+
+impl Execute for Controller {
+    fn apply(&mut self, config: &Configuration, state: &State) {
+        Controller::apply(self, config, state)
+    }
+
+    fn state(&self, config: &Configuration) -> State {
+        Controller::state(self, config)
+    }
+}
+
 pub struct ChannelPin<'a, P: Pwm> {
     controller: &'a mut P,
     channel: Channel,
@@ -131,3 +705,126 @@ impl<P: Pwm<Channel = pwm::Channel>> PwmPin for ChannelPin<'_, P> {
         self.controller.set_duty(self.channel.into(), duty);
     }
 }
+
+/// Drives any `PwmPin` with `state`, scaling its duty to the pin's range. Disabled states
+/// turn the pin off with zero duty.
+pub fn drive<P>(pin: &mut P, state: &State)
+where
+    P: PwmPin,
+    P::Duty: Copy + Into<u32> + TryFrom<u32>,
+{
+    let max_duty = pin.get_max_duty();
+    if state.enabled {
+        let duty = P::Duty::try_from(state.duty_for(max_duty.into())).unwrap_or(max_duty);
+        pin.set_duty(duty);
+        pin.enable();
+    } else {
+        pin.disable();
+        if let Ok(zero) = P::Duty::try_from(0) {
+            pin.set_duty(zero);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pwm::{pulse_ticks, wexctrl, Channel, Configuration, DeadTime, State, Timer};
+
+    #[test]
+    fn fractional_duty_scales_to_timer() {
+        let full = State::from_percent(100.0);
+        assert_eq!(full.duty_cycle, u32::MAX);
+        assert_eq!(full.duty_for(0xFFFF), 0xFFFF);
+        assert_eq!(full.duty_for(0xFF_FFFF), 0xFF_FFFF);
+
+        let half = State::from_ratio(1, 2);
+        assert!(half.enabled);
+        assert_eq!(half.duty_for(0xFFFF), 0x7FFF);
+        assert_eq!(State::from_ratio(3, 0), State::from_percent(0.0));
+        assert!(!State::from_percent(-5.0).enabled);
+        assert_eq!(State::from_ratio(5, 4), full);
+    }
+
+    #[test]
+    fn slew_limited_duty() {
+        let off = State::from_percent(0.0);
+        let full = State::from_percent(100.0);
+        let step = u32::MAX / 4;
+        let first = full.slewed(&off, step);
+        assert_eq!(first.duty_cycle, step);
+        assert_eq!(full.slewed(&first, step).duty_cycle, 2 * step);
+        assert_eq!(off.slewed(&full, step), off);
+        assert_eq!(
+            State::from_ratio(1, 8).slewed(&full, step).duty_cycle,
+            u32::MAX - step
+        );
+        assert_eq!(full.slewed(&off, u32::MAX), full);
+    }
+
+    #[test]
+    fn channel_indices_are_unique() {
+        let channels = [Channel::_0, Channel::_1, Channel::_2, Channel::_3];
+        let mut seen = 0u16;
+        for &channel in channels.iter() {
+            for config in [
+                Configuration::Tcc0(channel),
+                Configuration::Tcc1(channel),
+                Configuration::Tcc2(channel),
+            ]
+            .iter()
+            {
+                seen |= 1 << config.index();
+            }
+        }
+        seen |= 1 << Configuration::Tc3.index();
+        assert_eq!(seen, 0x1FFF);
+
+        for index in 0..13 {
+            assert_eq!(
+                Configuration::from_index(index).map(|c| c.index()),
+                Some(index)
+            );
+        }
+        assert!(Configuration::from_index(13).is_none());
+        assert_eq!(Configuration::from_index(9).unwrap().timer(), Timer::Tcc2);
+        assert_eq!(Configuration::Tc3.timer(), Timer::Tc3);
+    }
+
+    #[test]
+    fn one_shot_pulse_length() {
+        // 100 Hz with 480000 ticks per period is 48 ticks per microsecond.
+        assert_eq!(pulse_ticks(250, 480_000, 100), Some(12_000));
+        assert_eq!(pulse_ticks(10_000, 480_000, 100), Some(480_000));
+        assert_eq!(pulse_ticks(10_001, 480_000, 100), None);
+    }
+
+    #[test]
+    fn dead_time_register() {
+        let dead_time = DeadTime {
+            low_side: 12,
+            high_side: 4,
+        };
+        assert_eq!(wexctrl(0b0101, dead_time), 0x040C_0500);
+        assert_eq!(wexctrl(0, DeadTime::default()), 0);
+    }
+
+    #[test]
+    fn inverted_output() {
+        assert_eq!(
+            State::from_percent(0.0).inverted(),
+            State::from_percent(100.0)
+        );
+        assert_eq!(State::from_ratio(1, 4).inverted().duty_for(4), 3);
+        assert_eq!(State::from_percent(100.0).inverted().duty_cycle, 0);
+    }
+
+    #[test]
+    fn duty_read_back() {
+        let half = State::from_ratio(1, 2);
+        let raw = half.duty_for(0xFFFF);
+        let back = State::from_duty(true, raw, 0xFFFF);
+        assert_eq!(back.duty_for(0xFFFF), raw);
+        assert_eq!(State::from_duty(true, 0xFFFF, 0xFFFF).duty_cycle, u32::MAX);
+        assert_eq!(State::from_duty(false, 7, 0).duty_cycle, 0);
+    }
+}
@@ -0,0 +1,209 @@
+use embedded_hal::{blocking::i2c::Write, PwmPin};
+
+use crate::pwm::State;
+
+// Registers and bits, from the PCA9685 datasheet.
+const MODE1: u8 = 0x00;
+const MODE2: u8 = 0x01;
+const LED0_ON_L: u8 = 0x06;
+const PRE_SCALE: u8 = 0xFE;
+const MODE1_AI: u8 = 1 << 5;
+const MODE1_SLEEP: u8 = 1 << 4;
+const MODE2_INVRT: u8 = 1 << 4;
+const MODE2_OUTDRV: u8 = 1 << 2;
+const FULL: u8 = 1 << 4;
+const OSCILLATOR_HZ: u32 = 25_000_000;
+
+/// Channels on one PCA9685.
+pub const CHANNELS: usize = 16;
+/// Duty of a fully on channel.
+pub const MAX_DUTY: u16 = 4096;
+
+/// Pca9685 drives a PCA9685 16-channel, 12-bit PWM controller over I2C, for lamps and
+/// low-power coils when the TCC channels run out. Every channel shares one frequency,
+/// between 24 Hz and 1526 Hz.
+///
+/// Channels implement `PwmPin`, so they work with `pwm::drive` and the executors; I2C
+/// errors can't be reported through `PwmPin`, so use `apply` where they matter.
+pub struct Pca9685<I2C> {
+    bus: I2C,
+    address: u8,
+    duty: [u16; CHANNELS],
+    enabled: u16,
+}
+
+impl<I2C, E> Pca9685<I2C>
+where
+    I2C: Write<Error = E>,
+{
+    /// `address` is the 7-bit address, 0x40 with every address pin low.
+    pub fn new(bus: I2C, address: u8) -> Self {
+        Self {
+            bus,
+            address,
+            duty: [0; CHANNELS],
+            enabled: 0,
+        }
+    }
+
+    /// Sets the PWM frequency and output stage and turns every channel off. `inverted`
+    /// is for drivers that switch on a low output; `totem_pole` drives the outputs both
+    /// ways rather than open-drain.
+    pub fn init(&mut self, frequency_hz: u32, totem_pole: bool, inverted: bool) -> Result<(), E> {
+        let prescale = (OSCILLATOR_HZ + 2048 * frequency_hz.max(1)) / (4096 * frequency_hz.max(1));
+        let prescale = prescale.saturating_sub(1).clamp(3, 255) as u8;
+        let mut mode2 = 0;
+        if totem_pole {
+            mode2 |= MODE2_OUTDRV;
+        }
+        if inverted {
+            mode2 |= MODE2_INVRT;
+        }
+
+        // The prescaler can only be written while the oscillator sleeps.
+        self.bus.write(self.address, &[MODE1, MODE1_SLEEP])?;
+        self.bus.write(self.address, &[PRE_SCALE, prescale])?;
+        self.bus.write(self.address, &[MODE2, mode2])?;
+        self.bus.write(self.address, &[MODE1, MODE1_AI])?;
+        for channel in 0..CHANNELS as u8 {
+            self.write(channel, false, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Drives `channel` with `state`, scaled to the 12-bit range.
+    pub fn apply(&mut self, channel: u8, state: &State) -> Result<(), E> {
+        self.write(
+            channel,
+            state.enabled,
+            state.duty_for(MAX_DUTY as u32) as u16,
+        )
+    }
+
+    /// The state last written to `channel`.
+    pub fn state(&self, channel: u8) -> State {
+        let index = channel as usize % CHANNELS;
+        State::from_duty(
+            self.enabled & 1 << index != 0,
+            self.duty[index] as u32,
+            MAX_DUTY as u32,
+        )
+    }
+
+    /// A `PwmPin` view of one channel. Out of range channels panic.
+    pub fn channel(&mut self, channel: u8) -> Pca9685Channel<'_, I2C> {
+        assert!((channel as usize) < CHANNELS);
+        Pca9685Channel { pca: self, channel }
+    }
+
+    pub fn free(self) -> I2C {
+        self.bus
+    }
+
+    fn write(&mut self, channel: u8, enabled: bool, duty: u16) -> Result<(), E> {
+        let index = channel as usize % CHANNELS;
+        let duty = duty.min(MAX_DUTY);
+        self.duty[index] = duty;
+        if enabled {
+            self.enabled |= 1 << index;
+        } else {
+            self.enabled &= !(1 << index);
+        }
+
+        // Every channel turns on at count 0 and off at its duty; the FULL bits cover the
+        // ends of the range the counter can't express.
+        let (on_h, off) = match (enabled, duty) {
+            (false, _) | (true, 0) => (0, (FULL as u16) << 8),
+            (true, MAX_DUTY) => (FULL, 0),
+            (true, duty) => (0, duty),
+        };
+        let off = off.to_le_bytes();
+        self.bus.write(
+            self.address,
+            &[LED0_ON_L + 4 * channel, 0, on_h, off[0], off[1]],
+        )
+    }
+}
+
+/// One channel of a `Pca9685`. Every change is written to the chip immediately.
+pub struct Pca9685Channel<'a, I2C> {
+    pca: &'a mut Pca9685<I2C>,
+    channel: u8,
+}
+
+impl<I2C, E> PwmPin for Pca9685Channel<'_, I2C>
+where
+    I2C: Write<Error = E>,
+{
+    type Duty = u16;
+
+    fn disable(&mut self) {
+        let duty = self.get_duty();
+        let _ = self.pca.write(self.channel, false, duty);
+    }
+
+    fn enable(&mut self) {
+        let duty = self.get_duty();
+        let _ = self.pca.write(self.channel, true, duty);
+    }
+
+    fn get_duty(&self) -> u16 {
+        self.pca.duty[self.channel as usize]
+    }
+
+    fn get_max_duty(&self) -> u16 {
+        MAX_DUTY
+    }
+
+    fn set_duty(&mut self, duty: u16) {
+        let enabled = self.pca.enabled & 1 << self.channel != 0;
+        let _ = self.pca.write(self.channel, enabled, duty);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pwm::pca9685::{Pca9685, MAX_DUTY};
+    use crate::pwm::{self, State};
+    use core::convert::Infallible;
+    use embedded_hal::blocking::i2c::Write;
+    use heapless::{consts::*, Vec};
+
+    struct I2c(Vec<Vec<u8, U8>, U32>);
+
+    impl Write for I2c {
+        type Error = Infallible;
+
+        fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Infallible> {
+            assert_eq!(address, 0x40);
+            let mut write = Vec::new();
+            write.extend_from_slice(bytes).unwrap();
+            self.0.push(write).unwrap();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn channel_registers() {
+        let mut pca = Pca9685::new(I2c(Vec::new()), 0x40);
+        pca.init(200, true, false).unwrap();
+        {
+            let writes = &pca.bus.0;
+            assert_eq!(&writes[1][..], &[0xFE, 30]);
+            assert_eq!(&writes[2][..], &[0x01, 0x04]);
+            assert_eq!(writes.len(), 4 + 16);
+        }
+
+        pca.bus.0.clear();
+        pca.apply(2, &State::from_ratio(1, 4)).unwrap();
+        pwm::drive(&mut pca.channel(3), &State::from_percent(100.0));
+        pca.apply(4, &State::from_percent(0.0)).unwrap();
+        let writes = &pca.bus.0;
+        assert_eq!(&writes[0][..], &[0x0E, 0, 0, 0x00, 0x04]);
+        assert_eq!(writes.last().unwrap()[..], [0x16, 0, 0, 0x00, 0x10]);
+        assert!(writes.iter().any(|w| w[..] == [0x12, 0, 0x10, 0, 0]));
+
+        assert_eq!(pca.state(3).duty_for(MAX_DUTY as u32), MAX_DUTY as u32);
+        assert!(!pca.state(4).enabled);
+    }
+}
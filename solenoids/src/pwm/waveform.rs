@@ -0,0 +1,155 @@
+use feather_m0 as hal;
+use hal::pac::{DMAC, TCC0, TCC1, TCC2};
+
+use crate::dma::{self, Descriptor};
+use crate::pwm::{Channel, Configuration, State};
+use crate::Error;
+
+/// Waveforms that can play at once, one DMA channel each.
+pub const MAX_WAVEFORMS: usize = dma::WAVEFORM_CHANNELS;
+
+// DMAC register fields.
+const CHCTRLA_SWRST: u8 = 1 << 0;
+const CHCTRLA_ENABLE: u8 = 1 << 1;
+const CHCTRLB_TRIGSRC: u32 = 8;
+const CHCTRLB_TRIGACT_BEAT: u32 = 2 << 22;
+
+// Transfer descriptor BTCTRL fields.
+const BTCTRL_VALID: u16 = 1 << 0;
+const BTCTRL_BEATSIZE_WORD: u16 = 2 << 8;
+const BTCTRL_SRCINC: u16 = 1 << 10;
+
+// Trigger sources: each TCC's overflow, so one table entry is used per PWM period.
+const TRIGSRC_TCC0_OVF: u32 = 0x0D;
+const TRIGSRC_TCC1_OVF: u32 = 0x12;
+const TRIGSRC_TCC2_OVF: u32 = 0x15;
+
+// Offset of CCB0 in a TCC. Writing the buffered compare value makes the new duty take
+// effect at the next period boundary, so the output never glitches mid-period.
+const TCC_CCB0: u32 = 0x70;
+
+/// Waveforms streams precomputed duty tables to TCC channels by DMA, one entry per PWM
+/// period, for lamp fades and shaker rumble envelopes that cost no CPU once started.
+///
+/// Tables hold raw duty values for the target timer (see `Controller::max_duty` and
+/// `fill`). The channel must be enabled through the `Controller` before playing, and the
+/// controller's own writes to it will fight the table until `stop` is called.
+pub struct Waveforms {
+    dmac: DMAC,
+    playing: u8,
+}
+
+impl Waveforms {
+    pub fn new(dmac: DMAC) -> Self {
+        dma::enable(&dmac);
+        Self { dmac, playing: 0 }
+    }
+
+    /// Plays `table` on the channel of `config` using DMA channel `slot`, once or over and
+    /// over. Replaces whatever `slot` was playing. TC3 isn't supported, nor are empty
+    /// tables or tables longer than 65535 entries.
+    pub fn play(
+        &mut self,
+        slot: usize,
+        config: &Configuration,
+        table: &'static [u32],
+        looped: bool,
+    ) -> Result<(), Error> {
+        let (ccb, trigger) = match *config {
+            Configuration::Tcc0(channel) => (ccb(TCC0::ptr() as u32, channel), TRIGSRC_TCC0_OVF),
+            Configuration::Tcc1(channel) => (ccb(TCC1::ptr() as u32, channel), TRIGSRC_TCC1_OVF),
+            Configuration::Tcc2(channel) => (ccb(TCC2::ptr() as u32, channel), TRIGSRC_TCC2_OVF),
+            Configuration::Tc3 => return Err(Error::Unsupported),
+        };
+        if slot >= MAX_WAVEFORMS || table.is_empty() || table.len() > u16::MAX as usize {
+            return Err(Error::Unsupported);
+        }
+
+        self.stop(slot);
+        unsafe {
+            let descriptor = dma::descriptor(slot);
+            *descriptor = Descriptor {
+                btctrl: BTCTRL_VALID | BTCTRL_BEATSIZE_WORD | BTCTRL_SRCINC,
+                btcnt: table.len() as u16,
+                // With SRCINC the source address is the end of the block.
+                srcaddr: table.as_ptr().add(table.len()) as u32,
+                dstaddr: ccb,
+                descaddr: if looped {
+                    descriptor as *const Descriptor as u32
+                } else {
+                    0
+                },
+            };
+        }
+
+        self.select(slot);
+        self.dmac
+            .chctrlb
+            .write(|w| unsafe { w.bits(trigger << CHCTRLB_TRIGSRC | CHCTRLB_TRIGACT_BEAT) });
+        self.dmac
+            .chctrla
+            .write(|w| unsafe { w.bits(CHCTRLA_ENABLE) });
+        self.playing |= 1 << slot;
+        Ok(())
+    }
+
+    /// Stops `slot`, leaving its channel at the last duty written.
+    pub fn stop(&mut self, slot: usize) {
+        if slot >= MAX_WAVEFORMS {
+            return;
+        }
+        self.select(slot);
+        self.dmac.chctrla.write(|w| unsafe { w.bits(0) });
+        while self.dmac.chctrla.read().bits() & CHCTRLA_ENABLE != 0 {}
+        self.dmac
+            .chctrla
+            .write(|w| unsafe { w.bits(CHCTRLA_SWRST) });
+        self.playing &= !(1 << slot);
+    }
+
+    /// Whether `slot` is still running. One-shot tables stop by themselves at the end.
+    pub fn is_playing(&self, slot: usize) -> bool {
+        if self.playing & 1 << slot == 0 {
+            return false;
+        }
+        self.dmac.chid.write(|w| unsafe { w.bits(slot as u8) });
+        self.dmac.chctrla.read().bits() & CHCTRLA_ENABLE != 0
+    }
+
+    /// Stops every slot. The DMAC itself stays enabled, as DMA input reads may share it.
+    pub fn free(mut self) -> DMAC {
+        for slot in 0..MAX_WAVEFORMS {
+            self.stop(slot);
+        }
+        self.dmac
+    }
+
+    fn select(&mut self, slot: usize) {
+        self.dmac.chid.write(|w| unsafe { w.bits(slot as u8) });
+    }
+}
+
+fn ccb(tcc: u32, channel: Channel) -> u32 {
+    tcc + TCC_CCB0 + 4 * channel.index() as u32
+}
+
+/// Fills `table` with raw duties for a timer whose maximum duty is `max_duty`, from the
+/// state `shape` gives for each entry index.
+pub fn fill<F: Fn(usize) -> State>(table: &mut [u32], max_duty: u32, shape: F) {
+    for (i, entry) in table.iter_mut().enumerate() {
+        *entry = shape(i).duty_for(max_duty);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pwm::waveform::fill;
+    use crate::pwm::State;
+
+    #[test]
+    fn fill_fade() {
+        let mut table = [0; 5];
+        fill(&mut table, 400, |i| State::from_ratio(i as u32, 4));
+        assert_eq!(table, [0, 100, 200, 300, 400]);
+    }
+}
@@ -0,0 +1,100 @@
+/// What an actuator does after a soft reset when the black box shows it was active at the
+/// time. Actuators that were off, and every actuator after a power-on, start normally.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RestartPolicy {
+    /// Stay off until the actuator's inputs have all been released, so it only comes back
+    /// on when commanded again.
+    StayOff,
+    /// Carry on as before, e.g. re-energize a held up-post whose input is still high.
+    Resume,
+    /// Run the homing pulse (see `wrappers::Restart`), then behave like `StayOff`.
+    Home,
+}
+
+const MAGIC: u32 = 0x5EED_B0C5;
+
+/// BlackBox records which actuators are active so the firmware can tell, after a soft
+/// reset, what was energized when it went down. It is meant to live in RAM that the
+/// runtime doesn't initialize (e.g. a `.uninit` section); contents left over from a power
+/// cycle fail the integrity check and read as nothing active.
+///
+/// Actuators are identified by slot, normally the start offset of their input, which is
+/// unique and stable across a soft reset of the same firmware.
+#[repr(C)]
+pub struct BlackBox {
+    magic: u32,
+    active: u32,
+    check: u32,
+}
+
+impl BlackBox {
+    pub const fn new() -> Self {
+        Self {
+            magic: MAGIC,
+            active: 0,
+            check: !0,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.magic == MAGIC && self.check == !self.active
+    }
+
+    /// Whether the actuator in `slot` was recorded as active. Always false when the
+    /// contents are invalid.
+    pub fn was_active(&self, slot: u8) -> bool {
+        self.is_valid() && slot < 32 && self.active & (1 << slot) != 0
+    }
+
+    pub fn record(&mut self, slot: u8, active: bool) {
+        if !self.is_valid() {
+            self.clear();
+        }
+        if slot >= 32 {
+            return;
+        }
+        if active {
+            self.active |= 1 << slot;
+        } else {
+            self.active &= !(1 << slot);
+        }
+        self.check = !self.active;
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl Default for BlackBox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::restart::BlackBox;
+
+    #[test]
+    fn records_and_validates() {
+        let mut blackbox = BlackBox::new();
+        assert!(blackbox.is_valid());
+        assert!(!blackbox.was_active(3));
+
+        blackbox.record(3, true);
+        blackbox.record(5, true);
+        blackbox.record(5, false);
+        assert!(blackbox.was_active(3));
+        assert!(!blackbox.was_active(5));
+
+        // Left over from a power cycle.
+        blackbox.check = 0x1234;
+        assert!(!blackbox.was_active(3));
+        blackbox.record(1, true);
+        assert!(blackbox.is_valid());
+        assert!(blackbox.was_active(1));
+        assert!(!blackbox.was_active(3));
+    }
+}
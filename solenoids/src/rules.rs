@@ -0,0 +1,47 @@
+use crate::Edge;
+
+/// Most switch rules a `controller::Controller` holds.
+pub const MAX_RULES: usize = 32;
+
+/// What a `Rule` does to its actuator. Strengths are percentages, as for
+/// `command::Command::Fire`.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    /// Fire for `millis`.
+    Pulse { millis: u16, strength: u8 },
+    /// Fire at `strength` for `pulse_millis`, then at `hold_strength` until a `Disable`.
+    Enable {
+        pulse_millis: u16,
+        strength: u8,
+        hold_strength: u8,
+    },
+    /// End whatever a rule or the master started on the actuator.
+    Disable,
+}
+
+/// Rule binds an edge of a switch to an action on an actuator, carried out by the
+/// controller in the same tick that commits the edge, so a flipper or slingshot doesn't
+/// wait on the master. Edges are of the committed electrical level, as in `InputEvent`;
+/// for an active-low switch a closure is `Edge::Falling`.
+///
+/// The switch can't be one of the actuator's own inputs, which the action overrides.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rule {
+    /// Bit of the input word.
+    pub switch: u8,
+    pub edge: Edge,
+    /// Index of a registered actuator.
+    pub actuator: u8,
+    pub action: Action,
+}
+
+impl Rule {
+    /// Whether an update that changed `changed`, leaving the inputs at `raw`, triggers the
+    /// rule.
+    pub fn is_triggered(&self, changed: u64, raw: u64) -> bool {
+        let bit = 1u64.checked_shl(self.switch as u32).unwrap_or(0);
+        changed & bit != 0 && (raw & bit != 0) == (self.edge == Edge::Rising)
+    }
+}
@@ -0,0 +1,142 @@
+use bitflags::bitflags;
+
+bitflags! {
+    /// Everything that can inhibit the outputs, plus whether they are armed at all.
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    pub struct SafetyState: u8 {
+        const ARMED = 1 << 0;
+        const KILLED = 1 << 1;
+        const TILTED = 1 << 2;
+        const GLASS_OFF = 1 << 3;
+        const BUS_LOST = 1 << 4;
+        const OVER_TEMP = 1 << 5;
+    }
+}
+
+impl SafetyState {
+    /// Whether outputs must be held off in this state.
+    pub fn inhibited(self) -> bool {
+        !self.contains(SafetyState::ARMED)
+            || self.intersects(
+                SafetyState::KILLED
+                    | SafetyState::TILTED
+                    | SafetyState::GLASS_OFF
+                    | SafetyState::BUS_LOST
+                    | SafetyState::OVER_TEMP,
+            )
+    }
+}
+
+/// First byte of every safety summary frame.
+pub const SAFETY_FRAME_ID: u8 = 0x5A;
+
+/// Compact frame carrying the complete safety state. A new frame is produced on every
+/// change so the master and any diagnostic tool always know why outputs are inhibited.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SafetyFrame {
+    /// Incremented for every frame so listeners can spot a missed change.
+    pub sequence: u8,
+    pub state: SafetyState,
+}
+
+impl SafetyFrame {
+    pub fn to_bytes(&self) -> [u8; 3] {
+        [SAFETY_FRAME_ID, self.sequence, self.state.bits()]
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [SAFETY_FRAME_ID, sequence, bits] => Some(Self {
+                sequence: *sequence,
+                state: SafetyState::from_bits(*bits)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// SafetyMonitor tracks the interlock state and queues a summary frame whenever it
+/// changes. Several changes between polls collapse into one frame with the latest state.
+pub struct SafetyMonitor {
+    state: SafetyState,
+    sequence: u8,
+    pending: bool,
+}
+
+impl SafetyMonitor {
+    /// Starts disarmed, with an initial frame pending so the master learns the state at
+    /// boot.
+    pub fn new() -> Self {
+        Self {
+            state: SafetyState::empty(),
+            sequence: 0,
+            pending: true,
+        }
+    }
+
+    pub fn state(&self) -> SafetyState {
+        self.state
+    }
+
+    pub fn set(&mut self, flags: SafetyState, value: bool) {
+        let mut next = self.state;
+        next.set(flags, value);
+        if next != self.state {
+            self.state = next;
+            self.pending = true;
+        }
+    }
+
+    /// Returns the frame to broadcast if the state changed since the last poll.
+    pub fn poll(&mut self) -> Option<SafetyFrame> {
+        if !self.pending {
+            return None;
+        }
+
+        self.pending = false;
+        let frame = SafetyFrame {
+            sequence: self.sequence,
+            state: self.state,
+        };
+        self.sequence = self.sequence.wrapping_add(1);
+        Some(frame)
+    }
+}
+
+impl Default for SafetyMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::safety::{SafetyFrame, SafetyMonitor, SafetyState};
+
+    #[test]
+    fn frame_on_change_only() {
+        let mut monitor = SafetyMonitor::new();
+        let boot = monitor.poll().unwrap();
+        assert_eq!(boot.state, SafetyState::empty());
+        assert!(boot.state.inhibited());
+        assert!(monitor.poll().is_none());
+
+        monitor.set(SafetyState::ARMED, true);
+        let armed = monitor.poll().unwrap();
+        assert_eq!(armed.sequence, 1);
+        assert!(!armed.state.inhibited());
+
+        monitor.set(SafetyState::ARMED, true);
+        assert!(monitor.poll().is_none());
+
+        monitor.set(SafetyState::TILTED, true);
+        monitor.set(SafetyState::GLASS_OFF, true);
+        let frame = monitor.poll().unwrap();
+        assert_eq!(
+            frame.state,
+            SafetyState::ARMED | SafetyState::TILTED | SafetyState::GLASS_OFF
+        );
+        assert!(frame.state.inhibited());
+        assert_eq!(SafetyFrame::from_bytes(&frame.to_bytes()), Some(frame));
+    }
+}
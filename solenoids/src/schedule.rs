@@ -0,0 +1,167 @@
+use heapless::{consts::*, Vec};
+
+use crate::pwm::State;
+use crate::time::Instant;
+use crate::Error;
+
+/// Set `target` to `state` once time `at` has come.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Action<T> {
+    pub at: u32,
+    pub target: T,
+    pub state: State,
+}
+
+/// Scheduler holds up to 16 deferred actions until they're due: delayed resets, staged
+/// sequences, retries. Times are in the units of the input timestamps (see
+/// `InputArray::update_at`) and compared wrap-safe, so an action can be at most about half
+/// the timestamp range ahead.
+///
+/// The target is whatever identifies an output to whoever runs the actions: the index of a
+/// registered actuator for `controller::Controller::schedule`, or a `pwm::Configuration`
+/// when driving `pwm::Controller` directly through `run`.
+pub struct Scheduler<T> {
+    actions: Vec<Action<T>, U16>,
+}
+
+impl<T: Copy + PartialEq> Scheduler<T> {
+    pub fn new() -> Self {
+        Self {
+            actions: Vec::new(),
+        }
+    }
+
+    /// Queues `target` to be set to `state` at `at`. Actions due at the same time run in
+    /// the order they were scheduled.
+    pub fn schedule(&mut self, at: u32, target: T, state: State) -> Result<(), Error> {
+        self.actions
+            .push(Action { at, target, state })
+            .map_err(|_| Error::ScheduleFull)
+    }
+
+    /// Drops every pending action for `target`, returning how many there were.
+    pub fn cancel(&mut self, target: T) -> usize {
+        let mut removed = 0;
+        let mut i = 0;
+        while i < self.actions.len() {
+            if self.actions[i].target == target {
+                self.remove(i);
+                removed += 1;
+            } else {
+                i += 1;
+            }
+        }
+        removed
+    }
+
+    pub fn clear(&mut self) {
+        self.actions.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.actions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// When the earliest pending action is due.
+    pub fn next_due(&self) -> Option<u32> {
+        self.earliest().map(|i| self.actions[i].at)
+    }
+
+    /// Removes and returns the earliest action due at or before `now`.
+    pub fn pop_due(&mut self, now: u32) -> Option<Action<T>> {
+        let i = self.earliest()?;
+        if Instant::from_millis(now) < Instant::from_millis(self.actions[i].at) {
+            return None;
+        }
+        Some(self.remove(i))
+    }
+
+    /// Runs every action due at `now`, earliest first, through `apply`.
+    pub fn run<F: FnMut(&T, &State)>(&mut self, now: u32, mut apply: F) {
+        while let Some(action) = self.pop_due(now) {
+            apply(&action.target, &action.state);
+        }
+    }
+
+    // Shifts rather than swaps so same-time actions keep their order.
+    fn remove(&mut self, i: usize) -> Action<T> {
+        let action = self.actions[i];
+        for j in i..self.actions.len() - 1 {
+            self.actions[j] = self.actions[j + 1];
+        }
+        self.actions.pop();
+        action
+    }
+
+    // The first of the earliest, so same-time actions keep their order.
+    fn earliest(&self) -> Option<usize> {
+        self.actions
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, action)| Instant::from_millis(action.at))
+            .map(|(i, _)| i)
+    }
+}
+
+impl<T: Copy + PartialEq> Default for Scheduler<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pwm::{Channel, Configuration, State};
+    use crate::schedule::Scheduler;
+    use crate::Error;
+
+    const ON: State = State {
+        enabled: true,
+        duty_cycle: 100,
+    };
+    const OFF: State = State {
+        enabled: false,
+        duty_cycle: 0,
+    };
+
+    #[test]
+    fn runs_due_actions_in_time_order() {
+        let mut scheduler = Scheduler::new();
+        let start = u32::MAX - 5;
+        let tc3 = Configuration::Tc3;
+        let tcc = Configuration::Tcc0(Channel::_1);
+        scheduler
+            .schedule(start.wrapping_add(20), tc3, OFF)
+            .unwrap();
+        scheduler.schedule(start.wrapping_add(10), tcc, ON).unwrap();
+        scheduler.schedule(start.wrapping_add(10), tc3, ON).unwrap();
+        assert_eq!(scheduler.next_due(), Some(4));
+
+        let mut ran = [None; 3];
+        let mut count = 0;
+        let mut record = |target: &Configuration, state: &State| {
+            ran[count] = Some((*target, *state));
+            count += 1;
+        };
+        scheduler.run(start.wrapping_add(9), &mut record);
+        scheduler.run(start.wrapping_add(15), &mut record);
+        assert_eq!(ran, [Some((tcc, ON)), Some((tc3, ON)), None]);
+        assert_eq!(scheduler.len(), 1);
+
+        assert_eq!(scheduler.cancel(tc3), 1);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn full_queue_is_an_error() {
+        let mut scheduler = Scheduler::new();
+        for at in 0..16 {
+            scheduler.schedule(at, 0usize, OFF).unwrap();
+        }
+        assert_eq!(scheduler.schedule(16, 0, OFF), Err(Error::ScheduleFull));
+    }
+}
@@ -0,0 +1,200 @@
+use embedded_hal::{digital::v2::OutputPin, PwmPin};
+
+/// Most channels a `SoftPwm` drives.
+pub const MAX_SOFT_CHANNELS: usize = 16;
+
+/// A fixed set of output pins written together. Implemented for tuples of up to 16
+/// `OutputPin`s, which may all be different types; element 0 is channel 0.
+pub trait OutputSet {
+    const LEN: usize;
+
+    /// Drives every pin whose bit is set in `changed` to its bit in `levels`. Pin errors
+    /// are ignored, as most HALs can't fail here.
+    fn write(&mut self, levels: u16, changed: u16);
+}
+
+macro_rules! impl_output_set {
+    ($len:expr; $($pin:ident $idx:tt),+) => {
+        impl<$($pin: OutputPin),+> OutputSet for ($($pin,)+) {
+            const LEN: usize = $len;
+
+            fn write(&mut self, levels: u16, changed: u16) {
+                $(
+                    if changed & 1 << $idx != 0 {
+                        let _ = if levels & 1 << $idx != 0 {
+                            self.$idx.set_high()
+                        } else {
+                            self.$idx.set_low()
+                        };
+                    }
+                )+
+            }
+        }
+    };
+}
+
+impl_output_set!(1; P0 0);
+impl_output_set!(2; P0 0, P1 1);
+impl_output_set!(3; P0 0, P1 1, P2 2);
+impl_output_set!(4; P0 0, P1 1, P2 2, P3 3);
+impl_output_set!(5; P0 0, P1 1, P2 2, P3 3, P4 4);
+impl_output_set!(6; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5);
+impl_output_set!(7; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6);
+impl_output_set!(8; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7);
+impl_output_set!(9; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8);
+impl_output_set!(10; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9);
+impl_output_set!(11; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9, P10 10);
+impl_output_set!(
+    12; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9, P10 10, P11 11
+);
+impl_output_set!(
+    13; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9, P10 10, P11 11, P12 12
+);
+impl_output_set!(
+    14; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9, P10 10, P11 11, P12 12,
+    P13 13
+);
+impl_output_set!(
+    15; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9, P10 10, P11 11, P12 12,
+    P13 13, P14 14
+);
+impl_output_set!(
+    16; P0 0, P1 1, P2 2, P3 3, P4 4, P5 5, P6 6, P7 7, P8 8, P9 9, P10 10, P11 11, P12 12,
+    P13 13, P14 14, P15 15
+);
+
+/// SoftPwm generates PWM on plain GPIOs, so lamps, relays and GI strings don't use up TCC
+/// channels. Call `tick` from a periodic timer interrupt; each period is `steps` ticks
+/// long, so a 100 Hz output with 100 steps needs a 10 kHz tick. Pins are only written
+/// when their level changes.
+pub struct SoftPwm<PINS> {
+    pins: PINS,
+    steps: u16,
+    counter: u16,
+    duty: [u16; MAX_SOFT_CHANNELS],
+    enabled: u16,
+    levels: u16,
+}
+
+impl<PINS: OutputSet> SoftPwm<PINS> {
+    /// Takes `pins` and drives them all low. `steps` is the duty resolution, at least 1.
+    pub fn new(mut pins: PINS, steps: u16) -> Self {
+        pins.write(0, u16::MAX);
+        Self {
+            pins,
+            steps: steps.max(1),
+            counter: 0,
+            duty: [0; MAX_SOFT_CHANNELS],
+            enabled: 0,
+            levels: 0,
+        }
+    }
+
+    pub fn steps(&self) -> u16 {
+        self.steps
+    }
+
+    /// Advances the period by one step and updates the pins. Returns the pin levels.
+    pub fn tick(&mut self) -> u16 {
+        self.counter = if self.counter + 1 >= self.steps {
+            0
+        } else {
+            self.counter + 1
+        };
+        let mut levels = 0;
+        for (i, &duty) in self.duty.iter().enumerate().take(PINS::LEN) {
+            if self.enabled & 1 << i != 0 && self.counter < duty {
+                levels |= 1 << i;
+            }
+        }
+        self.pins.write(levels, levels ^ self.levels);
+        self.levels = levels;
+        levels
+    }
+
+    /// A `PwmPin` view of one channel, for the executors. Out of range channels panic.
+    pub fn channel(&mut self, index: usize) -> SoftChannel<'_, PINS> {
+        assert!(index < PINS::LEN);
+        SoftChannel { pwm: self, index }
+    }
+
+    pub fn free(mut self) -> PINS {
+        self.pins.write(0, u16::MAX);
+        self.pins
+    }
+}
+
+/// One channel of a `SoftPwm`. Changes take effect on the next tick.
+pub struct SoftChannel<'a, PINS> {
+    pwm: &'a mut SoftPwm<PINS>,
+    index: usize,
+}
+
+impl<PINS: OutputSet> PwmPin for SoftChannel<'_, PINS> {
+    type Duty = u16;
+
+    fn disable(&mut self) {
+        self.pwm.enabled &= !(1 << self.index);
+    }
+
+    fn enable(&mut self) {
+        self.pwm.enabled |= 1 << self.index;
+    }
+
+    fn get_duty(&self) -> u16 {
+        self.pwm.duty[self.index]
+    }
+
+    fn get_max_duty(&self) -> u16 {
+        self.pwm.steps
+    }
+
+    fn set_duty(&mut self, duty: u16) {
+        self.pwm.duty[self.index] = duty.min(self.pwm.steps);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pwm::{self, State};
+    use crate::soft_pwm::SoftPwm;
+    use core::cell::Cell;
+    use core::convert::Infallible;
+    use embedded_hal::{digital::v2::OutputPin, PwmPin};
+
+    struct MockPin<'a>(&'a Cell<u32>);
+
+    impl OutputPin for MockPin<'_> {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            self.0.set(self.0.get() + 1);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn duty_over_a_period() {
+        let (lamp, relay) = (Cell::new(0), Cell::new(0));
+        let mut soft = SoftPwm::new((MockPin(&lamp), MockPin(&relay)), 4);
+        pwm::drive(&mut soft.channel(0), &State::from_ratio(1, 4));
+        pwm::drive(&mut soft.channel(1), &State::from_percent(100.0));
+
+        let mut high = [0; 2];
+        for _ in 0..8 {
+            let levels = soft.tick();
+            high[0] += levels & 1;
+            high[1] += levels >> 1 & 1;
+        }
+        assert_eq!(high, [2, 8]);
+        // The relay is only switched on once, the lamp once per period.
+        assert_eq!((lamp.get(), relay.get()), (2, 1));
+
+        soft.channel(1).disable();
+        assert_eq!(soft.tick() & 0b10, 0);
+    }
+}
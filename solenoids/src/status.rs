@@ -0,0 +1,192 @@
+use embedded_hal::digital::v2::OutputPin;
+
+use crate::time::Duration;
+
+/// What the status LED is reporting.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Code {
+    /// Running normally.
+    Ok,
+    /// The link to the host has gone quiet.
+    CommLost,
+    /// Outputs are forced off, e.g. after the inputs stopped reading.
+    Fault,
+}
+
+/// A blink code: `blinks` flashes of `on` lit and `off` dark, then `pause` dark before it
+/// repeats. Times are in whatever units `StatusLed::update` is given, normally milliseconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pattern {
+    pub blinks: u8,
+    pub on: u32,
+    pub off: u32,
+    pub pause: u32,
+}
+
+impl Pattern {
+    pub const fn new(blinks: u8, on: u32, off: u32, pause: u32) -> Self {
+        Self {
+            blinks,
+            on,
+            off,
+            pause,
+        }
+    }
+
+    /// Like `new` with typed durations, fugit ones too with the `fugit` feature.
+    pub fn timed<D: Into<Duration>>(blinks: u8, on: D, off: D, pause: D) -> Self {
+        Self::new(
+            blinks,
+            on.into().millis(),
+            off.into().millis(),
+            pause.into().millis(),
+        )
+    }
+
+    /// Whether the LED is lit `elapsed` after the pattern started.
+    pub fn is_lit(&self, elapsed: u32) -> bool {
+        let blink = self.on.saturating_add(self.off);
+        let flashing = blink.saturating_mul(self.blinks as u32);
+        let period = flashing.saturating_add(self.pause);
+        if flashing == 0 {
+            return false;
+        }
+        let t = elapsed % period;
+        t < flashing && t % blink < self.on
+    }
+}
+
+/// A slow heartbeat, so a running board is distinguishable from a hung one.
+pub const OK: Pattern = Pattern::new(1, 100, 0, 900);
+/// Two quick flashes a second.
+pub const COMM_LOST: Pattern = Pattern::new(2, 100, 150, 500);
+/// Fast, even flashing.
+pub const FAULT: Pattern = Pattern::new(1, 100, 0, 100);
+
+/// StatusLed renders a blink code on `pin` from a periodic tick instead of a delay loop,
+/// so it can share the scan task. Call `update` with the current time each tick; the pin
+/// is only written when its level changes. A new code starts its pattern from the top.
+pub struct StatusLed<P: OutputPin> {
+    pin: P,
+    code: Code,
+    patterns: [Pattern; 3],
+    started: Option<u32>,
+    lit: bool,
+}
+
+impl<P: OutputPin> StatusLed<P> {
+    /// Takes `pin`, turns it off and shows `Code::Ok` from the first update.
+    pub fn new(mut pin: P) -> Self {
+        let _ = pin.set_low();
+        Self {
+            pin,
+            code: Code::Ok,
+            patterns: [OK, COMM_LOST, FAULT],
+            started: None,
+            lit: false,
+        }
+    }
+
+    pub fn with_pattern(mut self, code: Code, pattern: Pattern) -> Self {
+        self.patterns[code as usize] = pattern;
+        self
+    }
+
+    pub fn code(&self) -> Code {
+        self.code
+    }
+
+    pub fn set_code(&mut self, code: Code) {
+        if code != self.code {
+            self.code = code;
+            self.started = None;
+        }
+    }
+
+    pub fn is_lit(&self) -> bool {
+        self.lit
+    }
+
+    /// Drives the LED for time `now`; timestamps are expected to wrap.
+    pub fn update(&mut self, now: u32) -> Result<(), P::Error> {
+        let started = *self.started.get_or_insert(now);
+        let lit = self.patterns[self.code as usize].is_lit(now.wrapping_sub(started));
+        if lit != self.lit {
+            if lit {
+                self.pin.set_high()?;
+            } else {
+                self.pin.set_low()?;
+            }
+            self.lit = lit;
+        }
+        Ok(())
+    }
+
+    /// Turns the LED off and hands the pin back.
+    pub fn free(mut self) -> P {
+        let _ = self.pin.set_low();
+        self.pin
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::status::{Code, Pattern, StatusLed, COMM_LOST};
+    use crate::time::Duration;
+    use core::cell::Cell;
+    use core::convert::Infallible;
+    use embedded_hal::digital::v2::OutputPin;
+
+    struct MockPin<'a>(&'a Cell<u32>);
+
+    impl OutputPin for MockPin<'_> {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            self.0.set(self.0.get() & !1);
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            // Counts rising edges above the level bit.
+            self.0.set((self.0.get() | 1) + 2);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn blink_codes_follow_the_tick() {
+        assert!(COMM_LOST.is_lit(0));
+        assert!(!COMM_LOST.is_lit(100));
+        assert!(COMM_LOST.is_lit(250));
+        assert!(!COMM_LOST.is_lit(350));
+        assert!(!COMM_LOST.is_lit(999));
+        assert!(COMM_LOST.is_lit(1000));
+
+        let pin = Cell::new(0);
+        let mut led =
+            StatusLed::new(MockPin(&pin)).with_pattern(Code::Fault, Pattern::new(3, 10, 10, 40));
+        assert_eq!(
+            Pattern::timed(
+                3,
+                Duration::from_millis(10),
+                Duration::from_millis(10),
+                Duration::from_millis(40)
+            ),
+            Pattern::new(3, 10, 10, 40)
+        );
+        led.set_code(Code::Fault);
+        for now in 5000..5100 {
+            led.update(now).unwrap();
+        }
+        // Three flashes per 100ms period, written once each.
+        assert_eq!(pin.get() >> 1, 3);
+
+        led.set_code(Code::Ok);
+        led.update(5100).unwrap();
+        assert!(led.is_lit());
+        led.update(5200).unwrap();
+        assert!(!led.is_lit());
+    }
+}
@@ -0,0 +1,201 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+use cortex_m::peripheral::{syst::SystClkSource, SCB, SYST};
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+use feather_m0::time::Hertz;
+
+pub use crate::time::{Duration, Instant};
+
+static MILLIS: AtomicU32 = AtomicU32::new(0);
+static RELOAD: AtomicU32 = AtomicU32::new(0);
+static TICKS_PER_US: AtomicU32 = AtomicU32::new(0);
+
+/// Monotonic millisecond clock on SysTick. The counter interrupts once a millisecond and
+/// the application calls `on_systick` from that handler; everything else reads the
+/// shared count through `now`, `millis` and `micros`, so any task can timestamp without
+/// owning the clock.
+///
+/// SysTick can only serve one user, so this replaces `delay::Delay`. `SysDelay` busy-waits
+/// on the running clock instead.
+pub struct SysClock {
+    syst: SYST,
+}
+
+impl SysClock {
+    pub fn new<F: Into<Hertz>>(mut syst: SYST, core: F) -> Self {
+        let core = core.into().0;
+        let reload = core / 1000 - 1;
+        RELOAD.store(reload, Ordering::Relaxed);
+        TICKS_PER_US.store(core / 1_000_000, Ordering::Relaxed);
+        MILLIS.store(0, Ordering::Relaxed);
+
+        syst.set_clock_source(SystClkSource::Core);
+        syst.set_reload(reload);
+        syst.clear_current();
+        syst.enable_interrupt();
+        syst.enable_counter();
+        Self { syst }
+    }
+
+    pub fn now(&self) -> Instant {
+        now()
+    }
+
+    pub fn delay(&self) -> SysDelay {
+        SysDelay
+    }
+
+    /// Stops the clock and hands SysTick back. `now` stops advancing.
+    pub fn free(mut self) -> SYST {
+        self.syst.disable_interrupt();
+        self.syst.disable_counter();
+        self.syst
+    }
+}
+
+/// Advances the clock. Call this, and nothing else, from the SysTick exception.
+pub fn on_systick() {
+    // Only this handler writes, so a plain load/store is enough on the M0+.
+    let ms = MILLIS.load(Ordering::Relaxed);
+    MILLIS.store(ms.wrapping_add(1), Ordering::Release);
+}
+
+pub fn now() -> Instant {
+    Instant::from_millis(millis())
+}
+
+/// Milliseconds since `SysClock::new`, wrapping after about 49 days. Includes a tick
+/// whose interrupt is pending but masked, so it's correct inside critical sections too.
+pub fn millis() -> u32 {
+    let (ms, _) = sample();
+    ms
+}
+
+/// Microseconds since `SysClock::new`, wrapping after about 71 minutes.
+pub fn micros() -> u32 {
+    let (ms, current) = sample();
+    micros_at(
+        ms,
+        current,
+        RELOAD.load(Ordering::Relaxed),
+        TICKS_PER_US.load(Ordering::Relaxed),
+    )
+}
+
+// Reads the millisecond count and the SysTick counter as one consistent pair.
+fn sample() -> (u32, u32) {
+    loop {
+        let ms = MILLIS.load(Ordering::Acquire);
+        let current = SYST::get_current();
+        let pending = SCB::is_pendst_pending();
+        if MILLIS.load(Ordering::Acquire) != ms {
+            continue;
+        }
+        // A pending tick wrapped the counter either before `current` was read, so it
+        // belongs to the next millisecond, or just after, when the counter reloaded.
+        if pending && SYST::get_current() <= current {
+            return (ms.wrapping_add(1), current);
+        }
+        return (ms, current);
+    }
+}
+
+// SysTick counts down from `reload` to zero once per millisecond.
+fn micros_at(ms: u32, current: u32, reload: u32, ticks_per_us: u32) -> u32 {
+    let into_ms = match ticks_per_us {
+        0 => 0,
+        t => (reload - current.min(reload)) / t,
+    };
+    ms.wrapping_mul(1000).wrapping_add(into_ms)
+}
+
+/// Lets RTIC schedule on the clock, e.g. `pulse_end::spawn_after(Duration::from_millis(30))`.
+/// SysTick keeps its fixed millisecond period instead of being reprogrammed per deadline,
+/// so tasks run on the first tick at or after their instant and the count never stops.
+/// Bind the monotonic to `SysTick`; RTIC then calls `on_interrupt`, which replaces the
+/// application's own `on_systick` call.
+#[cfg(feature = "rtic")]
+impl rtic_monotonic::Monotonic for SysClock {
+    const DISABLE_INTERRUPT_ON_EMPTY_QUEUE: bool = false;
+
+    type Instant = Instant;
+    type Duration = Duration;
+
+    fn now(&mut self) -> Instant {
+        now()
+    }
+
+    fn set_compare(&mut self, _instant: Instant) {}
+
+    fn clear_compare_flag(&mut self) {}
+
+    fn zero() -> Instant {
+        Instant::from_millis(0)
+    }
+
+    unsafe fn reset(&mut self) {
+        self.syst.clear_current();
+        MILLIS.store(0, Ordering::Release);
+    }
+
+    fn on_interrupt(&mut self) {
+        on_systick();
+    }
+}
+
+/// Blocking delays measured on the running `SysClock`. Waits at least as long as asked,
+/// and keeps working across the millisecond interrupt, but needs that interrupt to run:
+/// with it masked for over a millisecond (e.g. in `init`) the wait ends early.
+#[derive(Clone, Copy, Debug)]
+pub struct SysDelay;
+
+impl DelayUs<u32> for SysDelay {
+    fn delay_us(&mut self, us: u32) {
+        let start = micros();
+        while micros().wrapping_sub(start) <= us {}
+    }
+}
+
+impl DelayUs<u16> for SysDelay {
+    fn delay_us(&mut self, us: u16) {
+        self.delay_us(us as u32);
+    }
+}
+
+impl DelayUs<u8> for SysDelay {
+    fn delay_us(&mut self, us: u8) {
+        self.delay_us(us as u32);
+    }
+}
+
+impl DelayMs<u32> for SysDelay {
+    fn delay_ms(&mut self, ms: u32) {
+        let start = now();
+        while now().duration_since(start).millis() <= ms {}
+    }
+}
+
+impl DelayMs<u16> for SysDelay {
+    fn delay_ms(&mut self, ms: u16) {
+        self.delay_ms(ms as u32);
+    }
+}
+
+impl DelayMs<u8> for SysDelay {
+    fn delay_ms(&mut self, ms: u8) {
+        self.delay_ms(ms as u32);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::sysclock::micros_at;
+
+    #[test]
+    fn micros_counts_into_the_millisecond() {
+        // 48MHz core: 48 counts per microsecond, reload 47_999.
+        assert_eq!(micros_at(3, 47_999, 47_999, 48), 3000);
+        assert_eq!(micros_at(3, 24_000, 47_999, 48), 3499);
+        assert_eq!(micros_at(3, 0, 47_999, 48), 3999);
+        assert_eq!(micros_at(4_294_968, 47_999, 47_999, 48), 704);
+    }
+}
@@ -0,0 +1,114 @@
+use heapless::{consts::*, Vec};
+
+use crate::controller::Snapshot;
+use crate::time::{Duration, Instant};
+
+/// Telemetry decides when to publish a `controller::Snapshot` so the master can show coil
+/// and switch status live. Poll it every tick with the current time; when a frame is due
+/// it builds the snapshot and returns it serialized, ready to send over palantir. Frames
+/// are spaced by the interval without drifting, but a publisher that falls more than an
+/// interval behind (the bus was busy) sends once and starts counting again rather than
+/// bursting to catch up.
+pub struct Telemetry {
+    interval: Option<Duration>,
+    next: Option<Instant>,
+}
+
+impl Telemetry {
+    /// Publishes every `interval`, in the units of the timestamps given to `poll`,
+    /// normally milliseconds. Takes a `time::Duration`, or with the `fugit` feature any
+    /// fugit duration. The first poll publishes straight away.
+    pub fn every<D: Into<Duration>>(interval: D) -> Self {
+        Self {
+            interval: Some(interval.into()),
+            next: None,
+        }
+    }
+
+    /// A publisher that stays quiet until given an interval.
+    pub fn disabled() -> Self {
+        Self {
+            interval: None,
+            next: None,
+        }
+    }
+
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+
+    /// Changes the rate, or stops publishing with `None`. The next poll publishes.
+    pub fn set_interval(&mut self, interval: Option<Duration>) {
+        self.interval = interval;
+        self.next = None;
+    }
+
+    /// Whether a frame is due at `now`.
+    pub fn is_due(&self, now: u32) -> bool {
+        match (self.interval, self.next) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(_), Some(next)) => Instant::from_millis(now) >= next,
+        }
+    }
+
+    /// Returns the serialized snapshot if one is due at `now`. `snapshot` is only called
+    /// when it is, e.g. `telemetry.poll(now, || controller.snapshot())`.
+    pub fn poll<F: FnOnce() -> Snapshot>(&mut self, now: u32, snapshot: F) -> Option<Vec<u8, U96>> {
+        if !self.is_due(now) {
+            return None;
+        }
+
+        let interval = self.interval?;
+        let now = Instant::from_millis(now);
+        let next = match self.next {
+            Some(next) if now.duration_since(next) < interval => next + interval,
+            _ => now + interval,
+        };
+        self.next = Some(next);
+        Some(snapshot().to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::controller::{Faults, Snapshot};
+    use crate::telemetry::Telemetry;
+    use crate::time::Duration;
+    use heapless::Vec;
+
+    fn snapshot(ticks: u32) -> Snapshot {
+        Snapshot {
+            ticks,
+            raw: 0,
+            faults: Faults::empty(),
+            states: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn publishes_at_the_interval() {
+        let mut telemetry = Telemetry::every(Duration::from_millis(100));
+        let mut sent = 0;
+        for now in (u32::MAX - 49)..=u32::MAX {
+            sent += telemetry.poll(now, || snapshot(now)).is_some() as u32;
+        }
+        for now in 0..250 {
+            sent += telemetry.poll(now, || snapshot(now)).is_some() as u32;
+        }
+        // At -50, 50 and 150 across the wrap.
+        assert_eq!(sent, 3);
+
+        // Late polls don't drift the schedule until a whole interval is missed.
+        assert!(telemetry.poll(260, || snapshot(260)).is_some());
+        assert!(!telemetry.is_due(349));
+        assert!(telemetry.is_due(350));
+        let frame = telemetry.poll(900, || snapshot(900)).unwrap();
+        assert_eq!(Snapshot::from_bytes(&frame), Some(snapshot(900)));
+        assert!(!telemetry.is_due(999));
+
+        telemetry.set_interval(None);
+        assert!(telemetry.poll(2000, || snapshot(2000)).is_none());
+        assert!(Telemetry::disabled().poll(0, || snapshot(0)).is_none());
+    }
+}
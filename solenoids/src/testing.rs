@@ -0,0 +1,92 @@
+// Test doubles shared by the host adapters' tests: a byte transport, a word source and an
+// output that remembers what it was last given.
+
+use core::convert::Infallible;
+use heapless::{consts::*, spsc::Queue, Vec};
+
+use crate::actuators::Basic;
+use crate::console::Transport;
+use crate::controller::{Controllable, Controller, Erased};
+use crate::output::OutputDriver;
+use crate::pwm::{Configuration, State};
+use crate::{Error, InputArray, SingleInput};
+
+// Reads back what it was loaded with and keeps whatever is written.
+pub struct Loopback {
+    pub rx: Queue<u8, U128>,
+    pub tx: Vec<u8, U256>,
+}
+
+impl Loopback {
+    pub fn new(input: &[u8]) -> Self {
+        let mut rx = Queue::new();
+        for &b in input {
+            rx.enqueue(b).unwrap();
+        }
+        Self { rx, tx: Vec::new() }
+    }
+}
+
+impl Transport for Loopback {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Infallible> {
+        self.rx.dequeue().ok_or(nb::Error::WouldBlock)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<usize, Infallible> {
+        self.tx.extend_from_slice(bytes).unwrap();
+        Ok(bytes.len())
+    }
+}
+
+pub struct Word16(pub u16);
+
+impl Controllable for Word16 {
+    fn load_data(&mut self) -> Result<u16, Error> {
+        Ok(self.0)
+    }
+}
+
+// Reads fail with `Error::Bus` while it holds `None`.
+pub struct FlakyWord16(pub Option<u16>);
+
+impl Controllable for FlakyWord16 {
+    fn load_data(&mut self) -> Result<u16, Error> {
+        self.0.ok_or(Error::Bus)
+    }
+}
+
+pub struct Latch(pub State);
+
+impl OutputDriver for Latch {
+    fn apply(&mut self, state: &State) {
+        self.0 = *state;
+    }
+
+    fn state(&self) -> State {
+        self.0
+    }
+}
+
+pub type BasicController = Controller<Word16, Erased<SingleInput, Basic>, Latch>;
+
+// A controller with one `Basic` on bit 0, its inputs low, not yet ticked.
+pub fn basic_controller() -> BasicController {
+    let mut controller: BasicController = Controller::new(Word16(0), InputArray::new());
+    let basic: Basic = controller
+        .inputs_mut()
+        .make_actuator(Configuration::Tc3)
+        .unwrap();
+    let off = State {
+        enabled: false,
+        duty_cycle: 0,
+    };
+    controller.register(Erased::new(basic), Latch(off)).unwrap();
+    controller
+}
+
+// Whether the first actuator's output is on.
+pub fn is_on(controller: &BasicController) -> bool {
+    controller.actuators()[0].1 .0.enabled
+}
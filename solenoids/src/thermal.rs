@@ -0,0 +1,215 @@
+use crate::pwm::State;
+use crate::safety::{SafetyMonitor, SafetyState};
+
+/// Over-temperature response stages, from least to most severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Normal,
+    /// Report only.
+    Warn,
+    /// Scale the bank's duty down to `Thresholds::derate_percent`.
+    Derate,
+    /// Turn off every output in the affected bank.
+    DisableBank,
+    /// Put the whole board in its safe state.
+    SafeState,
+}
+
+/// Temperatures, in tenths of a degree Celsius, at which each stage is entered. A stage is
+/// left once the temperature falls `hysteresis` below its threshold.
+#[derive(Clone, Copy, Debug)]
+pub struct Thresholds {
+    pub warn: i16,
+    pub derate: i16,
+    pub disable_bank: i16,
+    pub safe_state: i16,
+    pub hysteresis: i16,
+    pub derate_percent: u8,
+}
+
+impl Thresholds {
+    fn entry(&self, stage: Stage) -> i16 {
+        match stage {
+            Stage::Normal => i16::MIN,
+            Stage::Warn => self.warn,
+            Stage::Derate => self.derate,
+            Stage::DisableBank => self.disable_bank,
+            Stage::SafeState => self.safe_state,
+        }
+    }
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            warn: 600,
+            derate: 700,
+            disable_bank: 850,
+            safe_state: 1000,
+            hysteresis: 50,
+            derate_percent: 50,
+        }
+    }
+}
+
+/// Emitted whenever the policy changes stage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThermalEvent {
+    pub from: Stage,
+    pub to: Stage,
+    pub temperature: i16,
+}
+
+const STAGES: [Stage; 5] = [
+    Stage::Normal,
+    Stage::Warn,
+    Stage::Derate,
+    Stage::DisableBank,
+    Stage::SafeState,
+];
+
+/// ThermalPolicy runs the staged over-temperature response for one bank of outputs and its
+/// temperature sensor. Escalation happens as soon as a threshold is reached; recovery steps
+/// down only once the temperature is below the current stage's threshold by the hysteresis.
+pub struct ThermalPolicy {
+    thresholds: Thresholds,
+    stage: Stage,
+}
+
+impl ThermalPolicy {
+    pub fn new(thresholds: Thresholds) -> Self {
+        Self {
+            thresholds,
+            stage: Stage::Normal,
+        }
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    pub fn thresholds(&self) -> &Thresholds {
+        &self.thresholds
+    }
+
+    /// Feeds a new temperature sample, returning an event if the stage changed.
+    pub fn update(&mut self, temperature: i16) -> Option<ThermalEvent> {
+        let reached = *STAGES
+            .iter()
+            .rev()
+            .find(|&&s| temperature >= self.thresholds.entry(s))
+            .unwrap_or(&Stage::Normal);
+
+        let mut next = self.stage;
+        if reached > next {
+            next = reached;
+        }
+        // When cooling, step down through every stage whose exit point has been cleared.
+        while next > reached
+            && temperature
+                < self
+                    .thresholds
+                    .entry(next)
+                    .saturating_sub(self.thresholds.hysteresis)
+        {
+            next = STAGES[next as usize - 1];
+        }
+
+        if next == self.stage {
+            return None;
+        }
+        let event = ThermalEvent {
+            from: self.stage,
+            to: next,
+            temperature,
+        };
+        self.stage = next;
+        Some(event)
+    }
+
+    /// Applies the current stage to the states of the outputs in this policy's bank.
+    pub fn apply(&self, states: &mut [State]) {
+        match self.stage {
+            Stage::Normal | Stage::Warn => (),
+            Stage::Derate => {
+                for state in states.iter_mut() {
+                    let duty = state.duty_cycle as u64 * self.thresholds.derate_percent as u64;
+                    state.duty_cycle = (duty / 100) as u32;
+                }
+            }
+            Stage::DisableBank | Stage::SafeState => {
+                for state in states.iter_mut() {
+                    state.enabled = false;
+                }
+            }
+        }
+    }
+
+    /// Raises or clears the over-temperature fault in the safety monitor.
+    pub fn report(&self, monitor: &mut SafetyMonitor) {
+        monitor.set(SafetyState::OVER_TEMP, self.stage == Stage::SafeState);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pwm::State;
+    use crate::thermal::{Stage, ThermalPolicy, Thresholds};
+
+    fn stages(policy: &mut ThermalPolicy, temps: &[i16]) -> [Stage; 8] {
+        let mut out = [Stage::Normal; 8];
+        for (stage, &t) in out.iter_mut().zip(temps.iter()) {
+            policy.update(t);
+            *stage = policy.stage();
+        }
+        out
+    }
+
+    #[test]
+    fn escalates_and_recovers_with_hysteresis() {
+        let mut policy = ThermalPolicy::new(Thresholds::default());
+        let seen = stages(&mut policy, &[500, 650, 720, 1010, 980, 940, 660, 400]);
+        assert_eq!(
+            seen,
+            [
+                Stage::Normal,
+                Stage::Warn,
+                Stage::Derate,
+                Stage::SafeState,
+                Stage::SafeState,
+                Stage::DisableBank,
+                Stage::Derate,
+                Stage::Normal,
+            ]
+        );
+    }
+
+    #[test]
+    fn events_only_on_transition() {
+        let mut policy = ThermalPolicy::new(Thresholds::default());
+        assert!(policy.update(610).is_some());
+        assert!(policy.update(620).is_none());
+        let event = policy.update(870).unwrap();
+        assert_eq!(event.from, Stage::Warn);
+        assert_eq!(event.to, Stage::DisableBank);
+    }
+
+    #[test]
+    fn derate_and_disable() {
+        let mut policy = ThermalPolicy::new(Thresholds::default());
+        let on = State {
+            enabled: true,
+            duty_cycle: 1000,
+        };
+
+        policy.update(700);
+        let mut states = [on];
+        policy.apply(&mut states);
+        assert_eq!(states[0].duty_cycle, 500);
+
+        policy.update(900);
+        let mut states = [on];
+        policy.apply(&mut states);
+        assert!(!states[0].enabled);
+    }
+}
@@ -0,0 +1,275 @@
+use core::cmp::Ordering;
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+
+use crate::sysclock;
+
+/// A point in time, in milliseconds on a wrapping count such as `sysclock::now`. The same
+/// unit the controller's `tick_at` and `InputArray::update_at` timestamps normally use;
+/// the crate's own time math goes through these types whatever unit the timestamps are
+/// in, so it is wrap-safe everywhere.
+///
+/// Comparisons and differences are wrap-safe as long as the two instants are less than
+/// about 24 days apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Instant(u32);
+
+impl Instant {
+    pub const fn from_millis(millis: u32) -> Self {
+        Instant(millis)
+    }
+
+    /// The instant `ticks` periods of a `hz` counter after zero, e.g. a count of timer
+    /// interrupts. Only valid until the counter wraps.
+    pub fn from_ticks(ticks: u32, hz: u32) -> Self {
+        Instant(Duration::from_ticks(ticks, hz).0)
+    }
+
+    pub fn millis(self) -> u32 {
+        self.0
+    }
+
+    /// Time from `earlier` to `self`, or `None` if `earlier` is actually later.
+    pub fn checked_duration_since(self, earlier: Instant) -> Option<Duration> {
+        let diff = self.0.wrapping_sub(earlier.0);
+        if (diff as i32) < 0 {
+            None
+        } else {
+            Some(Duration(diff))
+        }
+    }
+
+    /// Time from `earlier` to `self`, zero if `earlier` is actually later.
+    pub fn duration_since(self, earlier: Instant) -> Duration {
+        self.checked_duration_since(earlier).unwrap_or(Duration(0))
+    }
+
+    /// Time since `self` on the running `SysClock`.
+    pub fn elapsed(self) -> Duration {
+        sysclock::now().duration_since(self)
+    }
+
+    pub fn checked_add(self, duration: Duration) -> Option<Instant> {
+        if duration.0 > i32::MAX as u32 {
+            None
+        } else {
+            Some(Instant(self.0.wrapping_add(duration.0)))
+        }
+    }
+
+    pub fn checked_sub(self, duration: Duration) -> Option<Instant> {
+        if duration.0 > i32::MAX as u32 {
+            None
+        } else {
+            Some(Instant(self.0.wrapping_sub(duration.0)))
+        }
+    }
+}
+
+impl Ord for Instant {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.0.wrapping_sub(other.0) as i32).cmp(&0)
+    }
+}
+
+impl PartialOrd for Instant {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, rhs: Duration) -> Instant {
+        Instant(self.0.wrapping_add(rhs.0))
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, rhs: Duration) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, rhs: Duration) -> Instant {
+        Instant(self.0.wrapping_sub(rhs.0))
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, rhs: Duration) {
+        *self = *self - rhs;
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Duration {
+        self.duration_since(rhs)
+    }
+}
+
+/// A span of time in milliseconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Duration(u32);
+
+impl Duration {
+    pub const ZERO: Duration = Duration(0);
+    pub const MAX: Duration = Duration(u32::MAX);
+
+    pub const fn from_millis(millis: u32) -> Self {
+        Duration(millis)
+    }
+
+    pub const fn from_secs(secs: u32) -> Self {
+        Duration(secs * 1000)
+    }
+
+    /// `ticks` periods of a `hz` counter, rounded down to whole milliseconds and saturating
+    /// at `MAX`. A zero rate gives `MAX`.
+    pub fn from_ticks(ticks: u32, hz: u32) -> Self {
+        if hz == 0 {
+            return Duration::MAX;
+        }
+        let millis = ticks as u64 * 1000 / hz as u64;
+        Duration(millis.min(u32::MAX as u64) as u32)
+    }
+
+    /// The number of `hz` periods that cover this duration, rounded up so a timeout counted
+    /// in ticks never ends early.
+    pub fn to_ticks(self, hz: u32) -> u32 {
+        let scaled = self.0 as u64 * hz as u64;
+        let mut ticks = scaled / 1000;
+        if ticks * 1000 < scaled {
+            ticks += 1;
+        }
+        ticks.min(u32::MAX as u64) as u32
+    }
+
+    pub fn millis(self) -> u32 {
+        self.0
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn checked_add(self, rhs: Duration) -> Option<Duration> {
+        self.0.checked_add(rhs.0).map(Duration)
+    }
+
+    pub fn checked_sub(self, rhs: Duration) -> Option<Duration> {
+        self.0.checked_sub(rhs.0).map(Duration)
+    }
+
+    pub fn checked_mul(self, rhs: u32) -> Option<Duration> {
+        self.0.checked_mul(rhs).map(Duration)
+    }
+
+    pub fn saturating_add(self, rhs: Duration) -> Duration {
+        Duration(self.0.saturating_add(rhs.0))
+    }
+
+    pub fn saturating_sub(self, rhs: Duration) -> Duration {
+        Duration(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    fn add(self, rhs: Duration) -> Duration {
+        Duration(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Duration {
+    type Output = Duration;
+
+    fn sub(self, rhs: Duration) -> Duration {
+        Duration(self.0 - rhs.0)
+    }
+}
+
+/// With the `fugit` feature any fugit duration converts, so time-taking APIs such as
+/// `ActuatorExt::cooldown_for` accept `MillisDurationU32::millis(30)` or
+/// `SecsDurationU32::secs(2)` and the units are checked by the compiler. Sub-millisecond
+/// parts are truncated.
+#[cfg(feature = "fugit")]
+impl<const NOM: u32, const DENOM: u32> From<fugit::Duration<u32, NOM, DENOM>> for Duration {
+    fn from(duration: fugit::Duration<u32, NOM, DENOM>) -> Self {
+        Duration(duration.to_millis())
+    }
+}
+
+#[cfg(feature = "fugit")]
+impl From<Duration> for fugit::MillisDurationU32 {
+    fn from(duration: Duration) -> Self {
+        fugit::MillisDurationU32::from_ticks(duration.0)
+    }
+}
+
+#[cfg(feature = "fugit")]
+impl From<fugit::TimerInstantU32<1_000>> for Instant {
+    fn from(instant: fugit::TimerInstantU32<1_000>) -> Self {
+        Instant(instant.ticks())
+    }
+}
+
+#[cfg(feature = "fugit")]
+impl From<Instant> for fugit::TimerInstantU32<1_000> {
+    fn from(instant: Instant) -> Self {
+        fugit::TimerInstantU32::from_ticks(instant.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::time::{Duration, Instant};
+
+    #[test]
+    fn instants_are_wrap_safe() {
+        let before = Instant::from_millis(u32::MAX - 4);
+        let after = before + Duration::from_millis(10);
+        assert_eq!(after.millis(), 5);
+        assert!(after > before);
+        assert_eq!(after - before, Duration::from_millis(10));
+        assert_eq!(before.checked_duration_since(after), None);
+        assert_eq!(before - after, Duration::from_millis(0));
+        assert_eq!(after - Duration::from_millis(10), before);
+    }
+
+    #[test]
+    fn tick_counts_convert() {
+        assert_eq!(Duration::from_ticks(250, 10_000), Duration::from_millis(25));
+        assert_eq!(Duration::from_ticks(5, 0), Duration::MAX);
+        assert_eq!(Duration::from_millis(25).to_ticks(10_000), 250);
+        assert_eq!(Duration::from_millis(3).to_ticks(100), 1);
+        assert_eq!(Instant::from_ticks(48_000, 1_000).millis(), 48_000);
+        assert_eq!(
+            Duration::from_millis(7).checked_mul(3),
+            Some(Duration::from_millis(21))
+        );
+    }
+
+    #[cfg(feature = "fugit")]
+    #[test]
+    fn fugit_durations_convert() {
+        use fugit::{MicrosDurationU32, MillisDurationU32, SecsDurationU32};
+
+        assert_eq!(
+            Duration::from(SecsDurationU32::secs(2)),
+            Duration::from_millis(2000)
+        );
+        assert_eq!(
+            Duration::from(MicrosDurationU32::micros(1500)),
+            Duration::from_millis(1)
+        );
+        let back: MillisDurationU32 = Duration::from_millis(30).into();
+        assert_eq!(back, MillisDurationU32::millis(30));
+    }
+}
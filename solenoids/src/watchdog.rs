@@ -0,0 +1,55 @@
+use crate::{Actuator, InputType};
+
+/// Maximum continuous on-times, in update cycles. Coils get the strict `coil` limit while
+/// actuators marked hold-capable (see `wrappers::Hold`) get the longer `hold` limit, so
+/// intentionally held outputs don't trip the watchdog. `wrappers::MaxOnTime` enforces
+/// these per actuator, and `controller::Controller::with_max_on_time` for every output,
+/// in the units of the input timestamps.
+#[derive(Clone, Copy, Debug)]
+pub struct OnTimeLimits {
+    pub coil: u32,
+    pub hold: u32,
+}
+
+impl OnTimeLimits {
+    pub fn new(coil: u32, hold: u32) -> Self {
+        Self { coil, hold }
+    }
+
+    pub fn limit_for<I: InputType, A: Actuator<I>>(&self, actuator: &A) -> u32 {
+        self.limit(actuator.hold_capable())
+    }
+
+    /// The limit for an actuator that is, or isn't, hold-capable.
+    pub fn limit(&self, hold_capable: bool) -> u32 {
+        if hold_capable {
+            self.hold
+        } else {
+            self.coil
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::actuators::Basic;
+    use crate::pwm::Configuration;
+    use crate::watchdog::OnTimeLimits;
+    use crate::wrappers::Hold;
+    use crate::{InputArray, SingleInput};
+
+    #[test]
+    fn hold_capable_gets_hold_limit() {
+        let mut inputs = InputArray::new();
+        let coil: Basic = inputs
+            .make_actuator::<SingleInput, _>(Configuration::Tc3)
+            .unwrap();
+        let post: Hold<Basic> = inputs
+            .make_actuator::<SingleInput, _>(Configuration::Tc3)
+            .unwrap();
+
+        let limits = OnTimeLimits::new(10, 500);
+        assert_eq!(limits.limit_for(&coil), 10);
+        assert_eq!(limits.limit_for(&post), 500);
+    }
+}
@@ -0,0 +1,953 @@
+use crate::controller::Erased;
+use crate::pwm::{Configuration, State};
+use crate::restart::RestartPolicy;
+use crate::time::{Duration, Instant};
+use crate::watchdog::OnTimeLimits;
+use crate::{Actuator, Error, InputConfig, InputData, InputType, Param};
+
+/// A Decorator adds behaviour on top of another actuator. Every decorator is also an
+/// `Actuator`: configuration is forwarded to the inner actuator and each state it produces
+/// is passed through `decorate`. Decorators are plain generics, so a stack such as
+/// `MaxOnTime<Cooldown<Basic>>` compiles down to nested calls with no dynamic dispatch,
+/// and any stack can wrap any `Actuator<I>`.
+pub trait Decorator {
+    type Inner;
+
+    /// Wraps `inner` with settings that leave its behaviour unchanged. This is what
+    /// `Actuator::new` uses.
+    fn from_inner(inner: Self::Inner) -> Self;
+    fn inner(&self) -> &Self::Inner;
+    fn inner_mut(&mut self) -> &mut Self::Inner;
+
+    /// Adjusts `next`, the state the inner actuator just produced for `data`.
+    fn decorate<I: InputType>(&mut self, data: &InputData<I>, next: State) -> State;
+
+    /// Whether the decorator itself makes the actuator hold-capable. An actuator is
+    /// hold-capable if this or the inner actuator says so.
+    fn adds_hold(&self) -> bool {
+        false
+    }
+
+    /// Applies `param` if it's one of the decorator's own settings, returning false to pass
+    /// it on to the inner actuator.
+    fn set_own_param(&mut self, _param: Param, _value: u32) -> bool {
+        false
+    }
+}
+
+impl<I, D> Actuator<I> for D
+where
+    I: InputType,
+    D: Decorator,
+    D::Inner: Actuator<I>,
+{
+    fn new(input_config: InputConfig<I>, pwm_config: Configuration) -> Self {
+        Self::from_inner(D::Inner::new(input_config, pwm_config))
+    }
+
+    fn input_config(&self) -> &InputConfig<I> {
+        Decorator::inner(self).input_config()
+    }
+
+    fn pwm_config(&self) -> &Configuration {
+        Decorator::inner(self).pwm_config()
+    }
+
+    fn update_state(&mut self, data: &InputData<I>, curr_state: State) -> State {
+        let next = self.inner_mut().update_state(data, curr_state);
+        self.decorate(data, next)
+    }
+
+    fn hold_capable(&self) -> bool {
+        self.adds_hold() || Decorator::inner(self).hold_capable()
+    }
+
+    fn set_param(&mut self, param: Param, value: u32) -> Result<(), Error> {
+        if self.set_own_param(param, value) {
+            return Ok(());
+        }
+        self.inner_mut().set_param(param, value)
+    }
+}
+
+/// How the timed decorators (`Cooldown`, `MaxOnTime`, `Ramp` and the `Restart` homing
+/// pulse) measure their times.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timebase {
+    /// Every update is one unit, so times are in update cycles. The default.
+    Cycles,
+    /// Times are in the units of the input timestamps, see `InputArray::update_at`. With
+    /// the inputs stamped from `sysclock::millis` they are milliseconds, whatever the scan
+    /// rate. Updates that share a timestamp take no time.
+    Timestamps,
+}
+
+// Time covered by each update in a decorator's timebase.
+#[derive(Clone, Copy, Debug)]
+struct Elapsed {
+    timebase: Timebase,
+    last: Option<Instant>,
+}
+
+impl Elapsed {
+    fn new(timebase: Timebase) -> Self {
+        Self {
+            timebase,
+            last: None,
+        }
+    }
+
+    fn step(&mut self, now: u32) -> u32 {
+        match self.timebase {
+            Timebase::Cycles => 1,
+            Timebase::Timestamps => {
+                let now = Instant::from_millis(now);
+                let step = self
+                    .last
+                    .map_or(Duration::ZERO, |last| now.duration_since(last));
+                self.last = Some(now);
+                step.millis()
+            }
+        }
+    }
+}
+
+/// Chainable constructors for the decorators in this module, e.g.
+/// `basic.cooldown(50).max_on_time(20)`.
+pub trait ActuatorExt<I: InputType>: Actuator<I> + Sized {
+    fn hold(self) -> Hold<Self> {
+        Hold::wrap(self)
+    }
+
+    fn cooldown(self, cycles: u32) -> Cooldown<Self> {
+        Cooldown::wrap(self, cycles)
+    }
+
+    fn max_on_time(self, limit: u32) -> MaxOnTime<Self> {
+        MaxOnTime::wrap(self, limit)
+    }
+
+    /// A cooldown in real time; the inputs must be stamped in milliseconds. Takes a
+    /// `time::Duration`, or with the `fugit` feature any fugit duration.
+    fn cooldown_for<D: Into<Duration>>(self, duration: D) -> Cooldown<Self> {
+        Cooldown::wrap(self, duration.into().millis()).with_timebase(Timebase::Timestamps)
+    }
+
+    /// An on-time limit in real time, see `cooldown_for`.
+    fn max_on_for<D: Into<Duration>>(self, duration: D) -> MaxOnTime<Self> {
+        MaxOnTime::wrap(self, duration.into().millis()).with_timebase(Timebase::Timestamps)
+    }
+
+    fn ramp(self, step: u32) -> Ramp<Self> {
+        Ramp::wrap(self, step)
+    }
+
+    fn randomize(self, min_percent: u8, max_percent: u8, seed: u32) -> Randomize<Self> {
+        Randomize::wrap(self, min_percent, max_percent, seed)
+    }
+
+    /// Erases the input type so the actuator can be registered with a `Controller`
+    /// alongside other kinds.
+    fn erased(self) -> Erased<I, Self> {
+        Erased::new(self)
+    }
+}
+
+impl<I: InputType, A: Actuator<I>> ActuatorExt<I> for A {}
+
+/// Hold marks the wrapped actuator as hold-capable so it is checked against the hold
+/// on-time limit rather than the coil limit. Behaviour is otherwise unchanged.
+pub struct Hold<A> {
+    inner: A,
+}
+
+impl<A> Hold<A> {
+    pub fn wrap(inner: A) -> Self {
+        Self { inner }
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+}
+
+impl<A> Decorator for Hold<A> {
+    type Inner = A;
+
+    fn from_inner(inner: A) -> Self {
+        Self::wrap(inner)
+    }
+
+    fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    fn inner_mut(&mut self) -> &mut A {
+        &mut self.inner
+    }
+
+    fn decorate<I: InputType>(&mut self, _data: &InputData<I>, next: State) -> State {
+        next
+    }
+
+    fn adds_hold(&self) -> bool {
+        true
+    }
+}
+
+/// Cooldown enforces a minimum off-time, in update cycles unless another `Timebase` is set,
+/// after each activation of the wrapped actuator. Activations requested during the lockout
+/// are suppressed, which stops coils like the knocker or ball launcher from being
+/// rapid-fired.
+pub struct Cooldown<A> {
+    inner: A,
+    cycles: u32,
+    remaining: u32,
+    active: bool,
+    elapsed: Elapsed,
+}
+
+impl<A> Cooldown<A> {
+    pub fn wrap(inner: A, cycles: u32) -> Self {
+        Self {
+            inner,
+            cycles,
+            remaining: 0,
+            active: false,
+            elapsed: Elapsed::new(Timebase::Cycles),
+        }
+    }
+
+    pub fn with_timebase(mut self, timebase: Timebase) -> Self {
+        self.elapsed = Elapsed::new(timebase);
+        self
+    }
+
+    pub fn timebase(&self) -> Timebase {
+        self.elapsed.timebase
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    pub fn cycles(&self) -> u32 {
+        self.cycles
+    }
+
+    pub fn set_cycles(&mut self, cycles: u32) {
+        self.cycles = cycles;
+        self.remaining = self.remaining.min(cycles);
+    }
+
+    pub fn is_cooling_down(&self) -> bool {
+        self.remaining > 0
+    }
+}
+
+/// Made through `Actuator::new`, the cooldown starts at zero cycles; use `set_cycles` or
+/// `Cooldown::wrap` to configure it.
+impl<A> Decorator for Cooldown<A> {
+    type Inner = A;
+
+    fn from_inner(inner: A) -> Self {
+        Self::wrap(inner, 0)
+    }
+
+    fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    fn inner_mut(&mut self) -> &mut A {
+        &mut self.inner
+    }
+
+    fn decorate<I: InputType>(&mut self, data: &InputData<I>, mut next: State) -> State {
+        let step = self.elapsed.step(data.timestamp());
+        if self.remaining > 0 {
+            self.remaining = self.remaining.saturating_sub(step);
+            next.enabled = false;
+        }
+
+        // The cycle that turns the output off counts towards the lockout.
+        if self.active && !next.enabled {
+            self.remaining = self.cycles.saturating_sub(step);
+        }
+        self.active = next.enabled;
+        next
+    }
+
+    fn set_own_param(&mut self, param: Param, value: u32) -> bool {
+        if param != Param::Cooldown {
+            return false;
+        }
+        self.set_cycles(value);
+        true
+    }
+}
+
+/// MaxOnTime forcibly disables the wrapped actuator once its output has been enabled for
+/// more than `limit` consecutive update cycles, or another `Timebase`'s units. After
+/// tripping, the output stays off until every bit of the actuator's input has returned low.
+pub struct MaxOnTime<A> {
+    inner: A,
+    limit: u32,
+    on_for: Option<u32>,
+    tripped: bool,
+    elapsed: Elapsed,
+}
+
+impl<A> MaxOnTime<A> {
+    pub fn wrap(inner: A, limit: u32) -> Self {
+        Self {
+            inner,
+            limit,
+            on_for: None,
+            tripped: false,
+            elapsed: Elapsed::new(Timebase::Cycles),
+        }
+    }
+
+    pub fn with_timebase(mut self, timebase: Timebase) -> Self {
+        self.elapsed = Elapsed::new(timebase);
+        self
+    }
+
+    pub fn timebase(&self) -> Timebase {
+        self.elapsed.timebase
+    }
+
+    /// Wraps `inner` using the coil or hold limit from `limits`, depending on whether the
+    /// actuator is hold-capable.
+    pub fn with_limits<I: InputType>(inner: A, limits: &OnTimeLimits) -> Self
+    where
+        A: Actuator<I>,
+    {
+        let limit = limits.limit_for(&inner);
+        Self::wrap(inner, limit)
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.limit
+    }
+
+    pub fn set_limit(&mut self, limit: u32) {
+        self.limit = limit;
+    }
+
+    pub fn is_tripped(&self) -> bool {
+        self.tripped
+    }
+}
+
+/// Made through `Actuator::new`, there is no limit until one is set with `set_limit`.
+impl<A> Decorator for MaxOnTime<A> {
+    type Inner = A;
+
+    fn from_inner(inner: A) -> Self {
+        Self::wrap(inner, u32::MAX)
+    }
+
+    fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    fn inner_mut(&mut self) -> &mut A {
+        &mut self.inner
+    }
+
+    fn decorate<I: InputType>(&mut self, data: &InputData<I>, mut next: State) -> State {
+        let step = self.elapsed.step(data.timestamp());
+        if self.tripped {
+            if data.is_any_high() {
+                next.enabled = false;
+                return next;
+            }
+            self.tripped = false;
+        }
+
+        if !next.enabled {
+            self.on_for = None;
+            return next;
+        }
+
+        // Timed from the update that switched the output on.
+        let on_for = self.on_for.map_or(0, |on_for| on_for.saturating_add(step));
+        if on_for >= self.limit {
+            warn!("max on-time tripped after {}", on_for);
+            self.on_for = None;
+            self.tripped = true;
+            next.enabled = false;
+        } else {
+            self.on_for = Some(on_for);
+        }
+        next
+    }
+}
+
+/// Ramp limits how fast the wrapped actuator's duty can rise, by `step` per update cycle
+/// (or per unit of another `Timebase`), starting from zero on each activation. Falling duty
+/// and switching off pass straight through. Soft-starting diverters and motors this way
+/// avoids a full-power inrush.
+pub struct Ramp<A> {
+    inner: A,
+    step: u32,
+    duty: u32,
+    elapsed: Elapsed,
+}
+
+impl<A> Ramp<A> {
+    pub fn wrap(inner: A, step: u32) -> Self {
+        Self {
+            inner,
+            step,
+            duty: 0,
+            elapsed: Elapsed::new(Timebase::Cycles),
+        }
+    }
+
+    pub fn with_timebase(mut self, timebase: Timebase) -> Self {
+        self.elapsed = Elapsed::new(timebase);
+        self
+    }
+
+    pub fn timebase(&self) -> Timebase {
+        self.elapsed.timebase
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    pub fn step(&self) -> u32 {
+        self.step
+    }
+
+    pub fn set_step(&mut self, step: u32) {
+        self.step = step;
+    }
+}
+
+/// Made through `Actuator::new`, the step is `u32::MAX`, so the target duty is reached on
+/// the first cycle until a step is set with `set_step`.
+impl<A> Decorator for Ramp<A> {
+    type Inner = A;
+
+    fn from_inner(inner: A) -> Self {
+        Self::wrap(inner, u32::MAX)
+    }
+
+    fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    fn inner_mut(&mut self) -> &mut A {
+        &mut self.inner
+    }
+
+    fn decorate<I: InputType>(&mut self, data: &InputData<I>, mut next: State) -> State {
+        let step = self.elapsed.step(data.timestamp());
+        if !next.enabled {
+            self.duty = 0;
+            return next;
+        }
+        let rise = self.step.saturating_mul(step);
+        self.duty = self.duty.saturating_add(rise).min(next.duty_cycle);
+        next.duty_cycle = self.duty;
+        next
+    }
+}
+
+/// Small xorshift PRNG, good enough to vary coil strength and cheap on a Cortex-M0.
+#[derive(Clone, Copy, Debug)]
+pub struct XorShift32 {
+    state: u32,
+}
+
+impl XorShift32 {
+    /// A zero seed would lock the generator at zero, so it is replaced with a fixed value.
+    pub fn new(seed: u32) -> Self {
+        Self {
+            state: if seed == 0 { 0x9E37_79B9 } else { seed },
+        }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    /// Uniform-ish value in `low..=high`.
+    pub fn range(&mut self, low: u32, high: u32) -> u32 {
+        if high <= low {
+            return low;
+        }
+        let span = (high - low) as u64 + 1;
+        low + (self.next_u32() as u64 % span) as u32
+    }
+}
+
+/// Randomize scales the wrapped actuator's duty by a random percentage, picked once per
+/// activation from `min_percent..=max_percent`, so pop bumpers and slingshots feel less
+/// mechanical. Seed it with a fixed value for reproducible tests.
+pub struct Randomize<A> {
+    inner: A,
+    rng: XorShift32,
+    min_percent: u8,
+    max_percent: u8,
+    percent: u32,
+    active: bool,
+}
+
+impl<A> Randomize<A> {
+    pub fn wrap(inner: A, min_percent: u8, max_percent: u8, seed: u32) -> Self {
+        Self {
+            inner,
+            rng: XorShift32::new(seed),
+            min_percent,
+            max_percent,
+            percent: 100,
+            active: false,
+        }
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    pub fn set_range(&mut self, min_percent: u8, max_percent: u8) {
+        self.min_percent = min_percent;
+        self.max_percent = max_percent;
+    }
+
+    pub fn reseed(&mut self, seed: u32) {
+        self.rng = XorShift32::new(seed);
+    }
+}
+
+/// Made through `Actuator::new`, the range is 100% to 100%, so duty is unchanged until a
+/// range is set with `set_range`.
+impl<A> Decorator for Randomize<A> {
+    type Inner = A;
+
+    fn from_inner(inner: A) -> Self {
+        Self::wrap(inner, 100, 100, 0)
+    }
+
+    fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    fn inner_mut(&mut self) -> &mut A {
+        &mut self.inner
+    }
+
+    fn decorate<I: InputType>(&mut self, _data: &InputData<I>, mut next: State) -> State {
+        if next.enabled {
+            if !self.active {
+                self.percent = self
+                    .rng
+                    .range(self.min_percent as u32, self.max_percent as u32);
+            }
+            let duty = next.duty_cycle as u64 * self.percent as u64 / 100;
+            next.duty_cycle = duty.min(u32::MAX as u64) as u32;
+        }
+        self.active = next.enabled;
+        next
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Phase {
+    Run,
+    Homing(u32),
+    WaitRelease,
+}
+
+/// Restart applies a `RestartPolicy` to the wrapped actuator after a soft reset. Wrap each
+/// actuator once at start-up, passing whether the black box shows it was active. If it
+/// wasn't, or the policy is `Resume`, the actuator runs normally from the first cycle.
+///
+/// The homing pulse drives the output at the homing duty for a number of cycles (or units
+/// of another `Timebase`), to bring a gate or post back to a known position, and then waits
+/// for the inputs to be released.
+pub struct Restart<A> {
+    inner: A,
+    phase: Phase,
+    home_cycles: u32,
+    home_duty: u32,
+    elapsed: Elapsed,
+}
+
+impl<A> Restart<A> {
+    pub fn wrap(inner: A, policy: RestartPolicy, was_active: bool) -> Self {
+        Self::with_homing(inner, policy, was_active, 10, u32::MAX)
+    }
+
+    /// Like `wrap`, with a homing pulse of `cycles` update cycles at `duty`.
+    pub fn with_homing(
+        inner: A,
+        policy: RestartPolicy,
+        was_active: bool,
+        cycles: u32,
+        duty: u32,
+    ) -> Self {
+        let phase = match (was_active, policy) {
+            (false, _) | (true, RestartPolicy::Resume) => Phase::Run,
+            (true, RestartPolicy::StayOff) => Phase::WaitRelease,
+            (true, RestartPolicy::Home) => Phase::Homing(cycles),
+        };
+        Self {
+            inner,
+            phase,
+            home_cycles: cycles,
+            home_duty: duty,
+            elapsed: Elapsed::new(Timebase::Cycles),
+        }
+    }
+
+    pub fn with_timebase(mut self, timebase: Timebase) -> Self {
+        self.elapsed = Elapsed::new(timebase);
+        self
+    }
+
+    pub fn timebase(&self) -> Timebase {
+        self.elapsed.timebase
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Whether the restart policy is still overriding the actuator.
+    pub fn is_restoring(&self) -> bool {
+        self.phase != Phase::Run
+    }
+
+    pub fn is_homing(&self) -> bool {
+        matches!(self.phase, Phase::Homing(_))
+    }
+
+    pub fn home_cycles(&self) -> u32 {
+        self.home_cycles
+    }
+}
+
+/// Made through `Actuator::new`, the actuator is treated as not having been active, so it
+/// runs normally.
+impl<A> Decorator for Restart<A> {
+    type Inner = A;
+
+    fn from_inner(inner: A) -> Self {
+        Self::wrap(inner, RestartPolicy::Resume, false)
+    }
+
+    fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    fn inner_mut(&mut self) -> &mut A {
+        &mut self.inner
+    }
+
+    fn decorate<I: InputType>(&mut self, data: &InputData<I>, mut next: State) -> State {
+        let step = self.elapsed.step(data.timestamp());
+        match self.phase {
+            Phase::Run => (),
+            Phase::Homing(remaining) => {
+                self.phase = if remaining > step {
+                    Phase::Homing(remaining - step)
+                } else {
+                    Phase::WaitRelease
+                };
+                if remaining > 0 {
+                    next.enabled = true;
+                    next.duty_cycle = self.home_duty;
+                } else {
+                    next.enabled = false;
+                }
+            }
+            Phase::WaitRelease => {
+                if data.is_any_high() {
+                    next.enabled = false;
+                } else {
+                    self.phase = Phase::Run;
+                }
+            }
+        }
+        next
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::actuators::Basic;
+    use crate::pwm::{Configuration, State};
+    use crate::restart::RestartPolicy;
+    use crate::time::Duration;
+    use crate::watchdog::OnTimeLimits;
+    use crate::wrappers::{
+        ActuatorExt, Cooldown, Decorator, Hold, MaxOnTime, Randomize, Restart, Timebase,
+    };
+    use crate::{Actuator, InputArray, SingleInput};
+
+    fn step<A: Actuator<SingleInput>>(inputs: &InputArray, actuator: &mut A) -> bool {
+        let off = State {
+            enabled: false,
+            duty_cycle: 0,
+        };
+        actuator
+            .update_state(&inputs.read(actuator.input_config()), off)
+            .enabled
+    }
+
+    #[test]
+    fn cooldown_locks_out_reactivation() {
+        let mut inputs = InputArray::new();
+        let basic: Basic = inputs.make_actuator(Configuration::Tc3).unwrap();
+        let mut knocker = Cooldown::wrap(basic, 3);
+
+        inputs.update(1);
+        assert!(step(&inputs, &mut knocker));
+        assert!(step(&inputs, &mut knocker));
+
+        inputs.update(0);
+        assert!(!step(&inputs, &mut knocker));
+        assert!(knocker.is_cooling_down());
+
+        inputs.update(1);
+        assert!(!step(&inputs, &mut knocker));
+        assert!(!step(&inputs, &mut knocker));
+        assert!(!knocker.is_cooling_down());
+        assert!(step(&inputs, &mut knocker));
+    }
+
+    #[test]
+    fn zero_cooldown_passes_through() {
+        let mut inputs = InputArray::new();
+        let mut actuator: Cooldown<Basic> = inputs.make_actuator(Configuration::Tc3).unwrap();
+
+        for &raw in [1, 0, 1, 0, 1].iter() {
+            inputs.update(raw);
+            assert_eq!(step(&inputs, &mut actuator), raw == 1);
+        }
+    }
+
+    #[test]
+    fn max_on_time_trips_and_rearms() {
+        let mut inputs = InputArray::new();
+        let basic: Basic = inputs.make_actuator(Configuration::Tc3).unwrap();
+        let mut coil = MaxOnTime::wrap(basic, 2);
+
+        inputs.update(1);
+        assert!(step(&inputs, &mut coil));
+        assert!(step(&inputs, &mut coil));
+        assert!(!step(&inputs, &mut coil));
+        assert!(coil.is_tripped());
+        assert!(!step(&inputs, &mut coil));
+
+        inputs.update(0);
+        assert!(!step(&inputs, &mut coil));
+        assert!(!coil.is_tripped());
+
+        inputs.update(1);
+        assert!(step(&inputs, &mut coil));
+    }
+
+    #[test]
+    fn max_on_time_uses_hold_limit() {
+        let mut inputs = InputArray::new();
+        let limits = OnTimeLimits::new(1, 3);
+        let post: Hold<Basic> = inputs.make_actuator(Configuration::Tc3).unwrap();
+        let mut post = MaxOnTime::with_limits(post, &limits);
+        assert_eq!(post.limit(), 3);
+
+        inputs.update(1);
+        for _ in 0..3 {
+            assert!(step(&inputs, &mut post));
+        }
+        assert!(!step(&inputs, &mut post));
+    }
+
+    #[test]
+    fn timed_decorators_follow_timestamps() {
+        let mut inputs = InputArray::new();
+        let basic: Basic = inputs.make_actuator(Configuration::Tc3).unwrap();
+        let mut coil = basic.max_on_for(Duration::from_millis(20));
+        assert_eq!(coil.timebase(), Timebase::Timestamps);
+
+        // Scanned every 5ms, the limit trips on the fifth sample, not the twenty-first.
+        for t in (0..20).step_by(5) {
+            inputs.update_at(1, t);
+            assert!(step(&inputs, &mut coil));
+            assert!(step(&inputs, &mut coil));
+        }
+        inputs.update_at(1, 20);
+        assert!(!step(&inputs, &mut coil));
+        assert!(coil.is_tripped());
+
+        let basic: Basic = inputs.make_actuator(Configuration::Tc3).unwrap();
+        let mut knocker = basic.cooldown_for(Duration::from_millis(10));
+        inputs.update_at(0b10, 100);
+        assert!(step(&inputs, &mut knocker));
+        inputs.update_at(0, 105);
+        assert!(!step(&inputs, &mut knocker));
+        inputs.update_at(0b10, 110);
+        assert!(!step(&inputs, &mut knocker));
+        inputs.update_at(0b10, 115);
+        assert!(step(&inputs, &mut knocker));
+    }
+
+    #[test]
+    fn randomize_once_per_activation() {
+        let mut inputs = InputArray::new();
+        let basic: Basic = inputs.make_actuator(Configuration::Tc3).unwrap();
+        let mut bumper = Randomize::wrap(basic, 80, 100, 1234);
+        let off = State {
+            enabled: false,
+            duty_cycle: 0,
+        };
+        let full = u32::MAX as u64;
+
+        let mut seen = [0u32; 4];
+        for duty in seen.iter_mut() {
+            inputs.update(1);
+            let first = bumper.update_state(&inputs.read(bumper.input_config()), off);
+            let second = bumper.update_state(&inputs.read(bumper.input_config()), off);
+            assert_eq!(first.duty_cycle, second.duty_cycle);
+            assert!(first.duty_cycle as u64 >= full * 80 / 100);
+            *duty = first.duty_cycle;
+
+            inputs.update(0);
+            bumper.update_state(&inputs.read(bumper.input_config()), off);
+        }
+        assert!(seen.iter().any(|&d| d != seen[0]));
+
+        // Same seed, same sequence.
+        let basic: Basic = inputs.make_actuator(Configuration::Tc3).unwrap();
+        let mut replay = Randomize::wrap(basic, 80, 100, 1234);
+        inputs.update(1 << 1);
+        let state = replay.update_state(&inputs.read(replay.input_config()), off);
+        assert_eq!(state.duty_cycle, seen[0]);
+    }
+
+    #[test]
+    fn ramp_limits_rise() {
+        let mut inputs = InputArray::new();
+        let basic: Basic = inputs.make_actuator(Configuration::Tc3).unwrap();
+        let mut diverter = basic.ramp(u32::MAX / 3 + 1);
+        let off = State {
+            enabled: false,
+            duty_cycle: 0,
+        };
+
+        inputs.update(1);
+        let mut duties = [0u32; 4];
+        for duty in duties.iter_mut() {
+            *duty = diverter
+                .update_state(&inputs.read(diverter.input_config()), off)
+                .duty_cycle;
+        }
+        assert_eq!(duties[0], u32::MAX / 3 + 1);
+        assert!(duties[1] > duties[0]);
+        assert_eq!(duties[2], u32::MAX);
+        assert_eq!(duties[3], u32::MAX);
+
+        inputs.update(0);
+        assert!(!step(&inputs, &mut diverter));
+        inputs.update(1);
+        let state = diverter.update_state(&inputs.read(diverter.input_config()), off);
+        assert_eq!(state.duty_cycle, duties[0]);
+    }
+
+    #[test]
+    fn decorators_compose() {
+        use crate::prelude::Knocker;
+
+        let mut inputs = InputArray::new();
+        let basic: Basic = inputs.make_actuator(Configuration::Tc3).unwrap();
+        let mut knocker: Knocker = basic.cooldown(3).max_on_time(2);
+        assert!(!knocker.hold_capable());
+
+        inputs.update(1);
+        assert!(step(&inputs, &mut knocker));
+        assert!(step(&inputs, &mut knocker));
+        assert!(!step(&inputs, &mut knocker));
+        assert!(knocker.is_tripped());
+
+        // The cooldown sits inside the limit, so it only starts once the input drops.
+        assert!(!Decorator::inner(&knocker).is_cooling_down());
+        inputs.update(0);
+        assert!(!step(&inputs, &mut knocker));
+        assert!(Decorator::inner(&knocker).is_cooling_down());
+
+        knocker.inner_mut().set_cycles(0);
+        inputs.update(1);
+        assert!(step(&inputs, &mut knocker));
+
+        let post: Basic = inputs.make_actuator(Configuration::Tc3).unwrap();
+        let post = post.hold().cooldown(1).max_on_time(10);
+        assert!(post.hold_capable());
+    }
+
+    #[test]
+    fn restart_policies() {
+        let mut inputs = InputArray::new();
+        let mut resumed = Restart::wrap(
+            inputs
+                .make_actuator::<_, Basic>(Configuration::Tc3)
+                .unwrap(),
+            RestartPolicy::Resume,
+            true,
+        );
+        let mut held = Restart::wrap(
+            inputs
+                .make_actuator::<_, Basic>(Configuration::Tc3)
+                .unwrap(),
+            RestartPolicy::StayOff,
+            true,
+        );
+        let mut cold = Restart::wrap(
+            inputs
+                .make_actuator::<_, Basic>(Configuration::Tc3)
+                .unwrap(),
+            RestartPolicy::StayOff,
+            false,
+        );
+        let mut homed = Restart::with_homing(
+            inputs
+                .make_actuator::<_, Basic>(Configuration::Tc3)
+                .unwrap(),
+            RestartPolicy::Home,
+            true,
+            2,
+            100,
+        );
+
+        inputs.update(0b1111);
+        assert!(step(&inputs, &mut resumed));
+        assert!(!step(&inputs, &mut held));
+        assert!(held.is_restoring());
+        assert!(step(&inputs, &mut cold));
+
+        inputs.update(0);
+        assert!(step(&inputs, &mut homed));
+        assert!(step(&inputs, &mut homed));
+        assert!(!homed.is_homing());
+        assert!(!step(&inputs, &mut homed));
+        assert!(!homed.is_restoring());
+
+        assert!(!step(&inputs, &mut held));
+        inputs.update(0b1111);
+        assert!(step(&inputs, &mut held));
+        assert!(step(&inputs, &mut homed));
+    }
+}